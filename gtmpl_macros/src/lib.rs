@@ -0,0 +1,180 @@
+//! Attribute macros for [gtmpl](https://docs.rs/gtmpl) template functions. Enable the
+//! `macros` feature of gtmpl to use them as `gtmpl::funcs::gtmpl_fn`.
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Error, FnArg, GenericArgument, ItemFn, Pat, PathArguments, Result,
+    ReturnType, Type,
+};
+
+/// Turns a function with typed parameters into a template function taking `&[Value]`.
+///
+/// Parameters can be of any type implementing `gtmpl::FromArg`, e.g. `&str`, `String`,
+/// `bool`, integers, floats, `Value`, `Vec<T>` and `HashMap<String, T>`. Trailing `Option<T>`
+/// parameters may be left out by calls. A last `Vec<T>` parameter marked `#[rest]` collects
+/// all remaining arguments, like Go's `...T`.
+///
+/// The function has to return a `Result` whose value converts into a `Value` and whose error
+/// converts into a `FuncError`, like `anyhow::Error`. Calls with the wrong number of arguments or arguments of the
+/// wrong type fail before the function is called.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::funcs::gtmpl_fn;
+/// use gtmpl::{Context, FuncError, Template};
+///
+/// #[gtmpl_fn]
+/// fn greet(name: &str, punctuation: Option<&str>) -> Result<String, FuncError> {
+///     Ok(format!("Hello {}{}", name, punctuation.unwrap_or("!")))
+/// }
+///
+/// #[gtmpl_fn]
+/// fn sum(#[rest] nums: Vec<i64>) -> Result<i64, FuncError> {
+///     Ok(nums.iter().sum())
+/// }
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_func("greet", greet);
+/// tmpl.add_func("sum", sum);
+/// tmpl.parse(r#"{{ greet "World" }} {{ greet "you" "?" }} {{ sum 1 2 3 }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "Hello World! Hello you? 6");
+///
+/// tmpl.parse(r#"{{ greet 1 }}"#).unwrap();
+/// assert!(tmpl.render(&Context::empty()).is_err());
+/// ```
+#[proc_macro_attribute]
+pub fn gtmpl_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let err = Error::new(Span::call_site(), "gtmpl_fn does not take arguments");
+        return err.to_compile_error().into();
+    }
+    let func = parse_macro_input!(item as ItemFn);
+    expand(func)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(mut func: ItemFn) -> Result<proc_macro2::TokenStream> {
+    let sig = &func.sig;
+    if let Some(param) = sig.generics.params.first() {
+        return Err(Error::new_spanned(
+            param,
+            "gtmpl_fn functions can not be generic",
+        ));
+    }
+    if sig.asyncness.is_some() {
+        return Err(Error::new_spanned(
+            sig.asyncness,
+            "gtmpl_fn functions can not be async",
+        ));
+    }
+    if let ReturnType::Default = sig.output {
+        return Err(Error::new_spanned(
+            sig,
+            "gtmpl_fn functions have to return a Result",
+        ));
+    }
+    let name = sig.ident.to_string();
+
+    let mut convert = Vec::new();
+    let mut idents = Vec::new();
+    let mut min = 0;
+    let mut max = Some(0usize);
+    let count = sig.inputs.len();
+    for (i, input) in func.sig.inputs.iter_mut().enumerate() {
+        let param = match input {
+            FnArg::Typed(param) => param,
+            FnArg::Receiver(recv) => {
+                return Err(Error::new_spanned(
+                    recv,
+                    "gtmpl_fn functions can not take self",
+                ))
+            }
+        };
+        let ident = match *param.pat {
+            Pat::Ident(ref pat) if pat.by_ref.is_none() && pat.subpat.is_none() => &pat.ident,
+            ref pat => return Err(Error::new_spanned(pat, "expected a parameter name")),
+        };
+        let var = format_ident!("__{}", ident);
+        let ty = &param.ty;
+        let rest = param.attrs.iter().position(|a| a.path().is_ident("rest"));
+        if let Some(pos) = rest {
+            if i + 1 != count {
+                return Err(Error::new_spanned(
+                    &param.attrs[pos],
+                    "#[rest] has to be last",
+                ));
+            }
+            param.attrs.remove(pos);
+            let item = vec_item(ty).ok_or_else(|| {
+                Error::new_spanned(ty, "#[rest] parameters have to be of type Vec<T>")
+            })?;
+            convert.push(quote! {
+                let #var = ::gtmpl::__private::rest::<#item>(#name, args, #i)?;
+            });
+            max = None;
+        } else {
+            convert.push(quote! {
+                let #var: #ty = ::gtmpl::__private::arg(#name, args, #i)?;
+            });
+            if !is_option(ty) {
+                min = i + 1;
+            }
+            max = max.map(|max| max + 1);
+        }
+        idents.push(var);
+    }
+    let max = match max {
+        Some(max) => quote!(::std::option::Option::Some(#max)),
+        None => quote!(::std::option::Option::None),
+    };
+
+    let attrs = std::mem::take(&mut func.attrs);
+    let vis = std::mem::replace(&mut func.vis, syn::Visibility::Inherited);
+    let outer = std::mem::replace(&mut func.sig.ident, format_ident!("inner"));
+    Ok(quote! {
+        #(#attrs)*
+        #vis fn #outer(
+            args: &[::gtmpl::Value],
+        ) -> ::std::result::Result<::gtmpl::Value, ::gtmpl::FuncError> {
+            #func
+            ::gtmpl::__private::check_arity(#name, args, #min, #max)?;
+            #(#convert)*
+            inner(#(#idents),*)
+                .map(::std::convert::Into::into)
+                .map_err(::std::convert::Into::into)
+        }
+    })
+}
+
+// The last segment of the path of `ty`, if its name is `name`.
+fn last_segment<'a>(ty: &'a Type, name: &str) -> Option<&'a PathArguments> {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            let last = path.path.segments.last()?;
+            if last.ident == name {
+                Some(&last.arguments)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    last_segment(ty, "Option").is_some()
+}
+
+// The item type `T` of `Vec<T>`.
+fn vec_item(ty: &Type) -> Option<&Type> {
+    match last_segment(ty, "Vec")? {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref item) => Some(item),
+            _ => None,
+        },
+        _ => None,
+    }
+}
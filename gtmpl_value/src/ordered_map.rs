@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
+
+use crate::value::Value;
+
+/// Map which keeps the order its keys were inserted in.
+///
+/// Keys added through [`as_map_mut`](OrderedMap::as_map_mut) follow the inserted keys in sorted
+/// order.
+///
+/// # Examples
+///
+/// ```rust
+/// use gtmpl_value::{OrderedMap, Value};
+///
+/// let mut m = OrderedMap::new();
+/// m.insert("b".to_owned(), Value::from(1));
+/// m.insert("a".to_owned(), Value::from(2));
+/// let keys: Vec<_> = m.iter().map(|(k, _)| k.as_str()).collect();
+/// assert_eq!(keys, vec!["b", "a"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct OrderedMap {
+    map: HashMap<String, Value>,
+    order: Vec<String>,
+}
+
+impl OrderedMap {
+    pub fn new() -> Self {
+        OrderedMap::default()
+    }
+
+    /// Inserts a value, adding the key after the others unless it is in the map already.
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        if !self.map.contains_key(&key) {
+            self.order.retain(|k| *k != key);
+            self.order.push(key.clone());
+        }
+        self.map.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.order.retain(|k| k != key);
+        self.map.remove(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.map.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// The entries in the order their keys were inserted in.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        let mut rest: Vec<_> = self
+            .map
+            .iter()
+            .filter(|&(k, _)| !self.order.contains(k))
+            .collect();
+        rest.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        self.order
+            .iter()
+            .filter_map(move |k| self.map.get_key_value(k))
+            .chain(rest)
+    }
+
+    pub fn as_map(&self) -> &HashMap<String, Value> {
+        &self.map
+    }
+
+    pub fn as_map_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.map
+    }
+
+    pub fn into_map(self) -> HashMap<String, Value> {
+        self.map
+    }
+}
+
+/// Ordered maps are equal if they have equal entries in the same order.
+impl PartialEq for OrderedMap {
+    fn eq(&self, other: &OrderedMap) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl fmt::Debug for OrderedMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for OrderedMap
+where
+    K: Into<String>,
+    V: Into<Value>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = OrderedMap::new();
+        for (k, v) in iter {
+            map.insert(k.into(), v.into());
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(m: &OrderedMap) -> Vec<&str> {
+        m.iter().map(|(k, _)| k.as_str()).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let mut m: OrderedMap = vec![("b", 1), ("a", 2), ("c", 3), ("a", 4)]
+            .into_iter()
+            .collect();
+        assert_eq!(keys(&m), vec!["b", "a", "c"]);
+        assert_eq!(m.get("a"), Some(&Value::from(4)));
+        assert_eq!(m.len(), 3);
+
+        m.remove("a");
+        m.as_map_mut().insert("e".to_owned(), Value::from(5));
+        m.as_map_mut().insert("d".to_owned(), Value::from(6));
+        assert_eq!(keys(&m), vec!["b", "c", "d", "e"]);
+        m.as_map_mut().remove("b");
+        m.insert("b".to_owned(), Value::from(7));
+        m.insert("a".to_owned(), Value::from(8));
+        assert_eq!(keys(&m), vec!["c", "b", "a", "d", "e"]);
+    }
+
+    #[test]
+    fn test_eq() {
+        let ab: OrderedMap = vec![("a", 1), ("b", 2)].into_iter().collect();
+        let ba: OrderedMap = vec![("b", 2), ("a", 1)].into_iter().collect();
+        assert_eq!(ab, ab.clone());
+        assert_ne!(ab, ba);
+        assert_eq!(ab.as_map(), ba.as_map());
+    }
+}
@@ -0,0 +1,60 @@
+//! Records of the functions called by executions.
+use gtmpl_value::Value;
+
+use crate::printf::format_value;
+
+// Longest summary of a single argument, in characters.
+const MAX_ARG_LEN: usize = 64;
+
+/// A function or method call made by an execution, see [`Template::set_audit_sink`].
+///
+/// [`Template::set_audit_sink`]: crate::Template::set_audit_sink
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The name of the function or method.
+    pub func: String,
+    /// The arguments, including a piped value, printed like `print` does and each shortened
+    /// to 64 characters.
+    pub args: Vec<String>,
+    /// The name of the template the call is in.
+    pub template: String,
+    /// The line of the call in the template text.
+    pub line: usize,
+}
+
+/// Receives an [`AuditRecord`] for every function call, e.g. to review what templates written
+/// by users did. Closures taking an `AuditRecord` are sinks.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: AuditRecord);
+}
+
+impl<F: Fn(AuditRecord) + Send + Sync> AuditSink for F {
+    fn record(&self, record: AuditRecord) {
+        self(record)
+    }
+}
+
+// Prints `arg` for an audit record.
+pub(crate) fn summarize(arg: &Value) -> String {
+    let text = format_value(arg);
+    match text.char_indices().nth(MAX_ARG_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(&Value::from("short")), "short");
+        assert_eq!(summarize(&Value::from(vec![1, 2])), "[1 2]");
+        let long = "ä".repeat(100);
+        assert_eq!(
+            summarize(&Value::from(long)),
+            format!("{}...", "ä".repeat(64))
+        );
+    }
+}
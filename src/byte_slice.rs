@@ -0,0 +1,48 @@
+//! Byte slices, which print like Go's `[]byte`.
+//!
+//! A `Vec<u8>` becomes an array of numbers, so [`byte_slice`] creates a `Value::Bytes`
+//! instead.
+use gtmpl_value::Value;
+
+/// Creates a byte slice which prints like Go's `[]byte`: `%v` and `{{ . }}` print the bytes as
+/// numbers, while `%s`, `%q`, `%x` and `%X` print them like a string. `len` returns the number
+/// of bytes, `index` and `range` return them as numbers, and `toJson` and `toYaml` write them
+/// in base64 like Go's `encoding/json`.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{byte_slice, template};
+///
+/// let output = template(r#"{{ . }} {{ printf "%s %x %q" . . . }}"#, byte_slice("hi"));
+/// assert_eq!(&output.unwrap(), r#"[104 105] hi 6869 "hi""#);
+/// ```
+pub fn byte_slice(bytes: impl AsRef<[u8]>) -> Value {
+    Value::Bytes(bytes.as_ref().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_slice() {
+        let render = |tmpl: &str| crate::template(tmpl, byte_slice("hi")).unwrap();
+        assert_eq!(render("{{ . }} {{ len . }}"), "[104 105] 2");
+        assert_eq!(
+            render("{{ range $i, $b := . }}{{ $i }}={{ $b }} {{ end }}"),
+            "0=104 1=105 "
+        );
+        assert_eq!(render("{{ printf \"%s|%x|%T\" . . . }}"), "hi|6869|[]uint8");
+        assert_eq!(render("{{ index . 0 }} {{ index . 1 }}"), "104 105");
+        assert_eq!(
+            render("{{ set (dict) \"b\" . }} {{ deepEqual . . }} {{ kindOf . }}"),
+            "map[b:[104 105]] true slice"
+        );
+        assert!(crate::template("{{ keys . }}", byte_slice("hi")).is_err());
+        assert_eq!(
+            crate::template("{{ if . }}x{{ else }}empty{{ end }}", byte_slice([])).unwrap(),
+            "empty"
+        );
+    }
+}
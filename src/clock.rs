@@ -0,0 +1,161 @@
+//! Time and randomness for template functions, which can be fixed for reproducible output.
+//!
+//! Functions reading the current time or random numbers should use [`now`] and [`random`]
+//! instead of `SystemTime::now` or a random number generator of their own. During executions
+//! with [`ExecOptions::deterministic`](crate::ExecOptions::deterministic) set, they return
+//! the frozen time and a seeded sequence of numbers.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! use gtmpl::clock::{self, Deterministic};
+//! use gtmpl::{Context, FuncError, Template, Value};
+//!
+//! fn year(_args: &[Value]) -> Result<Value, FuncError> {
+//!     let secs = clock::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+//!     Ok(Value::from(1970 + secs / 31_556_952))
+//! }
+//!
+//! let mut tmpl = Template::default();
+//! tmpl.add_func("year", year);
+//! tmpl.exec_options.deterministic = Some(Deterministic {
+//!     now: UNIX_EPOCH + Duration::from_secs(1_000_000_000),
+//!     seed: 42,
+//! });
+//! tmpl.parse("© {{ year }}").unwrap();
+//! assert_eq!(tmpl.render(&Context::empty()).unwrap(), "© 2001");
+//! ```
+use std::cell::Cell;
+use std::time::SystemTime;
+
+use rand::RngCore;
+
+/// The time and random seed of a deterministic execution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deterministic {
+    /// The time [`now`] returns.
+    pub now: SystemTime,
+    /// The seed of the numbers [`random`] returns.
+    pub seed: u64,
+}
+
+// The clock of the execution on the current thread.
+#[derive(Clone, Copy)]
+pub(crate) struct Frozen {
+    now: SystemTime,
+    state: u64,
+}
+
+impl From<Deterministic> for Frozen {
+    fn from(d: Deterministic) -> Frozen {
+        Frozen {
+            now: d.now,
+            state: d.seed,
+        }
+    }
+}
+
+thread_local! {
+    static FROZEN: Cell<Option<Frozen>> = const { Cell::new(None) };
+}
+
+/// The current time, or the frozen time of a deterministic execution.
+pub fn now() -> SystemTime {
+    match FROZEN.with(Cell::get) {
+        Some(frozen) => frozen.now,
+        None => SystemTime::now(),
+    }
+}
+
+/// A random number from the cryptographically secure generator of the current thread, or the
+/// next number of the seeded sequence of a deterministic execution.
+///
+/// The numbers of deterministic executions are predictable, so they are not suitable for
+/// cryptographic purposes.
+pub fn random() -> u64 {
+    FROZEN.with(|cell| match cell.get() {
+        Some(mut frozen) => {
+            let n = split_mix(&mut frozen.state);
+            cell.set(Some(frozen));
+            n
+        }
+        None => rand::thread_rng().next_u64(),
+    })
+}
+
+// Runs `f` with the clock `frozen`, e.g. a function called by a deterministic execution.
+pub(crate) fn with<R>(frozen: &Cell<Option<Frozen>>, f: impl FnOnce() -> R) -> R {
+    if frozen.get().is_none() {
+        return f();
+    }
+    // Restores the clock of an enclosing execution even if `f` panics.
+    struct Restore<'c> {
+        frozen: &'c Cell<Option<Frozen>>,
+        prev: Option<Frozen>,
+    }
+    impl Drop for Restore<'_> {
+        fn drop(&mut self) {
+            self.frozen.set(FROZEN.with(|cell| cell.replace(self.prev)));
+        }
+    }
+    let _restore = Restore {
+        frozen,
+        prev: FROZEN.with(|cell| cell.replace(frozen.get())),
+    };
+    f()
+}
+
+// The SplitMix64 generator.
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use gtmpl_value::{FuncError, Value};
+
+    use super::*;
+    use crate::{Context, Template};
+
+    fn dice(_args: &[Value]) -> Result<Value, FuncError> {
+        Ok(Value::from(random() % 6 + 1))
+    }
+
+    fn seconds(_args: &[Value]) -> Result<Value, FuncError> {
+        let since = now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(Value::from(since.as_secs()))
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let mut t = Template::default();
+        t.add_funcs(&[("dice", dice as crate::Func), ("seconds", seconds)]);
+        t.parse(&format!("{{{{ seconds }}}} {}", "{{ dice }}".repeat(8)))
+            .unwrap();
+        let live = t.render(&Context::empty()).unwrap();
+        assert_ne!(live.split(' ').next(), Some("7"));
+
+        t.exec_options.deterministic = Some(Deterministic {
+            now: UNIX_EPOCH + Duration::from_secs(7),
+            seed: 1,
+        });
+        let first = t.render(&Context::empty()).unwrap();
+        assert!(first.starts_with("7 "));
+        assert_eq!(t.render(&Context::empty()).unwrap(), first);
+        assert!(FROZEN.with(Cell::get).is_none());
+
+        t.exec_options.deterministic = Some(Deterministic {
+            now: UNIX_EPOCH,
+            seed: 2,
+        });
+        assert_ne!(t.render(&Context::empty()).unwrap()[2..], first[2..]);
+    }
+}
@@ -0,0 +1,237 @@
+//! [`miette::Diagnostic`] implementations for gtmpl's errors.
+//!
+//! Labels point at byte offsets in the template text. Attach the text to get
+//! rendered snippets:
+//!
+//! ```rust
+//! use gtmpl::Template;
+//!
+//! let text = "{{ if .foo }}";
+//! let err = Template::default().parse(text).unwrap_err();
+//! let report = miette::Report::new(err).with_source_code(text);
+//! assert!(format!("{:?}", report).contains("unexpected EOF"));
+//! ```
+use std::fmt::Display;
+
+use miette::{Diagnostic, LabeledSpan, Severity};
+
+use crate::error::{ExecError, ParseError, ParseWarning, TemplateError, ValidationError};
+use crate::node::{Node, Nodes, Pos};
+
+fn span(pos: Pos, node: &dyn Display, label: &str) -> LabeledSpan {
+    LabeledSpan::new(Some(label.to_owned()), pos, node.to_string().len())
+}
+
+fn node_label(node: &Nodes, label: &str) -> LabeledSpan {
+    span(node.pos(), node, label)
+}
+
+fn labels<'a>(spans: Vec<LabeledSpan>) -> Option<Box<dyn Iterator<Item = LabeledSpan> + 'a>> {
+    if spans.is_empty() {
+        None
+    } else {
+        Some(Box::new(spans.into_iter()))
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ParseError::code(self)))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match *self {
+            ParseError::NoDynamicTemplate => Some(Box::new(
+                "enable the gtmpl_dynamic_template feature of gtmpl",
+            )),
+            _ => None,
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match *self {
+            ParseError::WithContext(ref ctx, ref msg) => {
+                labels(vec![LabeledSpan::at_offset(ctx.pos, msg.clone())])
+            }
+            ParseError::UnexpectedInDefineClause(ref node) => {
+                labels(vec![node_label(node, "unexpected in define clause")])
+            }
+            _ => {
+                let ctx = self.context()?;
+                let msg = self.to_string();
+                let prefix = format!("template: {}:", ctx);
+                let msg = msg.strip_prefix(&prefix).unwrap_or(&msg).to_owned();
+                labels(vec![LabeledSpan::at_offset(ctx.pos, msg)])
+            }
+        }
+    }
+}
+
+impl ExecError {
+    fn code(&self) -> &'static str {
+        match *self {
+            ExecError::IncompleteTemplate(_) => "gtmpl::exec::incomplete_template",
+            ExecError::IOError(_) => "gtmpl::exec::io",
+            ExecError::UnknownNode(_) => "gtmpl::exec::unknown_node",
+            ExecError::ExpectedIfOrWith(_) => "gtmpl::exec::expected_if_or_with",
+            ExecError::Utf8ConversionFailed(_) => "gtmpl::exec::utf8",
+            ExecError::EmptyStack => "gtmpl::exec::empty_stack",
+            ExecError::VarContextToSmall(_) => "gtmpl::exec::var_context",
+            ExecError::InvalidRange(_) => "gtmpl::exec::invalid_range",
+            ExecError::PipelineMustYieldString => "gtmpl::exec::template_name",
+            ExecError::TemplateNotDefined(_) => "gtmpl::exec::template_not_defined",
+            ExecError::MaxDepthExceeded(_) => "gtmpl::exec::max_depth",
+            ExecError::ErrorEvaluatingPipe(_) => "gtmpl::exec::pipe",
+            ExecError::NoArgsForCommandNode(_) => "gtmpl::exec::empty_command",
+            ExecError::CannotEvaluateCommand(_) => "gtmpl::exec::command",
+            ExecError::FieldChainWithoutFields => "gtmpl::exec::field_chain",
+            ExecError::NotAFunctionButArguments(_) => "gtmpl::exec::not_a_function",
+            ExecError::NoFieldsInEvalChainNode => "gtmpl::exec::chain",
+            ExecError::NullInChain(_) => "gtmpl::exec::nil_in_chain",
+            ExecError::InvalidArgument(_) => "gtmpl::exec::invalid_argument",
+            ExecError::UndefinedFunction(_) => "gtmpl::exec::undefined_function",
+            ExecError::FuncError(_) => "gtmpl::exec::function",
+            ExecError::FuncCall { .. } => "gtmpl::exec::call",
+            ExecError::ArgumentForNonFunction(_) => "gtmpl::exec::argument_for_non_function",
+            ExecError::OnlyMapsAndObjectsHaveFields => "gtmpl::exec::no_fields",
+            ExecError::NoFiledFor(..) => "gtmpl::exec::no_field",
+            ExecError::MissingKey(_) => "gtmpl::exec::missing_key",
+            ExecError::NilData(_) => "gtmpl::exec::nil_data",
+            ExecError::NilPointerEvaluating(_) => "gtmpl::exec::nil_pointer",
+            ExecError::VariableNotFound(_) => "gtmpl::exec::variable_not_found",
+            ExecError::Cancelled => "gtmpl::exec::cancelled",
+            ExecError::OutputTooLarge(_) => "gtmpl::exec::max_output_size",
+            ExecError::TooManyIterations(_) => "gtmpl::exec::max_range_iterations",
+            ExecError::InTemplate { ref source, .. } => source.code(),
+        }
+    }
+}
+
+impl Diagnostic for ExecError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ExecError::code(self)))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = match *self {
+            ExecError::UnknownNode(ref node) => node_label(node, "unknown node"),
+            ExecError::ExpectedIfOrWith(ref node) => node_label(node, "expected if or with"),
+            ExecError::CannotEvaluateCommand(ref node) => {
+                node_label(node, "cannot evaluate command")
+            }
+            ExecError::InvalidArgument(ref node) => node_label(node, "invalid argument"),
+            ExecError::ArgumentForNonFunction(ref node) => node_label(node, "not a function"),
+            ExecError::ErrorEvaluatingPipe(ref pipe) => {
+                span(pipe.pos(), pipe, "error evaluating pipe")
+            }
+            ExecError::NoArgsForCommandNode(ref cmd) => span(cmd.pos(), cmd, "empty command"),
+            ExecError::NullInChain(ref chain) => {
+                span(chain.pos(), chain, "indirection through nil")
+            }
+            ExecError::FuncCall {
+                ref ctx, ref func, ..
+            } => span(ctx.pos, func, "error calling function"),
+            ExecError::InTemplate { ref source, .. } => return source.labels(),
+            _ => return None,
+        };
+        labels(vec![span])
+    }
+}
+
+impl Diagnostic for ValidationError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ValidationError::code(self)))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let ctx = self.context();
+        let msg = self.to_string();
+        let prefix = format!("template: {}: ", ctx);
+        let msg = msg.strip_prefix(&prefix).unwrap_or(&msg).to_owned();
+        labels(vec![LabeledSpan::at_offset(ctx.pos, msg)])
+    }
+}
+
+impl Diagnostic for ParseWarning {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(ParseWarning::code(self)))
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(Severity::Warning)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match *self {
+            ParseWarning::DeprecatedFunction {
+                ref ctx, ref name, ..
+            } => labels(vec![span(ctx.pos, name, "deprecated function")]),
+        }
+    }
+}
+
+impl Diagnostic for TemplateError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match *self {
+            TemplateError::ExecError(ref e) => Diagnostic::code(e),
+            TemplateError::ParseError(ref e) => Diagnostic::code(e),
+        }
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match *self {
+            TemplateError::ExecError(ref e) => e.help(),
+            TemplateError::ParseError(ref e) => e.help(),
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match *self {
+            TemplateError::ExecError(ref e) => e.labels(),
+            TemplateError::ParseError(ref e) => e.labels(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Template};
+
+    #[test]
+    fn test_parse_error_label() {
+        let text = "{{ .foo }} {{ foo }}";
+        let err = Template::default().parse(text).unwrap_err();
+        assert_eq!(
+            Diagnostic::code(&err).unwrap().to_string(),
+            "gtmpl::parse::undefined_function"
+        );
+        let label = err.labels().unwrap().next().unwrap();
+        assert_eq!(label.offset(), 14);
+        assert_eq!(label.label(), Some("function foo not defined"));
+    }
+
+    #[test]
+    fn test_exec_error_label() {
+        let mut t = Template::default();
+        t.parse("{{ 1 2 }}").unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            Diagnostic::code(&err).unwrap().to_string(),
+            "gtmpl::exec::argument_for_non_function"
+        );
+        let label = err.labels().unwrap().next().unwrap();
+        assert_eq!(label.offset(), 3);
+    }
+
+    #[test]
+    fn test_warning_label() {
+        let mut t = Template::default();
+        t.funcs.deprecate("len", "use count");
+        t.parse("{{ len . }}").unwrap();
+        let warning = t.warnings().remove(0);
+        assert_eq!(warning.severity(), Some(Severity::Warning));
+        let label = warning.labels().unwrap().next().unwrap();
+        assert_eq!((label.offset(), label.len()), (3, 3));
+    }
+}
@@ -1,12 +1,13 @@
 use crate::node::{ChainNode, CommandNode, Nodes, PipeNode};
 use gtmpl_value::{FuncError, Value};
-use std::{fmt, num::ParseIntError, string::FromUtf8Error};
+use std::{fmt, string::FromUtf8Error};
 use thiserror::Error;
 
 #[derive(Debug)]
 pub struct ErrorContext {
     pub name: String,
     pub line: usize,
+    pub pos: usize,
 }
 
 impl fmt::Display for ErrorContext {
@@ -15,14 +16,78 @@ impl fmt::Display for ErrorContext {
     }
 }
 
+/// The invocation of the template `name` by `{{template}}` at `caller`.
+#[derive(Debug)]
+pub struct TemplateCall {
+    pub name: String,
+    pub caller: ErrorContext,
+}
+
+impl fmt::Display for TemplateCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "in {:?} called from {:?} line {}",
+            self.name, self.caller.name, self.caller.line
+        )
+    }
+}
+
+fn fmt_calls(calls: &[TemplateCall]) -> String {
+    calls.iter().map(|call| format!("; {}", call)).collect()
+}
+
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("unexpected {0} in define clause")]
-    UnexpectedInDefineClause(Nodes),
+    UnexpectedInDefineClause(Box<Nodes>),
     #[error("unexpected end")]
     UnexpectedEnd,
     #[error("template: {0}:{1}")]
     WithContext(ErrorContext, String),
+    #[error("template: {ctx}:unexpected {found} in {context}")]
+    UnexpectedToken {
+        ctx: ErrorContext,
+        expected: Option<String>,
+        found: String,
+        context: String,
+    },
+    #[error("template: {ctx}:unexpected EOF in {context}")]
+    UnexpectedEof { ctx: ErrorContext, context: String },
+    #[error("template: {ctx}:function {name} not defined")]
+    UndefinedFunction { ctx: ErrorContext, name: String },
+    #[error("template: {ctx}:undefined variable {name}")]
+    UndefinedVariable { ctx: ErrorContext, name: String },
+    #[error("template: {ctx}:template multiple definitions of template {name}")]
+    MultipleDefinitions { ctx: ErrorContext, name: String },
+    #[error("template: {ctx}:too many declarations in {context}")]
+    TooManyDeclarations { ctx: ErrorContext, context: String },
+    #[error("template: {ctx}:missing value for {context}")]
+    MissingValue { ctx: ErrorContext, context: String },
+    #[error("template: {ctx}:non executable command in pipeline stage {stage}")]
+    NonExecutableCommand { ctx: ErrorContext, stage: usize },
+    #[error("template: {ctx}:empty command")]
+    EmptyCommand { ctx: ErrorContext },
+    #[error("template: {ctx}:unclosed right paren: unexpected {found}")]
+    UnclosedParen { ctx: ErrorContext, found: String },
+    #[error("template: {ctx}:invalid number {text}: {source}")]
+    InvalidNumber {
+        ctx: ErrorContext,
+        text: String,
+        source: NodeError,
+    },
+    #[error("template: {ctx}:unable to unquote string {text}")]
+    InvalidString { ctx: ErrorContext, text: String },
+    #[error("template: {ctx}:max nesting depth exceeded")]
+    MaxDepthExceeded { ctx: ErrorContext },
+    #[error("template: {ctx}:max expression depth exceeded")]
+    MaxExprDepthExceeded { ctx: ErrorContext },
+    #[error("template: {ctx}:more than {max} templates defined")]
+    TooManyTrees { ctx: ErrorContext, max: usize },
+    #[error("template: {ctx}:more than {max} nodes")]
+    TooManyNodes { ctx: ErrorContext, max: usize },
+    #[error("template text of {size} bytes exceeds the maximum of {max} bytes")]
+    SourceTooLarge { size: usize, max: usize },
     #[error("no tree")]
     NoTree,
     #[error(transparent)]
@@ -34,15 +99,105 @@ pub enum ParseError {
 }
 
 impl ParseError {
-    pub fn with_context(name: impl ToString, line: usize, msg: impl ToString) -> Self {
+    pub fn with_context(name: impl ToString, line: usize, pos: usize, msg: impl ToString) -> Self {
         Self::WithContext(
             ErrorContext {
                 name: name.to_string(),
                 line,
+                pos,
             },
             msg.to_string(),
         )
     }
+
+    /// Returns the location of the error in the template text, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            ParseError::WithContext(ref ctx, _)
+            | ParseError::UnexpectedToken { ref ctx, .. }
+            | ParseError::UnexpectedEof { ref ctx, .. }
+            | ParseError::UndefinedFunction { ref ctx, .. }
+            | ParseError::UndefinedVariable { ref ctx, .. }
+            | ParseError::MultipleDefinitions { ref ctx, .. }
+            | ParseError::TooManyDeclarations { ref ctx, .. }
+            | ParseError::MissingValue { ref ctx, .. }
+            | ParseError::NonExecutableCommand { ref ctx, .. }
+            | ParseError::EmptyCommand { ref ctx }
+            | ParseError::UnclosedParen { ref ctx, .. }
+            | ParseError::InvalidNumber { ref ctx, .. }
+            | ParseError::InvalidString { ref ctx, .. }
+            | ParseError::MaxDepthExceeded { ref ctx }
+            | ParseError::MaxExprDepthExceeded { ref ctx }
+            | ParseError::TooManyTrees { ref ctx, .. }
+            | ParseError::TooManyNodes { ref ctx, .. } => Some(ctx),
+            _ => None,
+        }
+    }
+
+    /// Returns a stable code identifying the kind of error.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// let err = gtmpl::Template::default().parse("{{ foo }}").unwrap_err();
+    /// assert_eq!(err.code(), "gtmpl::parse::undefined_function");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseError::UnexpectedInDefineClause(_) => "gtmpl::parse::define",
+            ParseError::UnexpectedEnd => "gtmpl::parse::unexpected_end",
+            ParseError::WithContext(..) => "gtmpl::parse::syntax",
+            ParseError::UnexpectedToken { .. } => "gtmpl::parse::unexpected_token",
+            ParseError::UnexpectedEof { .. } => "gtmpl::parse::unexpected_eof",
+            ParseError::UndefinedFunction { .. } => "gtmpl::parse::undefined_function",
+            ParseError::UndefinedVariable { .. } => "gtmpl::parse::undefined_variable",
+            ParseError::MultipleDefinitions { .. } => "gtmpl::parse::multiple_definitions",
+            ParseError::TooManyDeclarations { .. } => "gtmpl::parse::too_many_declarations",
+            ParseError::MissingValue { .. } => "gtmpl::parse::missing_value",
+            ParseError::NonExecutableCommand { .. } => "gtmpl::parse::non_executable_command",
+            ParseError::EmptyCommand { .. } => "gtmpl::parse::empty_command",
+            ParseError::UnclosedParen { .. } => "gtmpl::parse::unclosed_paren",
+            ParseError::InvalidNumber { .. } => "gtmpl::parse::invalid_number",
+            ParseError::InvalidString { .. } => "gtmpl::parse::invalid_string",
+            ParseError::MaxDepthExceeded { .. } => "gtmpl::parse::max_depth",
+            ParseError::MaxExprDepthExceeded { .. } => "gtmpl::parse::max_expr_depth",
+            ParseError::TooManyTrees { .. } => "gtmpl::parse::max_trees",
+            ParseError::TooManyNodes { .. } => "gtmpl::parse::max_nodes",
+            ParseError::SourceTooLarge { .. } => "gtmpl::parse::max_source_size",
+            ParseError::NoTree => "gtmpl::parse::no_tree",
+            ParseError::NodeError(_) => "gtmpl::parse::node",
+            ParseError::NoDynamicTemplate => "gtmpl::parse::dynamic_template",
+            ParseError::UnableToParseString(_) => "gtmpl::parse::string",
+        }
+    }
+}
+
+/// A problem with a parsed template which does not prevent executing it, see
+/// [`Template::warnings`](crate::Template::warnings).
+#[derive(Error, Debug)]
+pub enum ParseWarning {
+    #[error("template: {ctx}:function {name} is deprecated: {note}")]
+    DeprecatedFunction {
+        ctx: ErrorContext,
+        name: String,
+        note: String,
+    },
+}
+
+impl ParseWarning {
+    /// Returns the location of the warning in the template text.
+    pub fn context(&self) -> &ErrorContext {
+        match *self {
+            ParseWarning::DeprecatedFunction { ref ctx, .. } => ctx,
+        }
+    }
+
+    /// Returns a stable code identifying the kind of warning.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseWarning::DeprecatedFunction { .. } => "gtmpl::parse::deprecated_function",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -55,28 +210,6 @@ pub enum NodeError {
     NaTN,
 }
 
-#[derive(Error, Debug)]
-pub enum PrintError {
-    #[error("unable to process verb: {0}")]
-    UnableToProcessVerb(String),
-    #[error("{0:X} is not a valid char")]
-    NotAValidChar(i128),
-    #[error("unable to format {0} as {1}")]
-    UnableToFormat(Value, char),
-    #[error("unable to terminate format arg: {0}")]
-    UnableToTerminateFormatArg(String),
-    #[error("missing ] in {0}")]
-    MissingClosingBracket(String),
-    #[error("unable to parse index: {0}")]
-    UnableToParseIndex(ParseIntError),
-    #[error("unable to parse width: {0}")]
-    UnableToParseWidth(ParseIntError),
-    #[error("width after index (e.g. %[3]2d)")]
-    WithAfterIndex,
-    #[error("precision after index (e.g. %[3].2d)")]
-    PrecisionAfterIndex,
-}
-
 #[derive(Error, Debug)]
 pub enum ExecError {
     #[error("{0} is an incomplete or empty template")]
@@ -84,9 +217,9 @@ pub enum ExecError {
     #[error("{0}")]
     IOError(#[from] std::io::Error),
     #[error("unknown node: {0}")]
-    UnknownNode(Nodes),
+    UnknownNode(Box<Nodes>),
     #[error("expected if or with node, got {0}")]
-    ExpectedIfOrWith(Nodes),
+    ExpectedIfOrWith(Box<Nodes>),
     #[error("unable to convert output to uft-8: {0}")]
     Utf8ConversionFailed(FromUtf8Error),
     #[error("empty var stack")]
@@ -99,14 +232,14 @@ pub enum ExecError {
     PipelineMustYieldString,
     #[error("template {0} not defined")]
     TemplateNotDefined(String),
-    #[error("exceeded max template depth")]
-    MaxTemplateDepth,
+    #[error("exceeded maximum template depth ({0})")]
+    MaxDepthExceeded(usize),
     #[error("error evaluating pipe: {0}")]
     ErrorEvaluatingPipe(PipeNode),
     #[error("no arguments for command node: {0}")]
     NoArgsForCommandNode(CommandNode),
     #[error("cannot evaluate command: {0}")]
-    CannotEvaluateCommand(Nodes),
+    CannotEvaluateCommand(Box<Nodes>),
     #[error("field chain without fields :/")]
     FieldChainWithoutFields,
     #[error("{0} has arguments but cannot be invoked as function")]
@@ -116,19 +249,94 @@ pub enum ExecError {
     #[error("indirection through explicit nul in {0}")]
     NullInChain(ChainNode),
     #[error("cannot handle {0} as argument")]
-    InvalidArgument(Nodes),
+    InvalidArgument(Box<Nodes>),
     #[error("{0} is not a defined function")]
     UndefinedFunction(String),
     #[error(transparent)]
     FuncError(#[from] FuncError),
+    #[error("template: {ctx}: executing {:?} at <{func}>: error calling {func}: {source}", .ctx.name)]
+    FuncCall {
+        ctx: ErrorContext,
+        func: String,
+        source: FuncError,
+    },
     #[error("can't give argument to non-function {0}")]
-    ArgumentForNonFunction(Nodes),
+    ArgumentForNonFunction(Box<Nodes>),
     #[error("only maps and objects have fields")]
     OnlyMapsAndObjectsHaveFields,
     #[error("no field {0} for {1}")]
     NoFiledFor(String, Value),
+    #[error("map has no entry for key {0:?}")]
+    MissingKey(String),
+    /// A field of a missing value was evaluated with `MissingKey::Error`.
+    #[error("nil data; no entry for key {0:?}")]
+    NilData(String),
+    #[error("nil pointer evaluating interface {{}}.{0}")]
+    NilPointerEvaluating(String),
     #[error("variable {0} not found")]
     VariableNotFound(String),
+    #[error("execution timed out or was cancelled")]
+    Cancelled,
+    #[error("output exceeds maximum size of {0} bytes")]
+    OutputTooLarge(usize),
+    #[error("exceeded maximum of {0} range iterations")]
+    TooManyIterations(usize),
+    /// An error inside a template invoked by `{{template}}`. `calls` starts with the innermost
+    /// invocation.
+    #[error("{source}{}", fmt_calls(.calls))]
+    InTemplate {
+        source: Box<ExecError>,
+        calls: Vec<TemplateCall>,
+    },
+}
+
+/// A mismatch between a template and a sample context found by
+/// [`Template::validate`](crate::Template::validate).
+#[derive(Error, Debug)]
+pub enum ValidationError {
+    #[error("template: {ctx}: can't evaluate field {field} in type {typ}")]
+    NoField {
+        ctx: ErrorContext,
+        field: String,
+        typ: String,
+    },
+    #[error("template: {ctx}: map has no entry for key {key:?}")]
+    MissingKey { ctx: ErrorContext, key: String },
+    #[error("template: {ctx}: wrong number of args for {func}: want {want} got {got}")]
+    WrongArgCount {
+        ctx: ErrorContext,
+        func: String,
+        want: String,
+        got: usize,
+    },
+    #[error("template: {ctx}: range can't iterate over {value}")]
+    InvalidRange { ctx: ErrorContext, value: Value },
+    #[error("template: {ctx}: no such template {name:?}")]
+    TemplateNotDefined { ctx: ErrorContext, name: String },
+}
+
+impl ValidationError {
+    /// Returns the location of the mismatch in the template text.
+    pub fn context(&self) -> &ErrorContext {
+        match *self {
+            ValidationError::NoField { ref ctx, .. }
+            | ValidationError::MissingKey { ref ctx, .. }
+            | ValidationError::WrongArgCount { ref ctx, .. }
+            | ValidationError::InvalidRange { ref ctx, .. }
+            | ValidationError::TemplateNotDefined { ref ctx, .. } => ctx,
+        }
+    }
+
+    /// Returns a stable code identifying the kind of mismatch.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ValidationError::NoField { .. } => "gtmpl::validate::no_field",
+            ValidationError::MissingKey { .. } => "gtmpl::validate::missing_key",
+            ValidationError::WrongArgCount { .. } => "gtmpl::validate::arg_count",
+            ValidationError::InvalidRange { .. } => "gtmpl::validate::invalid_range",
+            ValidationError::TemplateNotDefined { .. } => "gtmpl::validate::template_not_defined",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -1,36 +1,323 @@
-use std::collections::VecDeque;
-use std::io::Write;
-
-use crate::error::ExecError;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+#[cfg(feature = "files")]
+use std::path::PathBuf;
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::audit::{self, AuditRecord};
+use crate::clock::{self, Deterministic, Frozen};
+use crate::error::{ErrorContext, ExecError, TemplateCall};
+use crate::funcs;
+#[cfg(feature = "i18n")]
+use crate::funcs::Catalog;
+#[cfg(feature = "net")]
+use crate::funcs::NetOptions;
+use crate::metrics::Metrics;
 use crate::node::*;
+use crate::ordered;
+use crate::parse::{parse, Tree};
+use crate::print_verb::type_name;
+use crate::printf::format_value;
+use crate::profile::Profile;
+use crate::source_map::SourceMap;
 use crate::template::Template;
 use crate::utils::is_true;
 
-use gtmpl_value::{Func, Value};
+use gtmpl_value::{Func, FuncError, Value};
+
+#[cfg(feature = "tokio")]
+mod async_exec;
+mod chunks;
+
+#[cfg(feature = "tokio")]
+pub use self::async_exec::{AsyncFunc, AsyncFuncResult};
+pub use self::chunks::Chunks;
+
+const MAX_DEPTH: usize = 100_000;
+// Templates are executed with an explicit stack of steps, but nested pipelines recurse. When
+// less than `RED_ZONE` bytes of stack are left, they continue on a new stack segment of
+// `STACK_SIZE` bytes.
+const RED_ZONE: usize = 128 * 1024;
+const STACK_SIZE: usize = 1024 * 1024;
+// The name of the template `tpl` parses its text as.
+const TPL: &str = "tpl";
+
+/// What a field evaluation yields when a map has no entry for the key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingKey {
+    /// Yield `Value::NoValue`, which prints as `<no value>`.
+    #[default]
+    Default,
+    /// Yield `Value::Nil`, the zero value of a dynamically typed value.
+    Zero,
+    /// Stop execution with `ExecError::MissingKey`.
+    Error,
+}
+
+/// What `{{template}}` does when the invoked template is not defined.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, MissingTemplate, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.exec_options.missing_template = MissingTemplate::Skip;
+/// tmpl.parse(r#"<body>{{ template "analytics" . }}</body>"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "<body></body>");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MissingTemplate {
+    /// Stop execution with `ExecError::TemplateNotDefined`, like Go.
+    #[default]
+    Error,
+    /// Write nothing, e.g. for optional partials.
+    Skip,
+    /// Write the given text instead.
+    Fallback(String),
+}
+
+/// Options controlling how templates are executed.
+#[derive(Clone, Debug)]
+pub struct ExecOptions {
+    /// Behavior for missing map keys, like Go's `missingkey` option.
+    pub missing_key: MissingKey,
+    /// Behavior for invoking templates which are not defined.
+    pub missing_template: MissingTemplate,
+    /// Maximum nesting depth of template invocations and parenthesized pipelines.
+    pub max_depth: usize,
+    /// Time after which an execution fails with `ExecError::Cancelled`.
+    pub timeout: Option<Duration>,
+    /// Flag to cancel executions, e.g. from another thread. While it is set, executions fail
+    /// with `ExecError::Cancelled`.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Maximum size of the output in bytes. Output exceeding it is not written and execution
+    /// fails with `ExecError::OutputTooLarge`.
+    pub max_output_size: usize,
+    /// Maximum number of `range` iterations in a single execution, counting those of nested
+    /// ranges as well.
+    pub max_range_iterations: usize,
+    /// Number of nodes after which [`ExecHook::on_yield`](crate::ExecHook::on_yield) is called,
+    /// e.g. for cooperative scheduling of large executions.
+    pub yield_interval: usize,
+    /// Whether `range` iterates over maps in sorted key order, like Go. Disabling it saves
+    /// sorting, but the order of the entries is then unspecified.
+    pub sort_map_keys: bool,
+    /// Fixes the time and seeds the random numbers functions get from the [`clock`](crate::clock)
+    /// module, so that the output is reproducible, e.g. in snapshot tests.
+    pub deterministic: Option<Deterministic>,
+    /// Whether the style functions like `color` and `bold` emit ANSI escape codes. Disable it
+    /// when the output is not a terminal, e.g. with `std::io::IsTerminal`, to get plain text.
+    pub colors: bool,
+    /// The directory the file functions added by
+    /// [`Template::add_file_funcs`](crate::Template::add_file_funcs) are confined to.
+    #[cfg(feature = "files")]
+    pub file_root: Option<PathBuf>,
+    /// The limits of the network functions added by
+    /// [`Template::add_net_funcs`](crate::Template::add_net_funcs).
+    #[cfg(feature = "net")]
+    pub net: Option<NetOptions>,
+    /// The messages the function `t` added by
+    /// [`Template::add_i18n_funcs`](crate::Template::add_i18n_funcs) translates.
+    #[cfg(feature = "i18n")]
+    pub catalog: Option<Arc<Catalog>>,
+}
+
+impl ExecOptions {
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+impl Default for ExecOptions {
+    fn default() -> ExecOptions {
+        ExecOptions {
+            missing_key: MissingKey::default(),
+            missing_template: MissingTemplate::default(),
+            max_depth: MAX_DEPTH,
+            timeout: None,
+            cancel: None,
+            max_output_size: usize::MAX,
+            max_range_iterations: usize::MAX,
+            yield_interval: usize::MAX,
+            sort_map_keys: true,
+            deterministic: None,
+            colors: true,
+            #[cfg(feature = "files")]
+            file_root: None,
+            #[cfg(feature = "net")]
+            net: None,
+            #[cfg(feature = "i18n")]
+            catalog: None,
+        }
+    }
+}
+
+// Adapts a `fmt::Write` to the `io::Write` execution writes to. Execution only writes whole
+// strings, so every buffer is valid UTF-8.
+struct FmtWriter<'a, W: fmt::Write>(&'a mut W);
 
-const MAX_TEMPLATE_DEPTH: usize = 100_000;
+impl<W: fmt::Write> Write for FmtWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let s = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.0.write_str(s).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
 struct Variable {
     name: String,
     value: Value,
 }
 
+// Collects the errors of an execution which continues after them.
+struct Recovery<'b> {
+    placeholder: &'b str,
+    errors: &'b mut Vec<ExecError>,
+}
+
+// What an execution records besides its output.
+#[derive(Default)]
+struct Recorders<'b> {
+    source_map: Option<&'b mut SourceMap>,
+    profile: Option<&'b mut Profile>,
+    recovery: Option<Recovery<'b>>,
+    metrics: Option<&'b mut Metrics>,
+}
+
+// A pending step of a tree walk.
+enum Step<'a> {
+    // Executes a node. `None` as dot stands for the context the execution started with.
+    Walk(&'a Nodes, Option<Arc<Context>>),
+    // Finishes a node started at the given time.
+    Exit(&'a Nodes, Option<Instant>),
+    Range(RangeState<'a>),
+    PopScope,
+    Return(Return<'a>),
+}
+
+// A `range` in progress.
+struct RangeState<'a> {
+    range: &'a RangeNode,
+    items: RangeItems,
+    index: usize,
+    dot: Option<Arc<Context>>,
+}
+
+enum RangeItems {
+    Entries(std::vec::IntoIter<(Value, Value)>),
+    Function(Func),
+    Stream(Stream),
+}
+
+// What is needed to return from the template `name` invoked at `pos` of `caller`.
+struct Return<'a> {
+    name: &'a str,
+    pos: Pos,
+    vars: VecDeque<VecDeque<Variable>>,
+    caller: &'a str,
+    // Entered until the template returns, unless a `Chunks` iterator is suspended.
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+// Pushes the steps executing the nodes of `list` in order.
+fn push_list<'a>(steps: &mut Vec<Step<'a>>, list: &'a ListNode, dot: &Option<Arc<Context>>) {
+    steps.extend(
+        list.nodes
+            .iter()
+            .rev()
+            .map(|node| Step::Walk(node, dot.clone())),
+    );
+}
+
 struct State<'a, 'b, T: Write> {
     template: &'a Template,
-    writer: &'b mut T,
+    writer: T,
     node: Option<&'a Nodes>,
     vars: VecDeque<VecDeque<Variable>>,
     depth: usize,
+    tree_name: &'a str,
+    source_map: Option<&'b mut SourceMap>,
+    profile: Option<&'b mut Profile>,
+    recovery: Option<Recovery<'b>>,
+    deadline: Option<Instant>,
+    written: usize,
+    iterations: usize,
+    // The time and random numbers of a deterministic execution.
+    clock: Cell<Option<Frozen>>,
+    memo: RefCell<Memo>,
+    // Counts everything but the calls, bytes and iterations, which are counted below.
+    metrics: Metrics,
+    calls: Cell<usize>,
+    snippets: Option<&'a Snippets<'a>>,
+}
+
+// Templates parsed by `tpl`. They are added on top of the template set and the snippets of
+// enclosing `tpl` calls, so the set does not need to be copied.
+struct Snippets<'a> {
+    tree_set: HashMap<String, Tree>,
+    outer: Option<&'a Snippets<'a>>,
+}
+
+// Results of calls of pure functions by a hash of the function name and the arguments.
+type Memo = HashMap<u64, Vec<(String, Vec<Value>, Value)>>;
+
+// An iterator added by `Context::with_iter`.
+#[derive(Clone)]
+struct Stream(Arc<Mutex<Box<dyn Iterator<Item = Value> + Send>>>);
+
+impl Stream {
+    fn next(&self) -> Option<Value> {
+        let mut iter = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        iter.next()
+    }
+}
+
+// The iterators added by `Context::with_iter` by the names of their fields. They are kept
+// apart from dot, so only `range` sees them.
+#[derive(Clone, Default)]
+struct Streams(HashMap<String, Stream>);
+
+impl Streams {
+    // The iterator `pipe` ranges over if it is just a field of dot with an iterator. `range`
+    // looks for it before looking up the field.
+    fn get(&self, pipe: &PipeNode) -> Option<Stream> {
+        match (&pipe.cmds[..], self.0.is_empty()) {
+            ([cmd], false) => match cmd.args[..] {
+                [Nodes::Field(ref field)] => match field.ident[..] {
+                    [ref name] => self.0.get(name).cloned(),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 /// A Context for the template. Passed to the template exectution.
 pub struct Context {
     dot: Value,
+    streams: Streams,
 }
 
 impl Context {
     pub fn empty() -> Context {
-        Context { dot: Value::Nil }
+        Context::from(Value::NoValue)
     }
 
     pub fn from<T>(value: T) -> Context
@@ -38,46 +325,362 @@ impl Context {
         T: Into<Value>,
     {
         let serialized: Value = value.into();
-        Context { dot: serialized }
+        Context {
+            dot: serialized,
+            streams: Streams::default(),
+        }
+    }
+
+    /// Adds `iter` as the field `name` of dot for `range`, which pulls items from it one at a
+    /// time, e.g. to stream database rows without collecting them first. The iterator can
+    /// capture state like a connection. It is consumed by the first `range` over it.
+    ///
+    /// Only `{{ range .name }}` sees the iterator, where dot is this context, i.e. outside of
+    /// `with`, `range` and invoked templates. Elsewhere `.name` is the field of dot, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if dot is not a map, an object or empty, since such a dot has no fields.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template, Value};
+    ///
+    /// let names = vec!["Ann", "Bob"];
+    /// let rows = names.into_iter().map(|name| Value::from(name.to_uppercase()));
+    /// let ctx = Context::empty().with_iter("rows", rows);
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ range .rows }}{{ . }} {{ else }}none{{ end }}").unwrap();
+    /// assert_eq!(tmpl.render(&ctx).unwrap(), "ANN BOB ");
+    /// ```
+    pub fn with_iter<I>(mut self, name: &str, iter: I) -> Context
+    where
+        I: IntoIterator<Item = Value>,
+        I::IntoIter: Send + 'static,
+    {
+        match self.dot {
+            Value::NoValue | Value::Nil => {}
+            ref dot => assert!(
+                ordered::as_map(dot).is_some(),
+                "with_iter: dot of type {} has no fields",
+                type_name(dot)
+            ),
+        }
+        let iter: Box<dyn Iterator<Item = Value> + Send> = Box::new(iter.into_iter());
+        let stream = Stream(Arc::new(Mutex::new(iter)));
+        self.streams.0.insert(name.to_owned(), stream);
+        self
     }
 }
 
 impl<'b> Template {
+    /// Executes the template with `data` and writes the output to `writer`.
+    ///
+    /// Text and the values of actions are written as soon as they are produced, so the output
+    /// is never held in memory as a whole. Wrap unbuffered writers like files or sockets in a
+    /// `BufWriter`. If execution fails, the output produced up to the error has already been
+    /// written.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::io::{BufWriter, Write};
+    ///
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ range . }}INSERT INTO t VALUES ({{ . }});\n{{ end }}").unwrap();
+    /// let mut out = BufWriter::new(Vec::new());
+    /// tmpl.execute(&mut out, &Context::from(vec![1, 2])).unwrap();
+    /// out.flush().unwrap();
+    /// let out = String::from_utf8(out.into_inner().unwrap()).unwrap();
+    /// assert_eq!(out, "INSERT INTO t VALUES (1);\nINSERT INTO t VALUES (2);\n");
+    /// ```
     pub fn execute<T: Write>(&self, writer: &'b mut T, data: &Context) -> Result<(), ExecError> {
-        let mut vars: VecDeque<VecDeque<Variable>> = VecDeque::new();
-        let mut dot = VecDeque::new();
-        dot.push_back(Variable {
-            name: "$".to_owned(),
-            value: data.dot.clone(),
-        });
-        vars.push_back(dot);
+        self.execute_mapped(writer, &self.name, data, Recorders::default())
+    }
+
+    /// Executes the template `name` associated with this template, e.g. by `define`, and
+    /// writes the output to `writer`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"{{ define "subject" }}Hi {{ . }}{{ end }}{{ define "body" }}Hello {{ . }}!{{ end }}"#)
+    ///     .unwrap();
+    /// let mut out = vec![];
+    /// tmpl.execute_template(&mut out, "body", &Context::from("Ann")).unwrap();
+    /// assert_eq!(out, b"Hello Ann!");
+    /// ```
+    pub fn execute_template<T: Write>(
+        &self,
+        writer: &'b mut T,
+        name: &str,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        if !self.tree_set.contains_key(name) {
+            return Err(ExecError::TemplateNotDefined(name.to_owned()));
+        }
+        self.execute_mapped(writer, name, data, Recorders::default())
+    }
 
+    /// Executes the template `name` associated with this template and returns the output as
+    /// `String`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse(r#"{{ define "subject" }}Hi {{ . }}{{ end }}{{ define "body" }}Hello {{ . }}!{{ end }}"#)
+    ///     .unwrap();
+    /// let subject = tmpl.render_template("subject", &Context::from("Ann"));
+    /// assert_eq!(subject.unwrap(), "Hi Ann");
+    /// ```
+    pub fn render_template(&self, name: &str, data: &Context) -> Result<String, ExecError> {
+        let mut w: Vec<u8> = vec![];
+        self.execute_template(&mut w, name, data)?;
+        String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)
+    }
+
+    fn execute_mapped<T: Write>(
+        &self,
+        writer: &'b mut T,
+        name: &str,
+        data: &Context,
+        recorders: Recorders<'b>,
+    ) -> Result<(), ExecError> {
+        let (tree_name, root) = self.root(name)?;
         let mut state = State {
-            template: self,
-            writer,
-            node: None,
-            vars,
-            depth: 0,
+            source_map: recorders.source_map,
+            profile: recorders.profile,
+            recovery: recorders.recovery,
+            ..State::new(self, writer, tree_name, data)
         };
 
-        let root = self
-            .tree_set
-            .get(&self.name)
-            .and_then(|tree| tree.root.as_ref())
-            .ok_or_else(|| ExecError::IncompleteTemplate(self.name.clone()))?;
-        state.walk(data, root)?;
-
-        Ok(())
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("execute", template = %tree_name).entered();
+        let result = state.walk(data, root);
+        if let Some(metrics) = recorders.metrics {
+            *metrics = state.metrics();
+        }
+        result
     }
 
+    /// Executes the template with `data` and returns the output as `String`.
     pub fn render(&self, data: &Context) -> Result<String, ExecError> {
         let mut w: Vec<u8> = vec![];
         self.execute(&mut w, data)?;
         String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)
     }
+
+    /// Renders the template against many contexts in parallel. The results are in the order
+    /// of `contexts`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("Hello {{ . }}!").unwrap();
+    /// let contexts: Vec<Context> = (0..100).map(Context::from).collect();
+    /// let outputs = tmpl.render_batch(&contexts);
+    /// assert_eq!(outputs.len(), 100);
+    /// assert_eq!(outputs[42].as_ref().unwrap(), "Hello 42!");
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn render_batch<I>(&self, contexts: I) -> Vec<Result<String, ExecError>>
+    where
+        I: rayon::iter::IntoParallelIterator,
+        I::Item: std::borrow::Borrow<Context>,
+    {
+        use rayon::iter::ParallelIterator;
+        use std::borrow::Borrow;
+
+        contexts
+            .into_par_iter()
+            .map(|data| self.render(data.borrow()))
+            .collect()
+    }
+
+    /// Executes the template with `data` and appends the output to `out`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ . }}!").unwrap();
+    /// let mut out = String::from("Hello ");
+    /// tmpl.render_to(&mut out, &Context::from("World")).unwrap();
+    /// assert_eq!(out, "Hello World!");
+    /// ```
+    pub fn render_to<W: fmt::Write>(&self, out: &mut W, data: &Context) -> Result<(), ExecError> {
+        self.execute(&mut FmtWriter(out), data)
+    }
+
+    /// Renders the template like `render` and records which node produced each part of the
+    /// output.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("a: {{ . }}\nb: {{ len . }}").unwrap();
+    /// let (output, map) = tmpl.render_with_source_map(&Context::from("xyz")).unwrap();
+    /// assert_eq!(output, "a: xyz\nb: 3");
+    /// let spans: Vec<_> = map.mappings().iter().map(|m| m.span.clone()).collect();
+    /// assert_eq!(spans, vec![0..3, 3..10, 10..14, 14..25]);
+    /// ```
+    pub fn render_with_source_map(&self, data: &Context) -> Result<(String, SourceMap), ExecError> {
+        let mut w: Vec<u8> = vec![];
+        let mut source_map = SourceMap::default();
+        let recorders = Recorders {
+            source_map: Some(&mut source_map),
+            ..Default::default()
+        };
+        self.execute_mapped(&mut w, &self.name, data, recorders)?;
+        let output = String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)?;
+        Ok((output, source_map))
+    }
+
+    /// Renders the template like `render` and measures how often each node was executed and
+    /// how long it took. See [`Profile`] for an example.
+    pub fn render_profiled(&self, data: &Context) -> Result<(String, Profile), ExecError> {
+        let mut w: Vec<u8> = vec![];
+        let mut profile = Profile::default();
+        let recorders = Recorders {
+            profile: Some(&mut profile),
+            ..Default::default()
+        };
+        self.execute_mapped(&mut w, &self.name, data, recorders)?;
+        let output = String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed)?;
+        profile.finish(self);
+        Ok((output, profile))
+    }
+
+    /// Renders the template like `render` and counts the work done, even if it fails. See
+    /// [`Metrics`] for an example.
+    pub fn render_with_metrics(&self, data: &Context) -> (Result<String, ExecError>, Metrics) {
+        let mut w: Vec<u8> = vec![];
+        let mut metrics = Metrics::default();
+        let recorders = Recorders {
+            metrics: Some(&mut metrics),
+            ..Default::default()
+        };
+        let output = self
+            .execute_mapped(&mut w, &self.name, data, recorders)
+            .and_then(|_| String::from_utf8(w).map_err(ExecError::Utf8ConversionFailed));
+        (output, metrics)
+    }
+
+    /// Renders the template like `render`, but continues after errors, e.g. for previews.
+    /// Actions, control structures and template invocations which fail are replaced by
+    /// `placeholder`. Returns the output and all errors that occurred.
+    ///
+    /// Errors concerning the execution as a whole, like exceeding `ExecOptions::timeout` or
+    /// `ExecOptions::max_output_size`, still stop it.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ .a }} {{ len 1 }} {{ .c }}").unwrap();
+    /// let (output, errors) = tmpl.render_lenient(&Context::from(1), "{{!error}}");
+    /// assert_eq!(output, "{{!error}} {{!error}} {{!error}}");
+    /// assert_eq!(errors.len(), 3);
+    /// ```
+    pub fn render_lenient(&self, data: &Context, placeholder: &str) -> (String, Vec<ExecError>) {
+        let mut w: Vec<u8> = vec![];
+        let mut errors = vec![];
+        let recorders = Recorders {
+            recovery: Some(Recovery {
+                placeholder,
+                errors: &mut errors,
+            }),
+            ..Default::default()
+        };
+        let result = self.execute_mapped(&mut w, &self.name, data, recorders);
+        if let Err(err) = result {
+            errors.push(err);
+        }
+        (String::from_utf8_lossy(&w).into_owned(), errors)
+    }
+
+    // Evaluates a pipeline outside of an execution, with nil as dot and no variables set.
+    pub(crate) fn eval_detached(&self, pipe: &PipeNode) -> Result<Value, ExecError> {
+        let mut state = State {
+            vars: VecDeque::new(),
+            deadline: None,
+            ..State::new(self, io::sink(), &self.name, &Context::empty())
+        };
+        state.eval_pipeline(&Context::empty(), pipe)
+    }
+
+    // The name and root of the tree `name`, which must have been parsed.
+    fn root(&self, name: &str) -> Result<(&String, &Nodes), ExecError> {
+        self.tree_set
+            .get_key_value(name)
+            .and_then(defined)
+            .ok_or_else(|| ExecError::IncompleteTemplate(name.to_owned()))
+    }
 }
 
 impl<'a, 'b, T: Write> State<'a, 'b, T> {
+    fn new(template: &'a Template, writer: T, tree_name: &'a str, data: &Context) -> Self {
+        let options = &template.exec_options;
+        State {
+            template,
+            writer,
+            node: None,
+            vars: top_level_vars(data.dot.clone()),
+            depth: 0,
+            tree_name,
+            source_map: None,
+            profile: None,
+            recovery: None,
+            deadline: options.deadline(),
+            written: 0,
+            iterations: 0,
+            clock: Cell::new(options.deterministic.map(Frozen::from)),
+            memo: RefCell::default(),
+            metrics: Metrics::default(),
+            calls: Cell::new(0),
+            snippets: None,
+        }
+    }
+
+    // The tree `name` in the template set with the snippets parsed by `tpl` added on top. Like
+    // `Template::add_trees`, later definitions replace earlier ones unless they are empty.
+    fn tree(&self, name: &str) -> Option<(&'a String, &'a Tree)> {
+        let mut layers = vec![];
+        let mut snippets = self.snippets;
+        while let Some(layer) = snippets {
+            layers.push(layer);
+            snippets = layer.outer;
+        }
+        let mut found = self.template.tree_set.get_key_value(name);
+        for layer in layers.into_iter().rev() {
+            match layer.tree_set.get_key_value(name) {
+                Some((_, tree)) if tree.is_empty() && found.is_some() => {}
+                Some(tree) => found = Some(tree),
+                None => {}
+            }
+        }
+        found
+    }
+
     fn set_kth_last_var_value(&mut self, k: usize, value: Value) -> Result<(), ExecError> {
         if let Some(last_vars) = self.vars.back_mut() {
             let i = last_vars.len() - k;
@@ -90,6 +693,27 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Err(ExecError::EmptyStack)
     }
 
+    // Assigns to the innermost variable called `key`.
+    fn set_var_value(&mut self, key: &str, value: Value) -> Result<(), ExecError> {
+        for context in self.vars.iter_mut().rev() {
+            for var in context.iter_mut().rev() {
+                if var.name == key {
+                    var.value = value;
+                    return Ok(());
+                }
+            }
+        }
+        Err(ExecError::VariableNotFound(key.to_string()))
+    }
+
+    fn push_scope(&mut self) {
+        self.vars.push_back(VecDeque::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.vars.pop_back();
+    }
+
     fn var_value(&self, key: &str) -> Result<Value, ExecError> {
         for context in self.vars.iter().rev() {
             for var in context.iter().rev() {
@@ -101,74 +725,335 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         Err(ExecError::VariableNotFound(key.to_string()))
     }
 
-    fn walk_list(&mut self, ctx: &Context, node: &'a ListNode) -> Result<(), ExecError> {
-        for n in &node.nodes {
-            self.walk(ctx, n)?;
+    // Executes `root` with `ctx` as dot. Instead of recursing into the children of a node, the
+    // steps executing and finishing them are pushed onto an explicit stack. When a step fails,
+    // the remaining steps only clean up until a node recovers from the error or the stack is
+    // empty.
+    fn walk(&mut self, ctx: &Context, root: &'a Nodes) -> Result<(), ExecError> {
+        let mut steps = vec![Step::Walk(root, None)];
+        let mut result = Ok(());
+        while let Some(step) = steps.pop() {
+            result = self.step(ctx, step, result, &mut steps);
         }
-        Ok(())
+        result
     }
 
-    // Top level walk function. Steps through the major parts for the template strcuture and
-    // writes to the output.
-    fn walk(&mut self, ctx: &Context, node: &'a Nodes) -> Result<(), ExecError> {
-        self.node = Some(node);
+    // Takes a single step. `result` is the outcome of the previous steps: after an error, only
+    // the steps cleaning up are taken.
+    fn step(
+        &mut self,
+        ctx: &Context,
+        step: Step<'a>,
+        result: Result<(), ExecError>,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        match (step, result) {
+            (Step::Walk(node, dot), Ok(())) => {
+                let node_ctx = dot.as_deref().unwrap_or(ctx);
+                self.enter(node_ctx, &dot, node, steps)
+            }
+            (Step::Range(range), Ok(())) => self.next_iteration(range, steps),
+            (Step::Walk(..), result) | (Step::Range(_), result) => result,
+            (Step::Exit(node, start), result) => self.exit(node, start, result),
+            (Step::PopScope, result) => {
+                self.pop_scope();
+                result
+            }
+            (Step::Return(ret), result) => self.return_from(ret, result),
+        }
+    }
+
+    // Starts executing `node`. Writes text and actions right away and pushes the steps for
+    // the children of other nodes.
+    fn enter(
+        &mut self,
+        ctx: &Context,
+        dot: &Option<Arc<Context>>,
+        node: &'a Nodes,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        self.begin(node, steps)?;
         match *node {
             Nodes::Action(ref n) => {
                 let val = self.eval_pipeline(ctx, &n.pipe)?;
-                if n.pipe.decl.is_empty() {
-                    self.print_value(&val)?;
-                }
+                self.print_action(node, n, val)
+            }
+            Nodes::If(ref n) | Nodes::With(ref n) => {
+                self.enter_scope(steps);
+                let val = self.eval_pipeline(ctx, &n.pipe)?;
+                self.branch(node, n, val, dot, steps)
+            }
+            Nodes::Range(ref n) => {
+                self.enter_scope(steps);
+                let stream = ctx.streams.get(&n.pipe);
+                let val = match stream {
+                    Some(_) => Value::NoValue,
+                    None => self.eval_pipeline(ctx, &n.pipe)?,
+                };
+                self.start_range(n, stream, val, dot, steps)
+            }
+            Nodes::Template(ref n) => {
+                let name = match n.name {
+                    PipeOrString::String(ref name) => name.to_owned(),
+                    PipeOrString::Pipe(ref pipe) => template_name(self.eval_pipeline(ctx, pipe)?)?,
+                };
+                let (tree_name, root) = match self.lookup_template(n, name)? {
+                    Some(found) => found,
+                    None => return Ok(()),
+                };
+                let value = match n.pipe {
+                    Some(ref pipe) => self.eval_pipeline(ctx, pipe)?,
+                    None => Value::NoValue,
+                };
+                self.enter_template(n, tree_name, root, value, steps);
+                Ok(())
+            }
+            _ => self.enter_list(node, dot, steps),
+        }
+    }
+
+    // Counts `node` and pushes the step finishing it.
+    fn begin(&mut self, node: &'a Nodes, steps: &mut Vec<Step<'a>>) -> Result<(), ExecError> {
+        self.check_cancelled()?;
+        self.node = Some(node);
+        self.count_node()?;
+        for hook in &self.template.hooks {
+            hook.before_node(self.tree_name, node);
+        }
+        let start = self.profile.as_mut().map(|profile| profile.enter());
+        steps.push(Step::Exit(node, start));
+        Ok(())
+    }
+
+    // Starts a scope for the variables declared by a node, which are not visible after it.
+    fn enter_scope(&mut self, steps: &mut Vec<Step<'a>>) {
+        self.push_scope();
+        steps.push(Step::PopScope);
+    }
+
+    // Prints the value of an action unless it declares variables.
+    fn print_action(
+        &mut self,
+        node: &Nodes,
+        action: &ActionNode,
+        mut val: Value,
+    ) -> Result<(), ExecError> {
+        if action.pipe.decl.is_empty() {
+            self.before_print(node, &mut val);
+            self.print_value(&val, action.span.clone())?;
+        }
+        Ok(())
+    }
+
+    // Pushes the steps for the list of an `if` or `with` chosen by the value `val` of its
+    // pipeline. `if` and `with` behave the same, except that `with` sets dot.
+    fn branch(
+        &mut self,
+        node: &Nodes,
+        branch: &'a BranchNode,
+        val: Value,
+        dot: &Option<Arc<Context>>,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        if is_true(&val) {
+            let dot = match *node {
+                Nodes::With(_) => Some(Arc::new(Context::from(val))),
+                _ => dot.clone(),
+            };
+            push_list(steps, &branch.list, &dot);
+        } else if let Some(ref otherwise) = branch.else_list {
+            push_list(steps, otherwise, dot);
+        }
+        Ok(())
+    }
+
+    // Pushes the step iterating over the items of a `range`, which are those of `stream` if
+    // it ranges over an iterator of `Context::with_iter`, or else those of the value `val` of
+    // its pipeline.
+    fn start_range(
+        &mut self,
+        range: &'a RangeNode,
+        stream: Option<Stream>,
+        val: Value,
+        dot: &Option<Arc<Context>>,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        if stream.is_some() {
+            self.assign_decls(&range.pipe, &Value::NoValue)?;
+        }
+        let items = match (stream, val) {
+            (Some(stream), _) => RangeItems::Stream(stream),
+            (None, map @ (Value::Object(_) | Value::Map(_) | Value::OrderedMap(_))) => {
+                RangeItems::Entries(self.map_entries(&map).into_iter())
+            }
+            (None, Value::Array(vec)) => {
+                let entries: Vec<(Value, Value)> = vec
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, v)| (Value::from(k), v))
+                    .collect();
+                RangeItems::Entries(entries.into_iter())
+            }
+            (None, Value::Bytes(bytes)) => {
+                let entries: Vec<(Value, Value)> = bytes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, b)| (Value::from(k), Value::from(b)))
+                    .collect();
+                RangeItems::Entries(entries.into_iter())
+            }
+            (None, Value::Function(f)) => RangeItems::Function(f.f),
+            // Missing and nil values have no items, like in Go.
+            (None, Value::NoValue | Value::Nil) => RangeItems::Entries(vec![].into_iter()),
+            (None, val) => return Err(ExecError::InvalidRange(val)),
+        };
+        steps.push(Step::Range(RangeState {
+            range,
+            items,
+            index: 0,
+            dot: dot.clone(),
+        }));
+        Ok(())
+    }
+
+    // Executes the nodes without pipelines: pushes the steps for the nodes of a list and
+    // writes text.
+    fn enter_list(
+        &mut self,
+        node: &'a Nodes,
+        dot: &Option<Arc<Context>>,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        match *node {
+            Nodes::List(ref n) => {
+                push_list(steps, n, dot);
                 Ok(())
             }
-            Nodes::If(_) | Nodes::With(_) => self.walk_if_or_with(node, ctx),
-            Nodes::Range(ref n) => self.walk_range(ctx, n),
-            Nodes::List(ref n) => self.walk_list(ctx, n),
-            Nodes::Text(ref n) => write!(self.writer, "{}", n).map_err(ExecError::IOError),
-            Nodes::Template(ref n) => self.walk_template(ctx, n),
-            _ => Err(ExecError::UnknownNode(node.clone())),
+            Nodes::Text(ref n) => self.write_str(&n.text, n.pos()..n.pos() + n.text.len()),
+            _ => Err(ExecError::UnknownNode(Box::new(node.clone()))),
         }
     }
 
-    fn walk_template(&mut self, ctx: &Context, template: &TemplateNode) -> Result<(), ExecError> {
-        let name = match template.name {
-            PipeOrString::String(ref name) => name.to_owned(),
-            PipeOrString::Pipe(ref pipe) => {
-                if let Value::String(s) = self.eval_pipeline(ctx, pipe)? {
-                    s
-                } else {
-                    return Err(ExecError::PipelineMustYieldString);
+    // Finishes `node` once its children are done.
+    fn exit(
+        &mut self,
+        node: &Nodes,
+        start: Option<Instant>,
+        result: Result<(), ExecError>,
+    ) -> Result<(), ExecError> {
+        if let (Some(profile), Some(start)) = (self.profile.as_mut(), start) {
+            profile.exit(self.tree_name, node, start);
+        }
+        for hook in &self.template.hooks {
+            hook.after_node(self.tree_name, node);
+        }
+        match result {
+            Err(err) if self.recovery.is_some() && !aborts(&err) => self.recover(node, err),
+            result => result,
+        }
+    }
+
+    // Records the error of a failed node and writes the placeholder instead of its output.
+    fn recover(&mut self, node: &Nodes, err: ExecError) -> Result<(), ExecError> {
+        let placeholder = match self.recovery {
+            Some(ref mut recovery) => {
+                recovery.errors.push(err);
+                recovery.placeholder
+            }
+            None => return Err(err),
+        };
+        self.write_str(placeholder, node.pos()..node.pos())
+    }
+
+    // Pushes the steps for the next iteration of a `range`, or for its `else` if there was
+    // nothing to iterate over.
+    fn next_iteration(
+        &mut self,
+        mut state: RangeState<'a>,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        let range = state.range;
+        let item = match state.items {
+            RangeItems::Entries(ref mut entries) => entries.next(),
+            RangeItems::Function(f) => self
+                .next_item(range, f, state.index)?
+                .map(|v| (Value::from(state.index), v)),
+            RangeItems::Stream(ref stream) => stream.next().map(|v| (Value::from(state.index), v)),
+        };
+        let (key, val) = match item {
+            Some(item) => item,
+            None => {
+                if let (0, Some(else_list)) = (state.index, range.else_list.as_ref()) {
+                    push_list(steps, else_list, &state.dot);
                 }
+                return Ok(());
             }
         };
-        if self.depth >= MAX_TEMPLATE_DEPTH {
-            return Err(ExecError::MaxTemplateDepth);
-        }
-        let tree = self.template.tree_set.get(&name);
-        if let Some(tree) = tree {
-            if let Some(ref root) = tree.root {
-                let mut vars = VecDeque::new();
-                let mut dot = VecDeque::new();
-                let value = if let Some(ref pipe) = template.pipe {
-                    self.eval_pipeline(ctx, pipe)?
-                } else {
-                    Value::NoValue
-                };
-                dot.push_back(Variable {
-                    name: "$".to_owned(),
-                    value: value.clone(),
-                });
-                vars.push_back(dot);
-                let mut new_state = State {
-                    template: self.template,
-                    writer: self.writer,
-                    node: None,
-                    vars,
-                    depth: self.depth + 1,
-                };
-                return new_state.walk(&Context::from(value), root);
+        self.check_cancelled()?;
+        self.count_iteration()?;
+        self.set_range_vars(key, &val, range)?;
+        state.index += 1;
+        steps.push(Step::Range(state));
+        self.push_scope();
+        steps.push(Step::PopScope);
+        push_list(steps, &range.list, &Some(Arc::new(Context::from(val))));
+        Ok(())
+    }
+
+    // The tree and root of the template `name` invoked by `template`. If it is not defined,
+    // writes what `ExecOptions::missing_template` asks for instead and returns `None`.
+    fn lookup_template(
+        &mut self,
+        template: &TemplateNode,
+        name: String,
+    ) -> Result<Option<(&'a String, &'a Nodes)>, ExecError> {
+        self.check_depth()?;
+        match self.tree(&name).and_then(defined) {
+            Some(found) => Ok(Some(found)),
+            None => {
+                let text = self.missing_template(name)?;
+                self.write_str(text, template.pos()..template.pos())?;
+                Ok(None)
             }
         }
-        Err(ExecError::TemplateNotDefined(name))
+    }
+
+    // Switches to the template `tree_name` invoked by `template` with `value` as dot and
+    // pushes the steps executing it.
+    fn enter_template(
+        &mut self,
+        template: &TemplateNode,
+        tree_name: &'a String,
+        root: &'a Nodes,
+        value: Value,
+        steps: &mut Vec<Step<'a>>,
+    ) {
+        #[cfg(feature = "tracing")]
+        let span = self.template_span(tree_name, template.pos()).entered();
+        steps.push(Step::Return(Return {
+            name: tree_name,
+            pos: template.pos(),
+            vars: mem::replace(&mut self.vars, top_level_vars(value.clone())),
+            caller: mem::replace(&mut self.tree_name, tree_name),
+            #[cfg(feature = "tracing")]
+            span,
+        }));
+        self.metrics.templates += 1;
+        self.descend();
+        steps.push(Step::Walk(root, Some(Arc::new(Context::from(value)))));
+    }
+
+    // Returns from an invoked template to its caller.
+    fn return_from(
+        &mut self,
+        ret: Return<'a>,
+        result: Result<(), ExecError>,
+    ) -> Result<(), ExecError> {
+        self.depth -= 1;
+        self.tree_name = ret.caller;
+        self.vars = ret.vars;
+        let (name, pos) = (ret.name, ret.pos);
+        result.map_err(|err| self.template_error(err, name, pos))
     }
 
     fn eval_pipeline(&mut self, ctx: &Context, pipe: &PipeNode) -> Result<Value, ExecError> {
@@ -178,7 +1063,108 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             // TODO
         }
         let val = val.ok_or_else(|| ExecError::ErrorEvaluatingPipe(pipe.clone()))?;
+        self.assign_decls(pipe, &val)?;
+        Ok(val)
+    }
+
+    // The text written in place of the undefined template `name`.
+    fn missing_template(&self, name: String) -> Result<&'a str, ExecError> {
+        match self.template.exec_options.missing_template {
+            MissingTemplate::Error => Err(ExecError::TemplateNotDefined(name)),
+            MissingTemplate::Skip => Ok(""),
+            MissingTemplate::Fallback(ref text) => Ok(text),
+        }
+    }
+
+    fn descend(&mut self) {
+        self.depth += 1;
+        self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+    }
+
+    fn metrics(&self) -> Metrics {
+        Metrics {
+            calls: self.calls.get(),
+            bytes_written: self.written,
+            range_iterations: self.iterations,
+            ..self.metrics
+        }
+    }
+
+    fn check_depth(&self) -> Result<(), ExecError> {
+        let max = self.template.exec_options.max_depth;
+        if self.depth >= max {
+            return Err(ExecError::MaxDepthExceeded(max));
+        }
+        Ok(())
+    }
+
+    // Counts a node and yields to the hooks every `ExecOptions::yield_interval` nodes.
+    // `usize::is_multiple_of` needs a newer Rust than the crate does.
+    #[allow(clippy::manual_is_multiple_of)]
+    fn count_node(&mut self) -> Result<(), ExecError> {
+        self.metrics.nodes += 1;
+        let interval = self.template.exec_options.yield_interval.max(1);
+        if self.metrics.nodes % interval != 0 {
+            return Ok(());
+        }
+        for hook in &self.template.hooks {
+            if hook.on_yield(self.tree_name, self.metrics.nodes).is_break() {
+                return Err(ExecError::Cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cancelled(&self) -> Result<(), ExecError> {
+        let cancelled = match self.template.exec_options.cancel {
+            Some(ref cancel) => cancel.load(Ordering::Relaxed),
+            None => false,
+        };
+        if cancelled
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(ExecError::Cancelled);
+        }
+        Ok(())
+    }
+
+    // Adds `len` bytes about to be written to the output size.
+    fn count_output(&mut self, len: usize) -> Result<(), ExecError> {
+        let max = self.template.exec_options.max_output_size;
+        if len > max - self.written {
+            return Err(ExecError::OutputTooLarge(max));
+        }
+        self.written += len;
+        Ok(())
+    }
+
+    fn count_iteration(&mut self) -> Result<(), ExecError> {
+        let max = self.template.exec_options.max_range_iterations;
+        if self.iterations >= max {
+            return Err(ExecError::TooManyIterations(max));
+        }
+        self.iterations += 1;
+        Ok(())
+    }
+
+    // Evaluates a parenthesized pipeline one level deeper.
+    fn eval_nested_pipeline(&mut self, ctx: &Context, pipe: &PipeNode) -> Result<Value, ExecError> {
+        self.check_depth()?;
+        self.descend();
+        let val = stacker::maybe_grow(RED_ZONE, STACK_SIZE, || self.eval_pipeline(ctx, pipe));
+        self.depth -= 1;
+        val
+    }
+
+    // Declares or assigns the variables of `pipe` with its value.
+    fn assign_decls(&mut self, pipe: &PipeNode, val: &Value) -> Result<(), ExecError> {
         for var in &pipe.decl {
+            if pipe.is_assign {
+                self.set_var_value(&var.ident[0], val.clone())?;
+                continue;
+            }
             self.vars
                 .back_mut()
                 .map(|v| {
@@ -189,73 +1175,408 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
                 })
                 .ok_or(ExecError::EmptyStack)?;
         }
-        Ok(val)
+        Ok(())
+    }
+
+    fn eval_command(
+        &mut self,
+        ctx: &Context,
+        cmd: &CommandNode,
+        val: &Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let first_word = &cmd
+            .args
+            .first()
+            .ok_or_else(|| ExecError::NoArgsForCommandNode(cmd.clone()))?;
+
+        match *(*first_word) {
+            Nodes::Field(ref n) => return self.eval_field_node(ctx, n, &cmd.args, val),
+            Nodes::Variable(ref n) => return self.eval_variable_node(ctx, n, &cmd.args, val),
+            Nodes::Pipe(ref n) => return self.eval_nested_pipeline(ctx, n),
+            Nodes::Chain(ref n) => return self.eval_chain_node(ctx, n, &cmd.args, val),
+            Nodes::Identifier(ref n) => return self.eval_function(ctx, n, &cmd.args, val),
+            _ => {}
+        }
+        not_a_function(&cmd.args, val)?;
+        match *(*first_word) {
+            Nodes::Bool(ref n) => Ok(n.value.clone()),
+            Nodes::Dot(_) => Ok(ctx.dot.clone()),
+            Nodes::Number(ref n) => Ok(n.value.clone()),
+            Nodes::String(ref n) => Ok(n.value.clone()),
+            _ => Err(ExecError::CannotEvaluateCommand(Box::new(
+                (*first_word).clone(),
+            ))),
+        }
+    }
+
+    fn eval_function(
+        &mut self,
+        ctx: &Context,
+        ident: &IdentifierNode,
+        args: &[Nodes],
+        fin: &Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let name = &ident.ident;
+        if name == "and" || name == "or" {
+            return self.eval_and_or(ctx, name, args, fin);
+        }
+        let arg_vals = self.eval_args(ctx, args, fin)?;
+        let function = self
+            .template
+            .func(name, arg_vals.len())
+            .ok_or_else(|| ExecError::UndefinedFunction(name.to_string()))?;
+        match name.as_str() {
+            "include" => return self.include(ident.pos(), &arg_vals),
+            "tpl" => return self.tpl(ident.pos(), &arg_vals),
+            _ => {}
+        }
+        let val = self.call(ident.pos(), name, &*function, &arg_vals)?;
+        self.write_back(name, args, &val)?;
+        Ok(val)
+    }
+
+    // Stores the map returned by the builtins `set`, `unset` and `merge` in the variable their
+    // map came from, e.g. `$d` or `$d.inner`. Maps can not be changed in place, so this is
+    // how `{{ $_ := set $d "a" 1 }}` changes `$d` like in Sprig.
+    fn write_back(&mut self, name: &str, args: &[Nodes], val: &Value) -> Result<(), ExecError> {
+        let var = match args.get(1) {
+            Some(Nodes::Variable(ref var)) => var,
+            _ => return Ok(()),
+        };
+        let builtin = self.template.funcs.get(name).is_some_and(|def| def.builtin);
+        if !builtin || !matches!(name, "set" | "unset" | "merge") {
+            return Ok(());
+        }
+        let (root, path) = match var.ident.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let mut value = self.var_value(root)?;
+        let mut target = &mut value;
+        for key in path {
+            target = match ordered::as_map_mut(target).and_then(|map| map.get_mut(key)) {
+                Some(inner) => inner,
+                None => return Ok(()),
+            };
+        }
+        *target = val.clone();
+        self.set_var_value(root, value)
+    }
+
+    // Executes the template `include` called at `pos` into a string. The template runs like
+    // one invoked by `{{template}}`, except that its output is returned instead of written.
+    fn include(&mut self, pos: Pos, args: &[Value]) -> Result<Value, ExecError> {
+        self.audit(pos, "include", args);
+        let (name, data) = match args {
+            [Value::String(name), data] => (name, data),
+            // Reports the wrong arguments.
+            _ => return funcs::include(args).map_err(|err| self.call_error(pos, "include", err)),
+        };
+        self.check_depth()?;
+        let (tree_name, root) = match self.tree(name).and_then(defined) {
+            Some(found) => found,
+            None => return self.missing_template(name.to_owned()).map(Value::from),
+        };
+        self.render_nested(self.snippets, tree_name, root, data, pos)
+    }
+
+    // Parses the text `tpl` was called with at `pos` as the template "tpl" and executes it into
+    // a string. The parsed templates are added on top of the template set, so the text can
+    // invoke the associated templates and define its own.
+    fn tpl(&mut self, pos: Pos, args: &[Value]) -> Result<Value, ExecError> {
+        self.audit(pos, "tpl", args);
+        let (text, data) = match args {
+            [Value::String(text), data] => (text, data),
+            // Reports the wrong arguments.
+            _ => return funcs::tpl(args).map_err(|err| self.call_error(pos, "tpl", err)),
+        };
+        self.check_depth()?;
+        let template = self.template;
+        let tree_set = parse(
+            TPL.to_owned(),
+            text.clone(),
+            &template.funcs,
+            template.async_func_names(),
+            template.parse_options.clone(),
+        )
+        .map_err(|err| self.call_error(pos, "tpl", FuncError::Generic(err.to_string())))?;
+        let snippets = Snippets {
+            tree_set,
+            outer: self.snippets,
+        };
+        let (tree_name, root) = snippets
+            .tree_set
+            .get_key_value(TPL)
+            .and_then(defined)
+            .ok_or_else(|| ExecError::IncompleteTemplate(TPL.to_owned()))?;
+        self.render_nested(Some(&snippets), tree_name, root, data, pos)
+    }
+
+    // Executes `root` of the tree `tree_name` with `data` as dot one level deeper and returns
+    // the output. The limits, clock and counters of this execution carry over.
+    fn render_nested(
+        &mut self,
+        snippets: Option<&Snippets>,
+        tree_name: &str,
+        root: &Nodes,
+        data: &Value,
+        pos: Pos,
+    ) -> Result<Value, ExecError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.template_span(tree_name, pos).entered();
+        let ctx = Context::from(data.clone());
+        let mut state = State {
+            depth: self.depth,
+            deadline: self.deadline,
+            written: self.written,
+            iterations: self.iterations,
+            clock: Cell::new(self.clock.get()),
+            memo: RefCell::new(self.memo.take()),
+            metrics: self.metrics,
+            calls: Cell::new(self.calls.get()),
+            snippets,
+            ..State::new(self.template, vec![], tree_name, &ctx)
+        };
+        state.metrics.templates += 1;
+        state.descend();
+        let result = stacker::maybe_grow(RED_ZONE, STACK_SIZE, || state.walk(&ctx, root));
+        // The output is counted once the caller writes it.
+        self.iterations = state.iterations;
+        self.clock.set(state.clock.get());
+        self.memo = state.memo;
+        self.metrics = state.metrics;
+        self.calls.set(state.calls.get());
+        result.map_err(|err| self.template_error(err, tree_name, pos))?;
+        String::from_utf8(state.writer)
+            .map(Value::from)
+            .map_err(ExecError::Utf8ConversionFailed)
+    }
+
+    // Calls `function` with the hooks around it.
+    fn call(
+        &self,
+        pos: Pos,
+        name: &str,
+        function: &dyn Fn(&[Value]) -> Result<Value, FuncError>,
+        args: &[Value],
+    ) -> Result<Value, ExecError> {
+        #[cfg(feature = "tracing")]
+        let _span = self.call_span(pos, name).entered();
+        self.audit(pos, name, args);
+        self.before_call(name, args);
+        self.calls.set(self.calls.get() + 1);
+        // Builtins are cheaper to call again than to look up.
+        let memoize = self
+            .template
+            .funcs
+            .get(name)
+            .is_some_and(|def| def.pure && !def.is_builtin());
+        let mut result = if memoize {
+            self.call_pure(name, function, args)
+        } else {
+            self.invoke(function, args)
+        };
+        self.after_call(name, args, &mut result);
+        result.map_err(|err| self.call_error(pos, name, err))
+    }
+
+    // Calls a pure function unless it was already called with the same arguments.
+    fn call_pure(
+        &self,
+        name: &str,
+        function: &dyn Fn(&[Value]) -> Result<Value, FuncError>,
+        args: &[Value],
+    ) -> Result<Value, FuncError> {
+        let key = hash_call(name, args);
+        if let Some(calls) = self.memo.borrow().get(&key) {
+            if let Some((_, _, val)) = calls.iter().find(|(n, a, _)| n == name && a == args) {
+                return Ok(val.clone());
+            }
+        }
+        let val = self.invoke(function, args)?;
+        self.memo.borrow_mut().entry(key).or_default().push((
+            name.to_owned(),
+            args.to_vec(),
+            val.clone(),
+        ));
+        Ok(val)
+    }
+
+    // Calls `function` with the clock, the colors, the root directory, the network limits and
+    // the message catalog of this execution.
+    fn invoke(
+        &self,
+        function: &dyn Fn(&[Value]) -> Result<Value, FuncError>,
+        args: &[Value],
+    ) -> Result<Value, FuncError> {
+        self.scoped(|| function(args))
+    }
+
+    // Runs `f` with the clock, the colors, the root directory, the network limits and the
+    // message catalog of this execution, e.g. a function call or a poll of an async function.
+    fn scoped<R>(&self, f: impl FnOnce() -> R) -> R {
+        let call = || clock::with(&self.clock, f);
+        let call = || funcs::with_colors(self.template.exec_options.colors, call);
+        let options = &self.template.exec_options;
+        let call =
+            || funcs::with_max_sizes(options.max_output_size, options.max_range_iterations, call);
+        #[cfg(feature = "files")]
+        let call = || match self.template.exec_options.file_root {
+            Some(ref root) => funcs::with_root(root, call),
+            None => call(),
+        };
+        #[cfg(feature = "net")]
+        let call = || match self.template.exec_options.net {
+            Some(limits) => funcs::with_limits(limits, call),
+            None => call(),
+        };
+        #[cfg(feature = "i18n")]
+        let call = || match self.template.exec_options.catalog {
+            Some(ref catalog) => funcs::with_catalog(catalog, call),
+            None => call(),
+        };
+        call()
+    }
+
+    // A span for the call of the function `name` at `pos`.
+    #[cfg(feature = "tracing")]
+    fn call_span(&self, pos: Pos, name: &str) -> tracing::Span {
+        tracing::debug_span!(
+            "call",
+            function = name,
+            template = self.tree_name,
+            line = self.line(pos)
+        )
+    }
+
+    // A span for the execution of the template `name` called at `pos`.
+    #[cfg(feature = "tracing")]
+    fn template_span(&self, name: &str, pos: Pos) -> tracing::Span {
+        tracing::debug_span!(
+            "template",
+            name,
+            caller = self.tree_name,
+            line = self.line(pos)
+        )
+    }
+
+    #[cfg(feature = "tracing")]
+    fn line(&self, pos: Pos) -> usize {
+        match self.tree(self.tree_name) {
+            Some((_, tree)) => tree.error_context(pos).line,
+            None => 0,
+        }
+    }
+
+    // Records the call of `name` at `pos` with the audit sink of the template.
+    fn audit(&self, pos: Pos, name: &str, args: &[Value]) {
+        if let Some(ref sink) = self.template.audit_sink {
+            let ctx = self.error_context(pos);
+            sink.record(AuditRecord {
+                func: name.to_owned(),
+                args: args.iter().map(audit::summarize).collect(),
+                template: ctx.name,
+                line: ctx.line,
+            });
+        }
+    }
+
+    fn before_call(&self, name: &str, args: &[Value]) {
+        for hook in &self.template.hooks {
+            hook.before_call(name, args);
+        }
     }
 
-    fn eval_command(
-        &mut self,
-        ctx: &Context,
-        cmd: &CommandNode,
-        val: &Option<Value>,
-    ) -> Result<Value, ExecError> {
-        let first_word = &cmd
-            .args
-            .first()
-            .ok_or_else(|| ExecError::NoArgsForCommandNode(cmd.clone()))?;
+    fn after_call(&self, name: &str, args: &[Value], result: &mut Result<Value, FuncError>) {
+        for hook in &self.template.hooks {
+            hook.after_call(name, args, result);
+        }
+    }
 
-        match *(*first_word) {
-            Nodes::Field(ref n) => return self.eval_field_node(ctx, n, &cmd.args, val),
-            Nodes::Variable(ref n) => return self.eval_variable_node(n, &cmd.args, val),
-            Nodes::Pipe(ref n) => return self.eval_pipeline(ctx, n),
-            Nodes::Chain(ref n) => return self.eval_chain_node(ctx, n, &cmd.args, val),
-            Nodes::Identifier(ref n) => return self.eval_function(ctx, n, &cmd.args, val),
-            _ => {}
+    fn before_print(&self, node: &Nodes, val: &mut Value) {
+        for hook in &self.template.hooks {
+            hook.before_print(self.tree_name, node, val);
         }
-        not_a_function(&cmd.args, val)?;
-        match *(*first_word) {
-            Nodes::Bool(ref n) => Ok(n.value.clone()),
-            Nodes::Dot(_) => Ok(ctx.dot.clone()),
-            Nodes::Number(ref n) => Ok(n.value.clone()),
-            Nodes::String(ref n) => Ok(n.value.clone()),
-            _ => Err(ExecError::CannotEvaluateCommand((*first_word).clone())),
+    }
+
+    fn error_context(&self, pos: Pos) -> ErrorContext {
+        match self.tree(self.tree_name) {
+            Some((_, tree)) => tree.error_context(pos),
+            None => ErrorContext {
+                name: self.tree_name.to_owned(),
+                line: 0,
+                pos,
+            },
         }
     }
 
-    fn eval_function(
+    // Attaches the location of the call at `pos` to the error returned by the function `name`.
+    fn call_error(&self, pos: Pos, name: &str, err: FuncError) -> ExecError {
+        ExecError::FuncCall {
+            ctx: self.error_context(pos),
+            func: name.to_owned(),
+            source: err,
+        }
+    }
+
+    // Adds the invocation of the template `name` at `pos` to the call chain of an error raised
+    // inside it. Errors about the execution as a whole are returned as they are.
+    fn template_error(&self, err: ExecError, name: &str, pos: Pos) -> ExecError {
+        let call = TemplateCall {
+            name: name.to_owned(),
+            caller: self.error_context(pos),
+        };
+        match err {
+            err if aborts(&err) => err,
+            ExecError::InTemplate { source, mut calls } => {
+                calls.push(call);
+                ExecError::InTemplate { source, calls }
+            }
+            err => ExecError::InTemplate {
+                source: Box::new(err),
+                calls: vec![call],
+            },
+        }
+    }
+
+    // Evaluates the arguments of `and` or `or` only until the result is known, like Go does.
+    fn eval_and_or(
         &mut self,
         ctx: &Context,
-        ident: &IdentifierNode,
+        name: &str,
         args: &[Nodes],
         fin: &Option<Value>,
     ) -> Result<Value, ExecError> {
-        let name = &ident.ident;
-        let function = self
-            .template
-            .funcs
-            .get(name.as_str())
-            .ok_or_else(|| ExecError::UndefinedFunction(name.to_string()))?;
-        self.eval_call(ctx, *function, args, fin)
+        let mut val = None;
+        for arg in args.iter().skip(1) {
+            let v = self.eval_arg(ctx, arg)?;
+            if is_true(&v) == (name == "or") {
+                return Ok(v);
+            }
+            val = Some(v);
+        }
+        fin.clone()
+            .or(val)
+            .ok_or_else(|| FuncError::AtLeastXArgs(name.to_owned(), 1).into())
     }
 
-    fn eval_call(
+    // Evaluates the arguments of a command, skipping the function or method name and appending
+    // the piped value.
+    fn eval_args(
         &mut self,
         ctx: &Context,
-        function: Func,
         args: &[Nodes],
         fin: &Option<Value>,
-    ) -> Result<Value, ExecError> {
+    ) -> Result<Vec<Value>, ExecError> {
         let mut arg_vals = vec![];
-        if !args.is_empty() {
-            for arg in &args[1..] {
-                let val = self.eval_arg(ctx, arg)?;
-                arg_vals.push(val);
-            }
+        for arg in args.iter().skip(1) {
+            let val = self.eval_arg(ctx, arg)?;
+            arg_vals.push(val);
         }
         if let Some(ref f) = *fin {
             arg_vals.push(f.clone());
         }
-
-        function(&arg_vals).map_err(Into::into)
+        Ok(arg_vals)
     }
 
     fn eval_chain_node(
@@ -272,23 +1593,23 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             return Err(ExecError::NullInChain(chain.clone()));
         }
         let pipe = self.eval_arg(ctx, &*chain.node)?;
-        self.eval_field_chain(&pipe, &chain.field, args, fin)
+        self.eval_field_chain(ctx, chain.pos(), &pipe, &chain.field, args, fin)
     }
 
     fn eval_arg(&mut self, ctx: &Context, node: &Nodes) -> Result<Value, ExecError> {
         match *node {
             Nodes::Dot(_) => Ok(ctx.dot.clone()),
-            //Nodes::Nil
+            Nodes::Nil(_) => Ok(Value::Nil),
             Nodes::Field(ref n) => self.eval_field_node(ctx, n, &[], &None), // args?
-            Nodes::Variable(ref n) => self.eval_variable_node(n, &[], &None),
-            Nodes::Pipe(ref n) => self.eval_pipeline(ctx, n),
+            Nodes::Variable(ref n) => self.eval_variable_node(ctx, n, &[], &None),
+            Nodes::Pipe(ref n) => self.eval_nested_pipeline(ctx, n),
             // Nodes::Identifier
             Nodes::Identifier(ref n) => self.eval_function(ctx, n, &[], &None),
             Nodes::Chain(ref n) => self.eval_chain_node(ctx, n, &[], &None),
             Nodes::String(ref n) => Ok(n.value.clone()),
             Nodes::Bool(ref n) => Ok(n.value.clone()),
             Nodes::Number(ref n) => Ok(n.value.clone()),
-            _ => Err(ExecError::InvalidArgument(node.clone())),
+            _ => Err(ExecError::InvalidArgument(Box::new(node.clone()))),
         }
     }
 
@@ -299,11 +1620,13 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         args: &[Nodes],
         fin: &Option<Value>,
     ) -> Result<Value, ExecError> {
-        self.eval_field_chain(&ctx.dot, &field.ident, args, fin)
+        self.eval_field_chain(ctx, field.pos(), &ctx.dot, &field.ident, args, fin)
     }
 
     fn eval_field_chain(
         &mut self,
+        ctx: &Context,
+        pos: Pos,
         receiver: &Value,
         ident: &[String],
         args: &[Nodes],
@@ -316,38 +1639,69 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
         // TODO clean shit up
         let mut r: Value = Value::from(0);
         for (i, id) in ident.iter().enumerate().take(n - 1) {
-            r = self.eval_field(if i == 0 { receiver } else { &r }, id, &[], &None)?;
+            r = self.eval_field(ctx, pos, if i == 0 { receiver } else { &r }, id, &[], &None)?;
         }
-        self.eval_field(if n == 1 { receiver } else { &r }, &ident[n - 1], args, fin)
+        self.eval_field(
+            ctx,
+            pos,
+            if n == 1 { receiver } else { &r },
+            &ident[n - 1],
+            args,
+            fin,
+        )
     }
 
     fn eval_field(
         &mut self,
+        ctx: &Context,
+        pos: Pos,
         receiver: &Value,
         field_name: &str,
         args: &[Nodes],
         fin: &Option<Value>,
     ) -> Result<Value, ExecError> {
-        let has_args = args.len() > 1 || fin.is_some();
-        if has_args {
-            return Err(ExecError::NotAFunctionButArguments(field_name.to_string()));
-        }
         let ret = match *receiver {
             Value::Object(ref o) => o
                 .get(field_name)
                 .cloned()
                 .ok_or_else(|| ExecError::NoFiledFor(field_name.to_string(), receiver.clone())),
-            Value::Map(ref o) => Ok(o.get(field_name).cloned().unwrap_or(Value::NoValue)),
+            Value::Map(_) | Value::OrderedMap(_) => {
+                match ordered::as_map(receiver).and_then(|o| o.get(field_name)) {
+                    Some(val) => Ok(val.clone()),
+                    None => match self.template.exec_options.missing_key {
+                        MissingKey::Default => Ok(Value::NoValue),
+                        MissingKey::Zero => Ok(Value::Nil),
+                        MissingKey::Error => Err(ExecError::MissingKey(field_name.to_string())),
+                    },
+                }
+            }
+            // Like in Go, fields of missing values are missing too, but nil values can't have
+            // fields.
+            Value::NoValue => match self.template.exec_options.missing_key {
+                MissingKey::Error => Err(ExecError::NilData(field_name.to_string())),
+                _ => Ok(Value::NoValue),
+            },
+            Value::Nil => Err(ExecError::NilPointerEvaluating(field_name.to_string())),
             _ => Err(ExecError::OnlyMapsAndObjectsHaveFields),
         };
-        if let Ok(Value::Function(ref f)) = ret {
-            return (f.f)(&[receiver.clone()]).map_err(Into::into);
+        // Functions in objects are methods and get called with the object followed by the
+        // arguments. Like in Go, functions in maps are plain values which can be passed to `call`.
+        match (receiver, ret) {
+            (Value::Object(_), Ok(Value::Function(ref f))) => {
+                let mut arg_vals = vec![receiver.clone()];
+                arg_vals.extend(self.eval_args(ctx, args, fin)?);
+                self.call(pos, field_name, &f.f, &arg_vals)
+            }
+            _ if args.len() > 1 || fin.is_some() => {
+                Err(ExecError::NotAFunctionButArguments(field_name.to_string()))
+            }
+            (_, ret) => ret,
         }
-        ret
     }
 
     fn eval_variable_node(
         &mut self,
+        ctx: &Context,
         variable: &VariableNode,
         args: &[Nodes],
         fin: &Option<Value>,
@@ -357,89 +1711,156 @@ impl<'a, 'b, T: Write> State<'a, 'b, T> {
             not_a_function(args, fin)?;
             return Ok(val);
         }
-        self.eval_field_chain(&val, &variable.ident[1..], args, fin)
+        self.eval_field_chain(ctx, variable.pos(), &val, &variable.ident[1..], args, fin)
     }
 
-    // Walks an `if` or `with` node. They behave the same, except that `with` sets dot.
-    fn walk_if_or_with(&mut self, node: &'a Nodes, ctx: &Context) -> Result<(), ExecError> {
-        let pipe = match *node {
-            Nodes::If(ref n) | Nodes::With(ref n) => &n.pipe,
-            _ => return Err(ExecError::ExpectedIfOrWith(node.clone())),
-        };
-        let val = self.eval_pipeline(ctx, pipe)?;
-        let truth = is_true(&val);
-        if truth {
-            match *node {
-                Nodes::If(ref n) => self.walk_list(ctx, &n.list)?,
-                Nodes::With(ref n) => {
-                    let ctx = Context { dot: val };
-                    self.walk_list(&ctx, &n.list)?;
-                }
-                _ => {}
+    // Sets the variables declared by a range to the key and value of the current iteration.
+    fn set_range_vars(
+        &mut self,
+        key: Value,
+        val: &Value,
+        range: &RangeNode,
+    ) -> Result<(), ExecError> {
+        let decl = &range.pipe.decl;
+        if range.pipe.is_assign {
+            if let Some(elem) = decl.last() {
+                self.set_var_value(&elem.ident[0], val.clone())?;
+            }
+            if decl.len() > 1 {
+                self.set_var_value(&decl[0].ident[0], key)?;
             }
         } else {
-            match *node {
-                Nodes::If(ref n) | Nodes::With(ref n) => {
-                    if let Some(ref otherwise) = n.else_list {
-                        self.walk_list(ctx, otherwise)?;
-                    }
-                }
-                _ => {}
+            if !decl.is_empty() {
+                self.set_kth_last_var_value(1, val.clone())?;
+            }
+            if decl.len() > 1 {
+                self.set_kth_last_var_value(2, key)?;
             }
         }
         Ok(())
     }
 
-    fn one_iteration(
-        &mut self,
-        key: Value,
-        val: Value,
-        range: &'a RangeNode,
-    ) -> Result<(), ExecError> {
-        if !range.pipe.decl.is_empty() {
-            self.set_kth_last_var_value(1, val.clone())?;
+    // Pulls the `i`th item from a function `range` iterates over. The function is called with
+    // the index and returns `Value::NoValue` once it is exhausted.
+    fn next_item(
+        &self,
+        range: &RangeNode,
+        function: Func,
+        i: usize,
+    ) -> Result<Option<Value>, ExecError> {
+        match self.call(range.pipe.pos(), "range", &function, &[Value::from(i)])? {
+            Value::NoValue => Ok(None),
+            item => Ok(Some(item)),
         }
-        if range.pipe.decl.len() > 1 {
-            self.set_kth_last_var_value(2, key)?;
+    }
+
+    // The entries of a map in the order `range` visits them.
+    fn map_entries(&self, map: &Value) -> Vec<(Value, Value)> {
+        ordered::entries(map, self.template.exec_options.sort_map_keys)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| (Value::from(k.as_str()), v.clone()))
+            .collect()
+    }
+
+    fn print_value(&mut self, val: &Value, span: Range<Pos>) -> Result<(), ExecError> {
+        self.write_str(&format_value(val), span)
+    }
+
+    // Writes the output of the node at `span`.
+    fn write_str(&mut self, s: &str, span: Range<Pos>) -> Result<(), ExecError> {
+        self.count_output(s.len())?;
+        self.writer
+            .write_all(s.as_bytes())
+            .map_err(ExecError::IOError)?;
+        if let Some(ref mut source_map) = self.source_map {
+            source_map.record(self.tree_name, span, s.len());
         }
-        let vars = VecDeque::new();
-        self.vars.push_back(vars);
-        let ctx = Context { dot: val };
-        self.walk_list(&ctx, &range.list)?;
-        self.vars.pop_back();
         Ok(())
     }
+}
+
+fn hash_call(name: &str, args: &[Value]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    for arg in args {
+        hash_value(arg, &mut hasher);
+    }
+    hasher.finish()
+}
 
-    fn walk_range(&mut self, ctx: &Context, range: &'a RangeNode) -> Result<(), ExecError> {
-        let val = self.eval_pipeline(ctx, &range.pipe)?;
-        match val {
-            Value::Object(ref map) | Value::Map(ref map) => {
-                for (k, v) in map.clone() {
-                    self.one_iteration(Value::from(k), v, range)?;
-                }
+// Hashes values consistently with their equality. The entries of maps are combined regardless
+// of their order.
+fn hash_value<H: Hasher>(val: &Value, hasher: &mut H) {
+    mem::discriminant(val).hash(hasher);
+    match *val {
+        Value::Bool(b) => b.hash(hasher),
+        Value::String(ref s) => s.hash(hasher),
+        Value::Bytes(ref b) => b.hash(hasher),
+        Value::Number(ref n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.hash(hasher),
+            (_, Some(u)) => u.hash(hasher),
+            _ => n.as_f64().map(f64::to_bits).hash(hasher),
+        },
+        Value::Array(ref a) => {
+            a.len().hash(hasher);
+            for v in a {
+                hash_value(v, hasher);
             }
-            Value::Array(ref vec) => {
-                for (k, v) in vec.iter().enumerate() {
-                    self.one_iteration(Value::from(k), v.clone(), range)?;
-                }
-            }
-            _ => return Err(ExecError::InvalidRange(val)),
         }
-        if let Some(ref else_list) = range.else_list {
-            self.walk_list(ctx, else_list)?;
+        Value::Object(_) | Value::Map(_) | Value::OrderedMap(_) => {
+            let entries = ordered::entries(val, false).unwrap_or_default();
+            let entries = entries.into_iter().fold(0u64, |sum, (k, v)| {
+                let mut entry = DefaultHasher::new();
+                k.hash(&mut entry);
+                hash_value(v, &mut entry);
+                sum.wrapping_add(entry.finish())
+            });
+            entries.hash(hasher);
         }
-        Ok(())
+        Value::NoValue | Value::Nil | Value::Function(_) => {}
     }
+}
 
-    fn print_value(&mut self, val: &Value) -> Result<(), ExecError> {
-        write!(self.writer, "{}", val).map_err(ExecError::IOError)?;
-        Ok(())
+fn top_level_vars(dot: Value) -> VecDeque<VecDeque<Variable>> {
+    let mut vars = VecDeque::new();
+    let mut top = VecDeque::new();
+    top.push_back(Variable {
+        name: "$".to_owned(),
+        value: dot,
+    });
+    vars.push_back(top);
+    vars
+}
+
+// The name of the template a `template` action with a pipeline invokes.
+fn template_name(val: Value) -> Result<String, ExecError> {
+    match val {
+        Value::String(s) => Ok(s),
+        _ => Err(ExecError::PipelineMustYieldString),
     }
 }
 
+// The name and root of a tree which is defined, i.e. has been parsed.
+fn defined<'a>((name, tree): (&'a String, &'a Tree)) -> Option<(&'a String, &'a Nodes)> {
+    tree.root.as_ref().map(|root| (name, root))
+}
+
+// Whether `err` concerns the execution as a whole rather than the node it occurred in.
+fn aborts(err: &ExecError) -> bool {
+    matches!(
+        err,
+        ExecError::IOError(_)
+            | ExecError::MaxDepthExceeded(_)
+            | ExecError::Cancelled
+            | ExecError::OutputTooLarge(_)
+            | ExecError::TooManyIterations(_)
+    )
+}
+
 fn not_a_function(args: &[Nodes], val: &Option<Value>) -> Result<(), ExecError> {
     if args.len() > 1 || val.is_some() {
-        return Err(ExecError::ArgumentForNonFunction(args[0].clone()));
+        return Err(ExecError::ArgumentForNonFunction(Box::new(args[0].clone())));
     }
     Ok(())
 }
@@ -513,108 +1934,571 @@ mod tests_mocked {
     }
 
     #[test]
-    fn test_sub() {
-        let data = Context::from(1u8);
-        let mut w: Vec<u8> = vec![];
+    fn test_sub() {
+        let data = Context::from(1u8);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1");
+
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: u8,
+        }
+        let f = Foo { foo: 1 };
+        let data = Context::from(f);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.foo}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_novalue() {
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: u8,
+        }
+        let f = Foo { foo: 1 };
+        let data = Context::from(f);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.foobar}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_err());
+
+        let map: HashMap<String, u64> = [("foo".to_owned(), 23u64)].iter().cloned().collect();
+        let data = Context::from(map);
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.foo2}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), Value::NoValue.to_string());
+    }
+
+    #[test]
+    fn test_dollar_dot() {
+        #[derive(Gtmpl, Clone)]
+        struct Foo {
+            foo: u8,
+        }
+        let data = Context::from(Foo { foo: 1u8 });
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{$.foo}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_function_via_dot() {
+        #[derive(Gtmpl)]
+        struct Foo {
+            foo: Func,
+        }
+        fn foo(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from("foobar"))
+        }
+        let data = Context::from(Foo { foo });
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.foo}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "foobar");
+
+        fn plus_one(args: &[Value]) -> Result<Value, FuncError> {
+            if let Value::Object(ref o) = &args[0] {
+                if let Some(Value::Number(ref n)) = o.get("num") {
+                    if let Some(i) = n.as_i64() {
+                        return Ok((i + 1).into());
+                    }
+                }
+            }
+            Err(anyhow!("integer required, got: {:?}", args).into())
+        }
+
+        #[derive(Gtmpl)]
+        struct AddMe {
+            num: u8,
+            plus_one: Func,
+        }
+        let data = Context::from(AddMe { num: 42, plus_one });
+        let mut w: Vec<u8> = vec![];
+        let mut t = Template::default();
+        assert!(t.parse(r#"{{.plus_one}}"#).is_ok());
+        let out = t.execute(&mut w, &data);
+        assert!(out.is_ok());
+        assert_eq!(String::from_utf8(w).unwrap(), "43");
+    }
+
+    #[test]
+    fn test_call_function_in_map() {
+        fn format(args: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(format!("<{}>", args[0])))
+        }
+        let mut m = HashMap::new();
+        m.insert("formatter".to_owned(), Value::from(format as Func));
+        m.insert("value".to_owned(), Value::from(42));
+        let data = Context::from(m);
+        let mut t = Template::default();
+        t.parse(r#"{{ call .formatter .value }}"#).unwrap();
+        assert_eq!(t.render(&data).unwrap(), "<42>");
+
+        t.parse(r#"{{ call .value }}"#).unwrap();
+        let err = t.render(&data).unwrap_err().to_string();
+        assert!(err.contains("non-function of type int"), "{}", err);
+        t.parse(r#"{{ call .missing }}"#).unwrap();
+        let err = t.render(&data).unwrap_err().to_string();
+        assert!(err.contains("call of nil"), "{}", err);
+    }
+
+    #[test]
+    fn test_method_with_args() {
+        fn greet(args: &[Value]) -> Result<Value, FuncError> {
+            match (&args[0], &args[1..]) {
+                (Value::Object(ref o), [greeting, punct]) => {
+                    Ok(Value::from(format!("{} {}{}", greeting, o["name"], punct)))
+                }
+                _ => Err(anyhow!("greeting and punctuation required").into()),
+            }
+        }
+
+        #[derive(Gtmpl)]
+        struct User {
+            name: String,
+            greet: Func,
+        }
+        let mut m = HashMap::new();
+        let user = User {
+            name: "Ann".to_owned(),
+            greet,
+        };
+        m.insert("user".to_owned(), Value::from(user));
+        let data = Context::from(m);
+        let mut t = Template::default();
+        t.parse(r#"{{ .user.greet "Hello" "!" }} {{ "?" | .user.greet "Hi" }}"#)
+            .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "Hello Ann! Hi Ann?");
+
+        t.parse(r#"{{ .user.name "Hello" }}"#).unwrap();
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::NotAFunctionButArguments(ref name)) if name == "name"
+        ));
+    }
+
+    #[test]
+    fn test_function_error() {
+        fn fail(_: &[Value]) -> Result<Value, FuncError> {
+            Err(FuncError::Generic("boom".into()))
+        }
+        let mut t = Template::default();
+        t.name = "tmpl".into();
+        t.add_func("fail", fail);
+        t.parse("a\n{{ define \"b\" }}\n\n{{ fail 1 }}{{ end }}{{ template \"b\" }}")
+            .unwrap();
+        let err = t.render(&Context::empty()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"template: b:4: executing "b" at <fail>: error calling fail: boom; in "b" called from "tmpl" line 4"#
+        );
+        match err {
+            ExecError::InTemplate { source, .. } => match *source {
+                ExecError::FuncCall { ctx, func, .. } => {
+                    assert_eq!(ctx.pos, 23);
+                    assert_eq!(func, "fail");
+                }
+                err => panic!("unexpected error {:?}", err),
+            },
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_template_call_chain() {
+        let mut t = Template::default();
+        t.name = "page".into();
+        t.parse(
+            "{{ define \"sidebar\" }}\n{{ .missing.x }}{{ end }}\
+             {{ define \"layout\" }}\n{{ template \"sidebar\" . }}{{ end }}\
+             \n\n{{ template \"layout\" . }}",
+        )
+        .unwrap();
+        let err = t.render(&Context::from(1)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "only maps and objects have fields; in \"sidebar\" called from \"layout\" line 3; \
+             in \"layout\" called from \"page\" line 5"
+        );
+        match err {
+            ExecError::InTemplate { source, calls } => {
+                assert!(matches!(*source, ExecError::OnlyMapsAndObjectsHaveFields));
+                let names: Vec<&str> = calls.iter().map(|c| c.name.as_str()).collect();
+                assert_eq!(names, vec!["sidebar", "layout"]);
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_include() {
+        let mut t = Template::default();
+        t.name = "page".into();
+        t.parse(
+            "{{ define \"item\" }}- {{ . }}\n- {{ . }}{{ end }}\
+             {{ define \"broken\" }}\n{{ .x }}{{ end }}\
+             list:{{ include \"item\" . | indent 2 | printf \"\\n%s\" }}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&Context::from(1)).unwrap(), "list:\n  - 1\n  - 1");
+
+        let (output, metrics) = t.render_with_metrics(&Context::from("a"));
+        assert_eq!(output.unwrap(), "list:\n  - a\n  - a");
+        assert_eq!(metrics.templates, 1);
+        assert_eq!(metrics.max_depth, 1);
+
+        let render = |text: &str| {
+            let mut t = t.clone();
+            t.parse(text).unwrap();
+            t.render(&Context::from(1)).unwrap_err().to_string()
+        };
+        assert_eq!(
+            render(r#"{{ include "broken" . }}"#),
+            "only maps and objects have fields; in \"broken\" called from \"page\" line 1"
+        );
+        assert_eq!(
+            render(r#"{{ include "missing" . }}"#),
+            "template missing not defined"
+        );
+        assert_eq!(
+            render(r#"{{ include 1 . }}"#),
+            "template: page:1: executing \"page\" at <include>: error calling include: \
+             include: wrong type for value; expected string; got int"
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ bold "a" }} {{ include "b" . }}{{ define "b" }}{{ color "red" . }}{{ end }}"#,
+        )
+        .unwrap();
+        let colored = t.render(&Context::from("c")).unwrap();
+        assert_eq!(colored, "\x1b[1ma\x1b[22m \x1b[31mc\x1b[39m");
+        t.exec_options.colors = false;
+        assert_eq!(t.render(&Context::from("c")).unwrap(), "a c");
+    }
+
+    #[test]
+    fn test_tpl() {
+        let mut t = Template::default();
+        t.name = "page".into();
+        t.parse(
+            "{{ define \"greeting\" }}Hello {{ . }}{{ end }}\
+             {{ range . }}{{ tpl . \"Ann\" }};{{ end }}",
+        )
+        .unwrap();
+        let texts = vec![
+            "{{ . | upper }}",
+            "{{ template \"greeting\" . }}!",
+            "{{ define \"x\" }}[{{ . }}]{{ end }}{{ template \"x\" . }}",
+            "",
+        ];
+        assert_eq!(
+            t.render(&Context::from(texts)).unwrap(),
+            "ANN;Hello Ann!;[Ann];;"
+        );
+        assert!(!t.tree_set.contains_key("x"));
+
+        let render = |texts: Vec<&str>| t.render(&Context::from(texts)).unwrap_err().to_string();
+        assert_eq!(
+            render(vec!["\n{{ .x }}"]),
+            "only maps and objects have fields; in \"tpl\" called from \"page\" line 1"
+        );
+        assert_eq!(
+            render(vec!["{{ if }}"]),
+            "template: page:1: executing \"page\" at <tpl>: error calling tpl: \
+             template: tpl:1:missing value for if"
+        );
+
+        let mut t = Template::default();
+        t.exec_options.max_depth = 100;
+        t.parse("{{ tpl . . }}").unwrap();
+        assert!(matches!(
+            t.render(&Context::from("{{ tpl . . }}")),
+            Err(ExecError::MaxDepthExceeded(100))
+        ));
+    }
+
+    #[test]
+    fn test_tpl_nested() {
+        let mut t = Template::default();
+        t.parse(
+            "{{ define \"a\" }}A{{ end }}{{ define \"b\" }}B{{ template \"a\" }}{{ end }}\
+             {{ tpl .outer .inner }}|{{ template \"b\" }}",
+        )
+        .unwrap();
+        let mut data = HashMap::new();
+        data.insert(
+            "outer".to_owned(),
+            Value::from(
+                "{{ define \"a\" }}a{{ end }}{{ define \"b\" }}{{ end }}\
+                 {{ template \"b\" }}{{ tpl . \"\" }}",
+            ),
+        );
+        data.insert(
+            "inner".to_owned(),
+            Value::from("{{ define \"c\" }}c{{ end }}{{ template \"a\" }}{{ template \"c\" }}"),
+        );
+        assert_eq!(t.render(&Context::from(data)).unwrap(), "Baac|BA");
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_render_batch() {
+        let mut t = Template::default();
+        t.parse("{{ index . 0 }}").unwrap();
+        let contexts = vec![
+            Context::from(vec![1]),
+            Context::from(Vec::<i32>::new()),
+            Context::from(vec![3]),
+        ];
+        let outputs = t.render_batch(contexts);
+        assert_eq!(outputs[0].as_ref().unwrap(), "1");
+        assert!(outputs[1].is_err());
+        assert_eq!(outputs[2].as_ref().unwrap(), "3");
+    }
+
+    #[test]
+    fn test_render_lenient() {
+        let mut map = HashMap::new();
+        map.insert("n".to_owned(), Value::from(1));
+        map.insert("l".to_owned(), Value::from(vec![1, 2]));
+        let data = Context::from(map);
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ define "t" }}<{{ .x }}>{{ end }}{{ $v := 1 }}{{ if .n.x }}a{{ end }}|{{ range .l }}{{ . }}{{ index 1 2 }}{{ end }}|{{ template "t" .n }}|{{ $v }}"#,
+        )
+        .unwrap();
+        let (output, errors) = t.render_lenient(&data, "?");
+        assert_eq!(output, "?|1?2?|<?>|1");
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0], ExecError::OnlyMapsAndObjectsHaveFields));
+        assert!(matches!(errors[1], ExecError::FuncCall { .. }));
+
+        t.exec_options.max_output_size = 3;
+        let (output, errors) = t.render_lenient(&data, "?");
+        assert_eq!(output, "?|1");
+        assert!(matches!(errors.last(), Some(ExecError::OutputTooLarge(3))));
+    }
+
+    #[test]
+    fn test_render_profiled() {
         let mut t = Template::default();
-        assert!(t.parse(r#"{{.}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "1");
+        t.parse(
+            r#"{{ define "row" }}<{{ . }}>{{ end }}{{ range . }}{{ template "row" . }}{{ end }}"#,
+        )
+        .unwrap();
+        let (output, profile) = t.render_profiled(&Context::from(vec![1, 2, 3])).unwrap();
+        assert_eq!(output, "<1><2><3>");
+        let action = profile
+            .entries()
+            .iter()
+            .find(|e| e.template == "row" && e.node == "{{.}}")
+            .unwrap();
+        assert_eq!(action.count, 3);
+        let range = profile
+            .entries()
+            .iter()
+            .find(|e| e.node.starts_with("{{range"))
+            .unwrap();
+        assert_eq!((range.count, range.line), (1, 1));
+        assert!(range.total >= range.self_time);
+        assert!(profile.to_string().contains("row:1 {{.}}"));
+    }
 
-        #[derive(Gtmpl)]
-        struct Foo {
-            foo: u8,
+    #[test]
+    fn test_hooks() {
+        use crate::ExecHook;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Log(Mutex<Vec<String>>);
+
+        impl ExecHook for Arc<Log> {
+            fn before_node(&self, template: &str, node: &Nodes) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {}", template, node));
+            }
+            fn after_node(&self, _template: &str, node: &Nodes) {
+                self.0.lock().unwrap().push(format!("/{}", node));
+            }
+            fn before_print(&self, _template: &str, _node: &Nodes, value: &mut Value) {
+                if *value == Value::from("secret") {
+                    *value = Value::from("***");
+                }
+            }
+            fn before_call(&self, name: &str, args: &[Value]) {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}({})", name, args.join(" ")));
+            }
+            fn after_call(
+                &self,
+                _name: &str,
+                _args: &[Value],
+                result: &mut Result<Value, FuncError>,
+            ) {
+                if let Ok(ref mut val) = *result {
+                    *val = Value::from(format!("<{}>", val));
+                }
+            }
         }
-        let f = Foo { foo: 1 };
-        let data = Context::from(f);
-        let mut w: Vec<u8> = vec![];
+
+        let log = Arc::new(Log::default());
         let mut t = Template::default();
-        assert!(t.parse(r#"{{.foo}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "1");
+        t.add_hook(log.clone());
+        t.parse(r#"{{ define "a" }}{{ . }}{{ end }}{{ print 1 }}{{ template "a" "secret" }}"#)
+            .unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "<1>***");
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            vec![
+                ": {{print 1}}{{template \"a\" \"secret\"}}",
+                ": {{print 1}}",
+                "print(1)",
+                "/{{print 1}}",
+                ": {{template \"a\" \"secret\"}}",
+                "a: {{.}}",
+                "a: {{.}}",
+                "/{{.}}",
+                "/{{.}}",
+                "/{{template \"a\" \"secret\"}}",
+                "/{{print 1}}{{template \"a\" \"secret\"}}",
+            ]
+        );
     }
 
     #[test]
-    fn test_novalue() {
-        #[derive(Gtmpl)]
-        struct Foo {
-            foo: u8,
-        }
-        let f = Foo { foo: 1 };
-        let data = Context::from(f);
-        let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
-        assert!(t.parse(r#"{{.foobar}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_err());
+    fn test_audit_sink() {
+        use crate::AuditRecord;
+        use std::sync::Mutex;
 
-        let map: HashMap<String, u64> = [("foo".to_owned(), 23u64)].iter().cloned().collect();
-        let data = Context::from(map);
-        let mut w: Vec<u8> = vec![];
+        let log = Arc::new(Mutex::new(vec![]));
+        let sink = Arc::clone(&log);
         let mut t = Template::default();
-        assert!(t.parse(r#"{{.foo2}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), Value::NoValue.to_string());
+        t.set_audit_sink(move |r: AuditRecord| {
+            let entry = format!("{}:{} {}({})", r.template, r.line, r.func, r.args.join(" "));
+            sink.lock().unwrap().push(entry);
+        });
+        t.parse("{{ define \"a\" }}\n{{ len . }}{{ end }}{{ include \"a\" . }}{{ index . 9 }}")
+            .unwrap();
+        assert!(t.render(&Context::from(vec![1, 2])).is_err());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![":2 include(a [1 2])", "a:2 len([1 2])", ":2 index([1 2] 9)",]
+        );
     }
 
     #[test]
-    fn test_dollar_dot() {
-        #[derive(Gtmpl, Clone)]
-        struct Foo {
-            foo: u8,
+    fn test_yield() {
+        use crate::ExecHook;
+        use std::ops::ControlFlow;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Yields(Mutex<Vec<usize>>);
+
+        impl ExecHook for Arc<Yields> {
+            fn on_yield(&self, _template: &str, nodes: usize) -> ControlFlow<()> {
+                let mut yields = self.0.lock().unwrap();
+                yields.push(nodes);
+                if yields.len() == 3 {
+                    return ControlFlow::Break(());
+                }
+                ControlFlow::Continue(())
+            }
         }
-        let data = Context::from(Foo { foo: 1u8 });
-        let mut w: Vec<u8> = vec![];
+
+        let yields = Arc::new(Yields::default());
         let mut t = Template::default();
-        assert!(t.parse(r#"{{$.foo}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "1");
+        t.add_hook(yields.clone());
+        t.exec_options.yield_interval = 4;
+        t.parse("{{ range . }}{{ . }}{{ end }}").unwrap();
+        assert_eq!(t.render(&Context::from(vec![1; 5])).unwrap(), "11111");
+        assert_eq!(*yields.0.lock().unwrap(), vec![4]);
+        assert!(matches!(
+            t.render(&Context::from(vec![1; 20])),
+            Err(ExecError::Cancelled)
+        ));
+        assert_eq!(*yields.0.lock().unwrap(), vec![4, 4, 8]);
     }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn test_function_via_dot() {
-        #[derive(Gtmpl)]
-        struct Foo {
-            foo: Func,
-        }
-        fn foo(_: &[Value]) -> Result<Value, FuncError> {
-            Ok(Value::from("foobar"))
-        }
-        let data = Context::from(Foo { foo });
-        let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
-        assert!(t.parse(r#"{{.foo}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "foobar");
+    fn test_tracing_spans() {
+        use std::fmt::Debug;
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
 
-        fn plus_one(args: &[Value]) -> Result<Value, FuncError> {
-            if let Value::Object(ref o) = &args[0] {
-                if let Some(Value::Number(ref n)) = o.get("num") {
-                    if let Some(i) = n.as_i64() {
-                        return Ok((i + 1).into());
-                    }
-                }
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Vec<String>>>);
+
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.push_str(&format!(" {}={:?}", field.name(), value));
             }
-            Err(anyhow!("integer required, got: {:?}", args).into())
         }
 
-        #[derive(Gtmpl)]
-        struct AddMe {
-            num: u8,
-            plus_one: Func,
+        impl Subscriber for Spans {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.0.lock().unwrap();
+                let mut fields = Fields(span.metadata().name().to_owned());
+                span.record(&mut fields);
+                spans.push(fields.0);
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
         }
-        let data = Context::from(AddMe { num: 42, plus_one });
-        let mut w: Vec<u8> = vec![];
-        let mut t = Template::default();
-        assert!(t.parse(r#"{{.plus_one}}"#).is_ok());
-        let out = t.execute(&mut w, &data);
-        assert!(out.is_ok());
-        assert_eq!(String::from_utf8(w).unwrap(), "43");
+
+        let spans = Spans::default();
+        let mut t = Template::with_name("main");
+        t.parse("{{ define \"a\" }}\n{{ len . }}{{ end }}\n{{ template \"a\" \"x\" }}")
+            .unwrap();
+        tracing::subscriber::with_default(spans.clone(), || {
+            t.render(&Context::empty()).unwrap();
+        });
+        assert_eq!(
+            *spans.0.lock().unwrap(),
+            vec![
+                r#"execute template=main"#,
+                r#"template name="a" caller="main" line=3"#,
+                r#"call function="len" template="a" line=2"#,
+            ]
+        );
     }
 
     #[test]
@@ -712,6 +2596,21 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "1000");
     }
 
+    #[test]
+    fn test_truth() {
+        let mut map = HashMap::new();
+        map.insert("object".to_owned(), Value::Object(HashMap::new()));
+        map.insert("map".to_owned(), Value::Map(HashMap::new()));
+        let data = Context::from(map);
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ if 0.0 }}a{{ end }}{{ with -1 }}{{ . }}{{ end }}{{ if .object }}b{{ end }}
+{{- if .map }}c{{ end }}{{ if .missing }}d{{ end }}{{ not .object }} {{ and 1 0.0 }}"#,
+        )
+        .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "-1bfalse 0");
+    }
+
     fn to_sorted_string(buf: Vec<u8>) -> String {
         let mut chars: Vec<char> = String::from_utf8(buf).unwrap().chars().collect();
         chars.sort_unstable();
@@ -741,6 +2640,149 @@ mod tests_mocked {
         assert_eq!(String::from_utf8(w).unwrap(), "foobar2000");
     }
 
+    #[test]
+    fn test_range_function() {
+        fn rows(args: &[Value]) -> Result<Value, FuncError> {
+            match gtmpl_value::from_value::<u64>(&args[0]) {
+                Some(i) if i < 3 => Ok(Value::from(format!("row{}", i))),
+                _ => Ok(Value::NoValue),
+            }
+        }
+        fn none(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::NoValue)
+        }
+        fn fail(_: &[Value]) -> Result<Value, FuncError> {
+            Err(FuncError::Generic("db closed".to_owned()))
+        }
+
+        let mut map = HashMap::new();
+        map.insert("rows".to_owned(), Value::from(rows as Func));
+        map.insert("none".to_owned(), Value::from(none as Func));
+        map.insert("fail".to_owned(), Value::from(fail as Func));
+        map.insert("empty".to_owned(), Value::from(Vec::<Value>::new()));
+        let data = Context::from(map);
+        let mut t = Template::default();
+        t.parse(
+            "{{ range $i, $r := .rows }}{{ $i }}={{ $r }} {{ else }}no rows{{ end }}|\
+             {{ range .none }}x{{ else }}none{{ end }}|\
+             {{ range .empty }}x{{ else }}empty{{ end }}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "0=row0 1=row1 2=row2 |none|empty");
+
+        t.parse("{{ range .fail }}{{ end }}").unwrap();
+        let err = t.render(&data).unwrap_err();
+        assert!(err.to_string().ends_with("error calling range: db closed"));
+    }
+
+    #[test]
+    fn test_range_iter() {
+        use std::sync::atomic::AtomicUsize;
+
+        // Rows of a database, which are fetched on demand.
+        struct Db {
+            rows: Vec<&'static str>,
+            fetched: AtomicUsize,
+        }
+
+        let db = Arc::new(Db {
+            rows: vec!["ann", "bob", "eve"],
+            fetched: AtomicUsize::new(0),
+        });
+        let cursor = db.clone();
+        let rows = (0..).map_while(move |i| {
+            cursor.fetched.fetch_add(1, Ordering::SeqCst);
+            cursor.rows.get(i).map(|&row| Value::from(row))
+        });
+        let mut map = HashMap::new();
+        map.insert("title".to_owned(), Value::from("users"));
+        let data = Context::from(map)
+            .with_iter("rows", rows)
+            .with_iter("none", std::iter::empty());
+        let mut t = Template::default();
+        t.parse(
+            "{{ .title }}: {{ range $i, $r := .rows }}{{ $i }}={{ $r }} {{ end }}|\
+             {{ range .rows }}again{{ else }}consumed{{ end }}|\
+             {{ range .none }}x{{ else }}none{{ end }}",
+        )
+        .unwrap();
+        assert_eq!(
+            t.render(&data).unwrap(),
+            "users: 0=ann 1=bob 2=eve |consumed|none"
+        );
+        assert_eq!(db.fetched.load(Ordering::SeqCst), 5);
+
+        // Only `range` sees the iterators, which are not entries of dot.
+        t.parse(
+            "{{ . }} {{ len . }} {{ .rows }} {{ with .title }}{{ range .rows }}{{ end }}{{ end }}",
+        )
+        .unwrap();
+        assert!(t
+            .render(&data)
+            .unwrap_err()
+            .to_string()
+            .contains("only maps and objects have fields"));
+        t.parse("{{ . }} {{ len . }} {{ .rows }}").unwrap();
+        assert_eq!(t.render(&data).unwrap(), "map[title:users] 1 <no value>");
+
+        // Items are pulled lazily, so an error stops the iteration early.
+        let db = Arc::new(Db {
+            rows: vec!["ann", "bob", "eve"],
+            fetched: AtomicUsize::new(0),
+        });
+        let cursor = db.clone();
+        let rows = (0..).map_while(move |i| {
+            cursor.fetched.fetch_add(1, Ordering::SeqCst);
+            cursor.rows.get(i).map(|&row| Value::from(row))
+        });
+        let data = Context::empty().with_iter("rows", rows);
+        t.parse("{{ range .rows }}{{ index . 5 }}{{ end }}")
+            .unwrap();
+        assert!(t.render(&data).is_err());
+        assert_eq!(db.fetched.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "with_iter: dot of type int has no fields")]
+    fn test_with_iter_no_fields() {
+        let _ = Context::from(1).with_iter("rows", std::iter::empty());
+    }
+
+    #[test]
+    fn test_range_sorted_keys() {
+        let map: HashMap<String, i32> = ["d", "b", "a", "e", "c", "B"]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), i as i32))
+            .collect();
+        let data = Context::from(map);
+        let mut t = Template::default();
+        t.parse(r#"{{ range $k, $v := . }}{{ $k }}{{ $v }}{{ end }}"#)
+            .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "B5a2b1c4d0e3");
+
+        t.exec_options.sort_map_keys = false;
+        let out = t.render(&data).unwrap();
+        assert_eq!(to_sorted_string(out.into_bytes()), "012345Babcde");
+    }
+
+    #[test]
+    fn test_range_ordered_map() {
+        let data = Context::from(crate::ordered_map(vec![("d", 0), ("b", 1), ("e", 2)]));
+        let mut t = Template::default();
+        t.parse(
+            "{{ range $k, $v := . }}{{ $k }}{{ $v }}{{ end }}|{{ len . }}|\
+             {{ range (set . \"a\" 3) }}{{ . }}{{ end }}|\
+             {{ range (omit . \"d\" \"b\" \"e\") }}x{{ else }}empty{{ end }}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "d0b1e2|3|0123|empty");
+
+        // The order does not depend on sorting.
+        t.exec_options.sort_map_keys = false;
+        assert_eq!(t.render(&data).unwrap(), "d0b1e2|3|0123|empty");
+    }
+
     #[test]
     fn test_proper_range() {
         let vec = vec!["a".to_string(), "b".to_string()];
@@ -950,4 +2992,429 @@ mod tests_mocked {
         assert!(out.is_ok());
         assert_eq!(String::from_utf8(w).unwrap(), "bar");
     }
+
+    #[test]
+    fn test_variable_scopes() {
+        let render = |text: &str| {
+            let mut w: Vec<u8> = vec![];
+            let mut t = Template::default();
+            t.parse(text).unwrap();
+            t.execute(&mut w, &Context::from(vec![1, 2])).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+        assert_eq!(
+            render("{{ $x := 1 }}{{ if true }}{{ $x := 2 }}{{ $x }}{{ end }}{{ $x }}"),
+            "21"
+        );
+        assert_eq!(
+            render("{{ $x := 1 }}{{ range . }}{{ $x = . }}{{ end }}{{ $x }}"),
+            "2"
+        );
+        assert_eq!(
+            render("{{ with $x := 3 }}{{ $x = 4 }}{{ end }}{{ range $i, $e := . }}{{ len $ }}{{ end }}"),
+            "22"
+        );
+        assert_eq!(
+            render("{{ $i := 0 }}{{ range $i = . }}{{ end }}{{ $i }}"),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut t = Template::default();
+        let text = r#"{{ define "item" }}<{{ . }}>{{ end }}{{ range . }}{{ template "item" . }}{{ end }}!"#;
+        t.parse(text).unwrap();
+        let (output, map) = t
+            .render_with_source_map(&Context::from(vec![1, 22]))
+            .unwrap();
+        assert_eq!(output, "<1><22>!");
+        let item = |m: &crate::Mapping| (m.output.clone(), m.template.clone(), m.span.clone());
+        let action = text.find("{{ . }}").unwrap();
+        assert_eq!(
+            item(map.lookup(4).unwrap()),
+            (4..6, String::from("item"), action..action + 7)
+        );
+        assert_eq!(map.lookup(6).unwrap().span, action + 7..action + 8);
+        assert_eq!(map.lookup(7).unwrap().template, "");
+        assert_eq!(map.mappings().len(), 7);
+        assert!(map.lookup(8).is_none());
+    }
+
+    #[test]
+    fn test_execute_streams_output() {
+        struct Chunks(Vec<String>);
+
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(String::from_utf8_lossy(buf).into_owned());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut t = Template::default();
+        t.parse("a{{ . }}b{{ .x }}c").unwrap();
+        let mut w = Chunks(vec![]);
+        assert!(t.execute(&mut w, &Context::from(1)).is_err());
+        assert_eq!(w.0, vec!["a", "1", "b"]);
+    }
+
+    #[test]
+    fn test_render_to() {
+        use std::fmt::Write as _;
+
+        let mut t = Template::default();
+        t.parse("{{ len . }}, ä{{ range . }}{{ . }}{{ end }}")
+            .unwrap();
+        let mut out = String::new();
+        write!(out, "{}: ", 1).unwrap();
+        t.render_to(&mut out, &Context::from(vec!["ö", "ü"]))
+            .unwrap();
+        assert_eq!(out, "1: 2, äöü");
+    }
+
+    #[test]
+    fn test_print_composite() {
+        #[derive(Gtmpl)]
+        struct Foo {
+            name: String,
+            tags: Vec<u8>,
+        }
+        let mut m = HashMap::new();
+        m.insert(
+            "b".to_owned(),
+            Value::from(vec![Value::from(2.5), Value::Nil]),
+        );
+        m.insert("a".to_owned(), Value::from("x"));
+        m.insert(
+            "foo".to_owned(),
+            Value::from(Foo {
+                name: "y".to_owned(),
+                tags: vec![1, 2],
+            }),
+        );
+        let mut t = Template::default();
+        t.parse("{{ . }} {{ .foo }} {{ .b }}").unwrap();
+        assert_eq!(
+            t.render(&Context::from(m)).unwrap(),
+            "map[a:x b:[2.5 <nil>] foo:{name:y tags:[1 2]}] {name:y tags:[1 2]} [2.5 <nil>]"
+        );
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), 1);
+        let data = Context::from(m);
+        let mut t = Template::default();
+        t.parse("{{ .a }} {{ .b }} {{ if .b }}b{{ else }}no b{{ end }}")
+            .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "1 <no value> no b");
+
+        t.exec_options.missing_key = MissingKey::Zero;
+        assert_eq!(t.render(&data).unwrap(), "1 <no value> no b");
+
+        t.exec_options.missing_key = MissingKey::Error;
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::MissingKey(ref key)) if key == "b"
+        ));
+    }
+
+    #[test]
+    fn test_render_with_metrics() {
+        let mut t = Template::default();
+        t.parse("{{ range . }}{{ if (len (print .)) }}{{ . }}{{ end }}{{ end }}")
+            .unwrap();
+        let (output, metrics) = t.render_with_metrics(&Context::from(vec![10, 200]));
+        assert_eq!(output.unwrap(), "10200");
+        assert_eq!(
+            metrics,
+            Metrics {
+                nodes: 6,
+                calls: 4,
+                bytes_written: 5,
+                templates: 0,
+                range_iterations: 2,
+                max_depth: 2,
+            }
+        );
+
+        t.parse("{{ range . }}{{ . }}{{ len . }}{{ end }}").unwrap();
+        let (output, metrics) = t.render_with_metrics(&Context::from(vec![10, 200]));
+        assert!(output.is_err());
+        assert_eq!((metrics.bytes_written, metrics.calls), (2, 1));
+    }
+
+    #[test]
+    fn test_pure_funcs() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn count(args: &[Value]) -> Result<Value, FuncError> {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(Value::from(args.len()))
+        }
+
+        let mut t = Template::default();
+        t.add_pure_func("count", count);
+        t.parse("{{ range . }}{{ count . }}{{ count . 1 }}{{ count $ }}{{ end }}")
+            .unwrap();
+        let data = Context::from(vec![
+            Value::from(1),
+            Value::from("1"),
+            Value::from(1),
+            Value::from(1.5),
+        ]);
+        assert_eq!(t.render(&data).unwrap(), "121121121121");
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 7);
+        t.render(&data).unwrap();
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 7);
+
+        t.add_func("count", count);
+        t.render(&data).unwrap();
+        assert_eq!(CALLS.swap(0, Ordering::SeqCst), 12);
+    }
+
+    #[test]
+    fn test_func_resolver() {
+        fn plugin(args: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(format!("plugin{}", args.len())))
+        }
+
+        let mut t = Template::default();
+        assert!(t.parse("{{ plugin 1 }}").is_err());
+
+        t.set_func_resolver(|name| match name {
+            "plugin" | "len" => Some(plugin as Func),
+            _ => None,
+        });
+        t.parse("{{ plugin 1 }} {{ len . }}").unwrap();
+        let data = Context::from(vec![1, 2]);
+        assert_eq!(t.render(&data).unwrap(), "plugin1 2");
+
+        t.parse("{{ if . }}{{ missing }}{{ end }}").unwrap();
+        assert_eq!(t.render(&Context::from(false)).unwrap(), "");
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::UndefinedFunction(ref name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_hash_value() {
+        let hash = |val: &Value| {
+            let mut hasher = DefaultHasher::new();
+            hash_value(val, &mut hasher);
+            hasher.finish()
+        };
+        let map = |entries: &[(&str, i32)]| {
+            let mut m = HashMap::new();
+            for &(k, v) in entries {
+                m.insert(k.to_owned(), Value::from(v));
+            }
+            Value::Map(m)
+        };
+        let (a, b) = (map(&[("a", 1), ("b", 2)]), map(&[("b", 2), ("a", 1)]));
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        assert_ne!(hash(&a), hash(&map(&[("a", 2), ("b", 1)])));
+        assert_ne!(hash(&Value::from("1")), hash(&Value::from(1)));
+    }
+
+    #[test]
+    fn test_nil_values() {
+        let mut m = HashMap::new();
+        m.insert("n".to_owned(), Value::Nil);
+        let data = Context::from(m);
+        let mut t = Template::default();
+        t.parse(
+            "{{ .x.y }} {{ range .x }}r{{ else }}e{{ end }} {{ range .n }}r{{ else }}e{{ end }}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&data).unwrap(), "<no value> e e");
+        assert_eq!(t.render(&Context::empty()).unwrap(), "<no value> e e");
+
+        let mut t = Template::default();
+        t.parse("{{ .n.y }}").unwrap();
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::NilPointerEvaluating(ref field)) if field == "y"
+        ));
+
+        let mut t = Template::default();
+        t.exec_options.missing_key = MissingKey::Zero;
+        t.parse("{{ .x.y }}").unwrap();
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::NilPointerEvaluating(_))
+        ));
+
+        let mut t = Template::default();
+        t.exec_options.missing_key = MissingKey::Error;
+        t.parse("{{ .y }}").unwrap();
+        assert!(matches!(
+            t.render(&Context::empty()),
+            Err(ExecError::NilData(ref key)) if key == "y"
+        ));
+
+        let mut t = Template::default();
+        t.parse(
+            r#"{{ printf "%v" nil }} {{ default "x" nil }} {{ coalesce nil 1 }} {{ list nil }} {{ dict "b" nil }}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            t.render(&Context::empty()).unwrap(),
+            "<nil> x 1 [<nil>] map[b:<nil>]"
+        );
+        let mut t = Template::default();
+        t.parse("{{ nil }}").unwrap();
+        assert!(t.render(&Context::empty()).is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut t = Template::default();
+        t.parse(r#"{{ define "a" }}{{ template "a" . }}{{ end }}{{ template "a" . }}"#)
+            .unwrap();
+        assert!(matches!(
+            t.render(&Context::empty()),
+            Err(ExecError::MaxDepthExceeded(100_000))
+        ));
+
+        let mut t = Template::default();
+        t.exec_options.max_depth = 2;
+        t.parse(r#"{{ define "a" }}{{ ((.)) }}{{ end }}{{ template "a" 1 }}"#)
+            .unwrap();
+        assert!(matches!(
+            t.render(&Context::empty()),
+            Err(ExecError::MaxDepthExceeded(2))
+        ));
+        t.exec_options.max_depth = 3;
+        assert_eq!(t.render(&Context::empty()).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_deep_templates_on_small_stack() {
+        let handle = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let mut t = Template::default();
+                t.parse(
+                    r#"{{ define "a" }}{{ range . }}{{ with . }}{{ template "a" $ }}{{ end }}{{ end }}{{ end }}{{ template "a" . }}"#,
+                )
+                .unwrap();
+                t.render(&Context::from(vec![1]))
+            })
+            .unwrap();
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(ExecError::MaxDepthExceeded(100_000))
+        ));
+    }
+
+    #[test]
+    fn test_deep_includes_on_small_stack() {
+        let handle = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let mut t = Template::default();
+                t.parse(r#"{{ define "a" }}{{ include "a" . }}{{ end }}{{ include "a" . }}"#)
+                    .unwrap();
+                t.render(&Context::from(1))
+            })
+            .unwrap();
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(ExecError::MaxDepthExceeded(100_000))
+        ));
+    }
+
+    #[test]
+    fn test_cancel() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        fn slow(args: &[Value]) -> Result<Value, FuncError> {
+            thread::sleep(Duration::from_millis(10));
+            Ok(args[0].clone())
+        }
+
+        let data = Context::from((0..1000).collect::<Vec<_>>());
+        let mut t = Template::default();
+        t.add_func("slow", slow);
+        t.parse("{{ range . }}{{ slow . }}{{ end }}").unwrap();
+        t.exec_options.timeout = Some(Duration::from_millis(50));
+        assert!(matches!(t.render(&data), Err(ExecError::Cancelled)));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        t.exec_options.timeout = None;
+        t.exec_options.cancel = Some(cancel.clone());
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert!(matches!(t.render(&data), Err(ExecError::Cancelled)));
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn test_max_output_size() {
+        let mut t = Template::default();
+        t.parse(r#"{{ define "a" }}{{ . }}{{ end }}ab{{ template "a" "cd" }}{{ "ef" }}"#)
+            .unwrap();
+        t.exec_options.max_output_size = 6;
+        assert_eq!(t.render(&Context::empty()).unwrap(), "abcdef");
+
+        t.exec_options.max_output_size = 5;
+        let mut w: Vec<u8> = vec![];
+        assert!(matches!(
+            t.execute(&mut w, &Context::empty()),
+            Err(ExecError::OutputTooLarge(5))
+        ));
+        assert_eq!(String::from_utf8(w).unwrap(), "abcd");
+    }
+
+    #[test]
+    fn test_max_range_iterations() {
+        let data = Context::from(vec![1, 2, 3]);
+        let mut t = Template::default();
+        t.parse("{{ range . }}{{ range $ }}.{{ end }}{{ end }}")
+            .unwrap();
+        t.exec_options.max_range_iterations = 12;
+        assert_eq!(t.render(&data).unwrap(), ".........");
+
+        t.exec_options.max_range_iterations = 11;
+        assert!(matches!(
+            t.render(&data),
+            Err(ExecError::TooManyIterations(11))
+        ));
+    }
+
+    #[test]
+    fn test_and_or_short_circuit() {
+        let mut user = HashMap::new();
+        user.insert("name".to_owned(), "foo".to_owned());
+        let mut with_user = HashMap::new();
+        with_user.insert("user".to_owned(), Value::from(user));
+        let mut t = Template::default();
+        t.parse(
+            "{{ if and .user .user.name }}{{ .user.name }}{{ else }}none{{ end }} \
+             {{ or .user.name .user }} {{ and 1 0 .user.name }} {{ 0 | or false }}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&Context::from(with_user)).unwrap(), "foo foo 0 0");
+
+        let mut t = Template::default();
+        t.parse("{{ if and .user .user.name }}{{ .user.name }}{{ else }}none{{ end }}")
+            .unwrap();
+        let without_user: HashMap<String, Value> = HashMap::new();
+        assert_eq!(t.render(&Context::from(without_user)).unwrap(), "none");
+    }
 }
@@ -0,0 +1,581 @@
+//! Execution into a `tokio::io::AsyncWrite`, with support for async template functions.
+//!
+//! Nodes are executed with the steps of the synchronous executor, so both share control flow,
+//! scopes, output and what is recorded besides it. Pipelines are evaluated by an async path
+//! which awaits async functions and hands everything else to the synchronous executor.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Poll};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+use super::{template_name, Context, Recorders, State, Step, RED_ZONE, STACK_SIZE};
+use crate::error::ExecError;
+use crate::node::*;
+use crate::template::Template;
+use crate::utils::is_true;
+
+use gtmpl_value::{FuncError, Value};
+
+/// The future returned by an [`AsyncFunc`].
+pub type AsyncFuncResult = Pin<Box<dyn Future<Output = Result<Value, FuncError>> + Send>>;
+
+/// An async template function. Async functions can only be called by `execute_async`.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{AsyncFuncResult, Value};
+///
+/// fn secret(args: Vec<Value>) -> AsyncFuncResult {
+///     Box::pin(async move { Ok(Value::from(format!("secret of {}", args[0]))) })
+/// }
+/// ```
+pub type AsyncFunc = fn(Vec<Value>) -> AsyncFuncResult;
+
+type BoxFuture<'f, T> = Pin<Box<dyn Future<Output = T> + 'f>>;
+
+// Polling nested futures recurses like nested calls do, so nested pipelines are polled on a
+// grown stack when needed.
+struct GrowStack<'f, T>(BoxFuture<'f, T>);
+
+impl<T> Future for GrowStack<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<T> {
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || self.0.as_mut().poll(cx))
+    }
+}
+
+// The future of an async function, which is polled with the clock, the root directory, the
+// network limits and the message catalog of the execution, like sync functions are called. So
+// the function sees them while it runs, not only when the future is created.
+struct Scoped<'s, 'a, 'b> {
+    state: &'s State<'a, 'b, Vec<u8>>,
+    future: AsyncFuncResult,
+}
+
+impl Future for Scoped<'_, '_, '_> {
+    type Output = Result<Value, FuncError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.state.scoped(|| this.future.as_mut().poll(cx))
+    }
+}
+
+impl Template {
+    /// Executes the template with `data` and writes the output to `writer`. Unlike `execute`
+    /// this can call the functions added with `add_async_func`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{AsyncFuncResult, Context, Template, Value};
+    ///
+    /// fn greeting(args: Vec<Value>) -> AsyncFuncResult {
+    ///     Box::pin(async move { Ok(Value::from(format!("Hello {}!", args[0]))) })
+    /// }
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_async_func("greeting", greeting);
+    /// tmpl.parse("{{ greeting . }}").unwrap();
+    /// let mut out = vec![];
+    /// let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    /// rt.block_on(tmpl.execute_async(&mut out, &Context::from("World")))
+    ///     .unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "Hello World!");
+    /// ```
+    pub async fn execute_async<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        data: &Context,
+    ) -> Result<(), ExecError> {
+        self.execute_async_mapped(writer, data, Recorders::default())
+            .await
+    }
+
+    // Like `execute_mapped`, but for `execute_async`.
+    async fn execute_async_mapped<'b, W: AsyncWrite + Unpin>(
+        &self,
+        writer: &'b mut W,
+        data: &Context,
+        recorders: Recorders<'b>,
+    ) -> Result<(), ExecError> {
+        let (tree_name, root) = self.root(&self.name)?;
+        let mut state = AsyncState {
+            state: State {
+                source_map: recorders.source_map,
+                profile: recorders.profile,
+                recovery: recorders.recovery,
+                ..State::new(self, vec![], tree_name, data)
+            },
+            writer,
+            #[cfg(feature = "tracing")]
+            spans: vec![],
+        };
+        let walk = state.walk(data, root);
+        #[cfg(feature = "tracing")]
+        let walk = walk.instrument(tracing::info_span!("execute", template = %self.name));
+        let result = walk.await;
+        if let Some(metrics) = recorders.metrics {
+            *metrics = state.state.metrics();
+        }
+        result
+    }
+}
+
+// Wraps the synchronous state, which writes the output of each step to a buffer, and writes
+// the buffer to an async writer after the step.
+struct AsyncState<'a, 'b, W> {
+    state: State<'a, 'b, Vec<u8>>,
+    writer: &'b mut W,
+    // The spans of the templates in progress, the innermost last. They are only entered while
+    // taking a step, so they are not entered while the execution is suspended.
+    #[cfg(feature = "tracing")]
+    spans: Vec<tracing::Span>,
+}
+
+impl<'a, 'b, W: AsyncWrite + Unpin> AsyncState<'a, 'b, W> {
+    // Executes `root` with `ctx` as dot like `State::walk`, but enters nodes with `enter`,
+    // which awaits their pipelines.
+    async fn walk(&mut self, ctx: &Context, root: &'a Nodes) -> Result<(), ExecError> {
+        let mut steps = vec![Step::Walk(root, None)];
+        let mut result = Ok(());
+        while let Some(step) = steps.pop() {
+            #[cfg(feature = "tracing")]
+            let span = self
+                .spans
+                .last()
+                .cloned()
+                .unwrap_or_else(tracing::Span::none);
+            result = match (step, result) {
+                (Step::Walk(node, dot), Ok(())) => {
+                    let node_ctx = dot.as_deref().unwrap_or(ctx);
+                    let enter = self.enter(node_ctx, &dot, node, &mut steps);
+                    #[cfg(feature = "tracing")]
+                    let enter = enter.instrument(span);
+                    enter.await
+                }
+                (step, result) => {
+                    #[cfg(feature = "tracing")]
+                    let _span = span.entered();
+                    #[cfg(feature = "tracing")]
+                    if let Step::Return(_) = step {
+                        self.spans.pop();
+                    }
+                    self.state.step(ctx, step, result, &mut steps)
+                }
+            };
+            self.flush().await?;
+        }
+        result
+    }
+
+    // Starts executing `node` like `State::enter`, but awaits its pipelines.
+    async fn enter(
+        &mut self,
+        ctx: &Context,
+        dot: &Option<Arc<Context>>,
+        node: &'a Nodes,
+        steps: &mut Vec<Step<'a>>,
+    ) -> Result<(), ExecError> {
+        self.state.begin(node, steps)?;
+        match *node {
+            Nodes::Action(ref n) => {
+                let val = self.eval_pipeline(ctx, &n.pipe).await?;
+                self.state.print_action(node, n, val)
+            }
+            Nodes::If(ref n) | Nodes::With(ref n) => {
+                self.state.enter_scope(steps);
+                let val = self.eval_pipeline(ctx, &n.pipe).await?;
+                self.state.branch(node, n, val, dot, steps)
+            }
+            Nodes::Range(ref n) => {
+                self.state.enter_scope(steps);
+                let stream = ctx.streams.get(&n.pipe);
+                let val = match stream {
+                    Some(_) => Value::NoValue,
+                    None => self.eval_pipeline(ctx, &n.pipe).await?,
+                };
+                self.state.start_range(n, stream, val, dot, steps)
+            }
+            Nodes::Template(ref n) => {
+                let name = match n.name {
+                    PipeOrString::String(ref name) => name.to_owned(),
+                    PipeOrString::Pipe(ref pipe) => {
+                        template_name(self.eval_pipeline(ctx, pipe).await?)?
+                    }
+                };
+                let (tree_name, root) = match self.state.lookup_template(n, name)? {
+                    Some(found) => found,
+                    None => return Ok(()),
+                };
+                let value = match n.pipe {
+                    Some(ref pipe) => self.eval_pipeline(ctx, pipe).await?,
+                    None => Value::NoValue,
+                };
+                self.state.enter_template(n, tree_name, root, value, steps);
+                #[cfg(feature = "tracing")]
+                self.suspend(steps);
+                Ok(())
+            }
+            _ => self.state.enter_list(node, dot, steps),
+        }
+    }
+
+    // Exits the span of the template just entered, which is entered again for each of its
+    // steps.
+    #[cfg(feature = "tracing")]
+    fn suspend(&mut self, steps: &mut [Step<'a>]) {
+        if let Some(Step::Return(ret)) = steps.iter_mut().rev().nth(1) {
+            let span = std::mem::replace(&mut ret.span, tracing::Span::none().entered());
+            self.spans.push(span.exit());
+        }
+    }
+
+    // Writes the output of the last step.
+    async fn flush(&mut self) -> Result<(), ExecError> {
+        if !self.state.writer.is_empty() {
+            self.writer
+                .write_all(&self.state.writer)
+                .await
+                .map_err(ExecError::IOError)?;
+            self.state.writer.clear();
+        }
+        Ok(())
+    }
+
+    fn eval_pipeline<'f>(
+        &'f mut self,
+        ctx: &'f Context,
+        pipe: &'f PipeNode,
+    ) -> BoxFuture<'f, Result<Value, ExecError>> {
+        Box::pin(async move {
+            let mut val: Option<Value> = None;
+            for cmd in &pipe.cmds {
+                val = Some(self.eval_command(ctx, cmd, val).await?);
+            }
+            let val = val.ok_or_else(|| ExecError::ErrorEvaluatingPipe(pipe.clone()))?;
+            self.state.assign_decls(pipe, &val)?;
+            Ok(val)
+        })
+    }
+
+    async fn eval_nested_pipeline(
+        &mut self,
+        ctx: &Context,
+        pipe: &PipeNode,
+    ) -> Result<Value, ExecError> {
+        self.state.check_depth()?;
+        self.state.descend();
+        let val = GrowStack(self.eval_pipeline(ctx, pipe)).await;
+        self.state.depth -= 1;
+        val
+    }
+
+    async fn eval_command(
+        &mut self,
+        ctx: &Context,
+        cmd: &CommandNode,
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let first_word = cmd
+            .args
+            .first()
+            .ok_or_else(|| ExecError::NoArgsForCommandNode(cmd.clone()))?;
+        match *first_word {
+            Nodes::Identifier(ref ident) => self.eval_function(ctx, ident, &cmd.args, fin).await,
+            Nodes::Pipe(ref pipe) => self.eval_nested_pipeline(ctx, pipe).await,
+            Nodes::Chain(ref chain) => self.eval_chain_node(ctx, chain, &cmd.args, fin).await,
+            _ => self.state.eval_command(ctx, cmd, &fin),
+        }
+    }
+
+    fn eval_arg<'f>(
+        &'f mut self,
+        ctx: &'f Context,
+        node: &'f Nodes,
+    ) -> BoxFuture<'f, Result<Value, ExecError>> {
+        Box::pin(async move {
+            match *node {
+                Nodes::Pipe(ref pipe) => self.eval_nested_pipeline(ctx, pipe).await,
+                Nodes::Identifier(ref ident) => self.eval_function(ctx, ident, &[], None).await,
+                Nodes::Chain(ref chain) => self.eval_chain_node(ctx, chain, &[], None).await,
+                _ => self.state.eval_arg(ctx, node),
+            }
+        })
+    }
+
+    fn eval_function<'f>(
+        &'f mut self,
+        ctx: &'f Context,
+        ident: &'f IdentifierNode,
+        args: &'f [Nodes],
+        fin: Option<Value>,
+    ) -> BoxFuture<'f, Result<Value, ExecError>> {
+        Box::pin(async move {
+            let name = ident.ident.as_str();
+            if name == "and" || name == "or" {
+                return self.eval_and_or(ctx, name, args, fin).await;
+            }
+            let mut arg_vals = vec![];
+            for arg in args.iter().skip(1) {
+                arg_vals.push(self.eval_arg(ctx, arg).await?);
+            }
+            arg_vals.extend(fin);
+            // Included templates are executed synchronously, so they cannot call async
+            // functions.
+            match name {
+                "include" => return self.state.include(ident.pos(), &arg_vals),
+                "tpl" => return self.state.tpl(ident.pos(), &arg_vals),
+                _ => {}
+            }
+            let template = self.state.template;
+            let function = match template.async_funcs.get(name) {
+                Some(function) => function,
+                None => {
+                    let function = template
+                        .func(name, arg_vals.len())
+                        .ok_or_else(|| ExecError::UndefinedFunction(name.to_owned()))?;
+                    let val = self.state.call(ident.pos(), name, &*function, &arg_vals)?;
+                    self.state.write_back(name, args, &val)?;
+                    return Ok(val);
+                }
+            };
+            self.state.audit(ident.pos(), name, &arg_vals);
+            self.state.before_call(name, &arg_vals);
+            let call = Scoped {
+                state: &self.state,
+                future: self.state.scoped(|| function(arg_vals.clone())),
+            };
+            #[cfg(feature = "tracing")]
+            let call = call.instrument(self.state.call_span(ident.pos(), name));
+            let mut result = call.await;
+            self.state.after_call(name, &arg_vals, &mut result);
+            result.map_err(|err| self.state.call_error(ident.pos(), name, err))
+        })
+    }
+
+    async fn eval_and_or(
+        &mut self,
+        ctx: &Context,
+        name: &str,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        let mut val = None;
+        for arg in args.iter().skip(1) {
+            let v = self.eval_arg(ctx, arg).await?;
+            if is_true(&v) == (name == "or") {
+                return Ok(v);
+            }
+            val = Some(v);
+        }
+        fin.or(val)
+            .ok_or_else(|| FuncError::AtLeastXArgs(name.to_owned(), 1).into())
+    }
+
+    async fn eval_chain_node(
+        &mut self,
+        ctx: &Context,
+        chain: &ChainNode,
+        args: &[Nodes],
+        fin: Option<Value>,
+    ) -> Result<Value, ExecError> {
+        if chain.field.is_empty() {
+            return Err(ExecError::NoFieldsInEvalChainNode);
+        }
+        if let Nodes::Nil(_) = *chain.node {
+            return Err(ExecError::NullInChain(chain.clone()));
+        }
+        let receiver = self.eval_arg(ctx, &chain.node).await?;
+        self.state
+            .eval_field_chain(ctx, chain.pos(), &receiver, &chain.field, args, &fin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shout(args: Vec<Value>) -> AsyncFuncResult {
+        Box::pin(async move {
+            match args.first() {
+                Some(Value::String(s)) => Ok(Value::from(s.to_uppercase())),
+                _ => Err(FuncError::AtLeastXArgs("shout".to_owned(), 1)),
+            }
+        })
+    }
+
+    fn run(tmpl: &Template, data: &Context) -> Result<String, ExecError> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut out = vec![];
+        rt.block_on(tmpl.execute_async(&mut out, data))?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_execute_async() {
+        let mut t = Template::default();
+        t.add_async_func("shout", shout);
+        t.parse(
+            r#"{{ define "item" }}<{{ shout . }}>{{ end -}}
+            {{ range $i, $s := . }}{{ $i }}{{ template "item" $s }}{{ end }}
+            {{- with $x := (shout "a" | printf "%s-%s" "b") }} {{ $x }}{{ end }}"#,
+        )
+        .unwrap();
+        let data = Context::from(vec!["x", "y"]);
+        assert_eq!(run(&t, &data).unwrap(), "0<X>1<Y> b-A");
+    }
+
+    #[test]
+    fn test_async_func_requires_execute_async() {
+        let mut t = Template::default();
+        t.add_async_func("shout", shout);
+        t.parse(r#"{{ shout "a" }}"#).unwrap();
+        assert!(matches!(
+            t.render(&Context::empty()),
+            Err(ExecError::UndefinedFunction(ref name)) if name == "shout"
+        ));
+        assert_eq!(run(&t, &Context::empty()).unwrap(), "A");
+    }
+
+    #[test]
+    fn test_range_function() {
+        fn items(args: &[Value]) -> Result<Value, FuncError> {
+            match gtmpl_value::from_value::<u64>(&args[0]) {
+                Some(i) if i < 2 => Ok(Value::from(format!("item{}", i))),
+                _ => Ok(Value::NoValue),
+            }
+        }
+
+        let mut t = Template::default();
+        t.add_async_func("shout", shout);
+        t.parse("{{ range . }}{{ shout . }}{{ else }}none{{ end }}")
+            .unwrap();
+        let data = Context::from(Value::from(items as gtmpl_value::Func));
+        assert_eq!(run(&t, &data).unwrap(), "ITEM0ITEM1");
+        let data = Context::from(Vec::<Value>::new());
+        assert_eq!(run(&t, &data).unwrap(), "none");
+
+        t.parse("{{ range .rows }}{{ shout . }}{{ else }}none{{ end }}")
+            .unwrap();
+        let prefix = String::from("row");
+        let rows = (0..2).map(move |i| Value::from(format!("{}{}", prefix, i)));
+        let data = Context::empty().with_iter("rows", rows);
+        assert_eq!(run(&t, &data).unwrap(), "ROW0ROW1");
+        let data = Context::empty().with_iter("rows", std::iter::empty());
+        assert_eq!(run(&t, &data).unwrap(), "none");
+
+        t.parse("{{ range $k, $v := . }}{{ shout $k }}{{ $v }}{{ end }}")
+            .unwrap();
+        let data = Context::from(crate::ordered_map(vec![("b", 1), ("a", 2)]));
+        assert_eq!(run(&t, &data).unwrap(), "B1A2");
+    }
+
+    #[test]
+    fn test_deterministic() {
+        use crate::clock::{self, Deterministic};
+        use std::time::{Duration, UNIX_EPOCH};
+
+        fn epoch(_: Vec<Value>) -> AsyncFuncResult {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                let secs = clock::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                Ok(Value::from(secs))
+            })
+        }
+
+        let mut t = Template::default();
+        t.add_async_func("epoch", epoch);
+        t.exec_options.deterministic = Some(Deterministic {
+            now: UNIX_EPOCH + Duration::from_secs(42),
+            seed: 1,
+        });
+        t.parse("{{ epoch }}").unwrap();
+        assert_eq!(run(&t, &Context::empty()).unwrap(), "42");
+    }
+
+    #[cfg(feature = "files")]
+    #[test]
+    fn test_file_root() {
+        fn load(args: &[Value]) -> Result<Value, FuncError> {
+            crate::funcs::read_file(args)
+        }
+
+        fn load_async(args: Vec<Value>) -> AsyncFuncResult {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                crate::funcs::read_file(&args)
+            })
+        }
+
+        let root = std::env::temp_dir().join("gtmpl-async-file-root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        let mut t = Template::default();
+        t.add_file_funcs(&root);
+        t.add_func("load", load);
+        t.add_async_func("load", load_async);
+        t.parse(r#"{{ load "a.txt" }}"#).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), "a");
+        assert_eq!(run(&t, &Context::empty()).unwrap(), "a");
+
+        t.parse(r#"{{ load "../a.txt" }}"#).unwrap();
+        let sync_err = t.render(&Context::empty()).unwrap_err().to_string();
+        let async_err = run(&t, &Context::empty()).unwrap_err().to_string();
+        assert!(sync_err.ends_with("readFile: ../a.txt is outside of the root directory"));
+        assert_eq!(sync_err, async_err);
+    }
+
+    #[test]
+    fn test_recorders() {
+        use crate::exec::Recovery;
+        use crate::SourceMap;
+
+        let mut t = Template::default();
+        t.add_async_func("shout", shout);
+        t.parse("{{ $x := 0 }}{{ with $x := 1 }}{{ shout 1 }}{{ end }}{{ $x }}")
+            .unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut out = vec![];
+        let mut errors = vec![];
+        let mut source_map = SourceMap::default();
+        let recorders = Recorders {
+            recovery: Some(Recovery {
+                placeholder: "?",
+                errors: &mut errors,
+            }),
+            source_map: Some(&mut source_map),
+            ..Default::default()
+        };
+        let data = Context::empty();
+        rt.block_on(t.execute_async_mapped(&mut out, &data, recorders))
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "?0");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(source_map.mappings().len(), 2);
+
+        let (output, errors) = t.render_lenient(&data, "?");
+        assert_eq!((output.as_str(), errors.len()), ("?0", 1));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut t = Template::default();
+        t.parse(r#"{{ define "a" }}{{ template "a" . }}{{ end }}{{ template "a" . }}"#)
+            .unwrap();
+        assert!(matches!(
+            run(&t, &Context::empty()),
+            Err(ExecError::MaxDepthExceeded(100_000))
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use std::mem;
+
+use bytes::Bytes;
+
+use super::{Context, State, Step};
+use crate::error::ExecError;
+use crate::template::Template;
+
+// Output is collected until a chunk has at least this many bytes.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Iterator over the output of a template in chunks, returned by
+/// [`Template::render_iter`](crate::Template::render_iter).
+///
+/// The template is only executed as far as needed for the next chunk. After an error, the
+/// iterator ends.
+pub struct Chunks<'a> {
+    state: State<'a, 'a, Vec<u8>>,
+    data: &'a Context,
+    steps: Vec<Step<'a>>,
+    result: Result<(), ExecError>,
+    // The span of the execution and those of the invoked templates, which are only entered
+    // while producing a chunk.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    suspended: Vec<tracing::Span>,
+}
+
+impl Template {
+    /// Renders the template with `data` in chunks of output, e.g. for streaming HTTP response
+    /// bodies. Chunks are produced on demand, so the output is never held in memory as a whole
+    /// and a slow consumer slows down the execution.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ range . }}{{ . }},{{ end }}").unwrap();
+    /// let data = Context::from(vec![1, 2, 3]);
+    /// let body: Vec<u8> = tmpl
+    ///     .render_iter(&data)
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap()
+    ///     .concat();
+    /// assert_eq!(body, b"1,2,3,");
+    /// ```
+    pub fn render_iter<'a>(&'a self, data: &'a Context) -> Chunks<'a> {
+        let (state, steps, result) = match self.root(&self.name) {
+            Ok((tree_name, root)) => (
+                State::new(self, vec![], tree_name, data),
+                vec![Step::Walk(root, None)],
+                Ok(()),
+            ),
+            Err(err) => (State::new(self, vec![], &self.name, data), vec![], Err(err)),
+        };
+        Chunks {
+            state,
+            data,
+            steps,
+            result,
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("execute", template = %self.name),
+            #[cfg(feature = "tracing")]
+            suspended: vec![],
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Chunks<'_> {
+    // Exits the spans of the templates in progress, the innermost first.
+    fn suspend(&mut self) {
+        for step in self.steps.iter_mut().rev() {
+            if let Step::Return(ref mut ret) = *step {
+                let span = std::mem::replace(&mut ret.span, tracing::Span::none().entered());
+                self.suspended.push(span.exit());
+            }
+        }
+    }
+
+    // Enters the spans of the templates in progress again, the outermost first.
+    fn resume(&mut self) {
+        let mut suspended = self.suspended.drain(..).rev();
+        for step in self.steps.iter_mut() {
+            if let Step::Return(ref mut ret) = *step {
+                if let Some(span) = suspended.next() {
+                    ret.span = span.entered();
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = Result<Bytes, ExecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "tracing")]
+        let _span = self.span.clone().entered();
+        #[cfg(feature = "tracing")]
+        self.resume();
+        while let Some(step) = self.steps.pop() {
+            let result = mem::replace(&mut self.result, Ok(()));
+            self.result = self.state.step(self.data, step, result, &mut self.steps);
+            if self.state.writer.len() >= CHUNK_SIZE {
+                #[cfg(feature = "tracing")]
+                self.suspend();
+                return Some(Ok(Bytes::from(mem::take(&mut self.state.writer))));
+            }
+        }
+        if !self.state.writer.is_empty() {
+            return Some(Ok(Bytes::from(mem::take(&mut self.state.writer))));
+        }
+        mem::replace(&mut self.result, Ok(())).err().map(Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_iter() {
+        let mut t = Template::default();
+        t.parse("{{ range . }}{{ . }}{{ end }}").unwrap();
+        let item = "x".repeat(CHUNK_SIZE / 2 + 1);
+        let data = Context::from(vec![item.clone(); 5]);
+        let chunks: Vec<Bytes> = t.render_iter(&data).map(Result::unwrap).collect();
+        let sizes: Vec<usize> = chunks.iter().map(Bytes::len).collect();
+        assert_eq!(sizes, vec![item.len() * 2, item.len() * 2, item.len()]);
+        assert_eq!(chunks.concat(), t.render(&data).unwrap().into_bytes());
+    }
+
+    #[test]
+    fn test_render_iter_error() {
+        let mut t = Template::default();
+        t.parse("a{{ len 1 }}b").unwrap();
+        let data = Context::empty();
+        let mut chunks = t.render_iter(&data);
+        assert_eq!(chunks.next().unwrap().unwrap(), Bytes::from("a"));
+        assert!(matches!(
+            chunks.next(),
+            Some(Err(ExecError::FuncCall { .. }))
+        ));
+        assert!(chunks.next().is_none());
+
+        let t = Template::default();
+        let mut chunks = t.render_iter(&data);
+        assert!(matches!(
+            chunks.next(),
+            Some(Err(ExecError::IncompleteTemplate(_)))
+        ));
+        assert!(chunks.next().is_none());
+    }
+}
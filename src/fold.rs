@@ -0,0 +1,155 @@
+//! Constant folding of parsed templates.
+use crate::node::*;
+use crate::printf::format_value;
+use crate::template::Template;
+
+impl Template {
+    /// Replaces actions which only consist of literals and pure builtin functions with the text
+    /// they render to, so they are evaluated once instead of on every execution.
+    ///
+    /// Builtins overridden by custom functions are not considered pure. Actions which fail to
+    /// evaluate are kept and report their error when the template is executed.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse(r#"{{ printf "%d-%s" 42 "foo" }} {{ . }}"#).unwrap();
+    /// tmpl.fold_constants();
+    /// let output = tmpl.render(&Context::from("bar"));
+    /// assert_eq!(&output.unwrap(), "42-foo bar");
+    /// ```
+    pub fn fold_constants(&mut self) {
+        let names: Vec<String> = self.tree_set.keys().cloned().collect();
+        for name in names {
+            let mut root = match self.tree_set.get_mut(&name).and_then(|t| t.root.take()) {
+                Some(root) => root,
+                None => continue,
+            };
+            self.fold(&mut root);
+            if let Some(tree) = self.tree_set.get_mut(&name) {
+                tree.root = Some(root);
+            }
+        }
+    }
+
+    fn fold(&self, node: &mut Nodes) {
+        match *node {
+            Nodes::List(ref mut list) => self.fold_list(list),
+            Nodes::If(ref mut n) | Nodes::With(ref mut n) | Nodes::Range(ref mut n) => {
+                self.fold_list(&mut n.list);
+                if let Some(ref mut else_list) = n.else_list {
+                    self.fold_list(else_list);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn fold_list(&self, list: &mut ListNode) {
+        for node in &mut list.nodes {
+            if let Nodes::Action(ref action) = *node {
+                if self.is_constant(&action.pipe) {
+                    if let Ok(val) = self.eval_detached(&action.pipe) {
+                        let text = TextNode::new(
+                            action.tree(),
+                            action.pos(),
+                            format_value(&val).into_owned(),
+                        );
+                        *node = Nodes::Text(text);
+                    }
+                }
+            }
+            self.fold(node);
+        }
+    }
+
+    fn is_constant(&self, pipe: &PipeNode) -> bool {
+        pipe.decl.is_empty()
+            && pipe.cmds.iter().all(|cmd| match cmd.args.first() {
+                Some(Nodes::Identifier(ref ident)) => {
+                    self.is_pure(&ident.ident) && cmd.args[1..].iter().all(|a| self.is_literal(a))
+                }
+                Some(arg) => cmd.args.len() == 1 && self.is_literal(arg),
+                None => false,
+            })
+    }
+
+    fn is_literal(&self, node: &Nodes) -> bool {
+        match *node {
+            Nodes::Bool(_) | Nodes::Number(_) | Nodes::String(_) => true,
+            Nodes::Identifier(ref ident) => self.is_pure(&ident.ident),
+            Nodes::Pipe(ref pipe) => self.is_constant(pipe),
+            _ => false,
+        }
+    }
+
+    fn is_pure(&self, name: &str) -> bool {
+        self.funcs
+            .get(name)
+            .is_some_and(|def| def.pure && def.is_builtin())
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use crate::Context;
+    use gtmpl_value::{FuncError, Value};
+
+    fn root_nodes(t: &Template) -> &[Nodes] {
+        match t.tree_set[""].root {
+            Some(Nodes::List(ref list)) => &list.nodes,
+            _ => panic!("no root"),
+        }
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let mut t = Template::default();
+        t.parse(r#"a {{ printf "%d" (len "foo") }} {{ if .x }}{{ eq 1 1 | not }}{{ end }}"#)
+            .unwrap();
+        t.fold_constants();
+        let nodes = root_nodes(&t);
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes[1].to_string(), "3");
+        assert_eq!(nodes[3].to_string(), "{{if .x}}false{{end}}");
+    }
+
+    #[test]
+    fn test_fold_constants_keeps_dynamic() {
+        fn print(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from("custom"))
+        }
+
+        let mut t = Template::default();
+        t.add_func("print", print);
+        t.parse(r#"{{ print 1 }}{{ printf "%v" . }}{{ index 1 2 }}{{ 1 }}"#)
+            .unwrap();
+        t.fold_constants();
+        let nodes = root_nodes(&t);
+        assert_eq!(nodes.len(), 4);
+        assert!(matches!(nodes[0], Nodes::Action(_)));
+        assert!(matches!(nodes[1], Nodes::Action(_)));
+        assert!(matches!(nodes[2], Nodes::Action(_)));
+        assert!(matches!(nodes[3], Nodes::Text(_)));
+        assert!(t.render(&Context::from(2)).is_err());
+    }
+
+    #[test]
+    fn test_fold_constants_composite_output() {
+        let src =
+            r#"{{ list 1 "a" }}|{{ dict "a" 1 "b" (list 2 3) }}|{{ split "," "x,y" }}|{{ seq 3 }}"#;
+        let mut t = Template::default();
+        t.parse(src).unwrap();
+        let expected = t.render(&Context::empty()).unwrap();
+        t.fold_constants();
+        assert!(root_nodes(&t)
+            .iter()
+            .all(|n| !matches!(n, Nodes::Action(_))));
+        assert_eq!(t.render(&Context::empty()).unwrap(), expected);
+        assert_eq!(expected, "[1 a]|map[a:1 b:[2 3]]|[x y]|[1 2 3]");
+    }
+}
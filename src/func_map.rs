@@ -0,0 +1,518 @@
+//! Functions templates can call, shared by parsing and execution.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::sync::{Arc, RwLock};
+
+use gtmpl_value::{Func, FuncError, Value};
+use lazy_static::lazy_static;
+
+use crate::funcs::BUILTIN_DEFS;
+
+lazy_static! {
+    static ref BUILTIN_MAP: FuncMap = BUILTIN_DEFS
+        .iter()
+        .map(|builtin| {
+            let mut def = FuncDef::new(builtin.func);
+            def.arity = Some(builtin.arity);
+            def.pure = builtin.pure;
+            def.builtin = true;
+            (builtin.name, def)
+        })
+        .collect();
+    static ref DEFAULTS: RwLock<FuncMap> = RwLock::new(BUILTIN_MAP.clone());
+}
+
+/// A template function which may capture state, e.g. a cache or the configuration of an
+/// application.
+pub type SharedFunc = Arc<dyn Fn(&[Value]) -> Result<Value, FuncError> + Send + Sync>;
+
+/// A function with what is known about it.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{FuncDef, FuncError, Value};
+///
+/// fn shout(args: &[Value]) -> Result<Value, FuncError> {
+///     Ok(Value::from(args[0].to_string().to_uppercase()))
+/// }
+///
+/// let def = FuncDef::new(shout)
+///     .with_arity(1, Some(1))
+///     .with_doc("Returns its argument in upper case.")
+///     .pure();
+/// assert_eq!(def.arity, Some((1, Some(1))));
+/// ```
+#[derive(Clone)]
+pub struct FuncDef {
+    pub func: SharedFunc,
+    /// The minimum and maximum number of arguments, including a piped value, if they are
+    /// known. `Template::validate` checks calls against them.
+    pub arity: Option<(usize, Option<usize>)>,
+    pub doc: Option<String>,
+    /// Whether the result only depends on the arguments. Results of pure custom functions are
+    /// cached during an execution and pure builtins are pre-rendered by
+    /// `Template::fold_constants`.
+    pub pure: bool,
+    /// Implementations for other numbers of arguments, see [`FuncDef::with_overload`].
+    pub overloads: Vec<FuncDef>,
+    /// Why the function is deprecated and what to use instead. Calls of deprecated functions
+    /// are reported by `Template::warnings`.
+    pub deprecated: Option<String>,
+    pub(crate) builtin: bool,
+}
+
+impl FuncDef {
+    /// Creates the definition of `func` without any metadata.
+    pub fn new(func: Func) -> FuncDef {
+        FuncDef::from_fn(func)
+    }
+
+    /// Creates the definition of a closure without any metadata. Unlike plain functions,
+    /// closures can capture state, e.g. an `Arc` shared with the application.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use std::sync::Arc;
+    ///
+    /// use gtmpl::{Context, FuncDef, FuncError, Template, Value};
+    ///
+    /// let mut prices = HashMap::new();
+    /// prices.insert("apple".to_owned(), 3);
+    /// let prices = Arc::new(prices);
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.funcs.insert(
+    ///     "price",
+    ///     FuncDef::from_fn(move |args: &[Value]| match args {
+    ///         [Value::String(item)] => Ok(Value::from(prices.get(item).copied().unwrap_or(0))),
+    ///         _ => Err(FuncError::ExactlyXArgs("price".into(), 1)),
+    ///     }),
+    /// );
+    /// tmpl.parse(r#"{{ price "apple" }}"#).unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "3");
+    /// ```
+    pub fn from_fn<F>(func: F) -> FuncDef
+    where
+        F: Fn(&[Value]) -> Result<Value, FuncError> + Send + Sync + 'static,
+    {
+        FuncDef {
+            func: Arc::new(func),
+            arity: None,
+            doc: None,
+            pure: false,
+            overloads: vec![],
+            deprecated: None,
+            builtin: false,
+        }
+    }
+
+    /// Sets the minimum and maximum number of arguments, where `None` means any number.
+    pub fn with_arity(mut self, min: usize, max: Option<usize>) -> FuncDef {
+        self.arity = Some((min, max));
+        self
+    }
+
+    /// Sets the documentation of the function.
+    pub fn with_doc<T: Into<String>>(mut self, doc: T) -> FuncDef {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    /// Marks the function as pure.
+    pub fn pure(mut self) -> FuncDef {
+        self.pure = true;
+        self
+    }
+
+    /// Marks the function as deprecated, where `note` tells what to use instead.
+    pub fn deprecated<T: Into<String>>(mut self, note: T) -> FuncDef {
+        self.deprecated = Some(note.into());
+        self
+    }
+
+    /// Adds an implementation which is called instead of `func` when the number of arguments,
+    /// including a piped value, matches the arity of `def`. Overloads are tried in the order
+    /// they were added and one without an arity accepts any number of arguments.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{from_value, Context, FuncDef, FuncError, Template, Value};
+    ///
+    /// fn round(args: &[Value]) -> Result<Value, FuncError> {
+    ///     let x: f64 = from_value(&args[0]).unwrap_or_default();
+    ///     Ok(Value::from(x.round()))
+    /// }
+    ///
+    /// fn round_to(args: &[Value]) -> Result<Value, FuncError> {
+    ///     let x: f64 = from_value(&args[0]).unwrap_or_default();
+    ///     let precision: i64 = from_value(&args[1]).unwrap_or_default();
+    ///     let factor = 10f64.powi(precision as i32);
+    ///     Ok(Value::from((x * factor).round() / factor))
+    /// }
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.funcs.insert(
+    ///     "round",
+    ///     FuncDef::new(round)
+    ///         .with_arity(1, Some(1))
+    ///         .with_overload(FuncDef::new(round_to).with_arity(2, Some(2))),
+    /// );
+    /// tmpl.parse("{{ round 2.345 }} {{ round 2.345 2 }}").unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "2 2.35");
+    /// ```
+    pub fn with_overload(mut self, def: FuncDef) -> FuncDef {
+        self.overloads.push(def);
+        self
+    }
+
+    /// The implementation called with `args` arguments.
+    pub fn func_for(&self, args: usize) -> &SharedFunc {
+        self.overloads
+            .iter()
+            .find(|def| match def.arity {
+                Some(arity) => accepts(arity, args),
+                None => true,
+            })
+            .map_or(&self.func, |def| &def.func)
+    }
+
+    /// The numbers of arguments the function and its overloads accept, or `None` if any of
+    /// them does not declare an arity.
+    pub fn arities(&self) -> Option<Vec<(usize, Option<usize>)>> {
+        std::iter::once(self)
+            .chain(&self.overloads)
+            .map(|def| def.arity)
+            .collect()
+    }
+
+    /// Whether this is one of the builtin functions.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+}
+
+impl From<Func> for FuncDef {
+    fn from(func: Func) -> FuncDef {
+        FuncDef::new(func)
+    }
+}
+
+impl fmt::Debug for FuncDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FuncDef")
+            .field("arity", &self.arity)
+            .field("doc", &self.doc)
+            .field("pure", &self.pure)
+            .field("overloads", &self.overloads)
+            .field("deprecated", &self.deprecated)
+            .field("builtin", &self.builtin)
+            .finish_non_exhaustive()
+    }
+}
+
+// Whether `args` is within the `(min, max)` arity.
+pub(crate) fn accepts((min, max): (usize, Option<usize>), args: usize) -> bool {
+    args >= min
+        && match max {
+            Some(max) => args <= max,
+            None => true,
+        }
+}
+
+/// The functions a template can call by name. Parsing accepts exactly the functions of the
+/// map and execution calls them, so both always agree.
+///
+/// Clones share the functions until one of them is changed.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, FuncDef, FuncError, FuncMap, Template, Value};
+///
+/// fn greet(args: &[Value]) -> Result<Value, FuncError> {
+///     Ok(Value::from(format!("Hello {}!", args[0])))
+/// }
+///
+/// let funcs = FuncMap::new()
+///     .with("greet", FuncDef::new(greet).with_arity(1, Some(1)));
+/// assert_eq!(funcs.get("greet").unwrap().arity, Some((1, Some(1))));
+///
+/// let mut tmpl = Template::default();
+/// tmpl.funcs.merge(&funcs);
+/// tmpl.parse("{{ greet . }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::from("World")).unwrap(), "Hello World!");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FuncMap {
+    funcs: Arc<HashMap<String, FuncDef>>,
+}
+
+impl FuncMap {
+    /// Creates an empty map.
+    pub fn new() -> FuncMap {
+        FuncMap::default()
+    }
+
+    /// Creates a map of the builtin functions, which every template starts with.
+    pub fn builtins() -> FuncMap {
+        BUILTIN_MAP.clone()
+    }
+
+    /// Returns the process-wide default functions, which templates created by
+    /// `Template::default` and `Template::with_name` start with. These are the builtins unless
+    /// they were replaced by [`FuncMap::set_defaults`].
+    pub fn defaults() -> FuncMap {
+        DEFAULTS.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replaces the process-wide default functions, e.g. to register the helpers of an
+    /// application once at startup instead of adding them to every template. Templates which
+    /// already exist keep their functions.
+    ///
+    /// Functions added to a template take precedence over its defaults. Start from
+    /// [`FuncMap::builtins`] to keep the builtins, or leave some of them out to forbid them in
+    /// all templates. `FuncMap::set_defaults(FuncMap::builtins())` restores the defaults, and
+    /// [`Template::with_funcs`](crate::Template::with_funcs) creates templates which ignore
+    /// them.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Func, FuncError, FuncMap, Template, Value};
+    ///
+    /// fn brand(_args: &[Value]) -> Result<Value, FuncError> {
+    ///     Ok(Value::from("ACME"))
+    /// }
+    ///
+    /// fn local_brand(_args: &[Value]) -> Result<Value, FuncError> {
+    ///     Ok(Value::from("ACME Europe"))
+    /// }
+    ///
+    /// FuncMap::set_defaults(FuncMap::builtins().with("brand", brand as Func));
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ brand | upper }}").unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "ACME");
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_func("brand", local_brand);
+    /// tmpl.parse("{{ brand }}").unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "ACME Europe");
+    /// ```
+    pub fn set_defaults(funcs: FuncMap) {
+        *DEFAULTS.write().unwrap_or_else(|e| e.into_inner()) = funcs;
+    }
+
+    /// Adds the function `name`, replacing any function of the same name.
+    pub fn with<N: Into<String>, D: Into<FuncDef>>(mut self, name: N, def: D) -> FuncMap {
+        self.insert(name, def);
+        self
+    }
+
+    /// Adds the function `name` and returns the function it replaced.
+    pub fn insert<N: Into<String>, D: Into<FuncDef>>(
+        &mut self,
+        name: N,
+        def: D,
+    ) -> Option<FuncDef> {
+        Arc::make_mut(&mut self.funcs).insert(name.into(), def.into())
+    }
+
+    /// Adds `def` as an overload of the function `name`, see [`FuncDef::with_overload`], or
+    /// adds it as the function `name` if there is none yet.
+    pub fn overload<N: Into<String>, D: Into<FuncDef>>(&mut self, name: N, def: D) {
+        let funcs = Arc::make_mut(&mut self.funcs);
+        let def = def.into();
+        match funcs.entry(name.into()) {
+            Entry::Occupied(mut entry) => entry.get_mut().overloads.push(def),
+            Entry::Vacant(entry) => {
+                entry.insert(def);
+            }
+        }
+    }
+
+    /// Adds `alias` as another name of the function `target`. Returns `false` if there is no
+    /// function `target`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// assert!(tmpl.funcs.alias("toUpper", "upper"));
+    /// tmpl.funcs.deprecate("toUpper", "use upper instead");
+    /// tmpl.parse(r#"{{ "a" | upper }}{{ "b" | toUpper }}"#).unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "AB");
+    ///
+    /// let warnings = tmpl.warnings();
+    /// assert_eq!(
+    ///     warnings[0].to_string(),
+    ///     "template: :1:function toUpper is deprecated: use upper instead"
+    /// );
+    /// ```
+    pub fn alias<N: Into<String>>(&mut self, alias: N, target: &str) -> bool {
+        match self.funcs.get(target).cloned() {
+            Some(def) => {
+                Arc::make_mut(&mut self.funcs).insert(alias.into(), def);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the function `name` as deprecated, see [`FuncDef::deprecated`]. Returns `false`
+    /// if there is no function `name`.
+    pub fn deprecate<T: Into<String>>(&mut self, name: &str, note: T) -> bool {
+        if !self.funcs.contains_key(name) {
+            return false;
+        }
+        match Arc::make_mut(&mut self.funcs).get_mut(name) {
+            Some(def) => {
+                def.deprecated = Some(note.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the function `name` and returns it.
+    pub fn remove(&mut self, name: &str) -> Option<FuncDef> {
+        if !self.funcs.contains_key(name) {
+            return None;
+        }
+        Arc::make_mut(&mut self.funcs).remove(name)
+    }
+
+    /// Adds all functions of `other`, replacing functions of the same name.
+    pub fn merge(&mut self, other: &FuncMap) {
+        if other.is_empty() {
+            return;
+        }
+        let funcs = Arc::make_mut(&mut self.funcs);
+        for (name, def) in other.iter() {
+            funcs.insert(name.to_owned(), def.clone());
+        }
+    }
+
+    /// The function called `name`.
+    pub fn get(&self, name: &str) -> Option<&FuncDef> {
+        self.funcs.get(name)
+    }
+
+    /// Whether there is a function called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.funcs.contains_key(name)
+    }
+
+    /// The number of functions.
+    pub fn len(&self) -> usize {
+        self.funcs.len()
+    }
+
+    /// Whether there are no functions.
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+
+    /// Iterates over the names and definitions in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &FuncDef)> {
+        self.funcs.iter().map(|(name, def)| (name.as_str(), def))
+    }
+
+    /// Iterates over the names in arbitrary order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.funcs.keys().map(String::as_str)
+    }
+}
+
+impl<N: Into<String>, D: Into<FuncDef>> FromIterator<(N, D)> for FuncMap {
+    fn from_iter<I: IntoIterator<Item = (N, D)>>(iter: I) -> FuncMap {
+        let mut funcs = FuncMap::new();
+        funcs.extend(iter);
+        funcs
+    }
+}
+
+impl<N: Into<String>, D: Into<FuncDef>> Extend<(N, D)> for FuncMap {
+    fn extend<I: IntoIterator<Item = (N, D)>>(&mut self, iter: I) {
+        let funcs = Arc::make_mut(&mut self.funcs);
+        for (name, def) in iter {
+            funcs.insert(name.into(), def.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use gtmpl_value::{FuncError, Value};
+
+    fn nop(_: &[Value]) -> Result<Value, FuncError> {
+        Ok(Value::Nil)
+    }
+
+    #[test]
+    fn test_builtins() {
+        let funcs = FuncMap::builtins();
+        assert_eq!(funcs.len(), crate::funcs::BUILTINS.len());
+        let eq = funcs.get("eq").unwrap();
+        assert!(eq.is_builtin() && eq.pure);
+        assert_eq!(eq.arity, Some((2, None)));
+        let rand_int = funcs.get("randInt").unwrap();
+        assert!(!rand_int.pure);
+    }
+
+    #[test]
+    fn test_insert_remove_merge() {
+        let mut funcs = FuncMap::builtins();
+        let shared = funcs.clone();
+        assert!(funcs.insert("eq", nop as Func).unwrap().is_builtin());
+        assert!(!funcs.get("eq").unwrap().is_builtin());
+        assert!(shared.get("eq").unwrap().is_builtin());
+
+        assert!(funcs.remove("eq").is_some());
+        assert!(funcs.remove("eq").is_none());
+        assert!(!funcs.contains("eq"));
+
+        let custom: FuncMap = vec![("eq", nop as Func), ("nop", nop as Func)]
+            .into_iter()
+            .collect();
+        funcs.merge(&custom);
+        assert!(funcs.contains("eq") && funcs.contains("nop"));
+        assert_eq!(funcs.len(), shared.len() + 1);
+        let mut names: Vec<&str> = custom.names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["eq", "nop"]);
+    }
+
+    #[test]
+    fn test_overload() {
+        fn one(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(1))
+        }
+        fn two(_: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(2))
+        }
+
+        let mut funcs = FuncMap::new();
+        funcs.overload("f", FuncDef::new(one).with_arity(1, Some(1)));
+        funcs.overload("f", FuncDef::new(two).with_arity(2, Some(3)));
+        let f = funcs.get("f").unwrap();
+        assert_eq!(f.arities(), Some(vec![(1, Some(1)), (2, Some(3))]));
+        let call = |args: usize| (f.func_for(args))(&[]).unwrap();
+        assert_eq!(call(1), Value::from(1));
+        assert_eq!(call(3), Value::from(2));
+        assert_eq!(call(4), Value::from(1));
+
+        let f = FuncDef::new(one).with_overload(FuncDef::new(two));
+        assert_eq!(f.arities(), None);
+        assert_eq!((f.func_for(1))(&[]).unwrap(), Value::from(2));
+    }
+}
@@ -1,44 +1,361 @@
 //! Builtin functions.
+
+use std::cell::Cell;
 use std::cmp::Ordering;
-use std::fmt::Write;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::slice;
 
 use gtmpl_value::{Func, FuncError, Value};
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
-
-use crate::printf::sprintf;
-use crate::utils::is_true;
-
-const QUERY_ENCODE: &AsciiSet = &CONTROLS
-    .add(b' ')
-    .add(b'"')
-    .add(b'<')
-    .add(b'>')
-    .add(b'#')
-    .add(b'`')
-    .add(b'`')
-    .add(b'?')
-    .add(b'{')
-    .add(b'}');
-
-pub static BUILTINS: &[(&str, Func)] = &[
-    ("eq", eq as Func),
-    ("ne", ne as Func),
-    ("lt", lt as Func),
-    ("le", le as Func),
-    ("gt", gt as Func),
-    ("ge", ge as Func),
-    ("len", len as Func),
-    ("and", and as Func),
-    ("or", or as Func),
-    ("not", not as Func),
-    ("urlquery", urlquery as Func),
-    ("print", print as Func),
-    ("println", println as Func),
-    ("printf", printf as Func),
-    ("index", index as Func),
-    ("call", call as Func),
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::clock;
+use crate::ordered;
+use crate::print_verb::type_name;
+use crate::printf::{format_value, sprint, sprintf, sprintln};
+use crate::utils::{self, is_true};
+
+pub(crate) mod arg;
+#[cfg(feature = "crypto")]
+mod crypto;
+#[cfg(feature = "dates")]
+mod date;
+#[cfg(feature = "files")]
+mod file;
+#[cfg(feature = "hash")]
+mod hash;
+#[cfg(feature = "i18n")]
+mod i18n;
+mod jsonpath;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+mod serial;
+mod style;
+#[cfg(feature = "semver")]
+mod version;
+
+pub use self::arg::FromArg;
+#[cfg(feature = "crypto")]
+pub use self::crypto::{bcrypt, derive_password, gen_private_key, htpasswd};
+#[cfg(feature = "dates")]
+pub use self::date::{
+    date, date_in_zone, date_modify, date_sub, duration, duration_round, humanize_duration, now,
+    strftime, time_ago, unix_epoch,
+};
+#[cfg(feature = "files")]
+pub(crate) use self::file::with_root;
+#[cfg(feature = "files")]
+pub use self::file::{file_exists, glob, read_file};
+#[cfg(feature = "hash")]
+pub use self::hash::{crc32, hmac_sha256, md5sum, sha1sum, sha256sum};
+#[cfg(feature = "i18n")]
+pub(crate) use self::i18n::with_catalog;
+#[cfg(feature = "i18n")]
+pub use self::i18n::{t, Catalog, CatalogError};
+pub use self::jsonpath::jsonpath;
+#[cfg(feature = "net")]
+pub(crate) use self::net::with_limits;
+#[cfg(feature = "net")]
+pub use self::net::{dns_lookup, http_get, NetOptions};
+#[cfg(feature = "json")]
+pub use self::serial::{from_json, to_json, to_pretty_json};
+#[cfg(feature = "toml")]
+pub use self::serial::{from_toml, to_toml};
+#[cfg(feature = "yaml")]
+pub use self::serial::{from_yaml, to_yaml};
+pub(crate) use self::style::with_colors;
+pub use self::style::{bg_color, bold, color, dim, italic, underline};
+#[cfg(feature = "semver")]
+pub use self::version::{semver, semver_compare};
+#[cfg(feature = "macros")]
+pub use gtmpl_macros::gtmpl_fn;
+
+// Everything but unreserved characters like Go's `url.QueryEscape`.
+const QUERY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// A builtin function with what is known about it.
+pub(crate) struct Builtin {
+    pub name: &'static str,
+    pub func: Func,
+    /// Whether the function has no side effects and its result only depends on its arguments.
+    pub pure: bool,
+    /// The minimum and maximum number of arguments, including a piped value.
+    pub arity: (usize, Option<usize>),
+}
+
+macro_rules! builtins {
+    (@pure pure) => {
+        true
+    };
+    (@pure impure) => {
+        false
+    };
+    ($($(#[$attr:meta])* ($name:literal, $func:path, $purity:ident, $min:expr, $max:expr),)*) => {
+        pub static BUILTINS: &[(&str, Func)] = &[$($(#[$attr])* ($name, $func as Func),)*];
+
+        pub(crate) static BUILTIN_DEFS: &[Builtin] = &[$($(#[$attr])* Builtin {
+            name: $name,
+            func: $func as Func,
+            pure: builtins!(@pure $purity),
+            arity: ($min, $max),
+        },)*];
+    };
+}
+
+// The builtins with whether they are pure and their minimum and maximum number of arguments.
+builtins! {
+    ("eq", eq, pure, 2, None),
+    ("ne", ne, pure, 2, Some(2)),
+    ("lt", lt, pure, 2, Some(2)),
+    ("le", le, pure, 2, Some(2)),
+    ("gt", gt, pure, 2, Some(2)),
+    ("ge", ge, pure, 2, Some(2)),
+    ("len", len, pure, 1, Some(1)),
+    ("runelen", runelen, pure, 1, Some(1)),
+    ("and", and, pure, 1, None),
+    ("or", or, pure, 1, None),
+    ("not", not, pure, 1, Some(1)),
+    ("default", default, pure, 1, Some(2)),
+    ("empty", empty, pure, 1, Some(1)),
+    ("coalesce", coalesce, pure, 0, None),
+    ("ternary", ternary, pure, 3, Some(3)),
+    ("typeOf", type_of, pure, 1, Some(1)),
+    ("kindOf", kind_of, pure, 1, Some(1)),
+    ("typeIs", type_is, pure, 2, Some(2)),
+    ("kindIs", kind_is, pure, 2, Some(2)),
+    ("deepEqual", deep_equal, pure, 2, Some(2)),
+    ("toString", to_string, pure, 1, Some(1)),
+    ("toStrings", to_strings, pure, 1, Some(1)),
+    ("toInt", to_int, pure, 1, Some(2)),
+    ("toInt64", to_int64, pure, 1, Some(2)),
+    ("toFloat", to_float, pure, 1, Some(2)),
+    ("toBool", to_bool, pure, 1, Some(2)),
+    ("atoi", atoi, pure, 1, Some(1)),
+    ("html", html, pure, 0, None),
+    ("js", js, pure, 0, None),
+    ("urlquery", urlquery, pure, 0, None),
+    ("xmlEscape", xml_escape, pure, 0, None),
+    ("print", print, pure, 0, None),
+    ("println", println, pure, 0, None),
+    ("printf", printf, pure, 1, None),
+    ("index", index, pure, 1, None),
+    ("call", call, impure, 1, None),
+    ("include", include, impure, 2, Some(2)),
+    ("tpl", tpl, impure, 2, Some(2)),
+    ("dict", dict, pure, 0, None),
+    ("get", get, pure, 2, Some(2)),
+    ("set", set, pure, 3, Some(3)),
+    ("unset", unset, pure, 2, Some(2)),
+    ("hasKey", has_key, pure, 2, Some(2)),
+    ("keys", keys, pure, 1, None),
+    ("values", values, pure, 1, Some(1)),
+    ("merge", merge, pure, 1, None),
+    ("deepCopy", deep_copy, pure, 1, Some(1)),
+    ("pick", pick, pure, 1, None),
+    ("omit", omit, pure, 1, None),
+    ("jsonpath", jsonpath, pure, 2, Some(2)),
+    ("list", list, pure, 0, None),
+    ("first", first, pure, 1, Some(1)),
+    ("last", last, pure, 1, Some(1)),
+    ("rest", rest, pure, 1, Some(1)),
+    ("initial", initial, pure, 1, Some(1)),
+    ("append", append, pure, 2, Some(2)),
+    ("prepend", prepend, pure, 2, Some(2)),
+    ("concat", concat, pure, 0, None),
+    ("reverse", reverse, pure, 1, Some(1)),
+    ("uniq", uniq, pure, 1, Some(1)),
+    ("without", without, pure, 1, None),
+    ("has", has, pure, 2, Some(2)),
+    ("compact", compact, pure, 1, Some(1)),
+    ("sortAlpha", sort_alpha, pure, 1, Some(1)),
+    ("sortBy", sort_by, pure, 2, Some(2)),
+    ("groupBy", group_by, pure, 2, Some(2)),
+    ("chunk", chunk, pure, 2, Some(2)),
+    ("zip", zip, pure, 1, None),
+    ("seq", seq, pure, 1, Some(3)),
+    ("until", until, pure, 1, Some(1)),
+    ("untilStep", until_step, pure, 3, Some(3)),
+    ("upper", upper, pure, 1, Some(1)),
+    ("lower", lower, pure, 1, Some(1)),
+    ("trim", trim, pure, 1, Some(1)),
+    ("trimAll", trim_all, pure, 2, Some(2)),
+    ("trimPrefix", trim_prefix, pure, 2, Some(2)),
+    ("trimSuffix", trim_suffix, pure, 2, Some(2)),
+    ("replace", replace, pure, 3, Some(3)),
+    ("split", split, pure, 2, Some(2)),
+    ("splitn", splitn, pure, 3, Some(3)),
+    ("join", join, pure, 2, Some(2)),
+    ("contains", contains, pure, 2, Some(2)),
+    ("hasPrefix", has_prefix, pure, 2, Some(2)),
+    ("hasSuffix", has_suffix, pure, 2, Some(2)),
+    ("repeat", repeat, pure, 2, Some(2)),
+    ("substr", substr, pure, 3, Some(3)),
+    ("indent", indent, pure, 2, Some(2)),
+    ("nindent", nindent, pure, 2, Some(2)),
+    ("wrap", wrap, pure, 2, Some(2)),
+    ("wrapWith", wrap_with, pure, 3, Some(3)),
+    ("trunc", trunc, pure, 2, Some(2)),
+    ("abbrev", abbrev, pure, 2, Some(2)),
+    ("padLeft", pad_left, pure, 2, Some(2)),
+    ("padRight", pad_right, pure, 2, Some(2)),
+    ("center", center, pure, 2, Some(2)),
+    ("table", table, pure, 1, None),
+    ("color", color, impure, 2, Some(2)),
+    ("bgColor", bg_color, impure, 2, Some(2)),
+    ("bold", bold, impure, 1, Some(1)),
+    ("dim", dim, impure, 1, Some(1)),
+    ("italic", italic, impure, 1, Some(1)),
+    ("underline", underline, impure, 1, Some(1)),
+    ("quote", quote, pure, 0, None),
+    ("squote", squote, pure, 0, None),
+    ("shellQuote", shell_quote, pure, 0, None),
+    ("powershellQuote", powershell_quote, pure, 0, None),
+    ("add", add, pure, 2, Some(2)),
+    ("sub", sub, pure, 2, Some(2)),
+    ("mul", mul, pure, 2, Some(2)),
+    ("div", div, pure, 2, Some(2)),
+    ("mod", modulo, pure, 2, Some(2)),
+    ("max", max, pure, 1, None),
+    ("min", min, pure, 1, None),
+    ("abs", abs, pure, 1, Some(1)),
+    ("neg", neg, pure, 1, Some(1)),
+    ("floor", floor, pure, 1, Some(1)),
+    ("ceil", ceil, pure, 1, Some(1)),
+    ("round", round, pure, 1, Some(2)),
+    ("addf", addf, pure, 2, None),
+    ("subf", subf, pure, 2, None),
+    ("mulf", mulf, pure, 2, None),
+    ("divf", divf, pure, 2, None),
+    ("band", band, pure, 2, None),
+    ("bor", bor, pure, 2, None),
+    ("bxor", bxor, pure, 2, None),
+    ("bnot", bnot, pure, 1, Some(1)),
+    ("shl", shl, pure, 2, Some(2)),
+    ("shr", shr, pure, 2, Some(2)),
+    #[cfg(feature = "dates")]
+    ("now", now, impure, 0, Some(0)),
+    #[cfg(feature = "dates")]
+    ("date", date, pure, 2, Some(2)),
+    #[cfg(feature = "dates")]
+    ("dateInZone", date_in_zone, pure, 3, Some(3)),
+    #[cfg(feature = "dates")]
+    ("dateModify", date_modify, pure, 2, Some(2)),
+    #[cfg(feature = "dates")]
+    ("unixEpoch", unix_epoch, pure, 1, Some(1)),
+    #[cfg(feature = "dates")]
+    ("strftime", strftime, pure, 2, Some(3)),
+    #[cfg(feature = "dates")]
+    ("dateSub", date_sub, pure, 2, Some(2)),
+    #[cfg(feature = "dates")]
+    ("duration", duration, pure, 1, Some(1)),
+    #[cfg(feature = "dates")]
+    ("durationRound", duration_round, pure, 1, Some(1)),
+    #[cfg(feature = "dates")]
+    ("humanizeDuration", humanize_duration, pure, 1, Some(1)),
+    #[cfg(feature = "dates")]
+    ("timeAgo", time_ago, impure, 1, Some(1)),
+    #[cfg(feature = "json")]
+    ("toJson", to_json, pure, 1, Some(1)),
+    #[cfg(feature = "json")]
+    ("toPrettyJson", to_pretty_json, pure, 1, Some(1)),
+    #[cfg(feature = "json")]
+    ("fromJson", from_json, pure, 1, Some(1)),
+    #[cfg(feature = "yaml")]
+    ("toYaml", to_yaml, pure, 1, Some(1)),
+    #[cfg(feature = "yaml")]
+    ("fromYaml", from_yaml, pure, 1, Some(1)),
+    #[cfg(feature = "toml")]
+    ("toToml", to_toml, pure, 1, Some(1)),
+    #[cfg(feature = "toml")]
+    ("fromToml", from_toml, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("title", title, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("untitle", untitle, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("camelcase", camelcase, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("snakecase", snakecase, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("kebabcase", kebabcase, pure, 1, Some(1)),
+    #[cfg(feature = "unicode")]
+    ("swapcase", swapcase, pure, 1, Some(1)),
+    #[cfg(feature = "hash")]
+    ("sha1sum", sha1sum, pure, 1, Some(1)),
+    #[cfg(feature = "hash")]
+    ("sha256sum", sha256sum, pure, 1, Some(1)),
+    #[cfg(feature = "hash")]
+    ("md5sum", md5sum, pure, 1, Some(1)),
+    #[cfg(feature = "hash")]
+    ("hmacSha256", hmac_sha256, pure, 2, Some(2)),
+    #[cfg(feature = "hash")]
+    ("crc32", crc32, pure, 1, Some(1)),
+    #[cfg(feature = "crypto")]
+    ("bcrypt", bcrypt, impure, 1, Some(1)),
+    #[cfg(feature = "crypto")]
+    ("htpasswd", htpasswd, impure, 2, Some(2)),
+    #[cfg(feature = "crypto")]
+    ("genPrivateKey", gen_private_key, impure, 1, Some(1)),
+    #[cfg(feature = "crypto")]
+    ("derivePassword", derive_password, pure, 5, Some(5)),
+    #[cfg(feature = "uuid")]
+    ("uuidv4", uuidv4, impure, 0, Some(0)),
+    #[cfg(feature = "uuid")]
+    ("uuidParse", uuid_parse, pure, 1, Some(1)),
+    #[cfg(feature = "uuid")]
+    ("isUuid", is_uuid, pure, 1, Some(1)),
+    #[cfg(feature = "markdown")]
+    ("markdown", markdown, pure, 1, Some(1)),
+    #[cfg(feature = "semver")]
+    ("semver", semver, pure, 1, Some(1)),
+    #[cfg(feature = "semver")]
+    ("semverCompare", semver_compare, pure, 2, Some(2)),
+    ("randAlphaNum", rand_alpha_num, impure, 1, Some(1)),
+    ("randAlpha", rand_alpha, impure, 1, Some(1)),
+    ("randNumeric", rand_numeric, impure, 1, Some(1)),
+    ("randInt", rand_int, impure, 2, Some(2)),
+    ("shuffle", shuffle, impure, 1, Some(1)),
+    ("humanizeBytes", humanize_bytes, pure, 1, Some(1)),
+    ("humanizeNumber", humanize_number, pure, 1, Some(1)),
+    ("ordinal", ordinal, pure, 1, Some(1)),
+    ("plural", plural, pure, 3, Some(3)),
+    ("pluralize", pluralize, pure, 2, Some(2)),
+}
+
+/// Functions reading the environment, which templates cannot call unless they are added by
+/// [`Template::add_env_funcs`](crate::Template::add_env_funcs).
+pub static ENV_FUNCS: &[(&str, Func)] = &[("env", env as Func), ("expandenv", expandenv as Func)];
+
+/// Functions reading files, which templates cannot call unless they are added by
+/// [`Template::add_file_funcs`](crate::Template::add_file_funcs).
+#[cfg(feature = "files")]
+pub static FILE_FUNCS: &[(&str, Func)] = &[
+    ("readFile", read_file as Func),
+    ("fileExists", file_exists as Func),
+    ("glob", glob as Func),
+];
+
+/// Functions fetching data over the network, which templates cannot call unless they are
+/// added by [`Template::add_net_funcs`](crate::Template::add_net_funcs).
+#[cfg(feature = "net")]
+pub static NET_FUNCS: &[(&str, Func)] = &[
+    ("httpGet", http_get as Func),
+    ("dnsLookup", dns_lookup as Func),
 ];
 
+/// Functions translating messages, which templates cannot call unless they are added by
+/// [`Template::add_i18n_funcs`](crate::Template::add_i18n_funcs).
+#[cfg(feature = "i18n")]
+pub static I18N_FUNCS: &[(&str, Func)] = &[("t", t as Func)];
+
 macro_rules! val {
     ($x:expr) => {
         Value::from($x)
@@ -46,8 +363,53 @@ macro_rules! val {
 }
 
 /// Help to write new functions for gtmpl.
+///
+/// A trailing `...name: T` parameter makes the function variadic like Go's `name ...T`. It
+/// accepts any number of additional arguments, which are collected into a `Vec<T>`.
+///
+/// # Example
+/// ```
+/// use gtmpl::{gtmpl_fn, FuncError, Template, Context};
+///
+/// gtmpl_fn!(
+/// fn join(sep: String, ...parts: String) -> Result<String, FuncError> {
+///     Ok(parts.join(&sep))
+/// });
+/// let mut tmpl = Template::default();
+/// tmpl.add_func("join", join);
+/// tmpl.parse(r#"{{ join "-" "a" "b" "c" }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "a-b-c");
+/// ```
 #[macro_export]
 macro_rules! gtmpl_fn {
+ (
+  $(#[$outer:meta])*
+  fn $name:ident($($arg:ident : $typ:ty,)* ...$rest:ident : $rtyp:ty) -> Result<$otyp:ty, FuncError>
+  { $($body:tt)* }
+ ) => {
+  $(#[$outer])*
+  pub fn $name(
+   args: &[$crate::Value]
+  ) -> Result<$crate::Value, FuncError> {
+   let required = <[&str]>::len(&[$(stringify!($arg)),*]);
+   if args.len() < required {
+    return Err(FuncError::AtLeastXArgs(stringify!($name).into(), required));
+   }
+   #[allow(unused_mut)]
+   let mut args = args.iter();
+   $(let x = args.next().ok_or(FuncError::UnableToConvertFromValue)?;
+     let $arg: $typ = $crate::from_value(x)
+    .ok_or(FuncError::UnableToConvertFromValue)?;)*
+   let rest = $crate::Value::Array(args.cloned().collect());
+   let $rest: Vec<$rtyp> = $crate::from_value(&rest)
+    .ok_or(FuncError::UnableToConvertFromValue)?;
+   fn inner($($arg : $typ,)* $rest: Vec<$rtyp>) -> Result<$otyp, FuncError> {
+    $($body)*
+   }
+   let ret: $crate::Value = inner($($arg,)* $rest)?.into();
+   Ok(ret)
+  }
+ };
  (
   $(#[$outer:meta])*
   fn $name:ident() -> Result<$otyp:ty, FuncError>
@@ -137,8 +499,9 @@ macro_rules! gn {
 
 /// Returns the boolean OR of its arguments by returning the
 /// first non-empty argument or the last argument, that is,
-/// "or x y" behaves as "if x then x else y". All the
-/// arguments are evaluated.
+/// "or x y" behaves as "if x then x else y". Templates
+/// evaluate the arguments from left to right and stop as
+/// soon as the result is known.
 ///
 /// # Example
 /// ```
@@ -160,8 +523,9 @@ pub fn or(args: &[Value]) -> Result<Value, FuncError> {
 
 /// Returns the boolean AND of its arguments by returning the
 /// first empty argument or the last argument, that is,
-/// "and x y" behaves as "if x then y else x". All the
-/// arguments are evaluated.
+/// "and x y" behaves as "if x then y else x". Templates
+/// evaluate the arguments from left to right and stop as
+/// soon as the result is known.
 ///
 /// # Example
 /// ```
@@ -197,13 +561,424 @@ pub fn not(args: &[Value]) -> Result<Value, FuncError> {
     }
 }
 
+/// Returns its last argument unless it is empty, and the first argument otherwise, i.e.
+/// `default fallback value`. Without a value, it returns the fallback.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let data: HashMap<String, String> = HashMap::new();
+/// let port = template(r#"{{ .port | default 8080 }}"#, data);
+/// assert_eq!(&port.unwrap(), "8080");
+/// ```
+pub fn default(args: &[Value]) -> Result<Value, FuncError> {
+    match *args {
+        [ref fallback] => Ok(fallback.clone()),
+        [ref fallback, ref val] => Ok(if is_true(val) { val } else { fallback }.clone()),
+        _ => Err(FuncError::Generic(
+            "default: wrong number of args: want 1 or 2".into(),
+        )),
+    }
+}
+
+/// Returns whether its argument is empty, i.e. nil, false, zero, or an empty string, array or
+/// map.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let data: HashMap<String, String> = HashMap::new();
+/// let empty = template("{{ empty .items }}", data);
+/// assert_eq!(&empty.unwrap(), "true");
+/// ```
+pub fn empty(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("empty", args)?;
+    Ok(val!(!is_true(val)))
+}
+
+/// Returns the first argument that is not empty, or nil if all of them are.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let data: HashMap<String, String> = HashMap::new();
+/// let name = template(r#"{{ coalesce .nick .name "anonymous" }}"#, data);
+/// assert_eq!(&name.unwrap(), "anonymous");
+/// ```
+pub fn coalesce(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(args
+        .iter()
+        .find(|arg| is_true(arg))
+        .cloned()
+        .unwrap_or(Value::Nil))
+}
+
+/// Returns the first argument if the boolean third argument is true and the second one
+/// otherwise, i.e. `ternary then else cond`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let state = template(r#"{{ . | ternary "on" "off" }}"#, true);
+/// assert_eq!(&state.unwrap(), "on");
+/// ```
+pub fn ternary(args: &[Value]) -> Result<Value, FuncError> {
+    match *exact::<3>("ternary", args)? {
+        [ref then, _, Value::Bool(true)] => Ok(then.clone()),
+        [_, ref otherwise, Value::Bool(false)] => Ok(otherwise.clone()),
+        [_, _, ref cond] => Err(FuncError::Generic(format!(
+            "ternary: wrong type for value; expected bool; got {}",
+            type_name(cond)
+        ))),
+    }
+}
+
+/// Returns the name of the Go type of its argument, as printed by `printf "%T"`, e.g. `int`,
+/// `string`, `[]interface {}` or `map[string]interface {}`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let types = template(r#"{{ typeOf . }} {{ typeOf 1.5 }} {{ typeOf (list) }}"#, "a");
+/// assert_eq!(&types.unwrap(), "string float64 []interface {}");
+/// ```
+pub fn type_of(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("typeOf", args)?;
+    Ok(val!(type_name(val)))
+}
+
+/// Returns the kind of its argument like Go's `reflect.Kind`, i.e. `bool`, `int`, `uint64`,
+/// `float64`, `string`, `slice`, `map`, `func`, or `invalid` for nil.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let kind = template(
+///     r#"{{ if eq (kindOf .) "slice" }}{{ len . }} items{{ else }}{{ . }}{{ end }}"#,
+///     vec![1, 2],
+/// );
+/// assert_eq!(&kind.unwrap(), "2 items");
+/// ```
+pub fn kind_of(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("kindOf", args)?;
+    Ok(val!(kind_name(val)))
+}
+
+/// Returns whether the Go type of a value has the given name, i.e. `typeIs name value`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let is_int = template(r#"{{ typeIs "int" . }} {{ typeIs "int" 1.5 }}"#, 1);
+/// assert_eq!(&is_int.unwrap(), "true false");
+/// ```
+pub fn type_is(args: &[Value]) -> Result<Value, FuncError> {
+    let [name, val] = exact::<2>("typeIs", args)?;
+    Ok(val!(string("typeIs", name)? == type_name(val)))
+}
+
+/// Returns whether a value is of the given kind, i.e. `kindIs kind value`, with kinds named
+/// like for `kindOf`.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let mut data = HashMap::new();
+/// data.insert("a".to_owned(), 1);
+/// let is_map = template(r#"{{ kindIs "map" . }} {{ kindIs "map" .a }}"#, data);
+/// assert_eq!(&is_map.unwrap(), "true false");
+/// ```
+pub fn kind_is(args: &[Value]) -> Result<Value, FuncError> {
+    let [kind, val] = exact::<2>("kindIs", args)?;
+    Ok(val!(string("kindIs", kind)? == kind_name(val)))
+}
+
+/// Returns whether two values are deeply equal, i.e. of the same kind with equal lists or maps
+/// of equal items. Unlike `eq` any values can be compared, but integer values never equal
+/// float values, so `deepEqual 1 1.0` is false.
+///
+/// # Example
+/// ```
+/// use gtmpl::{template, Value};
+/// let data = vec![Value::from(1), Value::from(vec!["a"])];
+/// let equal = template(r#"{{ deepEqual . (list 1 (list "a")) }} {{ deepEqual 1 "1" }}"#, data);
+/// assert_eq!(&equal.unwrap(), "true false");
+/// ```
+pub fn deep_equal(args: &[Value]) -> Result<Value, FuncError> {
+    let [a, b] = exact::<2>("deepEqual", args)?;
+    Ok(val!(deeply_equal(a, b)))
+}
+
+// The name of the `reflect.Kind` of the Go value that `val` corresponds to.
+fn kind_name(val: &Value) -> &'static str {
+    match *val {
+        Value::Nil | Value::NoValue => "invalid",
+        Value::Array(_) | Value::Bytes(_) => "slice",
+        Value::Map(_) | Value::OrderedMap(_) | Value::Object(_) => "map",
+        Value::Function(_) => "func",
+        Value::Number(ref n) if n.as_i64().is_some() => "int",
+        Value::Number(ref n) if n.as_u64().is_some() => "uint64",
+        Value::Number(_) => "float64",
+        Value::Bool(_) => "bool",
+        Value::String(_) => "string",
+    }
+}
+
+// Like Go's `reflect.DeepEqual`, functions only equal themselves.
+fn deeply_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| deeply_equal(a, b))
+        }
+        (Value::Function(a), Value::Function(b)) => a == b,
+        (Value::Bytes(a), Value::Bytes(b)) => a == b,
+        // The order of ordered maps does not matter, like for any Go map.
+        _ => match (ordered::as_map(a), ordered::as_map(b)) {
+            (Some(a), Some(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, a)| b.get(k).is_some_and(|b| deeply_equal(a, b)))
+            }
+            (Some(_), None) | (None, Some(_)) => false,
+            (None, None) => match (basic(a), basic(b)) {
+                (Basic::Bool(a), Basic::Bool(b)) => a == b,
+                (Basic::Int(a), Basic::Int(b)) => a == b,
+                (Basic::Float(a), Basic::Float(b)) => a == b,
+                (Basic::String(a), Basic::String(b)) => a == b,
+                (Basic::Nil, Basic::Nil) => true,
+                _ => false,
+            },
+        },
+    }
+}
+
+/// Returns its argument as a string, printed like by `print` except that nil is empty.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let s = template(r#"{{ if eq (toString .) "42" }}answer{{ end }}"#, 42);
+/// assert_eq!(&s.unwrap(), "answer");
+/// ```
+pub fn to_string(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("toString", args)?;
+    Ok(val!(string_of(val)))
+}
+
+/// Returns a list of the items of a list converted with `toString`. Nil is an empty list and
+/// other values are a list of one string.
+///
+/// # Example
+/// ```
+/// use gtmpl::{template, Value};
+/// let data = vec![Value::from(1), Value::from("a"), Value::from(true)];
+/// let s = template(r#"{{ join "," (toStrings .) }}"#, data);
+/// assert_eq!(&s.unwrap(), "1,a,true");
+/// ```
+pub fn to_strings(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("toStrings", args)?;
+    let strings = match *val {
+        Value::Array(ref items) => items.iter().map(|v| val!(string_of(v))).collect(),
+        Value::Nil | Value::NoValue => vec![],
+        _ => vec![val!(string_of(val))],
+    };
+    Ok(Value::Array(strings))
+}
+
+/// Returns its last argument as an integer, i.e. `toInt [fallback] value`. Floats are truncated
+/// and booleans are 0 or 1. Strings are parsed as decimal integers or floats, ignoring
+/// surrounding whitespace.
+///
+/// Values which can not be converted, like nil, other strings or numbers out of the range of
+/// `int64`, are an error, or return the fallback if there is one.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let n = template(r#"{{ add (toInt .) 1 }} {{ "n/a" | toInt -1 }}"#, " 41 ");
+/// assert_eq!(&n.unwrap(), "42 -1");
+/// assert!(template(r#"{{ toInt "n/a" }}"#, 0).is_err());
+/// ```
+pub fn to_int(args: &[Value]) -> Result<Value, FuncError> {
+    convert("toInt", "int", args, int_of)
+}
+
+/// Returns its last argument as an integer like `toInt`, i.e. `toInt64 [fallback] value`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let n = template("{{ toInt64 . }}", 2.9);
+/// assert_eq!(&n.unwrap(), "2");
+/// ```
+pub fn to_int64(args: &[Value]) -> Result<Value, FuncError> {
+    convert("toInt64", "int", args, int_of)
+}
+
+/// Returns its last argument as a float, i.e. `toFloat [fallback] value`. Booleans are 0 or 1
+/// and strings are parsed, ignoring surrounding whitespace.
+///
+/// Values which can not be converted, like nil or other strings, are an error, or return the
+/// fallback if there is one.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let n = template(r#"{{ mulf (toFloat .) 2 }} {{ toFloat 0.5 "" }}"#, "1.25");
+/// assert_eq!(&n.unwrap(), "2.5 0.5");
+/// ```
+pub fn to_float(args: &[Value]) -> Result<Value, FuncError> {
+    convert("toFloat", "float64", args, |val| {
+        let f = match basic(val) {
+            Basic::Int(i) => i as f64,
+            Basic::Float(f) => f,
+            Basic::Bool(b) => f64::from(u8::from(b)),
+            Basic::String(s) => s.trim().parse().ok()?,
+            Basic::Nil | Basic::Other => return None,
+        };
+        Some(val!(f))
+    })
+}
+
+/// Returns its last argument as a boolean, i.e. `toBool [fallback] value`. Numbers are true
+/// unless they are zero, and strings are parsed like Go's `strconv.ParseBool`, i.e. `1`, `t`,
+/// `T`, `TRUE`, `true` and `True` are true and `0`, `f`, `F`, `FALSE`, `false` and `False` are
+/// false.
+///
+/// Values which can not be converted, like nil or other strings, are an error, or return the
+/// fallback if there is one.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let b = template(r#"{{ if toBool . }}on{{ end }} {{ toBool false "yes" }}"#, "TRUE");
+/// assert_eq!(&b.unwrap(), "on false");
+/// ```
+pub fn to_bool(args: &[Value]) -> Result<Value, FuncError> {
+    convert("toBool", "bool", args, |val| {
+        let b = match basic(val) {
+            Basic::Bool(b) => b,
+            Basic::Int(i) => i != 0,
+            Basic::Float(f) => f != 0.0,
+            Basic::String("1" | "t" | "T" | "TRUE" | "true" | "True") => true,
+            Basic::String("0" | "f" | "F" | "FALSE" | "false" | "False") => false,
+            _ => return None,
+        };
+        Some(val!(b))
+    })
+}
+
+/// Returns a string parsed as a decimal integer like Go's `strconv.Atoi`. Other values and
+/// strings which are not integers are an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let n = template(r#"{{ if gt (atoi .) 9 }}big{{ end }}"#, "10");
+/// assert_eq!(&n.unwrap(), "big");
+/// assert!(template("{{ atoi . }}", "1.5").is_err());
+/// ```
+pub fn atoi(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("atoi", args)?;
+    let s = string("atoi", s)?;
+    s.parse::<i64>()
+        .map(|i| val!(i))
+        .map_err(|e| FuncError::Generic(format!("atoi: parsing {:?}: {}", s, e)))
+}
+
+// Converts the last of `args` with `f`, falling back to the first of two arguments for values
+// `f` can not convert.
+fn convert(
+    name: &str,
+    target: &str,
+    args: &[Value],
+    f: impl Fn(&Value) -> Option<Value>,
+) -> Result<Value, FuncError> {
+    let (fallback, val) = match *args {
+        [ref val] => (None, val),
+        [ref fallback, ref val] => (Some(fallback), val),
+        _ => {
+            return Err(FuncError::Generic(format!(
+                "{}: wrong number of args: want 1 or 2",
+                name
+            )))
+        }
+    };
+    match (f(val), fallback) {
+        (Some(converted), _) => Ok(converted),
+        (None, Some(fallback)) => Ok(fallback.clone()),
+        (None, None) => Err(FuncError::Generic(format!(
+            "{}: cannot convert {} {} to {}",
+            name,
+            type_name(val),
+            match *val {
+                Value::String(ref s) => format!("{:?}", s),
+                _ => format_value(val).into_owned(),
+            },
+            target
+        ))),
+    }
+}
+
+fn string_of(val: &Value) -> String {
+    match *val {
+        Value::Nil | Value::NoValue => String::new(),
+        _ => format_value(val).into_owned(),
+    }
+}
+
+// The value as an `i64`, with floats truncated.
+fn int_of(val: &Value) -> Option<Value> {
+    let i = match basic(val) {
+        Basic::Int(i) => i64::try_from(i).ok()?,
+        Basic::Float(f) => float_to_int(f)?,
+        Basic::Bool(b) => i64::from(b),
+        Basic::String(s) => {
+            let s = s.trim();
+            match s.parse::<i64>() {
+                Ok(i) => i,
+                Err(_) => float_to_int(s.parse().ok()?)?,
+            }
+        }
+        Basic::Nil | Basic::Other => return None,
+    };
+    Some(val!(i))
+}
+
+fn float_to_int(f: f64) -> Option<i64> {
+    let f = f.trunc();
+    // The bounds are exactly representable, unlike `i64::MAX`.
+    if (-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&f) {
+        Some(f as i64)
+    } else {
+        None
+    }
+}
+
 /// Returns the integer length of its argument.
 ///
+/// Like in Go the length of a string is its number of bytes. Use `runelen` for the number of
+/// characters.
+///
 /// # Example
 /// ```
 /// use gtmpl::template;
 /// let equal = template("{{ len . }}", "foo");
 /// assert_eq!(&equal.unwrap(), "3");
+/// let equal = template("{{ len . }}", "café");
+/// assert_eq!(&equal.unwrap(), "5");
 /// ```
 pub fn len(args: &[Value]) -> Result<Value, FuncError> {
     if args.len() != 1 {
@@ -213,15 +988,45 @@ pub fn len(args: &[Value]) -> Result<Value, FuncError> {
     let len = match *arg {
         Value::String(ref s) => s.len(),
         Value::Array(ref a) => a.len(),
+        Value::Bytes(ref b) => b.len(),
         Value::Object(ref o) => o.len(),
+        Value::Map(ref o) => o.len(),
+        Value::OrderedMap(ref o) => o.len(),
+        Value::Nil | Value::NoValue => {
+            return Err(FuncError::Generic("len of nil pointer".into()));
+        }
         _ => {
-            return Err(FuncError::Generic(format!("unable to call len on {}", arg)));
+            return Err(FuncError::Generic(format!(
+                "len of type {}",
+                type_name(arg)
+            )));
         }
     };
 
     Ok(val!(len))
 }
 
+/// Returns the number of characters (Unicode scalar values) of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let equal = template("{{ runelen . }}", "café");
+/// assert_eq!(&equal.unwrap(), "4");
+/// ```
+pub fn runelen(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() != 1 {
+        return Err(FuncError::ExactlyXArgs("runelen".into(), 1));
+    }
+    match args[0] {
+        Value::String(ref s) => Ok(val!(s.chars().count())),
+        ref arg => Err(FuncError::Generic(format!(
+            "runelen of type {}",
+            type_name(arg)
+        ))),
+    }
+}
+
 /// Returns the result of calling the first argument, which
 /// must be a function, with the remaining arguments as parameters.
 ///
@@ -238,17 +1043,66 @@ pub fn len(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "3");
 /// ```
 pub fn call(args: &[Value]) -> Result<Value, FuncError> {
-    if args.is_empty() {
-        Err(FuncError::AtLeastXArgs("call".into(), 1))
-    } else if let Value::Function(ref f) = args[0] {
-        (f.f)(&args[1..])
-    } else {
-        Err(FuncError::Generic(
-            "call requires the first argument to be a function".into(),
-        ))
+    match args.first() {
+        None => Err(FuncError::AtLeastXArgs("call".into(), 1)),
+        Some(Value::Function(f)) => (f.f)(&args[1..]),
+        Some(Value::Nil) | Some(Value::NoValue) => Err(FuncError::Generic("call of nil".into())),
+        Some(f) => Err(FuncError::Generic(format!(
+            "non-function of type {}",
+            type_name(f)
+        ))),
     }
 }
 
+/// Returns the output of the associated template called by the first argument, executed
+/// with the second argument as dot. Unlike the `template` action, the output can be piped
+/// into other functions. Templates run `include` themselves, so calling this function
+/// directly is an error.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.parse(r#"{{ define "labels" }}app: {{ . }}{{ end }}labels:{{ include "labels" . | nindent 2 }}"#)
+///     .unwrap();
+/// let output = tmpl.render(&Context::from("web"));
+/// assert_eq!(output.unwrap(), "labels:\n  app: web");
+/// ```
+pub fn include(args: &[Value]) -> Result<Value, FuncError> {
+    let [name, _] = exact::<2>("include", args)?;
+    string("include", name)?;
+    Err(FuncError::Generic(
+        "include: can only be called by a template".into(),
+    ))
+}
+
+/// Returns the output of the first argument parsed as template and executed with the second
+/// argument as dot, e.g. for templates stored in the data. The text can call the same
+/// functions and invoke the same associated templates as the calling template. Templates
+/// run `tpl` themselves, so calling this function directly is an error.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::{Context, Template};
+///
+/// let mut data = HashMap::new();
+/// data.insert("name".to_owned(), "web");
+/// data.insert("host".to_owned(), "{{ .name | upper }}.example.com");
+/// let mut tmpl = Template::default();
+/// tmpl.parse("{{ tpl .host . }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::from(data)).unwrap(), "WEB.example.com");
+/// ```
+pub fn tpl(args: &[Value]) -> Result<Value, FuncError> {
+    let [text, _] = exact::<2>("tpl", args)?;
+    string("tpl", text)?;
+    Err(FuncError::Generic(
+        "tpl: can only be called by a template".into(),
+    ))
+}
+
 /// An implementation of golang's fmt.Sprint
 ///
 /// Golang's Sprint formats using the default formats for its operands and returns the
@@ -261,22 +1115,7 @@ pub fn call(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "Hello world!");
 /// ```
 pub fn print(args: &[Value]) -> Result<Value, FuncError> {
-    let mut no_space = true;
-    let mut s = String::new();
-    for val in args {
-        if let Value::String(ref v) = *val {
-            no_space = true;
-            s.push_str(v);
-        } else {
-            if no_space {
-                s += &val.to_string();
-            } else {
-                s += &format!(" {}", val.to_string())
-            }
-            no_space = false;
-        }
-    }
-    Ok(val!(s))
+    Ok(val!(sprint(args)))
 }
 
 /// An implementation of golang's fmt.Sprintln
@@ -291,37 +1130,13 @@ pub fn print(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&equal.unwrap(), "Hello world !\n");
 /// ```
 pub fn println(args: &[Value]) -> Result<Value, FuncError> {
-    let mut iter = args.iter();
-    let s = match iter.next() {
-        None => String::from("\n"),
-        Some(first_elt) => {
-            let (lower, _) = iter.size_hint();
-            let mut result = String::with_capacity(lower + 1);
-            if let Value::String(ref v) = *first_elt {
-                result.push_str(v);
-            } else {
-                write!(&mut result, "{}", first_elt).unwrap();
-            }
-            for elt in iter {
-                result.push(' ');
-                if let Value::String(ref v) = *elt {
-                    result.push_str(v);
-                } else {
-                    write!(&mut result, "{}", elt).unwrap();
-                }
-            }
-            result.push('\n');
-            result
-        }
-    };
-    Ok(val!(s))
+    Ok(val!(sprintln(args)))
 }
 
 /// An implementation of golang's fmt.Sprintf
-/// Limitations:
-/// - float:
-///   * `g`, `G`, and `b` are weired and not implement yet
-/// - pretty sure there are more
+///
+/// Supports the verbs, flags, width, precision and argument indexes of Go's `fmt`. Like in Go,
+/// bad verbs or arguments are reported in the output, e.g. `%!d(string=foo)`.
 ///
 /// # Example
 /// ```
@@ -334,8 +1149,7 @@ pub fn printf(args: &[Value]) -> Result<Value, FuncError> {
         return Err(FuncError::AtLeastXArgs("printf".into(), 1));
     }
     if let Value::String(ref s) = args[0] {
-        let s = sprintf(s, &args[1..]).map_err(|e| FuncError::Other(e.into()))?;
-        Ok(val!(s))
+        Ok(val!(sprintf(s, &args[1..])))
     } else {
         Err(FuncError::Generic("printf requires a format string".into()))
     }
@@ -343,7 +1157,10 @@ pub fn printf(args: &[Value]) -> Result<Value, FuncError> {
 
 /// Returns the result of indexing its first argument by the
 /// following arguments. Thus "index x 1 2 3" is, in Go syntax,
-/// x[1][2][3]. Each indexed item must be a map, slice or array.
+/// x[1][2][3]. Each indexed item must be a map, slice, array or string.
+///
+/// Like in Go, a missing map key yields `<no value>` while indexing nil or an out of range
+/// index is an error. Indexing a string yields the byte at the index.
 ///
 /// # Example
 /// ```
@@ -353,195 +1170,3022 @@ pub fn printf(args: &[Value]) -> Result<Value, FuncError> {
 /// assert_eq!(&index.unwrap(), "42");
 /// ```
 pub fn index(args: &[Value]) -> Result<Value, FuncError> {
-    if args.len() < 2 {
-        return Err(FuncError::AtLeastXArgs("index".into(), 2));
+    if args.is_empty() {
+        return Err(FuncError::AtLeastXArgs("index".into(), 1));
+    }
+    let mut col = args[0].clone();
+    if let Value::Nil = col {
+        return Err(FuncError::Generic("index of untyped nil".into()));
     }
-    let mut col = &args[0];
-    for val in &args[1..] {
-        col = get_item(col, val)?;
+    for key in &args[1..] {
+        col = get_item(&col, key)?;
     }
 
-    Ok(col.clone())
+    Ok(col)
 }
 
-fn get_item<'a>(col: &'a Value, key: &Value) -> Result<&'a Value, FuncError> {
-    let ret = match (col, key) {
-        (&Value::Array(ref a), &Value::Number(ref n)) => {
-            if let Some(i) = n.as_u64() {
-                a.get(i as usize)
-            } else {
-                None
-            }
+fn get_item(col: &Value, key: &Value) -> Result<Value, FuncError> {
+    match (col, key) {
+        (Value::Array(a), _) => Ok(a[index_arg(key, a.len())?].clone()),
+        (Value::Bytes(b), _) => Ok(val!(b[index_arg(key, b.len())?])),
+        (Value::String(s), _) => Ok(val!(s.as_bytes()[index_arg(key, s.len())?])),
+        (Value::Map(_) | Value::OrderedMap(_), Value::Number(n)) => Ok(map("index", col)?
+            .get(&n.to_string())
+            .cloned()
+            .unwrap_or(Value::NoValue)),
+        (Value::Map(_) | Value::OrderedMap(_), Value::String(s)) => {
+            Ok(map("index", col)?.get(s).cloned().unwrap_or(Value::NoValue))
         }
-        (&Value::Object(ref o), &Value::Number(ref n))
-        | (&Value::Map(ref o), &Value::Number(ref n)) => o.get(&n.to_string()),
-        (&Value::Object(ref o), &Value::String(ref s))
-        | (&Value::Map(ref o), &Value::String(ref s)) => o.get(s),
-        _ => None,
-    };
-    match *col {
-        Value::Map(_) => Ok(ret.unwrap_or(&Value::NoValue)),
-        _ => ret.ok_or_else(|| FuncError::Generic(format!("unable to get {} in {}", key, col))),
+        (Value::Map(_) | Value::OrderedMap(_), _) => Err(FuncError::Generic(format!(
+            "value has type {}; should be string",
+            type_name(key)
+        ))),
+        (Value::Object(o), Value::Number(n)) => o
+            .get(&n.to_string())
+            .cloned()
+            .ok_or_else(|| FuncError::Generic(format!("unable to get {} in {}", key, col))),
+        (Value::Object(o), Value::String(s)) => o
+            .get(s)
+            .cloned()
+            .ok_or_else(|| FuncError::Generic(format!("unable to get {} in {}", key, col))),
+        (Value::Nil, _) | (Value::NoValue, _) => {
+            Err(FuncError::Generic("index of nil pointer".into()))
+        }
+        _ => Err(FuncError::Generic(format!(
+            "can't index item of type {}",
+            type_name(col)
+        ))),
     }
 }
 
-/// Returns the escaped value of the textual representation of
-/// its arguments in a form suitable for embedding in a URL query.
+fn index_arg(key: &Value, len: usize) -> Result<usize, FuncError> {
+    match basic(key) {
+        Basic::Int(i) if i >= 0 && i < len as i128 => Ok(i as usize),
+        Basic::Int(i) => Err(FuncError::Generic(format!("index out of range: {}", i))),
+        Basic::Nil => Err(FuncError::Generic(
+            "cannot index slice/array with nil".into(),
+        )),
+        _ => Err(FuncError::Generic(format!(
+            "cannot index slice/array with type {}",
+            type_name(key)
+        ))),
+    }
+}
+
+/// Returns a map of the key and value pairs given as arguments, i.e. `dict key value ...`.
+/// Keys are converted to strings and a key without a value maps to an empty string.
 ///
 /// # Example
 /// ```
 /// use gtmpl::template;
-/// let url = template(r#"{{ urlquery "foo bar?" }}"#, 0);
-/// assert_eq!(&url.unwrap(), "foo%20bar%3F");
+/// let name = template(r#"{{ (dict "name" . "age" 3).name }}"#, "Rex");
+/// assert_eq!(&name.unwrap(), "Rex");
+/// ```
+pub fn dict(args: &[Value]) -> Result<Value, FuncError> {
+    let entries = args.chunks(2).map(|pair| match *pair {
+        [ref k, ref v] => (eval_args(slice::from_ref(k)), v.clone()),
+        _ => (eval_args(pair), val!("")),
+    });
+    Ok(Value::Map(entries.collect()))
+}
+
+/// Returns the value of a key in a map, or an empty string if the map has no such key, i.e.
+/// `get map key`.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let mut data = HashMap::new();
+/// data.insert("a".to_owned(), 1);
+/// let value = template(r#"{{ get . "a" }}{{ get . "b" }}"#, data);
+/// assert_eq!(&value.unwrap(), "1");
+/// ```
+pub fn get(args: &[Value]) -> Result<Value, FuncError> {
+    let [m, key] = exact::<2>("get", args)?;
+    Ok(map("get", m)?
+        .get(string("get", key)?)
+        .cloned()
+        .unwrap_or_else(|| val!("")))
+}
+
+/// Returns a map with a key set to a value, i.e. `set map key value`. If the map is a variable
+/// like `$d` or `$d.inner`, the variable is changed as well, so `{{ $_ := set $d "a" 1 }}`
+/// works like in Sprig. Other maps, e.g. fields of dot, are copied and left as they are.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ $d := dict }}{{ $_ := set $d "a" 1 }}{{ $d.a }}"#, "");
+/// assert_eq!(&value.unwrap(), "1");
+/// ```
+pub fn set(args: &[Value]) -> Result<Value, FuncError> {
+    let [m, key, val] = exact::<3>("set", args)?;
+    let key = string("set", key)?;
+    change_map("set", m, |m| {
+        m.insert(key.to_owned(), val.clone());
+    })
+}
+
+/// Returns a map without a key, i.e. `unset map key`. Like with `set`, a variable holding the
+/// map is changed as well.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ unset (dict "a" 1 "b" 2) "a" | len }}"#, "");
+/// assert_eq!(&value.unwrap(), "1");
+/// ```
+pub fn unset(args: &[Value]) -> Result<Value, FuncError> {
+    let [m, key] = exact::<2>("unset", args)?;
+    let key = string("unset", key)?;
+    change_map("unset", m, |m| {
+        m.remove(key);
+    })
+}
+
+/// Returns whether a map has a key, i.e. `hasKey map key`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ hasKey (dict "a" "") "a" }}"#, "");
+/// assert_eq!(&value.unwrap(), "true");
+/// ```
+pub fn has_key(args: &[Value]) -> Result<Value, FuncError> {
+    let [m, key] = exact::<2>("hasKey", args)?;
+    Ok(val!(map("hasKey", m)?.contains_key(string("hasKey", key)?)))
+}
+
+/// Returns the keys of one or more maps as a list. The keys of each map are sorted, unless it
+/// was created by [`ordered_map`](crate::ordered_map).
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ keys (dict "b" 1 "a" 2) (dict "c" 3) }}"#, "");
+/// assert_eq!(&value.unwrap(), "[a b c]");
+/// ```
+pub fn keys(args: &[Value]) -> Result<Value, FuncError> {
+    if args.is_empty() {
+        return Err(FuncError::AtLeastXArgs("keys".into(), 1));
+    }
+    let mut keys = vec![];
+    for m in args {
+        map("keys", m)?;
+        let entries = ordered::entries(m, true).unwrap_or_default();
+        keys.extend(entries.into_iter().map(|(k, _)| val!(k.as_str())));
+    }
+    Ok(Value::Array(keys))
+}
+
+/// Returns the values of a map as a list, in the order of their sorted keys, or of their
+/// insertion for maps created by [`ordered_map`](crate::ordered_map).
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ values (dict "b" 1 "a" 2) }}"#, "");
+/// assert_eq!(&value.unwrap(), "[2 1]");
+/// ```
+pub fn values(args: &[Value]) -> Result<Value, FuncError> {
+    let [m] = exact::<1>("values", args)?;
+    map("values", m)?;
+    let entries = ordered::entries(m, true).unwrap_or_default();
+    Ok(Value::Array(
+        entries.into_iter().map(|(_, v)| v.clone()).collect(),
+    ))
+}
+
+/// Returns the first map merged with the others, i.e. `merge dst src ...`. Keys of earlier maps
+/// take precedence and nested maps are merged recursively. Like with `set`, a variable holding
+/// `dst` is changed as well.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"
+/// {{- $defaults := dict "port" 80 "tls" (dict "on" false "port" 443) -}}
+/// {{- $c := merge (dict "tls" (dict "on" true)) $defaults -}}
+/// {{ $c.port }} {{ $c.tls }}"#;
+/// let value = template(tmpl, "");
+/// assert_eq!(&value.unwrap(), "80 map[on:true port:443]");
+/// ```
+pub fn merge(args: &[Value]) -> Result<Value, FuncError> {
+    let (dst, srcs) = args
+        .split_first()
+        .ok_or_else(|| FuncError::AtLeastXArgs("merge".into(), 1))?;
+    let srcs = srcs
+        .iter()
+        .map(|src| map("merge", src))
+        .collect::<Result<Vec<_>, _>>()?;
+    change_map("merge", dst, |dst| {
+        for src in srcs {
+            merge_into(dst, src);
+        }
+    })
+}
+
+// Adds the entries of `src` that `dst` lacks to `dst`, merging maps in both recursively.
+fn merge_into(dst: &mut HashMap<String, Value>, src: &HashMap<String, Value>) {
+    for (k, v) in src {
+        match dst.get_mut(k) {
+            None => {
+                dst.insert(k.clone(), v.clone());
+            }
+            Some(d) => {
+                if let (Some(d), Some(s)) = (ordered::as_map_mut(d), ordered::as_map(v)) {
+                    merge_into(d, s);
+                }
+            }
+        }
+    }
+}
+
+/// Returns a copy of its argument.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ deepCopy . }}", vec![1, 2]);
+/// assert_eq!(&value.unwrap(), "[1 2]");
+/// ```
+pub fn deep_copy(args: &[Value]) -> Result<Value, FuncError> {
+    let [val] = exact::<1>("deepCopy", args)?;
+    Ok(val.clone())
+}
+
+/// Returns a copy of a map with only the given keys, i.e. `pick map key ...`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ pick (dict "a" 1 "b" 2 "c" 3) "a" "c" }}"#, "");
+/// assert_eq!(&value.unwrap(), "map[a:1 c:3]");
+/// ```
+pub fn pick(args: &[Value]) -> Result<Value, FuncError> {
+    filter_keys("pick", args, true)
+}
+
+/// Returns a copy of a map without the given keys, i.e. `omit map key ...`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ omit (dict "a" 1 "b" 2 "c" 3) "a" "c" }}"#, "");
+/// assert_eq!(&value.unwrap(), "map[b:2]");
+/// ```
+pub fn omit(args: &[Value]) -> Result<Value, FuncError> {
+    filter_keys("omit", args, false)
+}
+
+// Keeps the entries of a map whose keys are among the other arguments, or the others.
+fn filter_keys(name: &str, args: &[Value], keep: bool) -> Result<Value, FuncError> {
+    let (m, keys) = args
+        .split_first()
+        .ok_or_else(|| FuncError::AtLeastXArgs(name.into(), 1))?;
+    let keys = keys
+        .iter()
+        .map(|k| string(name, k))
+        .collect::<Result<Vec<_>, _>>()?;
+    change_map(name, m, |m| {
+        m.retain(|k, _| keys.contains(&k.as_str()) == keep)
+    })
+}
+
+// The map or object argument of the function `name`.
+fn map<'a>(name: &str, val: &'a Value) -> Result<&'a HashMap<String, Value>, FuncError> {
+    ordered::as_map(val).ok_or_else(|| {
+        FuncError::Generic(format!(
+            "{}: wrong type for value; expected map; got {}",
+            name,
+            type_name(val)
+        ))
+    })
+}
+
+// Returns a copy of the map `val` changed by `f`. Ordered maps stay ordered, while other maps
+// and objects become plain maps.
+fn change_map<F>(name: &str, val: &Value, f: F) -> Result<Value, FuncError>
+where
+    F: FnOnce(&mut HashMap<String, Value>),
+{
+    let mut val = match *val {
+        Value::OrderedMap(_) => val.clone(),
+        _ => Value::Map(map(name, val)?.clone()),
+    };
+    if let Some(m) = ordered::as_map_mut(&mut val) {
+        f(m);
+    }
+    Ok(val)
+}
+
+/// Returns a list of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ list 1 "a" . }}"#, true);
+/// assert_eq!(&value.unwrap(), "[1 a true]");
+/// ```
+pub fn list(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::Array(args.to_vec()))
+}
+
+/// Returns the first item of a list, or nil if it is empty.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ first . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "1");
+/// ```
+pub fn first(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("first", args)?;
+    Ok(array("first", l)?.first().cloned().unwrap_or(Value::Nil))
+}
+
+/// Returns the last item of a list, or nil if it is empty.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ last . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "3");
+/// ```
+pub fn last(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("last", args)?;
+    Ok(array("last", l)?.last().cloned().unwrap_or(Value::Nil))
+}
+
+/// Returns all but the first item of a list.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ rest . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "[2 3]");
+/// ```
+pub fn rest(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("rest", args)?;
+    let l = array("rest", l)?;
+    Ok(Value::Array(l.get(1..).unwrap_or_default().to_vec()))
+}
+
+/// Returns all but the last item of a list.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ initial . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "[1 2]");
+/// ```
+pub fn initial(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("initial", args)?;
+    let l = array("initial", l)?;
+    Ok(Value::Array(l[..l.len().saturating_sub(1)].to_vec()))
+}
+
+/// Returns a list with an item added at the end, i.e. `append list item`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ append . 4 }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "[1 2 3 4]");
+/// ```
+pub fn append(args: &[Value]) -> Result<Value, FuncError> {
+    let [l, item] = exact::<2>("append", args)?;
+    let mut l = array("append", l)?.clone();
+    l.push(item.clone());
+    Ok(Value::Array(l))
+}
+
+/// Returns a list with an item added at the start, i.e. `prepend list item`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ prepend . 0 }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "[0 1 2 3]");
+/// ```
+pub fn prepend(args: &[Value]) -> Result<Value, FuncError> {
+    let [l, item] = exact::<2>("prepend", args)?;
+    let mut l = array("prepend", l)?.clone();
+    l.insert(0, item.clone());
+    Ok(Value::Array(l))
+}
+
+/// Returns the items of all lists given as arguments in one list.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ concat . (list 3) . }}", vec![1, 2]);
+/// assert_eq!(&value.unwrap(), "[1 2 3 1 2]");
+/// ```
+pub fn concat(args: &[Value]) -> Result<Value, FuncError> {
+    let mut items = vec![];
+    for l in args {
+        items.extend_from_slice(array("concat", l)?);
+    }
+    Ok(Value::Array(items))
+}
+
+/// Returns a list in reverse order.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ reverse . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "[3 2 1]");
+/// ```
+pub fn reverse(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("reverse", args)?;
+    Ok(Value::Array(
+        array("reverse", l)?.iter().rev().cloned().collect(),
+    ))
+}
+
+/// Returns a list without repeated items. The first of equal items is kept.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ uniq . }}", vec![1, 2, 1, 3, 2]);
+/// assert_eq!(&value.unwrap(), "[1 2 3]");
+/// ```
+pub fn uniq(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("uniq", args)?;
+    let mut items: Vec<Value> = vec![];
+    for item in array("uniq", l)? {
+        if !items.contains(item) {
+            items.push(item.clone());
+        }
+    }
+    Ok(Value::Array(items))
+}
+
+/// Returns a list without the items equal to any of the other arguments, i.e.
+/// `without list item ...`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ without . 1 3 }}", vec![1, 2, 1, 3]);
+/// assert_eq!(&value.unwrap(), "[2]");
+/// ```
+pub fn without(args: &[Value]) -> Result<Value, FuncError> {
+    let (l, omitted) = args
+        .split_first()
+        .ok_or_else(|| FuncError::AtLeastXArgs("without".into(), 1))?;
+    Ok(Value::Array(
+        array("without", l)?
+            .iter()
+            .filter(|item| !omitted.contains(item))
+            .cloned()
+            .collect(),
+    ))
+}
+
+/// Returns whether a list contains an item, i.e. `has item list`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ has 2 . }}", vec![1, 2, 3]);
+/// assert_eq!(&value.unwrap(), "true");
+/// ```
+pub fn has(args: &[Value]) -> Result<Value, FuncError> {
+    let [item, l] = exact::<2>("has", args)?;
+    Ok(val!(array("has", l)?.contains(item)))
+}
+
+/// Returns a list without its empty items, see `empty`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ compact . }}", vec!["a", "", "b"]);
+/// assert_eq!(&value.unwrap(), "[a b]");
+/// ```
+pub fn compact(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("compact", args)?;
+    Ok(Value::Array(
+        array("compact", l)?
+            .iter()
+            .filter(|item| is_true(item))
+            .cloned()
+            .collect(),
+    ))
+}
+
+/// Returns the textual representations of the items of a list in alphabetical order.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ sortAlpha . }}", vec!["b", "C", "a"]);
+/// assert_eq!(&value.unwrap(), "[C a b]");
+/// ```
+pub fn sort_alpha(args: &[Value]) -> Result<Value, FuncError> {
+    let [l] = exact::<1>("sortAlpha", args)?;
+    let mut items: Vec<String> = array("sortAlpha", l)?
+        .iter()
+        .map(|item| eval_args(slice::from_ref(item)))
+        .collect();
+    items.sort();
+    Ok(Value::Array(items.into_iter().map(Value::from).collect()))
+}
+
+/// Returns a list of maps sorted by the value of a key, i.e. `sortBy key list`. The values
+/// have to be all numbers or all strings. Maps with equal values keep their order.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"{{ range list (dict "n" 2) (dict "n" 1) | sortBy "n" }}{{ .n }}{{ end }}"#;
+/// assert_eq!(&template(tmpl, "").unwrap(), "12");
+/// ```
+pub fn sort_by(args: &[Value]) -> Result<Value, FuncError> {
+    let [key, l] = exact::<2>("sortBy", args)?;
+    let key = string("sortBy", key)?;
+    let mut items = array("sortBy", l)?
+        .iter()
+        .map(|item| field("sortBy", item, key).map(|k| (basic(k), item)))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Numbers compare with numbers and strings with strings only.
+    let kinds = items.iter().map(|(k, _)| match *k {
+        Basic::Int(_) | Basic::Float(_) => Ok(0),
+        Basic::String(_) => Ok(1),
+        _ => Err(FuncError::Generic(format!(
+            "sortBy: invalid type for comparison of {}",
+            key
+        ))),
+    });
+    let mut kind = None;
+    for k in kinds {
+        let k = k?;
+        if kind.get_or_insert(k) != &k {
+            return Err(FuncError::Generic(format!(
+                "sortBy: incompatible types for comparison of {}",
+                key
+            )));
+        }
+    }
+    let float = |k: &Basic| match *k {
+        Basic::Int(i) => i as f64,
+        Basic::Float(f) => f,
+        _ => 0.0,
+    };
+    items.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Basic::Int(a), Basic::Int(b)) => a.cmp(b),
+        (Basic::String(a), Basic::String(b)) => a.cmp(b),
+        (a, b) => float(a).total_cmp(&float(b)),
+    });
+    Ok(Value::Array(
+        items.into_iter().map(|(_, item)| item.clone()).collect(),
+    ))
+}
+
+/// Returns a map from the values of a key to the maps of a list with that value, i.e.
+/// `groupBy key list`. Values are converted to strings and the maps keep their order.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"{{ $g := list (dict "t" "a" "n" 1) (dict "t" "b" "n" 2) (dict "t" "a" "n" 3) | groupBy "t" }}
+/// {{- range $t, $items := $g }}{{ $t }}:{{ range $items }}{{ .n }}{{ end }} {{ end }}"#;
+/// assert_eq!(&template(tmpl, "").unwrap(), "a:13 b:2 ");
+/// ```
+pub fn group_by(args: &[Value]) -> Result<Value, FuncError> {
+    let [key, l] = exact::<2>("groupBy", args)?;
+    let key = string("groupBy", key)?;
+    let mut groups: HashMap<String, Value> = HashMap::new();
+    for item in array("groupBy", l)? {
+        let group = eval_args(slice::from_ref(field("groupBy", item, key)?));
+        if let Value::Array(ref mut items) =
+            *groups.entry(group).or_insert_with(|| Value::Array(vec![]))
+        {
+            items.push(item.clone());
+        }
+    }
+    Ok(Value::Map(groups))
+}
+
+/// Returns a list split into lists of a size, the last of which may be shorter, i.e.
+/// `chunk size list`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ chunk 2 . }}", vec![1, 2, 3, 4, 5]);
+/// assert_eq!(&value.unwrap(), "[[1 2] [3 4] [5]]");
+/// ```
+pub fn chunk(args: &[Value]) -> Result<Value, FuncError> {
+    let [size, l] = exact::<2>("chunk", args)?;
+    let size = match usize::try_from(int("chunk", size)?) {
+        Ok(size) if size > 0 => size,
+        _ => return Err(FuncError::Generic("chunk: size must be positive".into())),
+    };
+    Ok(Value::Array(
+        array("chunk", l)?
+            .chunks(size)
+            .map(|c| Value::Array(c.to_vec()))
+            .collect(),
+    ))
+}
+
+/// Returns a list of lists of the items at the same position in all lists given as
+/// arguments. The result is as long as the shortest list.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ zip . (list "a" "b" "c") }}"#, vec![1, 2]);
+/// assert_eq!(&value.unwrap(), "[[1 a] [2 b]]");
+/// ```
+pub fn zip(args: &[Value]) -> Result<Value, FuncError> {
+    if args.is_empty() {
+        return Err(FuncError::AtLeastXArgs("zip".into(), 1));
+    }
+    let lists = args
+        .iter()
+        .map(|l| array("zip", l))
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = lists.iter().map(|l| l.len()).min().unwrap_or(0);
+    Ok(Value::Array(
+        (0..len)
+            .map(|i| Value::Array(lists.iter().map(|l| l[i].clone()).collect()))
+            .collect(),
+    ))
+}
+
+/// Returns a list of the integers from a start to an end, both included, like the shell's
+/// `seq`: `seq end` starts at 1, `seq start end` counts down if the end is lower and
+/// `seq start step end` counts in steps. Like for `until`, the list must not exceed the
+/// maximum number of range iterations of the execution.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ seq 3 }} {{ seq 5 3 }} {{ seq 0 5 10 }}", "");
+/// assert_eq!(&value.unwrap(), "[1 2 3] [5 4 3] [0 5 10]");
+/// ```
+pub fn seq(args: &[Value]) -> Result<Value, FuncError> {
+    let ints = args
+        .iter()
+        .map(|arg| int("seq", arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (start, step, end) = match ints[..] {
+        [end] => (1, if end < 1 { -1 } else { 1 }, end),
+        [start, end] => (start, if end < start { -1 } else { 1 }, end),
+        [start, step, end] => (start, step, end),
+        _ => {
+            return Err(FuncError::Generic(
+                "seq: wrong number of args: want 1, 2 or 3".into(),
+            ))
+        }
+    };
+    steps("seq", start, end, step, true)
+}
+
+/// Returns a list of the integers from 0 up to a count, or down to it if it is negative,
+/// excluding the count. The list must not exceed the maximum number of range iterations of the
+/// execution, or 16777216 items.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ range until 3 }}{{ . }}{{ end }}", "");
+/// assert_eq!(&value.unwrap(), "012");
+/// ```
+pub fn until(args: &[Value]) -> Result<Value, FuncError> {
+    let [count] = exact::<1>("until", args)?;
+    let count = int("until", count)?;
+    steps("until", 0, count, if count < 0 { -1 } else { 1 }, false)
+}
+
+/// Returns a list of the integers from a start to a stop in steps, excluding the stop, i.e.
+/// `untilStep start stop step`. The list is empty if the step does not lead from the start
+/// to the stop. Like for `until`, the list must not exceed the maximum number of range
+/// iterations of the execution.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ untilStep 0 10 4 }} {{ untilStep 3 0 -1 }}", "");
+/// assert_eq!(&value.unwrap(), "[0 4 8] [3 2 1]");
+/// ```
+pub fn until_step(args: &[Value]) -> Result<Value, FuncError> {
+    let [start, stop, step] = exact::<3>("untilStep", args)?;
+    let name = "untilStep";
+    steps(
+        name,
+        int(name, start)?,
+        int(name, stop)?,
+        int(name, step)?,
+        false,
+    )
+}
+
+// The integers from `start` towards `end` in steps of `step` for the function `name`.
+fn steps(name: &str, start: i64, end: i64, step: i64, inclusive: bool) -> Result<Value, FuncError> {
+    // The distance to cover in the direction of `step`, which is negative if it leads away.
+    let distance = (i128::from(end) - i128::from(start)) * i128::from(step.signum());
+    let step_size = i128::from(step).abs();
+    let len = match distance {
+        _ if step == 0 || distance < 0 => 0,
+        _ if inclusive => distance / step_size + 1,
+        _ => (distance + step_size - 1) / step_size,
+    };
+    let len = check_len(name, len)?;
+    let items = (0..len as i128).map(|k| val!((i128::from(start) + k * i128::from(step)) as i64));
+    Ok(Value::Array(items.collect()))
+}
+
+// The array argument of the function `name`.
+fn array<'a>(name: &str, val: &'a Value) -> Result<&'a Vec<Value>, FuncError> {
+    match *val {
+        Value::Array(ref a) => Ok(a),
+        _ => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected slice; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
+
+// The value of the key `key` of a map item of a list given to the function `name`.
+fn field<'a>(name: &str, item: &'a Value, key: &str) -> Result<&'a Value, FuncError> {
+    map(name, item)?
+        .get(key)
+        .ok_or_else(|| FuncError::Generic(format!("{}: map has no key {}", name, key)))
+}
+
+/// Returns the escaped HTML equivalent of the textual representation of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let html = template(r#"{{ html "<a href='x'>Tom & Jerry</a>" }}"#, 0);
+/// assert_eq!(&html.unwrap(), "&lt;a href=&#39;x&#39;&gt;Tom &amp; Jerry&lt;/a&gt;");
+/// ```
+pub fn html(args: &[Value]) -> Result<Value, FuncError> {
+    let s = eval_args(args);
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("&#34;"),
+            '\'' => escaped.push_str("&#39;"),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\0' => escaped.push('\u{FFFD}'),
+            _ => escaped.push(c),
+        }
+    }
+    Ok(val!(escaped))
+}
+
+/// Returns the escaped JavaScript equivalent of the textual representation of its arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let js = template(r#"{{ js "it's <b>\"bold\"</b>" }}"#, 0);
+/// assert_eq!(&js.unwrap(), r#"it\'s \u003Cb\u003E\"bold\"\u003C/b\u003E"#);
+/// ```
+pub fn js(args: &[Value]) -> Result<Value, FuncError> {
+    let s = eval_args(args);
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str(r"\\"),
+            '\'' => escaped.push_str(r"\'"),
+            '"' => escaped.push_str(r#"\""#),
+            '<' => escaped.push_str(r"\u003C"),
+            '>' => escaped.push_str(r"\u003E"),
+            '&' => escaped.push_str(r"\u0026"),
+            '=' => escaped.push_str(r"\u003D"),
+            ' ' => escaped.push(' '),
+            _ if c.is_control() || c.is_whitespace() => {
+                // Control and white space characters all fit into four hex digits.
+                escaped.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            _ => escaped.push(c),
+        }
+    }
+    Ok(val!(escaped))
+}
+
+/// Returns the escaped value of the textual representation of
+/// its arguments in a form suitable for embedding in a URL query.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let url = template(r#"{{ urlquery "foo bar?" }}"#, 0);
+/// assert_eq!(&url.unwrap(), "foo+bar%3F");
 /// ```
 pub fn urlquery(args: &[Value]) -> Result<Value, FuncError> {
-    if args.len() != 1 {
-        return Err(FuncError::ExactlyXArgs("urlquery".into(), 1));
+    let s = eval_args(args);
+    let escaped = utf8_percent_encode(&s, QUERY_ENCODE).to_string();
+    Ok(val!(escaped.replace("%20", "+")))
+}
+
+/// Returns the textual representation of its arguments escaped like Go's `xml.EscapeText`,
+/// so it can be used in XML text and attribute values. Characters XML does not allow are
+/// replaced by U+FFFD.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let xml = template(r#"<title a="{{ xmlEscape . }}"/>"#, "Q&A: \"<xml>\"");
+/// assert_eq!(&xml.unwrap(), r#"<title a="Q&amp;A: &#34;&lt;xml&gt;&#34;"/>"#);
+/// ```
+pub fn xml_escape(args: &[Value]) -> Result<Value, FuncError> {
+    let s = eval_args(args);
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("&#34;"),
+            '\'' => escaped.push_str("&#39;"),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' => escaped.push_str("&#x9;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..='\u{10FFFF}' => {
+                escaped.push(c)
+            }
+            _ => escaped.push('\u{FFFD}'),
+        }
+    }
+    Ok(val!(escaped))
+}
+
+// Returns the textual representation of the arguments of the escape functions like Go's
+// `evalArgs`.
+fn eval_args(args: &[Value]) -> String {
+    match args {
+        [Value::String(s)] => s.clone(),
+        _ => sprint(args),
+    }
+}
+
+/// Returns its string argument in upper case.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let upper = template("{{ upper . }}", "café");
+/// assert_eq!(&upper.unwrap(), "CAFÉ");
+/// ```
+pub fn upper(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("upper", args)?;
+    Ok(val!(string("upper", s)?.to_uppercase()))
+}
+
+/// Returns its string argument in lower case.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let lower = template("{{ lower . }}", "CAFÉ");
+/// assert_eq!(&lower.unwrap(), "café");
+/// ```
+pub fn lower(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("lower", args)?;
+    Ok(val!(string("lower", s)?.to_lowercase()))
+}
+
+/// Returns its string argument without leading and trailing white space.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template("[{{ trim . }}]", "  foo \n");
+/// assert_eq!(&trimmed.unwrap(), "[foo]");
+/// ```
+pub fn trim(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("trim", args)?;
+    Ok(val!(string("trim", s)?.trim()))
+}
+
+/// Returns the string without the characters of the cutset at its start and end, i.e.
+/// `trimAll cutset s` is Go's `strings.Trim(s, cutset)`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ trimAll "-=" . }}"#, "=-foo-=");
+/// assert_eq!(&trimmed.unwrap(), "foo");
+/// ```
+pub fn trim_all(args: &[Value]) -> Result<Value, FuncError> {
+    let [cutset, s] = exact::<2>("trimAll", args)?;
+    let cutset = string("trimAll", cutset)?;
+    let s = string("trimAll", s)?;
+    Ok(val!(s.trim_matches(|c| cutset.contains(c))))
+}
+
+/// Returns the string without the prefix, i.e. `trimPrefix prefix s`. The string is returned
+/// unchanged if it does not start with the prefix.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ . | trimPrefix "v" }}"#, "v1.2");
+/// assert_eq!(&trimmed.unwrap(), "1.2");
+/// ```
+pub fn trim_prefix(args: &[Value]) -> Result<Value, FuncError> {
+    let [prefix, s] = exact::<2>("trimPrefix", args)?;
+    let prefix = string("trimPrefix", prefix)?;
+    let s = string("trimPrefix", s)?;
+    Ok(val!(s.strip_prefix(prefix).unwrap_or(s)))
+}
+
+/// Returns the string without the suffix, i.e. `trimSuffix suffix s`. The string is returned
+/// unchanged if it does not end with the suffix.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let trimmed = template(r#"{{ . | trimSuffix ".txt" }}"#, "notes.txt");
+/// assert_eq!(&trimmed.unwrap(), "notes");
+/// ```
+pub fn trim_suffix(args: &[Value]) -> Result<Value, FuncError> {
+    let [suffix, s] = exact::<2>("trimSuffix", args)?;
+    let suffix = string("trimSuffix", suffix)?;
+    let s = string("trimSuffix", s)?;
+    Ok(val!(s.strip_suffix(suffix).unwrap_or(s)))
+}
+
+/// Returns the string with all occurrences of old replaced by new, i.e. `replace old new s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let replaced = template(r#"{{ . | replace " " "-" }}"#, "a b c");
+/// assert_eq!(&replaced.unwrap(), "a-b-c");
+/// ```
+pub fn replace(args: &[Value]) -> Result<Value, FuncError> {
+    let [old, new, s] = exact::<3>("replace", args)?;
+    let old = string("replace", old)?;
+    let new = string("replace", new)?;
+    Ok(val!(string("replace", s)?.replace(old, new)))
+}
+
+/// Returns the parts of the string between the separators, i.e. `split sep s`. An empty
+/// separator splits the string into its characters.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let parts = template(r#"{{ range split "," . }}[{{ . }}]{{ end }}"#, "a,b,,c");
+/// assert_eq!(&parts.unwrap(), "[a][b][][c]");
+/// ```
+pub fn split(args: &[Value]) -> Result<Value, FuncError> {
+    let [sep, s] = exact::<2>("split", args)?;
+    let sep = string("split", sep)?;
+    Ok(split_n(string("split", s)?, sep, -1))
+}
+
+/// Returns at most n parts of the string between the separators, i.e. `splitn sep n s`. The
+/// last part is the unsplit remainder. Like Go's `strings.SplitN` a negative n returns all
+/// parts and zero none.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let parts = template(r#"{{ range splitn "=" 2 . }}[{{ . }}]{{ end }}"#, "a=b=c");
+/// assert_eq!(&parts.unwrap(), "[a][b=c]");
+/// ```
+pub fn splitn(args: &[Value]) -> Result<Value, FuncError> {
+    let [sep, n, s] = exact::<3>("splitn", args)?;
+    let sep = string("splitn", sep)?;
+    let n = int("splitn", n)?;
+    Ok(split_n(string("splitn", s)?, sep, n))
+}
+
+fn split_n(s: &str, sep: &str, n: i64) -> Value {
+    let n = usize::try_from(n).unwrap_or(usize::MAX);
+    if n == 0 {
+        return Value::Array(vec![]);
+    }
+    if !sep.is_empty() {
+        return Value::Array(s.splitn(n, sep).map(Value::from).collect());
+    }
+    let mut parts = vec![];
+    for (i, c) in s.char_indices() {
+        if parts.len() + 1 == n {
+            parts.push(val!(&s[i..]));
+            break;
+        }
+        parts.push(val!(c.to_string()));
+    }
+    Value::Array(parts)
+}
+
+/// Returns the items of the array joined by the separator, i.e. `join sep items`. Items are
+/// formatted like `print` formats them.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let joined = template(r#"{{ join ", " . }}"#, vec![1, 2, 3]);
+/// assert_eq!(&joined.unwrap(), "1, 2, 3");
+/// ```
+pub fn join(args: &[Value]) -> Result<Value, FuncError> {
+    let [sep, items] = exact::<2>("join", args)?;
+    let sep = string("join", sep)?;
+    let items = match *items {
+        Value::Array(ref items) => items,
+        _ => {
+            return Err(FuncError::Generic(format!(
+                "join: wrong type for value; expected array; got {}",
+                type_name(items)
+            )))
+        }
+    };
+    let parts: Vec<String> = items
+        .iter()
+        .map(|item| sprint(std::slice::from_ref(item)))
+        .collect();
+    Ok(val!(parts.join(sep)))
+}
+
+/// Returns whether the string contains the substring, i.e. `contains substr s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let found = template(r#"{{ contains "ell" . }}"#, "hello");
+/// assert_eq!(&found.unwrap(), "true");
+/// ```
+pub fn contains(args: &[Value]) -> Result<Value, FuncError> {
+    let [substr, s] = exact::<2>("contains", args)?;
+    let substr = string("contains", substr)?;
+    Ok(val!(string("contains", s)?.contains(substr)))
+}
+
+/// Returns whether the string starts with the prefix, i.e. `hasPrefix prefix s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let found = template(r#"{{ hasPrefix "he" . }}"#, "hello");
+/// assert_eq!(&found.unwrap(), "true");
+/// ```
+pub fn has_prefix(args: &[Value]) -> Result<Value, FuncError> {
+    let [prefix, s] = exact::<2>("hasPrefix", args)?;
+    let prefix = string("hasPrefix", prefix)?;
+    Ok(val!(string("hasPrefix", s)?.starts_with(prefix)))
+}
+
+/// Returns whether the string ends with the suffix, i.e. `hasSuffix suffix s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let found = template(r#"{{ hasSuffix "lo" . }}"#, "hello");
+/// assert_eq!(&found.unwrap(), "true");
+/// ```
+pub fn has_suffix(args: &[Value]) -> Result<Value, FuncError> {
+    let [suffix, s] = exact::<2>("hasSuffix", args)?;
+    let suffix = string("hasSuffix", suffix)?;
+    Ok(val!(string("hasSuffix", s)?.ends_with(suffix)))
+}
+
+/// Returns the string repeated count times, i.e. `repeat count s`. The result must not exceed
+/// the maximum output size of the execution, or 1 GiB.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let repeated = template("{{ repeat 3 . }}", "ab");
+/// assert_eq!(&repeated.unwrap(), "ababab");
+/// ```
+pub fn repeat(args: &[Value]) -> Result<Value, FuncError> {
+    let [count, s] = exact::<2>("repeat", args)?;
+    let count = int("repeat", count)?;
+    let count = usize::try_from(count)
+        .map_err(|_| FuncError::Generic(format!("repeat: negative count {}", count)))?;
+    let s = string("repeat", s)?;
+    check_size("repeat", s.len().checked_mul(count))?;
+    Ok(val!(s.repeat(count)))
+}
+
+/// Returns the characters of the string from start up to but excluding end, i.e.
+/// `substr start end s`. A negative start starts at the beginning of the string and a
+/// negative end or one past the string ends at its end.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sub = template("{{ substr 1 3 . }} {{ substr 2 -1 . }}", "café");
+/// assert_eq!(&sub.unwrap(), "af fé");
+/// ```
+pub fn substr(args: &[Value]) -> Result<Value, FuncError> {
+    let [start, end, s] = exact::<3>("substr", args)?;
+    let start = usize::try_from(int("substr", start)?).unwrap_or(0);
+    let end = usize::try_from(int("substr", end)?).unwrap_or(usize::MAX);
+    let s = string("substr", s)?;
+    let sub: String = s.chars().take(end).skip(start).collect();
+    Ok(val!(sub))
+}
+
+/// Returns the string with every line indented by a number of spaces, i.e. `indent n s`. The
+/// result must not exceed the maximum output size.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let indented = template("{{ indent 2 . }}", "a:\n  b: 1");
+/// assert_eq!(&indented.unwrap(), "  a:\n    b: 1");
+/// ```
+pub fn indent(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("indent", args)?;
+    Ok(val!(indent_lines("indent", n, s)?))
+}
+
+/// Returns the string with every line indented by a number of spaces after a newline, i.e.
+/// `nindent n s`. This starts a block on a line of its own, e.g. the output of `toYaml`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let yaml = template("spec:{{ nindent 2 . }}", "replicas: 1\nimage: app");
+/// assert_eq!(&yaml.unwrap(), "spec:\n  replicas: 1\n  image: app");
+/// ```
+pub fn nindent(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("nindent", args)?;
+    Ok(val!(format!("\n{}", indent_lines("nindent", n, s)?)))
+}
+
+// Indents the lines of the string `s` by `n` spaces.
+fn indent_lines(name: &str, n: &Value, s: &Value) -> Result<String, FuncError> {
+    let n = int(name, n)?;
+    let n = usize::try_from(n)
+        .map_err(|_| FuncError::Generic(format!("{}: negative count {}", name, n)))?;
+    let s = string(name, s)?;
+    let lines = s.matches('\n').count() + 1;
+    check_size(
+        name,
+        n.checked_mul(lines)
+            .and_then(|pad| pad.checked_add(s.len())),
+    )?;
+    let pad = " ".repeat(n);
+    Ok(format!("{}{}", pad, s.replace('\n', &format!("\n{}", pad))))
+}
+
+/// Returns the string wrapped at a number of columns, i.e. `wrap n s`. Lines are broken
+/// between words, and words wider than the columns get a line of their own.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let wrapped = template("{{ wrap 10 . }}", "The quick brown fox jumps");
+/// assert_eq!(&wrapped.unwrap(), "The quick\nbrown fox\njumps");
+/// ```
+pub fn wrap(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("wrap", args)?;
+    Ok(val!(wrap_lines("wrap", n, "\n", s)?))
+}
+
+/// Returns the string wrapped at a number of columns with a custom line break, i.e.
+/// `wrapWith n sep s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let wrapped = template(r#"{{ wrapWith 10 "<br>" . }}"#, "The quick brown fox");
+/// assert_eq!(&wrapped.unwrap(), "The quick<br>brown fox");
+/// ```
+pub fn wrap_with(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, sep, s] = exact::<3>("wrapWith", args)?;
+    let sep = string("wrapWith", sep)?;
+    Ok(val!(wrap_lines("wrapWith", n, sep, s)?))
+}
+
+/// Returns the string truncated to a number of columns, i.e. `trunc n s`. A negative number
+/// keeps the columns at the end of the string.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let truncated = template("{{ trunc 5 . }} {{ trunc -3 . }}", "hello world");
+/// assert_eq!(&truncated.unwrap(), "hello rld");
+/// ```
+pub fn trunc(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("trunc", args)?;
+    let n = int("trunc", n)?;
+    let s = string("trunc", s)?;
+    let columns = usize::try_from(n.unsigned_abs()).unwrap_or(usize::MAX);
+    let truncated: String = if n < 0 {
+        let tail: Vec<char> = take_columns(s.chars().rev(), columns).collect();
+        tail.into_iter().rev().collect()
+    } else {
+        take_columns(s.chars(), columns).collect()
+    };
+    Ok(val!(truncated))
+}
+
+/// Returns the string abbreviated with `...` if it is wider than a number of columns, i.e.
+/// `abbrev n s`. The number includes the ellipsis and must be at least 4.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let abbreviated = template("{{ abbrev 8 . }}|{{ abbrev 8 \"short\" }}", "hello world");
+/// assert_eq!(&abbreviated.unwrap(), "hello...|short");
+/// ```
+pub fn abbrev(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("abbrev", args)?;
+    let n = columns("abbrev", n)?;
+    let s = string("abbrev", s)?;
+    if n < 4 {
+        return Err(FuncError::Generic(format!(
+            "abbrev: width {} is less than 4",
+            n
+        )));
+    }
+    if s.width() <= n {
+        return Ok(val!(s));
+    }
+    let abbreviated: String = take_columns(s.chars(), n - 3).collect();
+    Ok(val!(abbreviated + "..."))
+}
+
+/// Returns the string padded with spaces on the left to a number of columns, i.e.
+/// `padLeft n s`, e.g. to align numbers in a column. Like with `repeat`, the result must not
+/// exceed the maximum output size.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let padded = template("[{{ padLeft 6 . }}]", "1.5");
+/// assert_eq!(&padded.unwrap(), "[   1.5]");
+/// ```
+pub fn pad_left(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("padLeft", args)?;
+    let (pad, s) = padding("padLeft", n, s)?;
+    Ok(val!(format!("{}{}", " ".repeat(pad), s)))
+}
+
+/// Returns the string padded with spaces on the right to a number of columns, i.e.
+/// `padRight n s`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let padded = template("[{{ padRight 6 . }}]", "日本");
+/// assert_eq!(&padded.unwrap(), "[日本  ]");
+/// ```
+pub fn pad_right(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("padRight", args)?;
+    let (pad, s) = padding("padRight", n, s)?;
+    Ok(val!(format!("{}{}", s, " ".repeat(pad))))
+}
+
+/// Returns the string centered in a number of columns with spaces, i.e. `center n s`. An odd
+/// space goes to the right.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let centered = template("[{{ center 8 . }}]", "title");
+/// assert_eq!(&centered.unwrap(), "[ title  ]");
+/// ```
+pub fn center(args: &[Value]) -> Result<Value, FuncError> {
+    let [n, s] = exact::<2>("center", args)?;
+    let (pad, s) = padding("center", n, s)?;
+    let left = pad / 2;
+    Ok(val!(format!(
+        "{}{}{}",
+        " ".repeat(left),
+        s,
+        " ".repeat(pad - left)
+    )))
+}
+
+/// Returns rows of maps or lists as a text table with aligned columns, i.e.
+/// `table column ... rows`. A column like `name:Name` shows the key `name` of maps under the
+/// header `Name`, and a leading `>` aligns it to the right. Lists fill the columns in order.
+/// A header is followed by a separator row, so maps and lists with the same columns render
+/// alike. Without columns, maps show all their keys in sorted order, or in insertion order for
+/// maps created by [`ordered_map`](crate::ordered_map), while lists have no keys to name the
+/// columns, so they get neither a header nor a separator.
+///
+/// # Example
+/// ```
+/// use gtmpl::{template, Value};
+/// let rows = vec![
+///     vec![Value::from("web"), Value::from(3)],
+///     vec![Value::from("worker"), Value::from(12)],
+/// ];
+/// let table = template(r#"{{ table "NAME" ">READY" . }}"#, rows);
+/// assert_eq!(
+///     &table.unwrap(),
+///     "NAME    READY\n------  -----\nweb         3\nworker     12"
+/// );
+/// ```
+pub fn table(args: &[Value]) -> Result<Value, FuncError> {
+    let (rows, specs) = args
+        .split_last()
+        .ok_or_else(|| FuncError::AtLeastXArgs("table".into(), 1))?;
+    let rows = array("table", rows)?;
+    // The key, header and right alignment of each column.
+    let mut columns = specs
+        .iter()
+        .map(|spec| {
+            let spec = string("table", spec)?;
+            let (right, spec) = match spec.strip_prefix('>') {
+                Some(spec) => (true, spec),
+                None => (false, spec),
+            };
+            let (key, header) = spec.split_once(':').unwrap_or((spec, spec));
+            Ok((key, header, right))
+        })
+        .collect::<Result<Vec<_>, FuncError>>()?;
+    if columns.is_empty() {
+        let mut keys: Vec<&str> = vec![];
+        for (k, _) in rows
+            .iter()
+            .flat_map(|m| ordered::entries(m, true).unwrap_or_default())
+        {
+            if !keys.contains(&k.as_str()) {
+                keys.push(k);
+            }
+        }
+        if !rows.iter().any(|m| matches!(*m, Value::OrderedMap(_))) {
+            keys.sort_unstable();
+        }
+        columns = keys.into_iter().map(|key| (key, key, false)).collect();
+    }
+    let mut cells = vec![];
+    if !columns.is_empty() {
+        cells.push(
+            columns
+                .iter()
+                .map(|&(_, header, _)| header.to_owned())
+                .collect(),
+        );
+    }
+    for row in rows {
+        let row_cells: Vec<String> = match (row, ordered::as_map(row)) {
+            (_, Some(m)) => columns
+                .iter()
+                .map(|&(key, _, _)| m.get(key).map_or_else(String::new, table_cell))
+                .collect(),
+            (Value::Array(items), _) if columns.is_empty() => {
+                items.iter().map(table_cell).collect()
+            }
+            (Value::Array(items), _) => (0..columns.len())
+                .map(|i| items.get(i).map_or_else(String::new, table_cell))
+                .collect(),
+            _ => {
+                return Err(FuncError::Generic(format!(
+                    "table: wrong type for row; expected map or slice; got {}",
+                    type_name(row)
+                )))
+            }
+        };
+        cells.push(row_cells);
+    }
+    let mut widths = vec![0; cells.iter().map(Vec::len).max().unwrap_or(0)];
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    if !columns.is_empty() {
+        cells.insert(1, widths.iter().map(|&w| "-".repeat(w)).collect());
+    }
+    let lines: Vec<String> = cells
+        .iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let pad = " ".repeat(widths[i] - cell.width());
+                let right = columns.get(i).is_some_and(|&(_, _, right)| right);
+                if i > 0 {
+                    line.push_str("  ");
+                }
+                if right {
+                    line.extend([&*pad, &**cell]);
+                } else {
+                    line.extend([&**cell, &*pad]);
+                }
+            }
+            line.trim_end().to_owned()
+        })
+        .collect();
+    Ok(val!(lines.join("\n")))
+}
+
+// A value printed in a table cell. Nil is left empty and lines are joined to keep the
+// alignment.
+fn table_cell(val: &Value) -> String {
+    match *val {
+        Value::Nil | Value::NoValue => String::new(),
+        _ => format_value(val).replace('\n', " "),
+    }
+}
+
+// The number of columns argument of the function `name`.
+fn columns(name: &str, n: &Value) -> Result<usize, FuncError> {
+    let n = int(name, n)?;
+    usize::try_from(n).map_err(|_| FuncError::Generic(format!("{}: negative width {}", name, n)))
+}
+
+// The string `s` and the number of spaces it needs to fill `n` columns, which must not make it
+// exceed the maximum size.
+fn padding<'a>(name: &str, n: &Value, s: &'a Value) -> Result<(usize, &'a str), FuncError> {
+    let n = columns(name, n)?;
+    let s = string(name, s)?;
+    let pad = n.saturating_sub(s.width());
+    check_size(name, s.len().checked_add(pad))?;
+    Ok((pad, s))
+}
+
+// The leading characters of `chars` filling at most `n` columns.
+fn take_columns(chars: impl Iterator<Item = char>, n: usize) -> impl Iterator<Item = char> {
+    let mut used = 0;
+    chars.take_while(move |c| {
+        used += c.width().unwrap_or(0);
+        used <= n
+    })
+}
+
+// Wraps the lines of the string `s` at `n` columns, breaking them with `sep`.
+fn wrap_lines(name: &str, n: &Value, sep: &str, s: &Value) -> Result<String, FuncError> {
+    let n = columns(name, n)?;
+    let s = string(name, s)?;
+    let mut wrapped = String::with_capacity(s.len());
+    for (i, line) in s.split('\n').enumerate() {
+        if i > 0 {
+            wrapped.push_str(sep);
+        }
+        let mut used = 0;
+        for word in line.split(' ').filter(|word| !word.is_empty()) {
+            let width = word.width();
+            if used > 0 && used + 1 + width > n {
+                wrapped.push_str(sep);
+                used = 0;
+            } else if used > 0 {
+                wrapped.push(' ');
+                used += 1;
+            }
+            wrapped.push_str(word);
+            used += width;
+        }
+    }
+    Ok(wrapped)
+}
+
+/// Returns the textual representations of its arguments in double quotes with Go escapes,
+/// separated by spaces. Nil arguments are left out.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quoted = template("{{ quote . 1 }}", "say \"hi\"");
+/// assert_eq!(&quoted.unwrap(), r#""say \"hi\"" "1""#);
+/// ```
+pub fn quote(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(val!(quote_args(args, utils::quote_str)))
+}
+
+/// Returns the textual representations of its arguments in single quotes, separated by
+/// spaces. Nil arguments are left out.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quoted = template("{{ squote . 1 }}", "hi");
+/// assert_eq!(&quoted.unwrap(), "'hi' '1'");
+/// ```
+pub fn squote(args: &[Value]) -> Result<Value, FuncError> {
+    Ok(val!(quote_args(args, |s| format!("'{}'", s))))
+}
+
+// Quotes the textual representations of the arguments which are not nil.
+fn quote_args(args: &[Value], quote: impl Fn(&str) -> String) -> String {
+    let quoted: Vec<String> = args
+        .iter()
+        .filter(|arg| !matches!(arg, Value::Nil | Value::NoValue))
+        .map(|arg| quote(&eval_args(slice::from_ref(arg))))
+        .collect();
+    quoted.join(" ")
+}
+
+/// Returns its arguments as words for POSIX shells, separated by spaces. Lists are quoted
+/// element by element and nil arguments are left out. Words with characters other than
+/// letters, digits and `@%+=:,./_-` are put in single quotes.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let command = template("rm {{ shellQuote . \"file-1.txt\" }}", "it's here");
+/// assert_eq!(&command.unwrap(), r"rm 'it'\''s here' file-1.txt");
+/// ```
+pub fn shell_quote(args: &[Value]) -> Result<Value, FuncError> {
+    let quoted = quote_words("shellQuote", args, |s| {
+        let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+        if !s.is_empty() && s.chars().all(safe) {
+            return s.to_owned();
+        }
+        format!("'{}'", s.replace('\'', r"'\''"))
+    })?;
+    Ok(val!(quoted))
+}
+
+/// Returns its arguments in single quotes for PowerShell, separated by spaces. Lists are
+/// quoted element by element and nil arguments are left out.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let command = template("Remove-Item {{ powershellQuote . }}", "it's $here");
+/// assert_eq!(&command.unwrap(), "Remove-Item 'it''s $here'");
+/// ```
+pub fn powershell_quote(args: &[Value]) -> Result<Value, FuncError> {
+    let quoted = quote_words("powershellQuote", args, |s| {
+        let mut quoted = String::with_capacity(s.len() + 2);
+        quoted.push('\'');
+        for c in s.chars() {
+            // PowerShell also ends single quoted strings with typographic single quotes.
+            if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                quoted.push(c);
+            }
+            quoted.push(c);
+        }
+        quoted.push('\'');
+        quoted
+    })?;
+    Ok(val!(quoted))
+}
+
+// Quotes the textual representations of the arguments and of the elements of list arguments
+// which are not nil for the function `name`. Arguments of commands cannot contain NUL.
+fn quote_words(
+    name: &str,
+    args: &[Value],
+    quote: impl Fn(&str) -> String,
+) -> Result<String, FuncError> {
+    let mut words = vec![];
+    for arg in args {
+        let items = match *arg {
+            Value::Array(ref items) => &items[..],
+            _ => slice::from_ref(arg),
+        };
+        for item in items {
+            if matches!(item, Value::Nil | Value::NoValue) {
+                continue;
+            }
+            let word = eval_args(slice::from_ref(item));
+            if word.contains('\0') {
+                return Err(FuncError::Generic(format!(
+                    "{}: argument contains NUL",
+                    name
+                )));
+            }
+            words.push(quote(&word));
+        }
+    }
+    Ok(words.join(" "))
+}
+
+/// Returns the string with the first letter of each word in title case like Go's
+/// `strings.Title`. Words are found by Unicode's word boundary rules, so apostrophes and
+/// combining marks do not start a new word.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let title = template("{{ title . }}", "ǆemal's ŝip-yard");
+/// assert_eq!(&title.unwrap(), "ǅemal's Ŝip-Yard");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn title(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("title", args)?;
+    Ok(val!(map_word_starts(string("title", s)?, push_title)))
+}
+
+/// Returns the string with the first letter of each word in lower case, the reverse of
+/// `title`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let untitled = template("{{ untitle . }}", "Élan Vital");
+/// assert_eq!(&untitled.unwrap(), "élan vital");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn untitle(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("untitle", args)?;
+    Ok(val!(map_word_starts(string("untitle", s)?, |c, out| {
+        out.extend(c.to_lowercase())
+    })))
+}
+
+/// Returns the words of the string joined in upper camel case, e.g. `HttpServer` for
+/// `http_server` or `HTTPServer`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let camel = template("{{ camelcase . }}", "straße_name");
+/// assert_eq!(&camel.unwrap(), "StraßeName");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn camelcase(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("camelcase", args)?;
+    let mut camel = String::new();
+    for word in words(string("camelcase", s)?) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            push_title(first, &mut camel);
+            camel.push_str(&chars.as_str().to_lowercase());
+        }
+    }
+    Ok(val!(camel))
+}
+
+/// Returns the words of the string in lower case joined by underscores.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let snake = template("{{ snakecase . }}", "ÜberCoolThing");
+/// assert_eq!(&snake.unwrap(), "über_cool_thing");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn snakecase(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("snakecase", args)?;
+    Ok(val!(lower_words(string("snakecase", s)?, "_")))
+}
+
+/// Returns the words of the string in lower case joined by hyphens.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let kebab = template("{{ kebabcase . }}", "HTTPServer v2");
+/// assert_eq!(&kebab.unwrap(), "http-server-v2");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn kebabcase(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("kebabcase", args)?;
+    Ok(val!(lower_words(string("kebabcase", s)?, "-")))
+}
+
+/// Returns the string with upper case letters in lower case and the other way around.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let swapped = template("{{ swapcase . }}", "Hello ΣΟΦΊΑ");
+/// assert_eq!(&swapped.unwrap(), "hELLO σοφία");
+/// ```
+#[cfg(feature = "unicode")]
+pub fn swapcase(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("swapcase", args)?;
+    let mut swapped = String::new();
+    for c in string("swapcase", s)?.chars() {
+        if c.is_uppercase() {
+            swapped.extend(c.to_lowercase());
+        } else if c.is_lowercase() {
+            swapped.extend(c.to_uppercase());
+        } else {
+            swapped.push(c);
+        }
+    }
+    Ok(val!(swapped))
+}
+
+// Pushes the title case of `c`, which differs from its upper case for digraphs like `ǆ`.
+#[cfg(feature = "unicode")]
+fn push_title(c: char, out: &mut String) {
+    let title = unicode_case_mapping::to_titlecase(c);
+    if title == [0; 3] {
+        out.push(c);
+    } else {
+        let chars = title.iter().take_while(|&&u| u != 0);
+        out.extend(chars.filter_map(|&u| char::from_u32(u)));
+    }
+}
+
+// Replaces the first character of each word of `s` by what `f` pushes.
+#[cfg(feature = "unicode")]
+fn map_word_starts(s: &str, f: impl Fn(char, &mut String)) -> String {
+    let mut out = String::with_capacity(s.len());
+    for segment in s.split_word_bounds() {
+        let mut chars = segment.chars();
+        match chars.next() {
+            Some(first) if first.is_alphanumeric() => {
+                f(first, &mut out);
+                out.push_str(chars.as_str());
+            }
+            _ => out.push_str(segment),
+        }
+    }
+    out
+}
+
+#[cfg(feature = "unicode")]
+fn lower_words(s: &str, sep: &str) -> String {
+    let words: Vec<String> = words(s).into_iter().map(str::to_lowercase).collect();
+    words.join(sep)
+}
+
+// The words of an identifier or phrase, e.g. `["HTTP", "Server", "id"]` for `HTTPServer_id`.
+// Words are separated by anything but letters and digits and start at upper case letters
+// following lower case letters or digits, or preceding lower case letters.
+#[cfg(feature = "unicode")]
+fn words(s: &str) -> Vec<&str> {
+    // Combining marks belong to the character before them.
+    let graphemes: Vec<(usize, char)> = s
+        .grapheme_indices(true)
+        .filter_map(|(i, g)| g.chars().next().map(|c| (i, c)))
+        .collect();
+    let mut words = vec![];
+    let mut start = None;
+    for (k, &(i, c)) in graphemes.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(start) = start.take() {
+                words.push(&s[start..i]);
+            }
+            continue;
+        }
+        let prev = graphemes[..k].last().map(|&(_, p)| p);
+        let next = graphemes.get(k + 1).map(|&(_, n)| n);
+        let hump = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+        match start {
+            Some(word) if hump => {
+                words.push(&s[word..i]);
+                start = Some(i);
+            }
+            Some(_) => {}
+            None => start = Some(i),
+        }
+    }
+    if let Some(start) = start {
+        words.push(&s[start..]);
+    }
+    words
+}
+
+/// Returns a random version 4 UUID. Deterministic executions get the same UUIDs for the same
+/// seed.
+///
+/// # Example
+/// ```
+/// use std::time::UNIX_EPOCH;
+///
+/// use gtmpl::clock::Deterministic;
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.exec_options.deterministic = Some(Deterministic {
+///     now: UNIX_EPOCH,
+///     seed: 42,
+/// });
+/// tmpl.parse("{{ uuidv4 }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "bdd73226-2feb-4e95-a8ef-e333b266f103");
+/// ```
+#[cfg(feature = "uuid")]
+pub fn uuidv4(args: &[Value]) -> Result<Value, FuncError> {
+    exact::<0>("uuidv4", args)?;
+    let bits = u128::from(clock::random()) << 64 | u128::from(clock::random());
+    let uuid = uuid::Builder::from_random_bytes(bits.to_be_bytes()).into_uuid();
+    Ok(val!(uuid.hyphenated().to_string()))
+}
+
+/// Returns a UUID in its hyphenated lower case form. The UUID may also be written without
+/// hyphens, in braces or as a URN.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let uuid = template("{{ uuidParse . }}", "{67E55044-10B1-426F-9247-BB680E5FE0C8}");
+/// assert_eq!(&uuid.unwrap(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+/// ```
+#[cfg(feature = "uuid")]
+pub fn uuid_parse(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("uuidParse", args)?;
+    let s = string("uuidParse", s)?;
+    uuid::Uuid::parse_str(s)
+        .map(|uuid| val!(uuid.hyphenated().to_string()))
+        .map_err(|e| FuncError::Generic(format!("uuidParse: {}", e)))
+}
+
+/// Returns whether a string is a UUID in one of the forms `uuidParse` accepts.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let valid = template("{{ isUuid . }}", "67e55044-10b1-426f-9247");
+/// assert_eq!(&valid.unwrap(), "false");
+/// ```
+#[cfg(feature = "uuid")]
+pub fn is_uuid(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("isUuid", args)?;
+    Ok(val!(uuid::Uuid::parse_str(string("isUuid", s)?).is_ok()))
+}
+
+/// Returns Markdown converted to HTML, with the extensions for tables, strikethrough, footnotes
+/// and task lists. HTML in the Markdown is escaped as text, but the URLs of links are not
+/// checked, so the HTML of untrusted Markdown still needs sanitizing.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let html = template("{{ markdown . }}", "# Notes\n\nSome *emphasis* <b>here</b>.");
+/// assert_eq!(
+///     &html.unwrap(),
+///     "<h1>Notes</h1>\n<p>Some <em>emphasis</em> &lt;b&gt;here&lt;/b&gt;.</p>\n"
+/// );
+/// ```
+#[cfg(feature = "markdown")]
+pub fn markdown(args: &[Value]) -> Result<Value, FuncError> {
+    use pulldown_cmark::{html, Event, Options, Parser};
+
+    let [s] = exact::<1>("markdown", args)?;
+    let s = string("markdown", s)?;
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(s, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        event => event,
+    });
+    let mut out = String::with_capacity(s.len() * 3 / 2);
+    html::push_html(&mut out, events);
+    Ok(val!(out))
+}
+
+/// Returns a string of random letters and digits of the given length, e.g. for generated
+/// passwords.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let password = template("{{ randAlphaNum 16 }}", "").unwrap();
+/// assert_eq!(password.len(), 16);
+/// assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+/// ```
+pub fn rand_alpha_num(args: &[Value]) -> Result<Value, FuncError> {
+    random_string(
+        "randAlphaNum",
+        args,
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+    )
+}
+
+/// Returns a string of random letters of the given length.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let name = template("{{ randAlpha 8 }}", "").unwrap();
+/// assert!(name.len() == 8 && name.chars().all(|c| c.is_ascii_alphabetic()));
+/// ```
+pub fn rand_alpha(args: &[Value]) -> Result<Value, FuncError> {
+    random_string(
+        "randAlpha",
+        args,
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+    )
+}
+
+/// Returns a string of random digits of the given length.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let pin = template("{{ randNumeric 4 }}", "").unwrap();
+/// assert!(pin.len() == 4 && pin.chars().all(|c| c.is_ascii_digit()));
+/// ```
+pub fn rand_numeric(args: &[Value]) -> Result<Value, FuncError> {
+    random_string("randNumeric", args, b"0123456789")
+}
+
+/// Returns a random integer which is at least the first and less than the second argument.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let port = template("{{ randInt 8000 8100 }}", "").unwrap();
+/// assert!((8000..8100).contains(&port.parse::<i64>().unwrap()));
+/// ```
+pub fn rand_int(args: &[Value]) -> Result<Value, FuncError> {
+    let [min, max] = exact::<2>("randInt", args)?;
+    let (min, max) = (int("randInt", min)?, int("randInt", max)?);
+    if min >= max {
+        return Err(FuncError::Generic(format!(
+            "randInt: min {} is not less than max {}",
+            min, max
+        )));
+    }
+    // The difference fits into an `u64`, and so does the result of adding to `min`.
+    let span = (i128::from(max) - i128::from(min)) as u64;
+    Ok(Value::from(min.wrapping_add(random_below(span) as i64)))
+}
+
+/// Returns the characters of a string or the items of a list in random order.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let shuffled = template("{{ shuffle . }}", "abc").unwrap();
+/// let mut chars: Vec<char> = shuffled.chars().collect();
+/// chars.sort();
+/// assert_eq!(chars, vec!['a', 'b', 'c']);
+/// ```
+pub fn shuffle(args: &[Value]) -> Result<Value, FuncError> {
+    let [v] = exact::<1>("shuffle", args)?;
+    match *v {
+        Value::String(ref s) => {
+            let mut chars: Vec<char> = s.chars().collect();
+            shuffle_slice(&mut chars);
+            Ok(Value::from(chars.into_iter().collect::<String>()))
+        }
+        Value::Array(ref items) => {
+            let mut items = items.clone();
+            shuffle_slice(&mut items);
+            Ok(Value::Array(items))
+        }
+        ref v => Err(FuncError::Generic(format!(
+            "shuffle: wrong type for value; expected string or slice; got {}",
+            type_name(v)
+        ))),
+    }
+}
+
+// A string of `n` characters picked at random from `chars`, where `n` is the only argument and
+// must not exceed the maximum size.
+fn random_string(name: &str, args: &[Value], chars: &[u8]) -> Result<Value, FuncError> {
+    let [n] = exact::<1>(name, args)?;
+    let n = usize::try_from(int(name, n)?)
+        .map_err(|_| FuncError::Generic(format!("{}: negative count {}", name, n)))?;
+    let n = check_size(name, Some(n))?;
+    let s = (0..n)
+        .map(|_| char::from(chars[random_below(chars.len() as u64) as usize]))
+        .collect::<String>();
+    Ok(Value::from(s))
+}
+
+// Shuffles `items` with the Fisher-Yates algorithm.
+fn shuffle_slice<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, random_below(i as u64 + 1) as usize);
+    }
+}
+
+// A random number less than `n` from `clock::random`. The bias is negligible for the small
+// ranges templates use.
+fn random_below(n: u64) -> u64 {
+    ((u128::from(clock::random()) * u128::from(n)) >> 64) as u64
+}
+
+/// Returns a size in bytes with a binary unit, e.g. `1.5 GiB`. Sizes of 10 units or more are
+/// rounded to whole units.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let size = template("{{ humanizeBytes 512 }}, {{ humanizeBytes . }}", 1_610_612_736);
+/// assert_eq!(&size.unwrap(), "512 B, 1.5 GiB");
+/// ```
+pub fn humanize_bytes(args: &[Value]) -> Result<Value, FuncError> {
+    const UNITS: [&str; 8] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB", "ZiB", "YiB"];
+    let [n] = exact::<1>("humanizeBytes", args)?;
+    let bytes = number("humanizeBytes", n)?.float();
+    let sign = if bytes < 0.0 { "-" } else { "" };
+    let mut size = bytes.abs();
+    if size < 1024.0 || size.is_nan() {
+        return Ok(val!(format!("{}{} B", sign, size.round())));
+    }
+    let mut unit = 0;
+    size /= 1024.0;
+    // Rounding up may reach the next unit, e.g. for 1023.9 KiB.
+    while size.round() >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    let rounded = if size < 10.0 {
+        format!("{:.1}", size)
+    } else {
+        format!("{:.0}", size)
+    };
+    Ok(val!(format!("{}{} {}", sign, rounded, UNITS[unit])))
+}
+
+/// Returns a number with commas separating groups of thousands, e.g. `1,234,567.5`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let number = template("{{ humanizeNumber . }}", 1_234_567);
+/// assert_eq!(&number.unwrap(), "1,234,567");
+/// ```
+pub fn humanize_number(args: &[Value]) -> Result<Value, FuncError> {
+    let [n] = exact::<1>("humanizeNumber", args)?;
+    let text = match number("humanizeNumber", n)? {
+        Num::Int(i) => i.to_string(),
+        Num::Float(f) => f.to_string(),
+    };
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", &text[..]),
+    };
+    let (int, frac) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    // Infinities and NaN are printed as they are.
+    if !int.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(val!(text));
+    }
+    let mut grouped = String::from(sign);
+    for (i, c) in int.char_indices() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped.push_str(frac);
+    Ok(val!(grouped))
+}
+
+/// Returns an integer with its English ordinal suffix, e.g. `3rd` or `11th`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let place = template("{{ ordinal 1 }} {{ ordinal 12 }} {{ ordinal . }}", 23);
+/// assert_eq!(&place.unwrap(), "1st 12th 23rd");
+/// ```
+pub fn ordinal(args: &[Value]) -> Result<Value, FuncError> {
+    let [n] = exact::<1>("ordinal", args)?;
+    let n = int("ordinal", n)?;
+    let suffix = match (n.unsigned_abs() % 100, n.unsigned_abs() % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    };
+    Ok(val!(format!("{}{}", n, suffix)))
+}
+
+/// Returns `singular` if a count is 1 and `plural` otherwise, i.e. `plural count singular
+/// plural`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let items = template(r#"{{ . }} {{ plural . "item" "items" }}"#, 3);
+/// assert_eq!(&items.unwrap(), "3 items");
+/// ```
+pub fn plural(args: &[Value]) -> Result<Value, FuncError> {
+    let [count, singular, plural] = exact::<3>("plural", args)?;
+    let word = match is_one("plural", count)? {
+        true => string("plural", singular)?,
+        false => string("plural", plural)?,
+    };
+    Ok(val!(word))
+}
+
+/// Returns the English plural of the last word of a string unless a count is 1, i.e.
+/// `pluralize count word`. Common irregular and uncountable nouns are known, and the case of
+/// the word is kept.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"{{ pluralize 1 "reply" }}, {{ pluralize . "reply" }}, {{ "Child" | pluralize . }}"#;
+/// assert_eq!(&template(tmpl, 2).unwrap(), "reply, replies, Children");
+/// ```
+pub fn pluralize(args: &[Value]) -> Result<Value, FuncError> {
+    let [count, word] = exact::<2>("pluralize", args)?;
+    let word = string("pluralize", word)?;
+    match is_one("pluralize", count)? {
+        true => Ok(val!(word)),
+        false => Ok(val!(inflect_plural(word))),
+    }
+}
+
+// Nouns with irregular plurals.
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+    ("calf", "calves"),
+    ("child", "children"),
+    ("elf", "elves"),
+    ("foot", "feet"),
+    ("goose", "geese"),
+    ("half", "halves"),
+    ("knife", "knives"),
+    ("leaf", "leaves"),
+    ("life", "lives"),
+    ("loaf", "loaves"),
+    ("man", "men"),
+    ("mouse", "mice"),
+    ("ox", "oxen"),
+    ("person", "people"),
+    ("self", "selves"),
+    ("shelf", "shelves"),
+    ("thief", "thieves"),
+    ("tooth", "teeth"),
+    ("wife", "wives"),
+    ("wolf", "wolves"),
+    ("woman", "women"),
+];
+
+// Nouns without a plural form.
+const UNCOUNTABLE: &[&str] = &[
+    "data",
+    "deer",
+    "equipment",
+    "feedback",
+    "fish",
+    "information",
+    "metadata",
+    "money",
+    "news",
+    "rice",
+    "series",
+    "sheep",
+    "software",
+    "species",
+];
+
+// The English plural of the last word of `s`.
+fn inflect_plural(s: &str) -> String {
+    let start = s
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !c.is_alphabetic())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let (head, word) = s.split_at(start);
+    let lower = word.to_lowercase();
+    let upper = word.chars().count() > 1 && !word.chars().any(char::is_lowercase);
+    if word.is_empty() || UNCOUNTABLE.contains(&&*lower) {
+        return s.to_owned();
+    }
+    if let Some(&(_, plural)) = IRREGULAR_PLURALS.iter().find(|&&(one, _)| one == lower) {
+        let plural = match word.chars().next() {
+            _ if upper => plural.to_uppercase(),
+            Some(c) if c.is_uppercase() => c.to_uppercase().chain(plural.chars().skip(1)).collect(),
+            _ => plural.to_owned(),
+        };
+        return format!("{}{}", head, plural);
+    }
+    let ends = |suffix: &&str| lower.ends_with(suffix);
+    let consonant_y = lower.ends_with('y') && !["ay", "ey", "iy", "oy", "uy"].iter().any(ends);
+    // The number of bytes to replace at the end of the word and their replacement, which is
+    // lower case to pluralize acronyms like `URL` as usual.
+    let (strip, suffix) = if ends(&"quiz") {
+        (0, "zes")
+    } else if ["matrix", "vertex", "index"].iter().any(ends) {
+        (2, "ices")
+    } else if ends(&"sis") {
+        (2, "es")
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(ends) {
+        (0, "es")
+    } else if consonant_y {
+        (1, "ies")
+    } else if ["hero", "potato", "tomato", "echo", "veto"]
+        .iter()
+        .any(ends)
+    {
+        (0, "es")
+    } else {
+        (0, "s")
+    };
+    format!("{}{}", &s[..s.len() - strip], suffix)
+}
+
+// Whether the count argument of the function `name` is 1.
+fn is_one(name: &str, count: &Value) -> Result<bool, FuncError> {
+    match number(name, count)? {
+        Num::Int(i) => Ok(i == 1),
+        Num::Float(f) => Ok(f == 1.0),
+    }
+}
+
+/// Returns the value of an environment variable, or an empty string if it is not set. Only
+/// available after [`Template::add_env_funcs`](crate::Template::add_env_funcs).
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// std::env::set_var("GTMPL_EXAMPLE_USER", "ann");
+/// let mut tmpl = Template::default();
+/// tmpl.add_env_funcs();
+/// tmpl.parse(r#"user: {{ env "GTMPL_EXAMPLE_USER" }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "user: ann");
+/// ```
+pub fn env(args: &[Value]) -> Result<Value, FuncError> {
+    let [name] = exact::<1>("env", args)?;
+    Ok(Value::from(getenv(string("env", name)?)))
+}
+
+/// Replaces `$var` and `${var}` in a string by the values of the environment variables like
+/// Go's `os.ExpandEnv`. Variables which are not set are replaced by an empty string. Only
+/// available after [`Template::add_env_funcs`](crate::Template::add_env_funcs).
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// std::env::set_var("GTMPL_EXAMPLE_HOME", "/home/ann");
+/// let mut tmpl = Template::default();
+/// tmpl.add_env_funcs();
+/// tmpl.parse("{{ expandenv . }}").unwrap();
+/// let output = tmpl.render(&Context::from("${GTMPL_EXAMPLE_HOME}/.config"));
+/// assert_eq!(output.unwrap(), "/home/ann/.config");
+/// ```
+pub fn expandenv(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("expandenv", args)?;
+    Ok(Value::from(expand(string("expandenv", s)?, getenv)))
+}
+
+fn getenv(name: &str) -> String {
+    if name.is_empty() || name.contains(&['=', '\0'][..]) {
+        return String::new();
+    }
+    std::env::var_os(name)
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Replaces the variables in `s` by the values `mapping` returns for their names like Go's
+// `os.Expand`. A `$` which is not followed by a name is kept, invalid `${}` are dropped.
+fn expand(s: &str, mapping: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if rest.is_empty() {
+            out.push('$');
+            break;
+        }
+        let (name, len) = shell_name(rest);
+        if !name.is_empty() {
+            out.push_str(&mapping(name));
+        } else if len == 0 {
+            out.push('$');
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The name of the variable at the start of `s`, which follows a `$`, and the number of bytes
+// it takes up, like Go's `getShellName`.
+fn shell_name(s: &str) -> (&str, usize) {
+    let bytes = s.as_bytes();
+    let special = |c: u8| b"*#$@!?-".contains(&c) || c.is_ascii_digit();
+    if bytes[0] == b'{' {
+        if bytes.len() > 2 && special(bytes[1]) && bytes[2] == b'}' {
+            return (&s[1..2], 3);
+        }
+        return match s.find('}') {
+            Some(1) => ("", 2),
+            Some(end) => (&s[1..end], end + 1),
+            None => ("", 1),
+        };
+    }
+    if special(bytes[0]) {
+        return (&s[..1], 1);
+    }
+    let len = bytes
+        .iter()
+        .take_while(|&&c| c == b'_' || c.is_ascii_alphanumeric())
+        .count();
+    (&s[..len], len)
+}
+
+// The arguments of a function taking exactly `N` of them.
+fn exact<'a, const N: usize>(name: &str, args: &'a [Value]) -> Result<&'a [Value; N], FuncError> {
+    args.try_into()
+        .map_err(|_| FuncError::ExactlyXArgs(name.into(), N))
+}
+
+// The string argument of the function `name`.
+fn string<'a>(name: &str, val: &'a Value) -> Result<&'a str, FuncError> {
+    match *val {
+        Value::String(ref s) => Ok(s),
+        _ => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected string; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
+
+// The integer argument of the function `name`.
+fn int(name: &str, val: &Value) -> Result<i64, FuncError> {
+    match basic(val) {
+        Basic::Int(i) => i64::try_from(i)
+            .map_err(|_| FuncError::Generic(format!("{}: integer {} out of range", name, i))),
+        _ => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected int; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
+
+// The largest strings and lists functions like `repeat` and `until` build, so a huge count
+// fails instead of aborting the process when memory runs out.
+const MAX_SIZE: usize = 1 << 30;
+const MAX_LEN: usize = 1 << 24;
+
+thread_local! {
+    // The largest strings in bytes and lists in items functions build in the execution on the
+    // current thread.
+    static MAX_SIZES: Cell<(usize, usize)> = const { Cell::new((MAX_SIZE, MAX_LEN)) };
+}
+
+// Runs `f` with functions building strings of at most `max_size` bytes and lists of at most
+// `max_len` items, e.g. the maximum output size and number of iterations of an execution.
+pub(crate) fn with_max_sizes<R>(max_size: usize, max_len: usize, f: impl FnOnce() -> R) -> R {
+    // Restores the limits of an enclosing execution even if `f` panics.
+    struct Restore((usize, usize));
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MAX_SIZES.with(|cell| cell.set(self.0));
+        }
+    }
+    let max = (max_size.min(MAX_SIZE), max_len.min(MAX_LEN));
+    let _restore = Restore(MAX_SIZES.with(|cell| cell.replace(max)));
+    f()
+}
+
+// Fails unless the function `name` may build a string of `size` bytes, where `None` stands for
+// a size which overflows.
+fn check_size(name: &str, size: Option<usize>) -> Result<usize, FuncError> {
+    let (max, _) = MAX_SIZES.with(Cell::get);
+    size.filter(|&size| size <= max).ok_or_else(|| {
+        FuncError::Generic(format!(
+            "{}: result exceeds the maximum size of {} bytes",
+            name, max
+        ))
+    })
+}
+
+// Fails unless the function `name` may build a list of `len` items.
+fn check_len(name: &str, len: impl TryInto<usize>) -> Result<usize, FuncError> {
+    let (_, max) = MAX_SIZES.with(Cell::get);
+    len.try_into()
+        .ok()
+        .filter(|&len| len <= max)
+        .ok_or_else(|| {
+            FuncError::Generic(format!(
+                "{}: result exceeds the maximum length of {} items",
+                name, max
+            ))
+        })
+}
+
+/// Returns the boolean truth of arg1 == arg2 [|| arg1 == arg3 ...], i.e. whether arg1 is equal
+/// to any of the other arguments.
+///
+/// Integers compare by value regardless of their type, but comparing an integer with a float
+/// fails like in Go, even for `eq 1 1.0`. Arrays, maps and functions can not be compared.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let equal = template("{{ eq 1 1 . }}", 1);
+/// assert_eq!(&equal.unwrap(), "true");
+///
+/// let equal = template("{{ eq . 1 2 3 }}", 2);
+/// assert_eq!(&equal.unwrap(), "true");
+/// ```
+pub fn eq(args: &[Value]) -> Result<Value, FuncError> {
+    if args.len() < 2 {
+        return Err(FuncError::AtLeastXArgs("eq".into(), 2));
+    }
+    let first = &args[0];
+    for arg in &args[1..] {
+        if equal(first, arg)? {
+            return Ok(Value::from(true));
+        }
+    }
+    Ok(Value::from(false))
+}
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 != arg2
+
+# Example
+```
+use gtmpl::template;
+let not_equal = template(\"{{ ne 2 . }}\", 1);
+assert_eq!(&not_equal.unwrap(), \"true\");
+```
+"]
+ne(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
+ Ok(Value::from(!equal(a, b)?))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 < arg2
+
+Only numbers and strings can be ordered.
+
+# Example
+```
+use gtmpl::template;
+let less_than = template(\"{{ lt 0 . }}\", 1);
+assert_eq!(&less_than.unwrap(), \"true\");
+```
+"]
+lt(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
+ Ok(Value::from(less(a, b)?))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 <= arg2
+
+# Example
+```
+use gtmpl::template;
+let less_or_equal = template(\"{{ le 1.4 . }}\", 1.4);
+assert_eq!(less_or_equal.unwrap(), \"true\");
+
+let less_or_equal = template(\"{{ le 0.2 . }}\", 1.4);
+assert_eq!(&less_or_equal.unwrap(), \"true\");
+```
+"]
+le(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
+ Ok(Value::from(less(a, b)? || equal(a, b)?))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 > arg2
+
+# Example
+```
+use gtmpl::template;
+let greater_than = template(\"{{ gt 1.4 . }}\", 1.2);
+assert_eq!(&greater_than.unwrap(), \"true\");
+```
+"]
+gt(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
+ Ok(Value::from(!(less(a, b)? || equal(a, b)?)))
+});
+
+gn!(
+#[doc="
+Returns the boolean truth of arg1 >= arg2
+
+# Example
+```
+use gtmpl::template;
+let greater_or_equal = template(\"{{ ge 1.4 1.3 }}\", 1.2);
+assert_eq!(greater_or_equal.unwrap(), \"true\");
+
+let greater_or_equal = template(\"{{ ge 1.4 . }}\", 0.2);
+assert_eq!(&greater_or_equal.unwrap(), \"true\");
+```
+"]
+ge(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
+ Ok(Value::from(!less(a, b)?))
+});
+
+/// Returns the sum of its two numeric arguments.
+///
+/// Integers stay integers and fail on overflow. If either argument is a float, both are
+/// converted to floats.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ add 1 2 }} {{ add 1 0.5 }}", "");
+/// assert_eq!(&sum.unwrap(), "3 1.5");
+/// ```
+pub fn add(args: &[Value]) -> Result<Value, FuncError> {
+    arith("add", args, i128::checked_add, |a, b| a + b)
+}
+
+/// Returns the difference of its two numeric arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let difference = template("{{ sub 3 1.5 }}", "");
+/// assert_eq!(&difference.unwrap(), "1.5");
+/// ```
+pub fn sub(args: &[Value]) -> Result<Value, FuncError> {
+    arith("sub", args, i128::checked_sub, |a, b| a - b)
+}
+
+/// Returns the product of its two numeric arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let product = template("{{ mul 2 3 }}", "");
+/// assert_eq!(&product.unwrap(), "6");
+/// ```
+pub fn mul(args: &[Value]) -> Result<Value, FuncError> {
+    arith("mul", args, i128::checked_mul, |a, b| a * b)
+}
+
+/// Returns the quotient of its two numeric arguments. Integers use integer division, which
+/// truncates towards zero. Dividing by zero fails, also for floats.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quotient = template("{{ div 7 2 }} {{ div 7.5 2 }}", "");
+/// assert_eq!(&quotient.unwrap(), "3 3.75");
+/// ```
+pub fn div(args: &[Value]) -> Result<Value, FuncError> {
+    divisor("div", args)?;
+    arith("div", args, i128::checked_div, |a, b| a / b)
+}
+
+/// Returns the remainder of dividing its two numeric arguments, which has the sign of the
+/// dividend like Go's `%` and `math.Mod`. Dividing by zero fails.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let remainder = template("{{ mod 7 3 }} {{ mod -7 3 }} {{ mod 7.5 2 }}", "");
+/// assert_eq!(&remainder.unwrap(), "1 -1 1.5");
+/// ```
+pub fn modulo(args: &[Value]) -> Result<Value, FuncError> {
+    divisor("mod", args)?;
+    arith("mod", args, i128::checked_rem, |a, b| a % b)
+}
+
+/// Returns the largest of its numeric arguments. The argument is returned as it is, i.e. an
+/// integer stays an integer even if other arguments are floats.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let largest = template("{{ max 1 . 2.5 }}", 3);
+/// assert_eq!(&largest.unwrap(), "3");
+/// ```
+pub fn max(args: &[Value]) -> Result<Value, FuncError> {
+    extreme("max", args, Ordering::Greater)
+}
+
+/// Returns the smallest of its numeric arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let smallest = template("{{ min 1 . 2.5 }}", -3);
+/// assert_eq!(&smallest.unwrap(), "-3");
+/// ```
+pub fn min(args: &[Value]) -> Result<Value, FuncError> {
+    extreme("min", args, Ordering::Less)
+}
+
+/// Returns the absolute value of its numeric argument.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let absolute = template("{{ abs -2 }} {{ abs . }}", 1.5);
+/// assert_eq!(&absolute.unwrap(), "2 1.5");
+/// ```
+pub fn abs(args: &[Value]) -> Result<Value, FuncError> {
+    let [x] = exact::<1>("abs", args)?;
+    match number("abs", x)? {
+        Num::Int(i) => int_value("abs", Some(i.abs())),
+        Num::Float(f) => Ok(val!(f.abs())),
+    }
+}
+
+/// Returns its numeric argument negated.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let negated = template("{{ neg 2 }} {{ neg . }}", -3);
+/// assert_eq!(&negated.unwrap(), "-2 3");
+/// ```
+pub fn neg(args: &[Value]) -> Result<Value, FuncError> {
+    let [x] = exact::<1>("neg", args)?;
+    match number("neg", x)? {
+        Num::Int(i) => int_value("neg", Some(-i)),
+        Num::Float(f) => Ok(val!(-f)),
     }
-    let val = &args[0];
-    match *val {
-        Value::String(ref s) => Ok(val!(utf8_percent_encode(s, QUERY_ENCODE).to_string())),
-        _ => Err(FuncError::Generic(
-            "Arguments need to be of type String".into(),
-        )),
+}
+
+/// Returns the greatest integer value less than or equal to its numeric argument as a float.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let floored = template("{{ floor 1.7 }} {{ floor 0.5 }}", "");
+/// assert_eq!(&floored.unwrap(), "1 0");
+/// ```
+pub fn floor(args: &[Value]) -> Result<Value, FuncError> {
+    let [x] = exact::<1>("floor", args)?;
+    Ok(val!(number("floor", x)?.float().floor()))
+}
+
+/// Returns the least integer value greater than or equal to its numeric argument as a float.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let ceiled = template("{{ ceil 1.2 }} {{ ceil 0.5 }}", "");
+/// assert_eq!(&ceiled.unwrap(), "2 1");
+/// ```
+pub fn ceil(args: &[Value]) -> Result<Value, FuncError> {
+    let [x] = exact::<1>("ceil", args)?;
+    Ok(val!(number("ceil", x)?.float().ceil()))
+}
+
+/// Returns its numeric argument rounded to the given number of decimal places, or to an
+/// integer value without them, i.e. `round x [places]`. Halves are rounded away from zero
+/// like Go's `math.Round`. Negative places round to tens, hundreds and so on.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let rounded = template("{{ round 2.5 }} {{ round 3.14159 2 }} {{ round 1234 -2 }}", "");
+/// assert_eq!(&rounded.unwrap(), "3 3.14 1200");
+/// ```
+pub fn round(args: &[Value]) -> Result<Value, FuncError> {
+    let (x, places) = match args {
+        [x] => (x, 0),
+        [x, places] => (x, int("round", places)?),
+        _ => {
+            return Err(FuncError::Generic(
+                "round: wrong number of args: want 1 or 2".into(),
+            ))
+        }
+    };
+    let x = number("round", x)?.float();
+    let places = i32::try_from(places).unwrap_or(if places < 0 { i32::MIN } else { i32::MAX });
+    let scale = 10f64.powi(places);
+    let rounded = match (x * scale).round() / scale {
+        r if r.is_finite() => r,
+        // The scale over- or underflowed, so there are no more places to round to.
+        _ if places > 0 => x,
+        _ => 0.0,
+    };
+    Ok(val!(rounded))
+}
+
+/// Returns the sum of its numeric arguments as a float.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ addf 1.5 2 0.25 }}", "");
+/// assert_eq!(&sum.unwrap(), "3.75");
+/// ```
+pub fn addf(args: &[Value]) -> Result<Value, FuncError> {
+    fold_floats("addf", args, |a, b| Ok(a + b))
+}
+
+/// Returns the first numeric argument minus the others as a float.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let difference = template("{{ subf 10 2.5 0.5 }}", "");
+/// assert_eq!(&difference.unwrap(), "7");
+/// ```
+pub fn subf(args: &[Value]) -> Result<Value, FuncError> {
+    fold_floats("subf", args, |a, b| Ok(a - b))
+}
+
+/// Returns the product of its numeric arguments as a float.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let product = template("{{ mulf 1.5 3 }}", "");
+/// assert_eq!(&product.unwrap(), "4.5");
+/// ```
+pub fn mulf(args: &[Value]) -> Result<Value, FuncError> {
+    fold_floats("mulf", args, |a, b| Ok(a * b))
+}
+
+/// Returns the first numeric argument divided by the others as a float. Dividing by zero
+/// fails.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let quotient = template("{{ divf 7 2 }}", "");
+/// assert_eq!(&quotient.unwrap(), "3.5");
+/// ```
+pub fn divf(args: &[Value]) -> Result<Value, FuncError> {
+    fold_floats("divf", args, |a, b| {
+        if b == 0.0 {
+            return Err(FuncError::Generic("divf: division by zero".into()));
+        }
+        Ok(a / b)
+    })
+}
+
+/// Returns the bitwise AND of its integer arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let masked = template("{{ band 65280 4080 }} {{ band . 6 3 }}", 7);
+/// assert_eq!(&masked.unwrap(), "3840 2");
+/// ```
+pub fn band(args: &[Value]) -> Result<Value, FuncError> {
+    fold_ints("band", args, |a, b| a & b)
+}
+
+/// Returns the bitwise OR of its integer arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let flags = template("{{ bor 1 4 8 }}", "");
+/// assert_eq!(&flags.unwrap(), "13");
+/// ```
+pub fn bor(args: &[Value]) -> Result<Value, FuncError> {
+    fold_ints("bor", args, |a, b| a | b)
+}
+
+/// Returns the bitwise XOR of its integer arguments.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let toggled = template("{{ bxor 12 10 }}", "");
+/// assert_eq!(&toggled.unwrap(), "6");
+/// ```
+pub fn bxor(args: &[Value]) -> Result<Value, FuncError> {
+    fold_ints("bxor", args, |a, b| a ^ b)
+}
+
+/// Returns the bitwise complement of its integer argument like Go's `^x`, so the result of a
+/// non-negative integer is negative.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let complement = template("{{ bnot 0 }} {{ band 255 (bnot 15) }}", "");
+/// assert_eq!(&complement.unwrap(), "-1 240");
+/// ```
+pub fn bnot(args: &[Value]) -> Result<Value, FuncError> {
+    let [x] = exact::<1>("bnot", args)?;
+    int_value("bnot", Some(!bits("bnot", x)?))
+}
+
+/// Returns its first integer argument shifted left by the second one. Shifting bits out of
+/// 64 bits fails.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let netmask = template("{{ shl 1 8 }} {{ band (shl 4294967295 (sub 32 24)) 4294967295 }}", "");
+/// assert_eq!(&netmask.unwrap(), "256 4294967040");
+/// ```
+pub fn shl(args: &[Value]) -> Result<Value, FuncError> {
+    let [x, n] = exact::<2>("shl", args)?;
+    let (x, n) = (bits("shl", x)?, shift("shl", n)?);
+    match x {
+        0 => Ok(val!(0)),
+        _ if n >= 64 => int_value("shl", None),
+        _ => int_value("shl", Some(x << n)),
     }
 }
 
-/// Returns the boolean truth of arg1 == arg2 [== arg3 ...]
+/// Returns its first integer argument shifted right by the second one. Negative integers
+/// keep their sign like Go's `>>` on signed integers.
 ///
 /// # Example
 /// ```
 /// use gtmpl::template;
-/// let equal = template("{{ eq 1 1 . }}", 1);
-/// assert_eq!(&equal.unwrap(), "true");
+/// let shifted = template("{{ shr 256 4 }} {{ shr -16 2 }}", "");
+/// assert_eq!(&shifted.unwrap(), "16 -4");
 /// ```
-pub fn eq(args: &[Value]) -> Result<Value, FuncError> {
+pub fn shr(args: &[Value]) -> Result<Value, FuncError> {
+    let [x, n] = exact::<2>("shr", args)?;
+    let (x, n) = (bits("shr", x)?, shift("shr", n)?);
+    int_value("shr", Some(x >> n.min(127)))
+}
+
+// Combines at least two integer arguments from left to right.
+fn fold_ints(name: &str, args: &[Value], f: fn(i128, i128) -> i128) -> Result<Value, FuncError> {
     if args.len() < 2 {
-        return Err(FuncError::AtLeastXArgs("eq".into(), 2));
+        return Err(FuncError::AtLeastXArgs(name.into(), 2));
     }
-    let first = &args[0];
-    Ok(Value::from(args.iter().skip(1).all(|x| *x == *first)))
+    let mut acc = bits(name, &args[0])?;
+    for arg in &args[1..] {
+        acc = f(acc, bits(name, arg)?);
+    }
+    int_value(name, Some(acc))
 }
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 != arg2
+// The integer argument of a bitwise function `name`. Floats are rejected instead of
+// truncated.
+fn bits(name: &str, val: &Value) -> Result<i128, FuncError> {
+    match number(name, val)? {
+        Num::Int(i) => Ok(i),
+        Num::Float(_) => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected int; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
 
-# Example
-```
-use gtmpl::template;
-let not_equal = template(\"{{ ne 2 . }}\", 1);
-assert_eq!(&not_equal.unwrap(), \"true\");
-```
-"]
-ne(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
- Ok(Value::from(a != b))
-});
+// The shift count argument of the function `name`, which must not be negative.
+fn shift(name: &str, val: &Value) -> Result<u32, FuncError> {
+    let n = bits(name, val)?;
+    if n < 0 {
+        return Err(FuncError::Generic(format!(
+            "{}: negative shift amount {}",
+            name, n
+        )));
+    }
+    Ok(u32::try_from(n).unwrap_or(u32::MAX))
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 < arg2
+// Combines at least two numeric arguments as floats from left to right.
+fn fold_floats(
+    name: &str,
+    args: &[Value],
+    f: impl Fn(f64, f64) -> Result<f64, FuncError>,
+) -> Result<Value, FuncError> {
+    if args.len() < 2 {
+        return Err(FuncError::AtLeastXArgs(name.into(), 2));
+    }
+    let mut acc = number(name, &args[0])?.float();
+    for arg in &args[1..] {
+        acc = f(acc, number(name, arg)?.float())?;
+    }
+    Ok(val!(acc))
+}
 
-# Example
-```
-use gtmpl::template;
-let less_than = template(\"{{ lt 0 . }}\", 1);
-assert_eq!(&less_than.unwrap(), \"true\");
-```
-"]
-lt(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
- let ret = match cmp(a, b) {
-  None => return Err(FuncError::Generic(format!("unable to compare {} and {}", a, b))),
-  Some(Ordering::Less) => true,
-  _ => false,
- };
- Ok(Value::from(ret))
-});
+// Numbers as seen by the arithmetic functions. Values do not keep the type of integers, so
+// integers of all types fit into an `i128`.
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i128),
+    Float(f64),
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 <= arg2
+impl Num {
+    fn float(self) -> f64 {
+        match self {
+            Num::Int(i) => i as f64,
+            Num::Float(f) => f,
+        }
+    }
+}
 
-# Example
-```
-use gtmpl::template;
-let less_or_equal = template(\"{{ le 1.4 . }}\", 1.4);
-assert_eq!(less_or_equal.unwrap(), \"true\");
+// The numeric argument of the function `name`.
+fn number(name: &str, val: &Value) -> Result<Num, FuncError> {
+    match basic(val) {
+        Basic::Int(i) => Ok(Num::Int(i)),
+        Basic::Float(f) => Ok(Num::Float(f)),
+        _ => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected number; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
 
-let less_or_equal = template(\"{{ le 0.2 . }}\", 1.4);
-assert_eq!(&less_or_equal.unwrap(), \"true\");
-```
-"]
-le(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
- let ret = match cmp(a, b) {
-  None => return Err(FuncError::Generic(format!("unable to compare {} and {}", a, b))),
-  Some(Ordering::Less) | Some(Ordering::Equal) => true,
-  _ => false,
- };
- Ok(Value::from(ret))
-});
+// The result of an integer operation, which fails if it does not fit into an `i64` or `u64`.
+fn int_value(name: &str, i: Option<i128>) -> Result<Value, FuncError> {
+    match i {
+        Some(i) if i64::try_from(i).is_ok() => Ok(val!(i as i64)),
+        Some(i) if u64::try_from(i).is_ok() => Ok(val!(i as u64)),
+        _ => Err(FuncError::Generic(format!("{}: integer overflow", name))),
+    }
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 > arg2
+fn arith(
+    name: &str,
+    args: &[Value],
+    int: fn(i128, i128) -> Option<i128>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value, FuncError> {
+    let [l, r] = exact::<2>(name, args)?;
+    match (number(name, l)?, number(name, r)?) {
+        (Num::Int(l), Num::Int(r)) => int_value(name, int(l, r)),
+        (l, r) => Ok(val!(float(l.float(), r.float()))),
+    }
+}
 
-# Example
-```
-use gtmpl::template;
-let greater_than = template(\"{{ gt 1.4 . }}\", 1.2);
-assert_eq!(&greater_than.unwrap(), \"true\");
-```
-"]
-gt(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
- let ret = match cmp(a, b) {
-  None => return Err(FuncError::Generic(format!("unable to compare {} and {}", a, b))),
-  Some(Ordering::Greater) => true,
-  _ => false,
- };
- Ok(Value::from(ret))
-});
+// Fails if the second of two arguments is zero.
+fn divisor(name: &str, args: &[Value]) -> Result<(), FuncError> {
+    if let [_, r] = args {
+        if number(name, r)?.float() == 0.0 {
+            return Err(FuncError::Generic(format!("{}: division by zero", name)));
+        }
+    }
+    Ok(())
+}
 
-gn!(
-#[doc="
-Returns the boolean truth of arg1 >= arg2
+// The first argument which compares as `want` to all others.
+fn extreme(name: &str, args: &[Value], want: Ordering) -> Result<Value, FuncError> {
+    let (first, rest) = args
+        .split_first()
+        .ok_or_else(|| FuncError::AtLeastXArgs(name.into(), 1))?;
+    let mut best = (first, number(name, first)?);
+    for arg in rest {
+        let n = number(name, arg)?;
+        let ord = match (n, best.1) {
+            (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+            (a, b) => a.float().partial_cmp(&b.float()),
+        };
+        if ord == Some(want) {
+            best = (arg, n);
+        }
+    }
+    Ok(best.0.clone())
+}
 
-# Example
-```
-use gtmpl::template;
-let greater_or_equal = template(\"{{ ge 1.4 1.3 }}\", 1.2);
-assert_eq!(greater_or_equal.unwrap(), \"true\");
+// Values as seen by comparisons, like Go's basic kinds. Values do not keep the signedness of
+// integers, so all integers are of a single kind.
+enum Basic<'a> {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    String(&'a str),
+    Nil,
+    Other,
+}
 
-let greater_or_equal = template(\"{{ ge 1.4 . }}\", 0.2);
-assert_eq!(&greater_or_equal.unwrap(), \"true\");
-```
-"]
-ge(a: ref Value, b: ref Value) -> Result<Value, FuncError> {
- let ret = match cmp(a, b) {
-  None => return Err(FuncError::Generic(format!("unable to compare {} and {}", a, b))),
-  Some(Ordering::Greater) | Some(Ordering::Equal) => true,
-  _ => false,
- };
- Ok(Value::from(ret))
-});
+fn basic(val: &Value) -> Basic<'_> {
+    match *val {
+        Value::Bool(b) => Basic::Bool(b),
+        Value::Number(ref n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, _) => Basic::Int(i128::from(i)),
+            (_, Some(u), _) => Basic::Int(i128::from(u)),
+            (_, _, Some(f)) => Basic::Float(f),
+            _ => Basic::Other,
+        },
+        Value::String(ref s) => Basic::String(s),
+        Value::Nil | Value::NoValue => Basic::Nil,
+        _ => Basic::Other,
+    }
+}
 
-fn cmp(left: &Value, right: &Value) -> Option<Ordering> {
-    match (left, right) {
-        (&Value::Number(ref l), &Value::Number(ref r)) => {
-            if let (Some(lf), Some(rf)) = (l.as_f64(), r.as_f64()) {
-                return lf.partial_cmp(&rf);
-            }
-            if let (Some(li), Some(ri)) = (l.as_i64(), r.as_i64()) {
-                return li.partial_cmp(&ri);
-            }
-            if let (Some(lu), Some(ru)) = (l.as_u64(), r.as_u64()) {
-                return lu.partial_cmp(&ru);
-            }
-            None
+fn equal(left: &Value, right: &Value) -> Result<bool, FuncError> {
+    match (basic(left), basic(right)) {
+        (Basic::Bool(l), Basic::Bool(r)) => Ok(l == r),
+        (Basic::Int(l), Basic::Int(r)) => Ok(l == r),
+        (Basic::Float(l), Basic::Float(r)) => Ok(l == r),
+        (Basic::String(l), Basic::String(r)) => Ok(l == r),
+        (Basic::Nil, Basic::Nil) => Ok(true),
+        (Basic::Nil, _) | (_, Basic::Nil) => Ok(false),
+        (Basic::Other, Basic::Other) => match (left, right) {
+            (Value::Object(_), Value::Object(_)) => Ok(left == right),
+            _ => Err(FuncError::Generic(format!(
+                "non-comparable types {}, {}",
+                left, right
+            ))),
+        },
+        _ => Err(FuncError::Generic(
+            "incompatible types for comparison".into(),
+        )),
+    }
+}
+
+fn less(left: &Value, right: &Value) -> Result<bool, FuncError> {
+    match (basic(left), basic(right)) {
+        (Basic::Int(l), Basic::Int(r)) => Ok(l < r),
+        (Basic::Float(l), Basic::Float(r)) => Ok(l < r),
+        (Basic::String(l), Basic::String(r)) => Ok(l < r),
+        (Basic::Bool(_), _) | (Basic::Nil, _) | (Basic::Other, _) => {
+            Err(FuncError::Generic("invalid type for comparison".into()))
+        }
+        (_, Basic::Bool(_)) | (_, Basic::Nil) | (_, Basic::Other) => {
+            Err(FuncError::Generic("invalid type for comparison".into()))
         }
-        (&Value::Bool(ref l), &Value::Bool(ref r)) => l.partial_cmp(r),
-        (&Value::String(ref l), &Value::String(ref r)) => l.partial_cmp(r),
-        (&Value::Array(ref l), &Value::Array(ref r)) => l.len().partial_cmp(&r.len()),
-        _ => None,
+        _ => Err(FuncError::Generic(
+            "incompatible types for comparison".into(),
+        )),
     }
 }
 
@@ -582,6 +4226,12 @@ mod tests_mocked {
         let vals: Vec<Value> = vec![val!(false), val!(false), val!(false)];
         let ret = eq(&vals);
         assert_eq!(ret.unwrap(), Value::Bool(true));
+        let vals: Vec<Value> = vec![val!(3u8), val!(1i8), val!(2u64), val!(3i64)];
+        let ret = eq(&vals);
+        assert_eq!(ret.unwrap(), Value::Bool(true));
+        let vals: Vec<Value> = vec![val!(4u8), val!(1i8), val!(2u64), val!(3i64)];
+        let ret = eq(&vals);
+        assert_eq!(ret.unwrap(), Value::Bool(false));
     }
 
     #[test]
@@ -606,6 +4256,191 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::from(0u8));
     }
 
+    #[test]
+    fn test_defaults() {
+        assert_eq!(default(&[val!("a"), val!("")]).unwrap(), val!("a"));
+        assert_eq!(default(&[val!("a"), Value::NoValue]).unwrap(), val!("a"));
+        assert_eq!(default(&[val!(1), val!(0)]).unwrap(), val!(1));
+        assert_eq!(default(&[val!(1), val!(false)]).unwrap(), val!(1));
+        assert_eq!(default(&[val!(1), val!(2.5)]).unwrap(), val!(2.5));
+        assert_eq!(default(&[val!("a")]).unwrap(), val!("a"));
+        assert_eq!(
+            default(&[]).unwrap_err().to_string(),
+            "default: wrong number of args: want 1 or 2"
+        );
+
+        assert_eq!(empty(&[Value::Nil]).unwrap(), val!(true));
+        assert_eq!(empty(&[Value::Array(vec![])]).unwrap(), val!(true));
+        assert_eq!(empty(&[Value::Map(HashMap::new())]).unwrap(), val!(true));
+        assert_eq!(empty(&[val!(0.0)]).unwrap(), val!(true));
+        assert_eq!(
+            empty(&[Value::Object(HashMap::new())]).unwrap(),
+            val!(false)
+        );
+        assert_eq!(empty(&[val!(" ")]).unwrap(), val!(false));
+
+        assert_eq!(
+            coalesce(&[Value::NoValue, val!(0), val!("x"), val!("y")]).unwrap(),
+            val!("x")
+        );
+        assert_eq!(coalesce(&[val!(""), val!(false)]).unwrap(), Value::Nil);
+        assert_eq!(coalesce(&[]).unwrap(), Value::Nil);
+
+        assert_eq!(ternary(&[val!(1), val!(2), val!(true)]).unwrap(), val!(1));
+        assert_eq!(ternary(&[val!(1), val!(2), val!(false)]).unwrap(), val!(2));
+        assert_eq!(
+            ternary(&[val!(1), val!(2), val!(1)])
+                .unwrap_err()
+                .to_string(),
+            "ternary: wrong type for value; expected bool; got int"
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), val!(vec![1, 2]));
+        let m = Value::Map(m);
+        let kinds: Vec<_> = [
+            Value::Nil,
+            val!(true),
+            val!(-1),
+            val!(u64::MAX),
+            val!(1.5),
+            val!("s"),
+            val!(vec![1]),
+            m.clone(),
+            Value::Function(gtmpl_value::Function { f: type_of }),
+        ]
+        .iter()
+        .map(|v| {
+            (
+                type_of(&[v.clone()]).unwrap(),
+                kind_of(&[v.clone()]).unwrap(),
+            )
+        })
+        .map(|(t, k)| format!("{}/{}", t, k))
+        .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "<nil>/invalid",
+                "bool/bool",
+                "int/int",
+                "uint64/uint64",
+                "float64/float64",
+                "string/string",
+                "[]interface {}/slice",
+                "map[string]interface {}/map",
+                "func/func",
+            ]
+        );
+        assert_eq!(type_is(&[val!("int"), val!(1)]).unwrap(), val!(true));
+        assert_eq!(
+            type_is(&[val!("slice"), val!(vec![1])]).unwrap(),
+            val!(false)
+        );
+        assert_eq!(
+            kind_is(&[val!("slice"), val!(vec![1])]).unwrap(),
+            val!(true)
+        );
+        assert_eq!(
+            kind_is(&[val!(1), val!(1)]).unwrap_err().to_string(),
+            "kindIs: wrong type for value; expected string; got int"
+        );
+
+        let deep = |a: Value, b: Value| deep_equal(&[a, b]).unwrap();
+        let mut other = HashMap::new();
+        other.insert("a".to_owned(), val!(vec![1u8, 2u8]));
+        assert_eq!(deep(m.clone(), Value::Object(other.clone())), val!(true));
+        other.insert("b".to_owned(), Value::Nil);
+        assert_eq!(deep(m.clone(), Value::Map(other)), val!(false));
+        assert_eq!(deep(val!(vec![1, 2]), val!(vec![2, 1])), val!(false));
+        assert_eq!(deep(val!(1), val!(1.5)), val!(false));
+        assert_eq!(deep(val!(1), val!(1.0)), val!(false));
+        assert_eq!(deep(val!(u64::MAX), val!(u64::MAX)), val!(true));
+        assert_eq!(deep(Value::Nil, Value::NoValue), val!(true));
+        assert_eq!(deep(val!("1"), val!(1)), val!(false));
+        let f = |f: Func| Value::Function(gtmpl_value::Function { f });
+        assert_eq!(deep(f(type_of), f(type_of)), val!(true));
+        assert_eq!(deep(f(type_of), f(kind_of)), val!(false));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let conv = |f: Func, args: &[Value]| f(args).map_err(|e| e.to_string());
+        assert_eq!(conv(to_string, &[val!(1.5)]), Ok(val!("1.5")));
+        assert_eq!(conv(to_string, &[Value::Nil]), Ok(val!("")));
+        assert_eq!(conv(to_string, &[val!(vec![1, 2])]), Ok(val!("[1 2]")));
+        assert_eq!(
+            conv(to_strings, &[val!(vec![val!(1), Value::Nil])]),
+            Ok(val!(vec!["1", ""]))
+        );
+        assert_eq!(
+            conv(to_strings, &[Value::NoValue]),
+            Ok(Value::Array(vec![]))
+        );
+        assert_eq!(conv(to_strings, &[val!(false)]), Ok(val!(vec!["false"])));
+
+        assert_eq!(conv(to_int, &[val!(" -7\n")]), Ok(val!(-7)));
+        assert_eq!(conv(to_int, &[val!("2.9")]), Ok(val!(2)));
+        assert_eq!(conv(to_int, &[val!(-2.9)]), Ok(val!(-2)));
+        assert_eq!(conv(to_int, &[val!(true)]), Ok(val!(1)));
+        assert_eq!(conv(to_int64, &[val!(i64::MIN)]), Ok(val!(i64::MIN)));
+        assert_eq!(
+            conv(to_int, &[val!(u64::MAX)]),
+            Err("toInt: cannot convert uint64 18446744073709551615 to int".into())
+        );
+        assert_eq!(
+            conv(to_int, &[val!("9.3e18")]),
+            Err("toInt: cannot convert string \"9.3e18\" to int".into())
+        );
+        assert_eq!(
+            conv(to_int64, &[val!("12a")]),
+            Err("toInt64: cannot convert string \"12a\" to int".into())
+        );
+        assert_eq!(
+            conv(to_int, &[Value::Nil]),
+            Err("toInt: cannot convert <nil> <no value> to int".into())
+        );
+        assert_eq!(conv(to_int, &[val!(0), val!("NaN")]), Ok(val!(0)));
+        assert_eq!(conv(to_int, &[val!(0), val!("5")]), Ok(val!(5)));
+        assert_eq!(
+            conv(to_int, &[val!(0), val!(1), val!(2)]),
+            Err("toInt: wrong number of args: want 1 or 2".into())
+        );
+
+        assert_eq!(conv(to_float, &[val!(3)]), Ok(val!(3.0)));
+        assert_eq!(conv(to_float, &[val!("1e3 ")]), Ok(val!(1000.0)));
+        assert_eq!(conv(to_float, &[val!(-1.0), Value::Nil]), Ok(val!(-1.0)));
+        assert_eq!(conv(to_float, &[val!("-1.5")]), Ok(val!(-1.5)));
+        assert_eq!(conv(to_float, &[val!(-0.25)]), Ok(val!(-0.25)));
+        assert_eq!(
+            conv(to_float, &[val!(vec![1])]),
+            Err("toFloat: cannot convert []interface {} [1] to float64".into())
+        );
+
+        assert_eq!(conv(to_bool, &[val!("t")]), Ok(val!(true)));
+        assert_eq!(conv(to_bool, &[val!("False")]), Ok(val!(false)));
+        assert_eq!(conv(to_bool, &[val!(0.0)]), Ok(val!(false)));
+        assert_eq!(conv(to_bool, &[val!(-1)]), Ok(val!(true)));
+        assert_eq!(conv(to_bool, &[val!(true), val!("yes")]), Ok(val!(true)));
+        assert_eq!(
+            conv(to_bool, &[val!("yes")]),
+            Err("toBool: cannot convert string \"yes\" to bool".into())
+        );
+
+        assert_eq!(conv(atoi, &[val!("-12")]), Ok(val!(-12)));
+        assert_eq!(
+            conv(atoi, &[val!(" 1")]),
+            Err("atoi: parsing \" 1\": invalid digit found in string".into())
+        );
+        assert_eq!(
+            conv(atoi, &[val!(1)]),
+            Err("atoi: wrong type for value; expected string; got int".into())
+        );
+    }
+
     #[test]
     fn test_ne() {
         let vals: Vec<Value> = vec![val!(0i32), val!(1u8)];
@@ -625,6 +4460,56 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::from(false));
     }
 
+    #[test]
+    fn test_compare_like_go() {
+        let vals: Vec<Value> = vec![val!(-1i64), val!(u64::MAX)];
+        assert_eq!(eq(&vals).unwrap(), Value::from(false));
+        assert_eq!(lt(&vals).unwrap(), Value::from(true));
+        assert_eq!(ge(&vals).unwrap(), Value::from(false));
+
+        let vals: Vec<Value> = vec![val!(1i64), val!(1.5f64)];
+        assert_eq!(
+            eq(&vals).unwrap_err().to_string(),
+            FuncError::Generic("incompatible types for comparison".into()).to_string()
+        );
+        assert!(lt(&vals).is_err());
+        let vals: Vec<Value> = vec![val!(1i64), val!(1.0f64)];
+        assert!(eq(&vals).is_err());
+        assert!(ge(&vals).is_err());
+        let vals: Vec<Value> = vec![val!(-1.5f64), val!(-1.2f64)];
+        assert_eq!(lt(&vals).unwrap(), Value::from(true));
+        assert_eq!(eq(&vals).unwrap(), Value::from(false));
+
+        let render = |tmpl: &str| crate::template(tmpl, 0).map_err(|e| e.to_string());
+        assert_eq!(
+            render("{{ lt -1.5 -1.2 }} {{ eq 1.0 1.0 }}").unwrap(),
+            "true true"
+        );
+        assert!(render("{{ eq 1 1.0 }}")
+            .unwrap_err()
+            .contains("incompatible types for comparison"));
+        assert!(render("{{ lt 1 2.5 }}")
+            .unwrap_err()
+            .contains("incompatible types for comparison"));
+
+        let vals: Vec<Value> = vec![val!(true), val!(false)];
+        assert_eq!(ne(&vals).unwrap(), Value::from(true));
+        assert_eq!(
+            lt(&vals).unwrap_err().to_string(),
+            FuncError::Generic("invalid type for comparison".into()).to_string()
+        );
+
+        let vals: Vec<Value> = vec![val!(vec![1]), val!(vec![1])];
+        assert!(eq(&vals).is_err());
+        assert!(le(&vals).is_err());
+
+        let vals: Vec<Value> = vec![Value::Nil, val!(1)];
+        assert_eq!(eq(&vals).unwrap(), Value::from(false));
+        assert!(lt(&vals).is_err());
+        let vals: Vec<Value> = vec![Value::Nil, Value::NoValue];
+        assert_eq!(eq(&vals).unwrap(), Value::from(true));
+    }
+
     #[test]
     fn test_lt() {
         let vals: Vec<Value> = vec![val!(-1i32), val!(1u8)];
@@ -685,6 +4570,29 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::from(true));
     }
 
+    #[test]
+    fn test_len() {
+        let ret = len(&[val!("café")]);
+        assert_eq!(ret.unwrap(), Value::from(5));
+        let ret = runelen(&[val!("café")]);
+        assert_eq!(ret.unwrap(), Value::from(4));
+
+        let mut o = HashMap::new();
+        o.insert(String::from("foo"), 1);
+        let ret = len(&[Value::from(o)]);
+        assert_eq!(ret.unwrap(), Value::from(1));
+
+        let ret = len(&[Value::Nil]);
+        assert_eq!(ret.unwrap_err().to_string(), "len of nil pointer");
+        let ret = len(&[val!(1)]);
+        assert_eq!(ret.unwrap_err().to_string(), "len of type int");
+        let ret = runelen(&[val!(vec![1])]);
+        assert_eq!(
+            ret.unwrap_err().to_string(),
+            "runelen of type []interface {}"
+        );
+    }
+
     #[test]
     fn test_print() {
         let vals: Vec<Value> = vec![val!("foo"), val!(1u8)];
@@ -711,6 +4619,676 @@ mod tests_mocked {
         assert_eq!(ret.unwrap(), Value::from("\n"));
     }
 
+    #[test]
+    fn test_escape() {
+        let ret = html(&[val!("\"O'Reilly\" & <b>\0")]);
+        assert_eq!(
+            ret.unwrap(),
+            Value::from("&#34;O&#39;Reilly&#34; &amp; &lt;b&gt;\u{FFFD}")
+        );
+        let ret = html(&[val!("a"), val!(1), val!(2)]);
+        assert_eq!(ret.unwrap(), Value::from("a1 2"));
+
+        let ret = js(&[val!("a\\b'c\"d<e>f&g=h\ni\u{2028}é")]);
+        assert_eq!(
+            ret.unwrap(),
+            Value::from(r#"a\\b\'c\"d\u003Ce\u003Ef\u0026g\u003Dh\u000Ai\u2028é"#)
+        );
+
+        let ret = urlquery(&[val!("a b&c=d/é~")]);
+        assert_eq!(ret.unwrap(), Value::from("a+b%26c%3Dd%2F%C3%A9~"));
+        let ret = urlquery(&[val!(1), val!("x")]);
+        assert_eq!(ret.unwrap(), Value::from("1x"));
+
+        let ret = xml_escape(&[val!("<a b='c'>\"d\" & é\t\r\n\0\u{FFFE}")]);
+        assert_eq!(
+            ret.unwrap(),
+            Value::from(
+                "&lt;a b=&#39;c&#39;&gt;&#34;d&#34; &amp; é&#x9;&#xD;&#xA;\u{FFFD}\u{FFFD}"
+            )
+        );
+        let ret = xml_escape(&[val!(1), val!(2)]);
+        assert_eq!(ret.unwrap(), Value::from("1 2"));
+    }
+
+    #[test]
+    fn test_dicts() {
+        let d = dict(&[val!("a"), val!(1), val!(2), val!(true), val!("c")]).unwrap();
+        let mut m = HashMap::new();
+        m.insert("a".to_owned(), val!(1));
+        m.insert("2".to_owned(), val!(true));
+        m.insert("c".to_owned(), val!(""));
+        assert_eq!(d, Value::Map(m.clone()));
+        assert_eq!(dict(&[]).unwrap(), Value::Map(HashMap::new()));
+
+        assert_eq!(get(&[d.clone(), val!("a")]).unwrap(), val!(1));
+        assert_eq!(get(&[d.clone(), val!("x")]).unwrap(), val!(""));
+        assert_eq!(has_key(&[d.clone(), val!("c")]).unwrap(), val!(true));
+        assert_eq!(has_key(&[d.clone(), val!("x")]).unwrap(), val!(false));
+
+        let d2 = set(&[d.clone(), val!("a"), val!(2)]).unwrap();
+        assert_eq!(get(&[d2.clone(), val!("a")]).unwrap(), val!(2));
+        assert_eq!(get(&[d.clone(), val!("a")]).unwrap(), val!(1));
+        let d3 = unset(&[d2, val!("a")]).unwrap();
+        assert_eq!(keys(&[d3.clone()]).unwrap(), val!(vec!["2", "c"]));
+        assert_eq!(
+            keys(&[d3.clone(), d.clone()]).unwrap(),
+            val!(vec!["2", "c", "2", "a", "c"])
+        );
+        assert_eq!(
+            values(&[d.clone()]).unwrap(),
+            Value::Array(vec![val!(true), val!(1), val!("")])
+        );
+
+        assert_eq!(
+            pick(&[d.clone(), val!("a"), val!("x")]).unwrap(),
+            dict(&[val!("a"), val!(1)]).unwrap()
+        );
+        assert_eq!(
+            omit(&[d.clone(), val!("a"), val!("2")]).unwrap(),
+            dict(&[val!("c")]).unwrap()
+        );
+        assert_eq!(deep_copy(&[d.clone()]).unwrap(), d);
+
+        let inner = dict(&[val!("x"), val!(1)]).unwrap();
+        let dst = dict(&[val!("n"), inner, val!("a"), val!(0)]).unwrap();
+        let inner = dict(&[val!("x"), val!(2), val!("y"), val!(3)]).unwrap();
+        let src = dict(&[val!("n"), inner, val!("a"), val!(5), val!("b"), val!(6)]).unwrap();
+        let merged = merge(&[dst.clone(), src, Value::Map(HashMap::new())]).unwrap();
+        let inner = dict(&[val!("x"), val!(1), val!("y"), val!(3)]).unwrap();
+        assert_eq!(
+            merged,
+            dict(&[val!("n"), inner, val!("a"), val!(0), val!("b"), val!(6)]).unwrap()
+        );
+        assert_eq!(merge(&[dst.clone()]).unwrap(), dst);
+
+        assert_eq!(
+            get(&[val!(1), val!("a")]).unwrap_err().to_string(),
+            "get: wrong type for value; expected map; got int"
+        );
+        assert_eq!(
+            keys(&[]).unwrap_err().to_string(),
+            FuncError::AtLeastXArgs("keys".into(), 1).to_string()
+        );
+        assert_eq!(
+            pick(&[d, val!(1)]).unwrap_err().to_string(),
+            "pick: wrong type for value; expected string; got int"
+        );
+
+        let render = |tmpl: &str| crate::template(tmpl, dict(&[val!("x"), val!(1)]).unwrap());
+        assert_eq!(
+            render(
+                r#"{{ $d := dict "n" (dict) }}{{ $_ := set $d "a" 1 }}{{ $_ = set $d.n "b" 2 }}
+                {{- range $i := list 3 4 }}{{ $_ := set $d "c" $i }}{{ end }}
+                {{- $_ = unset $d "a" }}{{ $_ = merge $d (dict "e" 5) }}{{ $d }}"#
+            )
+            .unwrap(),
+            "map[c:4 e:5 n:map[b:2]]"
+        );
+        assert_eq!(
+            render(
+                r#"{{ $_ := set . "x" 2 }}{{ $d := . }}{{ $_ = set $d "y" 1 }}{{ . }} {{ $d }}"#
+            )
+            .unwrap(),
+            "map[x:1] map[x:1 y:1]"
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let l = val!(vec![1, 2, 3]);
+        let empty = Value::Array(vec![]);
+        assert_eq!(
+            list(&[val!(1), val!("a")]).unwrap(),
+            Value::Array(vec![val!(1), val!("a")])
+        );
+        assert_eq!(first(&[l.clone()]).unwrap(), val!(1));
+        assert_eq!(first(&[empty.clone()]).unwrap(), Value::Nil);
+        assert_eq!(last(&[l.clone()]).unwrap(), val!(3));
+        assert_eq!(last(&[empty.clone()]).unwrap(), Value::Nil);
+        assert_eq!(rest(&[l.clone()]).unwrap(), val!(vec![2, 3]));
+        assert_eq!(rest(&[empty.clone()]).unwrap(), empty);
+        assert_eq!(initial(&[l.clone()]).unwrap(), val!(vec![1, 2]));
+        assert_eq!(initial(&[empty.clone()]).unwrap(), empty);
+        assert_eq!(
+            append(&[l.clone(), val!(4)]).unwrap(),
+            val!(vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            prepend(&[l.clone(), val!(0)]).unwrap(),
+            val!(vec![0, 1, 2, 3])
+        );
+        assert_eq!(
+            concat(&[l.clone(), empty.clone(), l.clone()]).unwrap(),
+            val!(vec![1, 2, 3, 1, 2, 3])
+        );
+        assert_eq!(concat(&[]).unwrap(), empty);
+        assert_eq!(reverse(&[l.clone()]).unwrap(), val!(vec![3, 2, 1]));
+        assert_eq!(uniq(&[val!(vec![1, 1, 2, 1])]).unwrap(), val!(vec![1, 2]));
+        assert_eq!(
+            without(&[l.clone(), val!(2), val!(5)]).unwrap(),
+            val!(vec![1, 3])
+        );
+        assert_eq!(has(&[val!(2), l.clone()]).unwrap(), val!(true));
+        assert_eq!(has(&[val!("2"), l.clone()]).unwrap(), val!(false));
+        assert_eq!(
+            compact(&[Value::Array(vec![
+                val!(0),
+                Value::Nil,
+                val!("a"),
+                val!(false)
+            ])])
+            .unwrap(),
+            val!(vec!["a"])
+        );
+        assert_eq!(
+            sort_alpha(&[Value::Array(vec![val!("b"), val!(10), val!(true), val!(2)])]).unwrap(),
+            val!(vec!["10", "2", "b", "true"])
+        );
+
+        let item = |k: &str, n: Value| dict(&[val!("k"), val!(k), val!("n"), n]).unwrap();
+        let items = Value::Array(vec![
+            item("a", val!(2)),
+            item("b", val!(1.5)),
+            item("a", val!(-1)),
+            item("c", val!(2)),
+        ]);
+        assert_eq!(
+            sort_by(&[val!("n"), items.clone()]).unwrap(),
+            Value::Array(vec![
+                item("a", val!(-1)),
+                item("b", val!(1.5)),
+                item("a", val!(2)),
+                item("c", val!(2)),
+            ])
+        );
+        let mut groups = HashMap::new();
+        groups.insert(
+            "a".to_owned(),
+            Value::Array(vec![item("a", val!(2)), item("a", val!(-1))]),
+        );
+        groups.insert("b".to_owned(), Value::Array(vec![item("b", val!(1.5))]));
+        groups.insert("c".to_owned(), Value::Array(vec![item("c", val!(2))]));
+        assert_eq!(
+            group_by(&[val!("k"), items.clone()]).unwrap(),
+            Value::Map(groups)
+        );
+        assert_eq!(
+            sort_by(&[val!("x"), items.clone()])
+                .unwrap_err()
+                .to_string(),
+            "sortBy: map has no key x"
+        );
+        let mixed = append(&[items, item("d", val!("1"))]).unwrap();
+        assert_eq!(
+            sort_by(&[val!("n"), mixed]).unwrap_err().to_string(),
+            "sortBy: incompatible types for comparison of n"
+        );
+
+        assert_eq!(
+            chunk(&[val!(2), l.clone()]).unwrap(),
+            Value::Array(vec![val!(vec![1, 2]), val!(vec![3])])
+        );
+        assert_eq!(chunk(&[val!(2), empty.clone()]).unwrap(), empty);
+        assert_eq!(
+            chunk(&[val!(0), l.clone()]).unwrap_err().to_string(),
+            "chunk: size must be positive"
+        );
+        assert_eq!(
+            zip(&[l.clone(), val!(vec!["a", "b"])]).unwrap(),
+            Value::Array(vec![
+                Value::Array(vec![val!(1), val!("a")]),
+                Value::Array(vec![val!(2), val!("b")])
+            ])
+        );
+        assert_eq!(
+            first(&[val!("abc")]).unwrap_err().to_string(),
+            "first: wrong type for value; expected slice; got string"
+        );
+    }
+
+    #[test]
+    fn test_sequences() {
+        let ints = |v: Vec<i64>| val!(v);
+        assert_eq!(seq(&[val!(3)]).unwrap(), ints(vec![1, 2, 3]));
+        assert_eq!(seq(&[val!(0)]).unwrap(), ints(vec![1, 0]));
+        assert_eq!(seq(&[val!(-2), val!(1)]).unwrap(), ints(vec![-2, -1, 0, 1]));
+        assert_eq!(seq(&[val!(2), val!(2)]).unwrap(), ints(vec![2]));
+        assert_eq!(
+            seq(&[val!(1), val!(3), val!(8)]).unwrap(),
+            ints(vec![1, 4, 7])
+        );
+        assert_eq!(seq(&[val!(1), val!(-1), val!(8)]).unwrap(), ints(vec![]));
+        assert_eq!(
+            seq(&[]).unwrap_err().to_string(),
+            "seq: wrong number of args: want 1, 2 or 3"
+        );
+        assert_eq!(until(&[val!(3)]).unwrap(), ints(vec![0, 1, 2]));
+        assert_eq!(until(&[val!(-2)]).unwrap(), ints(vec![0, -1]));
+        assert_eq!(until(&[val!(0)]).unwrap(), ints(vec![]));
+        assert_eq!(
+            until_step(&[val!(0), val!(10), val!(3)]).unwrap(),
+            ints(vec![0, 3, 6, 9])
+        );
+        assert_eq!(
+            until_step(&[val!(0), val!(10), val!(0)]).unwrap(),
+            ints(vec![])
+        );
+        assert_eq!(
+            until_step(&[val!(0), val!(10), val!(-1)]).unwrap(),
+            ints(vec![])
+        );
+        assert_eq!(
+            until_step(&[val!(i64::MAX - 1), val!(i64::MAX), val!(5)]).unwrap(),
+            ints(vec![i64::MAX - 1])
+        );
+        assert_eq!(
+            seq(&[val!(i64::MAX - 1), val!(2), val!(i64::MAX)]).unwrap(),
+            ints(vec![i64::MAX - 1])
+        );
+        assert_eq!(
+            until(&[val!("3")]).unwrap_err().to_string(),
+            "until: wrong type for value; expected int; got string"
+        );
+        assert_eq!(
+            until(&[val!(100_000_000_000i64)]).unwrap_err().to_string(),
+            "until: result exceeds the maximum length of 16777216 items"
+        );
+        assert!(until_step(&[val!(i64::MIN), val!(i64::MAX), val!(1)]).is_err());
+        assert!(seq(&[val!(i64::MAX), val!(-1), val!(i64::MIN)]).is_err());
+        let mut t = crate::Template::default();
+        t.exec_options.max_range_iterations = 3;
+        t.parse("{{ until 3 | len }}{{ untilStep 0 8 2 | len }}")
+            .unwrap();
+        assert!(t
+            .render(&crate::Context::empty())
+            .unwrap_err()
+            .to_string()
+            .contains("untilStep: result exceeds the maximum length of 3 items"));
+    }
+
+    #[test]
+    fn test_indent_quote() {
+        assert_eq!(
+            indent(&[val!(2), val!("a\n\nb")]).unwrap(),
+            val!("  a\n  \n  b")
+        );
+        assert_eq!(indent(&[val!(0), val!("a\nb")]).unwrap(), val!("a\nb"));
+        assert_eq!(
+            nindent(&[val!(1), val!("a\nb\n")]).unwrap(),
+            val!("\n a\n b\n ")
+        );
+        assert_eq!(
+            indent(&[val!(-1), val!("a")]).unwrap_err().to_string(),
+            "indent: negative count -1"
+        );
+        assert_eq!(
+            nindent(&[val!(1), val!(1)]).unwrap_err().to_string(),
+            "nindent: wrong type for value; expected string; got int"
+        );
+        assert_eq!(
+            indent(&[val!(100_000_000_000i64), val!("a")])
+                .unwrap_err()
+                .to_string(),
+            "indent: result exceeds the maximum size of 1073741824 bytes"
+        );
+        assert!(nindent(&[val!(1 << 20), val!("\n".repeat(1 << 10))]).is_err());
+
+        assert_eq!(
+            quote(&[val!("a\tb"), Value::Nil, val!(true), val!("")]).unwrap(),
+            val!(r#""a\tb" "true" """#)
+        );
+        assert_eq!(quote(&[]).unwrap(), val!(""));
+        assert_eq!(
+            squote(&[val!("it's"), Value::NoValue, val!(1.5)]).unwrap(),
+            val!("'it's' '1.5'")
+        );
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn test_markdown() {
+        let html = |s: &str| markdown(&[val!(s)]).unwrap().to_string();
+        assert_eq!(html(""), "");
+        assert_eq!(
+            html("| a | b |\n|---|---|\n| 1 | ~~2~~ |"),
+            "<table><thead><tr><th>a</th><th>b</th></tr></thead><tbody>\n\
+             <tr><td>1</td><td><del>2</del></td></tr>\n</tbody></table>\n"
+        );
+        assert_eq!(
+            html("- [x] done"),
+            "<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\ndone</li>\n</ul>\n"
+        );
+        assert_eq!(
+            html("<script>alert(1)</script>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(
+            markdown(&[val!(1)]).unwrap_err().to_string(),
+            "markdown: wrong type for value; expected string; got int"
+        );
+    }
+
+    #[test]
+    fn test_random() {
+        use crate::clock::Deterministic;
+        use crate::{Context, Template};
+        use std::time::UNIX_EPOCH;
+
+        let mut t = Template::default();
+        t.parse("{{ randAlphaNum 32 }} {{ randInt -3 3 }} {{ shuffle (list 1 2 3 4 5 6) }}")
+            .unwrap();
+        let live = t.render(&Context::empty()).unwrap();
+        assert_ne!(t.render(&Context::empty()).unwrap(), live);
+        t.exec_options.deterministic = Some(Deterministic {
+            now: UNIX_EPOCH,
+            seed: 3,
+        });
+        let first = t.render(&Context::empty()).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), first);
+
+        for _ in 0..100 {
+            let n = rand_int(&[val!(-2), val!(2)]).unwrap();
+            assert!((-2..2).contains(&n.to_string().parse::<i64>().unwrap()));
+        }
+        let extremes = rand_int(&[val!(i64::MIN), val!(i64::MAX)]).unwrap();
+        assert!(extremes.to_string().parse::<i64>().is_ok());
+        assert_eq!(rand_int(&[val!(5), val!(6)]).unwrap(), val!(5));
+        assert_eq!(
+            rand_int(&[val!(1), val!(1)]).unwrap_err().to_string(),
+            "randInt: min 1 is not less than max 1"
+        );
+
+        assert_eq!(rand_alpha(&[val!(0)]).unwrap(), val!(""));
+        let digits = rand_numeric(&[val!(50)]).unwrap().to_string();
+        assert!(digits.len() == 50 && digits.bytes().all(|b| b.is_ascii_digit()));
+        assert_eq!(
+            rand_alpha_num(&[val!(-1)]).unwrap_err().to_string(),
+            "randAlphaNum: negative count -1"
+        );
+        assert_eq!(
+            rand_numeric(&[val!(100_000_000_000i64)])
+                .unwrap_err()
+                .to_string(),
+            "randNumeric: result exceeds the maximum size of 1073741824 bytes"
+        );
+
+        let shuffled = shuffle(&[val!("héllo")]).unwrap().to_string();
+        let mut chars: Vec<char> = shuffled.chars().collect();
+        chars.sort_unstable();
+        assert_eq!(chars, vec!['h', 'l', 'l', 'o', 'é']);
+        assert_eq!(shuffle(&[val!(vec![1])]).unwrap(), val!(vec![1]));
+        assert_eq!(
+            shuffle(&[val!(1)]).unwrap_err().to_string(),
+            "shuffle: wrong type for value; expected string or slice; got int"
+        );
+    }
+
+    #[test]
+    fn test_humanize() {
+        let bytes = |n: Value| humanize_bytes(&[n]).unwrap().to_string();
+        assert_eq!(bytes(val!(0)), "0 B");
+        assert_eq!(bytes(val!(1023)), "1023 B");
+        assert_eq!(bytes(val!(1024)), "1.0 KiB");
+        assert_eq!(bytes(val!(-1536.0)), "-1.5 KiB");
+        assert_eq!(bytes(val!(82_854_982)), "79 MiB");
+        assert_eq!(bytes(val!(1024 * 1024 - 1)), "1.0 MiB");
+        assert_eq!(bytes(val!(u64::MAX)), "16 EiB");
+
+        let number = |n: Value| humanize_number(&[n]).unwrap().to_string();
+        assert_eq!(number(val!(0)), "0");
+        assert_eq!(number(val!(999)), "999");
+        assert_eq!(number(val!(-1000)), "-1,000");
+        assert_eq!(number(val!(u64::MAX)), "18,446,744,073,709,551,615");
+        assert_eq!(number(val!(1234567.25)), "1,234,567.25");
+        assert_eq!(number(val!(f64::NEG_INFINITY)), "-inf");
+        assert_eq!(
+            humanize_number(&[val!("1000")]).unwrap_err().to_string(),
+            "humanizeNumber: wrong type for value; expected number; got string"
+        );
+
+        let ordinals: Vec<String> = [0, 1, 2, 3, 4, 11, 12, 13, 21, 102, 111, -1]
+            .iter()
+            .map(|&n| ordinal(&[val!(n)]).unwrap().to_string())
+            .collect();
+        assert_eq!(
+            ordinals,
+            [
+                "0th", "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "102nd",
+                "111th", "-1st"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let call =
+            |f: Func, args: &[Value]| f(args).map(|v| v.to_string()).map_err(|e| e.to_string());
+        let text = val!("Lorem ipsum dolor sit amet,\nconsectetur  adipiscing");
+        assert_eq!(
+            call(wrap, &[val!(11), text.clone()]),
+            Ok("Lorem ipsum\ndolor sit\namet,\nconsectetur\nadipiscing".to_owned())
+        );
+        assert_eq!(call(wrap, &[val!(0), val!("a b")]), Ok("a\nb".to_owned()));
+        assert_eq!(
+            call(wrap_with, &[val!(5), val!("\r\n"), val!("日本語 の 文字")]),
+            Ok("日本語\r\nの\r\n文字".to_owned())
+        );
+        assert_eq!(
+            call(wrap, &[val!(-1), text]),
+            Err("wrap: negative width -1".to_owned())
+        );
+
+        assert_eq!(call(trunc, &[val!(3), val!("日本語")]), Ok("日".to_owned()));
+        assert_eq!(
+            call(trunc, &[val!(-4), val!("日本語")]),
+            Ok("本語".to_owned())
+        );
+        assert_eq!(
+            call(trunc, &[val!(9), val!("short")]),
+            Ok("short".to_owned())
+        );
+        assert_eq!(
+            call(trunc, &[val!(i64::MIN), val!("short")]),
+            Ok("short".to_owned())
+        );
+        assert_eq!(
+            call(abbrev, &[val!(6), val!("日本語の文字")]),
+            Ok("日...".to_owned())
+        );
+        assert_eq!(
+            call(abbrev, &[val!(3), val!("text")]),
+            Err("abbrev: width 3 is less than 4".to_owned())
+        );
+
+        assert_eq!(call(pad_left, &[val!(4), val!("é")]), Ok("   é".to_owned()));
+        assert_eq!(
+            call(pad_right, &[val!(2), val!("long")]),
+            Ok("long".to_owned())
+        );
+        assert_eq!(
+            call(center, &[val!(7), val!("日本")]),
+            Ok(" 日本  ".to_owned())
+        );
+        assert_eq!(
+            call(center, &[val!(7), val!(1)]),
+            Err("center: wrong type for value; expected string; got int".to_owned())
+        );
+        for (f, name) in [
+            (pad_left as Func, "padLeft"),
+            (pad_right, "padRight"),
+            (center, "center"),
+        ] {
+            assert_eq!(
+                call(f, &[val!(100_000_000_000i64), val!("a")]),
+                Err(format!(
+                    "{}: result exceeds the maximum size of 1073741824 bytes",
+                    name
+                ))
+            );
+        }
+        let mut t = crate::Template::default();
+        t.parse("{{ padLeft 8 . }}").unwrap();
+        t.exec_options.max_output_size = 4;
+        assert!(t
+            .render(&crate::Context::from("a"))
+            .unwrap_err()
+            .to_string()
+            .contains("padLeft: result exceeds the maximum size of 4 bytes"));
+    }
+
+    #[test]
+    fn test_table() {
+        let table_ = |args: &[Value]| {
+            table(args)
+                .map(|v| v.to_string())
+                .map_err(|e| e.to_string())
+        };
+        let mut web = HashMap::new();
+        web.insert("name".to_owned(), val!("web"));
+        web.insert("ports".to_owned(), val!(vec![80, 443]));
+        let mut db = HashMap::new();
+        db.insert("name".to_owned(), val!("データ"));
+        db.insert("note".to_owned(), val!("two\nlines"));
+        db.insert("ports".to_owned(), Value::Nil);
+        let rows = Value::Array(vec![Value::Map(web), Value::Object(db)]);
+        assert_eq!(
+            table_(&[rows.clone()]),
+            Ok("name    note       ports\n\
+                ------  ---------  --------\n\
+                web                [80 443]\n\
+                データ  two lines"
+                .to_owned())
+        );
+        assert_eq!(
+            table_(&[val!(">name:Service"), val!("missing:?"), rows]),
+            Ok("Service  ?\n-------  -\n    web\n データ".to_owned())
+        );
+
+        let lists = val!(vec![vec![val!("a"), val!(1.5)], vec![val!("bcd")]]);
+        assert_eq!(table_(&[lists.clone()]), Ok("a    1.5\nbcd".to_owned()));
+        assert_eq!(
+            table_(&[val!("Key"), val!(">Value"), val!("Extra"), lists]),
+            Ok("Key  Value  Extra\n---  -----  -----\na      1.5\nbcd".to_owned())
+        );
+        let mut a = HashMap::new();
+        a.insert("Key".to_owned(), val!("a"));
+        a.insert("Value".to_owned(), val!(1.5));
+        let mut bcd = HashMap::new();
+        bcd.insert("Key".to_owned(), val!("bcd"));
+        let maps = Value::Array(vec![Value::Map(a), Value::Map(bcd)]);
+        let lists = val!(vec![vec![val!("a"), val!(1.5)], vec![val!("bcd")]]);
+        let expected = Ok("Key  Value\n---  -----\na    1.5\nbcd".to_owned());
+        assert_eq!(table_(&[maps.clone()]), expected);
+        assert_eq!(table_(&[val!("Key"), val!("Value"), maps]), expected);
+        assert_eq!(table_(&[val!("Key"), val!("Value"), lists]), expected);
+        assert_eq!(table_(&[val!(Vec::<Value>::new())]), Ok(String::new()));
+        assert_eq!(
+            table_(&[val!(vec![1])]),
+            Err("table: wrong type for row; expected map or slice; got int".to_owned())
+        );
+        assert_eq!(
+            table_(&[val!("a")]),
+            Err("table: wrong type for value; expected slice; got string".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        let sh = |args: &[Value]| shell_quote(args).unwrap().to_string();
+        assert_eq!(sh(&[]), "");
+        assert_eq!(sh(&[val!("")]), "''");
+        assert_eq!(
+            sh(&[val!("a=b,c:d@e%f+g/h.i_j-k")]),
+            "a=b,c:d@e%f+g/h.i_j-k"
+        );
+        assert_eq!(sh(&[val!("$(rm -rf ~)")]), "'$(rm -rf ~)'");
+        assert_eq!(sh(&[val!("''")]), r"''\'''\'''");
+        assert_eq!(sh(&[val!("a\nb"), val!("héllo")]), "'a\nb' 'héllo'");
+        assert_eq!(
+            sh(&[val!(vec![val!("-v"), Value::Nil, val!("x y")]), val!(3)]),
+            "-v 'x y' 3"
+        );
+        assert_eq!(
+            shell_quote(&[val!("a\0b")]).unwrap_err().to_string(),
+            "shellQuote: argument contains NUL"
+        );
+
+        let ps = |args: &[Value]| powershell_quote(args).unwrap().to_string();
+        assert_eq!(ps(&[val!("")]), "''");
+        assert_eq!(ps(&[val!("plain")]), "'plain'");
+        assert_eq!(ps(&[val!("`$env:PATH\"")]), "'`$env:PATH\"'");
+        assert_eq!(ps(&[val!("it\u{2019}s")]), "'it\u{2019}\u{2019}s'");
+        assert_eq!(ps(&[val!(vec!["a", "b"])]), "'a' 'b'");
+    }
+
+    #[test]
+    fn test_plural() {
+        let plural_ = |n: Value| plural(&[n, val!("item"), val!("items")]).unwrap();
+        assert_eq!(plural_(val!(1)), val!("item"));
+        assert_eq!(plural_(val!(1.0)), val!("item"));
+        assert_eq!(plural_(val!(0)), val!("items"));
+        assert_eq!(plural_(val!(-1)), val!("items"));
+        assert_eq!(
+            plural(&[val!("1"), val!("item"), val!("items")])
+                .unwrap_err()
+                .to_string(),
+            "plural: wrong type for value; expected number; got string"
+        );
+
+        let cases = [
+            ("item", "items"),
+            ("box", "boxes"),
+            ("church", "churches"),
+            ("status", "statuses"),
+            ("quiz", "quizzes"),
+            ("city", "cities"),
+            ("day", "days"),
+            ("analysis", "analyses"),
+            ("index", "indices"),
+            ("potato", "potatoes"),
+            ("photo", "photos"),
+            ("wolf", "wolves"),
+            ("roof", "roofs"),
+            ("person", "people"),
+            ("Woman", "Women"),
+            ("CHILD", "CHILDREN"),
+            ("API", "APIs"),
+            ("iPhone", "iPhones"),
+            ("sheep", "sheep"),
+            ("new message", "new messages"),
+            ("pull-request", "pull-requests"),
+            ("sales person", "sales people"),
+            ("café", "cafés"),
+            ("42", "42"),
+            ("", ""),
+        ];
+        for &(one, many) in &cases {
+            assert_eq!(pluralize(&[val!(2), val!(one)]).unwrap(), val!(many));
+            assert_eq!(pluralize(&[val!(1), val!(one)]).unwrap(), val!(one));
+        }
+    }
+
+    #[test]
+    fn test_env() {
+        std::env::set_var("GTMPL_TEST_ENV", "value");
+        assert_eq!(env(&[val!("GTMPL_TEST_ENV")]).unwrap(), val!("value"));
+        assert_eq!(env(&[val!("GTMPL_TEST_UNSET")]).unwrap(), val!(""));
+        assert_eq!(env(&[val!("")]).unwrap(), val!(""));
+        assert_eq!(env(&[val!("A=B")]).unwrap(), val!(""));
+        assert_eq!(
+            expandenv(&[val!("$GTMPL_TEST_ENV/${GTMPL_TEST_ENV}x$GTMPL_TEST_UNSET")]).unwrap(),
+            val!("value/valuex")
+        );
+
+        let expand = |s: &str| expand(s, |name| format!("<{}>", name));
+        assert_eq!(expand("a$b_1.c"), "a<b_1>.c");
+        assert_eq!(expand("${a b}${1}$12$$x"), "<a b><1><1>2<$>x");
+        assert_eq!(expand("${}a${b"), "ab");
+        assert_eq!(expand("$ $/$"), "$ $/$");
+        assert_eq!(expand("é$é"), "é$é");
+    }
+
     #[test]
     fn test_index() {
         let vals: Vec<Value> = vec![val!(vec![vec![1, 2], vec![3, 4]]), val!(1), val!(0)];
@@ -730,6 +5308,395 @@ mod tests_mocked {
         let vals: Vec<Value> = vec![col, val!("foo2")];
         let ret = index(&vals);
         assert_eq!(ret.unwrap(), Value::NoValue);
+
+        let vals: Vec<Value> = vec![val!("abc"), val!(1)];
+        let ret = index(&vals);
+        assert_eq!(ret.unwrap(), Value::from(98));
+
+        let vals: Vec<Value> = vec![val!(vec![1, 2])];
+        let ret = index(&vals);
+        assert_eq!(ret.unwrap(), val!(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_index_errors() {
+        let err = |vals: Vec<Value>| index(&vals).unwrap_err().to_string();
+        assert_eq!(
+            err(vec![val!(vec![1, 2]), val!(2)]),
+            "index out of range: 2"
+        );
+        assert_eq!(
+            err(vec![val!(vec![1, 2]), val!(-1)]),
+            "index out of range: -1"
+        );
+        assert_eq!(
+            err(vec![val!(vec![1, 2]), val!(1.5)]),
+            "cannot index slice/array with type float64"
+        );
+        assert_eq!(
+            err(vec![val!(vec![1, 2]), Value::Nil]),
+            "cannot index slice/array with nil"
+        );
+        assert_eq!(err(vec![Value::Nil, val!(0)]), "index of untyped nil");
+        assert_eq!(err(vec![val!(1), val!(0)]), "can't index item of type int");
+
+        let mut o = HashMap::new();
+        o.insert(String::from("foo"), String::from("bar"));
+        assert_eq!(
+            err(vec![Value::from(o), val!("missing"), val!(0)]),
+            "index of nil pointer"
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let call = |f: Func, args: &[&str]| {
+            let args: Vec<Value> = args.iter().map(|&a| val!(a)).collect();
+            f(&args)
+        };
+        assert_eq!(call(upper, &["ß"]).unwrap(), val!("SS"));
+        assert_eq!(call(trim_all, &["", " x "]).unwrap(), val!(" x "));
+        assert_eq!(call(trim_prefix, &["x", "yx"]).unwrap(), val!("yx"));
+        assert_eq!(call(replace, &["", "-", "ab"]).unwrap(), val!("-a-b-"));
+        assert_eq!(call(split, &["", "añ"]).unwrap(), val!(vec!["a", "ñ"]));
+        assert_eq!(call(split, &[",", ""]).unwrap(), val!(vec![""]));
+        assert_eq!(call(has_suffix, &["", "a"]).unwrap(), val!(true));
+
+        let splitn_ = |sep: &str, n: i64, s: &str| splitn(&[val!(sep), val!(n), val!(s)]);
+        assert_eq!(splitn_(",", 0, "a,b").unwrap(), Value::Array(vec![]));
+        assert_eq!(
+            splitn_(",", -1, "a,b,c").unwrap(),
+            val!(vec!["a", "b", "c"])
+        );
+        assert_eq!(splitn_("", 2, "abc").unwrap(), val!(vec!["a", "bc"]));
+        assert_eq!(splitn_("", 5, "ab").unwrap(), val!(vec!["a", "b"]));
+
+        let join_ = |items: Value| join(&[val!("+"), items]);
+        assert_eq!(join_(val!(Vec::<Value>::new())).unwrap(), val!(""));
+        assert_eq!(
+            join_(Value::Array(vec![val!("a"), val!(1), Value::Nil])).unwrap(),
+            val!("a+1+<nil>")
+        );
+        assert_eq!(
+            join_(val!("a")).unwrap_err().to_string(),
+            "join: wrong type for value; expected array; got string"
+        );
+
+        let substr_ = |start: i64, end: i64| substr(&[val!(start), val!(end), val!("héllo")]);
+        assert_eq!(substr_(-3, 2).unwrap(), val!("hé"));
+        assert_eq!(substr_(3, 99).unwrap(), val!("lo"));
+        assert_eq!(substr_(4, 2).unwrap(), val!(""));
+
+        assert_eq!(
+            repeat(&[val!(-1), val!("a")]).unwrap_err().to_string(),
+            "repeat: negative count -1"
+        );
+        assert_eq!(
+            repeat(&[val!("2"), val!("a")]).unwrap_err().to_string(),
+            "repeat: wrong type for value; expected int; got string"
+        );
+        assert_eq!(
+            repeat(&[val!(100_000_000_000i64), val!("a")])
+                .unwrap_err()
+                .to_string(),
+            "repeat: result exceeds the maximum size of 1073741824 bytes"
+        );
+        assert!(repeat(&[val!(i64::MAX), val!("ab")]).is_err());
+        let mut t = crate::Template::default();
+        t.exec_options.max_output_size = 4;
+        t.parse("{{ repeat 2 \"ab\" | len }}|{{ repeat 5 \"a\" | len }}")
+            .unwrap();
+        assert!(t
+            .render(&crate::Context::empty())
+            .unwrap_err()
+            .to_string()
+            .contains("repeat: result exceeds the maximum size of 4 bytes"));
+        assert_eq!(
+            upper(&[val!(1)]).unwrap_err().to_string(),
+            "upper: wrong type for value; expected string; got int"
+        );
+        assert!(matches!(
+            lower(&[]),
+            Err(FuncError::ExactlyXArgs(ref name, 1)) if name == "lower"
+        ));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        assert_eq!(
+            add(&[val!(u64::MAX), val!(-1)]).unwrap(),
+            val!(u64::MAX - 1)
+        );
+        assert_eq!(
+            err(add(&[val!(u64::MAX), val!(1)])),
+            "add: integer overflow"
+        );
+        assert_eq!(
+            err(mul(&[val!(u64::MAX), val!(2)])),
+            "mul: integer overflow"
+        );
+        assert_eq!(sub(&[val!(0), val!(i64::MIN)]).unwrap(), val!(1u64 << 63));
+        assert_eq!(err(div(&[val!(1), val!(0)])), "div: division by zero");
+        assert_eq!(err(div(&[val!(1.5), val!(0.0)])), "div: division by zero");
+        assert_eq!(err(modulo(&[val!(1), val!(0)])), "mod: division by zero");
+        assert_eq!(div(&[val!(-7), val!(2)]).unwrap(), val!(-3));
+        assert_eq!(
+            err(add(&[val!(1), val!("2")])),
+            "add: wrong type for value; expected number; got string"
+        );
+        assert!(matches!(
+            sub(&[val!(1)]),
+            Err(FuncError::ExactlyXArgs(ref name, 2)) if name == "sub"
+        ));
+
+        assert_eq!(max(&[val!(2), val!(2.0), val!(1)]).unwrap(), val!(2));
+        assert_eq!(min(&[val!(u64::MAX), val!(0.5)]).unwrap(), val!(0.5));
+        assert_eq!(
+            max(&[val!(u64::MAX), val!(i64::MAX)]).unwrap(),
+            val!(u64::MAX)
+        );
+        assert!(matches!(
+            min(&[]),
+            Err(FuncError::AtLeastXArgs(ref name, 1)) if name == "min"
+        ));
+        assert_eq!(abs(&[val!(i64::MIN)]).unwrap(), val!(1u64 << 63));
+        assert_eq!(err(neg(&[val!(u64::MAX)])), "neg: integer overflow");
+        assert_eq!(neg(&[val!(0)]).unwrap(), val!(0));
+    }
+
+    #[test]
+    fn test_bits() {
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        assert_eq!(band(&[val!(u64::MAX), val!(-1)]).unwrap(), val!(u64::MAX));
+        assert_eq!(bor(&[val!(i64::MIN), val!(1)]).unwrap(), val!(i64::MIN + 1));
+        assert_eq!(
+            err(bxor(&[val!(u64::MAX), val!(-1)])),
+            "bxor: integer overflow"
+        );
+        assert_eq!(bnot(&[val!(-1)]).unwrap(), val!(0));
+        assert_eq!(err(bnot(&[val!(u64::MAX)])), "bnot: integer overflow");
+        assert_eq!(shl(&[val!(1), val!(63)]).unwrap(), val!(1u64 << 63));
+        assert_eq!(err(shl(&[val!(1), val!(64)])), "shl: integer overflow");
+        assert_eq!(shl(&[val!(0), val!(u64::MAX)]).unwrap(), val!(0));
+        assert_eq!(shr(&[val!(u64::MAX), val!(200)]).unwrap(), val!(0));
+        assert_eq!(shr(&[val!(-5), val!(200)]).unwrap(), val!(-1));
+        assert_eq!(
+            err(shr(&[val!(1), val!(-1)])),
+            "shr: negative shift amount -1"
+        );
+        assert_eq!(
+            err(band(&[val!(1), val!(1.5)])),
+            "band: wrong type for value; expected int; got float64"
+        );
+        assert!(matches!(
+            bor(&[val!(1)]),
+            Err(FuncError::AtLeastXArgs(ref name, 2)) if name == "bor"
+        ));
+    }
+
+    #[test]
+    fn test_floats() {
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        assert_eq!(floor(&[val!(3)]).unwrap(), val!(3.0));
+        assert_eq!(round(&[val!(0.5)]).unwrap(), val!(1.0));
+        assert_eq!(round(&[val!(1.005), val!(400)]).unwrap(), val!(1.005));
+        assert_eq!(round(&[val!(1.5e300), val!(-400)]).unwrap(), val!(0.0));
+        assert_eq!(round(&[val!(15), val!(-1)]).unwrap(), val!(20.0));
+        assert_eq!(
+            err(round(&[val!(1), val!(1.5)])),
+            "round: wrong type for value; expected int; got float64"
+        );
+        assert_eq!(err(round(&[])), "round: wrong number of args: want 1 or 2");
+        assert_eq!(
+            addf(&[val!(u64::MAX), val!(0)]).unwrap(),
+            val!(u64::MAX as f64)
+        );
+        assert_eq!(subf(&[val!(1), val!(0.5), val!(0.25)]).unwrap(), val!(0.25));
+        assert_eq!(
+            err(divf(&[val!(1), val!(2), val!(0)])),
+            "divf: division by zero"
+        );
+        assert!(matches!(
+            mulf(&[val!(1)]),
+            Err(FuncError::AtLeastXArgs(ref name, 2)) if name == "mulf"
+        ));
+        assert_eq!(
+            err(ceil(&[Value::Nil])),
+            "ceil: wrong type for value; expected number; got <nil>"
+        );
+    }
+
+    #[test]
+    fn test_negative_floats() {
+        let render = |tmpl: &str| crate::template(tmpl, "").unwrap();
+        assert_eq!(render("{{ floor -1.5 }} {{ floor -0.5 }}"), "-2 -1");
+        // Like in Go, negative zero prints as `-0`.
+        assert_eq!(render("{{ ceil -1.5 }} {{ ceil -0.5 }}"), "-1 -0");
+        assert_eq!(
+            render("{{ round -2.5 }} {{ round -2.345 2 }} {{ round -0.4 }}"),
+            "-3 -2.35 -0"
+        );
+        assert_eq!(
+            render("{{ addf -1 0.25 }} {{ addf -0.5 -0.25 }}"),
+            "-0.75 -0.75"
+        );
+        assert_eq!(render("{{ subf 0 0.5 }} {{ subf -1.5 1 }}"), "-0.5 -2.5");
+        assert_eq!(
+            render("{{ mulf -1 2.5 }} {{ mulf -0.5 -0.5 }}"),
+            "-2.5 0.25"
+        );
+        assert_eq!(render("{{ divf -1 4 }} {{ divf 1 -8 }}"), "-0.25 -0.125");
+        assert_eq!(
+            render("{{ abs -1.5 }} {{ neg 1.5 }} {{ neg -1.5 }}"),
+            "1.5 -1.5 1.5"
+        );
+        assert_eq!(
+            render("{{ add -1.5 1 }} {{ sub 1 1.5 }} {{ mul -0.5 3 }} {{ div -3.5 2 }}"),
+            "-0.5 -0.5 -1.5 -1.75"
+        );
+        assert_eq!(
+            render("{{ mod -7.5 2 }} {{ max -1.5 -2.5 }} {{ min -1.5 -1.25 }}"),
+            "-1.5 -1.5 -1.5"
+        );
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid() {
+        use crate::clock::Deterministic;
+        use crate::{Context, Template};
+        use std::time::UNIX_EPOCH;
+
+        let mut t = Template::default();
+        t.parse("{{ uuidv4 }} {{ uuidv4 }}").unwrap();
+        let live = t.render(&Context::empty()).unwrap();
+        let (a, b) = live.split_once(' ').unwrap();
+        assert_ne!(a, b);
+        assert_eq!(uuid_parse(&[val!(a)]).unwrap(), val!(a));
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+        assert!("89ab".contains(&a[19..20]));
+
+        t.exec_options.deterministic = Some(Deterministic {
+            now: UNIX_EPOCH,
+            seed: 7,
+        });
+        let first = t.render(&Context::empty()).unwrap();
+        assert_eq!(t.render(&Context::empty()).unwrap(), first);
+        assert_ne!(first, live);
+
+        assert_eq!(
+            uuid_parse(&[val!("urn:uuid:67E55044-10B1-426F-9247-BB680E5FE0C8")]).unwrap(),
+            val!("67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+        assert_eq!(
+            uuid_parse(&[val!("67e5504410b1426f9247bb680e5fe0c8")]).unwrap(),
+            val!("67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+        assert!(uuid_parse(&[val!("67e55044")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("uuidParse: invalid length"));
+        assert_eq!(
+            is_uuid(&[val!("67e55044-10b1-426f-9247-bb680e5fe0c8")]).unwrap(),
+            val!(true)
+        );
+        assert_eq!(
+            is_uuid(&[val!("67e55044-10b1-426f-9247-bb680e5fe0cg")]).unwrap(),
+            val!(false)
+        );
+        assert_eq!(
+            is_uuid(&[val!(1)]).unwrap_err().to_string(),
+            "isUuid: wrong type for value; expected string; got int"
+        );
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_case() {
+        let call = |f: Func, s: &str| f(&[val!(s)]).unwrap();
+        assert_eq!(call(title, "o'neil is_here"), val!("O'neil Is_here"));
+        // A decomposed `é` stays a single letter.
+        assert_eq!(call(title, "e\u{301}te\u{301}"), val!("E\u{301}te\u{301}"));
+        assert_eq!(call(title, "ǉubav"), val!("ǈubav"));
+        assert_eq!(call(untitle, "ǅemal"), val!("ǆemal"));
+        assert_eq!(call(camelcase, "ǆa_ǈB"), val!("ǅaǈb"));
+        assert_eq!(call(camelcase, "  "), val!(""));
+        assert_eq!(call(snakecase, "e\u{301}teÉte"), val!("e\u{301}te_éte"));
+        assert_eq!(call(snakecase, "ΣΑΣ ΟΔΟΣ"), val!("σας_οδος"));
+        assert_eq!(
+            call(kebabcase, "parseHTTP2Response"),
+            val!("parse-http2-response")
+        );
+        assert_eq!(
+            call(kebabcase, "already-kebab_case"),
+            val!("already-kebab-case")
+        );
+        assert_eq!(call(swapcase, "ǅ1a"), val!("ǅ1A"));
+        assert_eq!(
+            kebabcase(&[val!(1)]).unwrap_err().to_string(),
+            "kebabcase: wrong type for value; expected string; got int"
+        );
+    }
+
+    #[test]
+    fn test_ordered_map() {
+        let data = crate::ordered_map(vec![
+            ("name", Value::from("web")),
+            ("image", Value::from("nginx")),
+            (
+                "ports",
+                crate::ordered_map(vec![("https", 443), ("http", 80)]),
+            ),
+        ]);
+        let render = |tmpl: &str| crate::template(tmpl, data.clone()).unwrap();
+        assert_eq!(
+            render("{{ . }}"),
+            "map[name:web image:nginx ports:map[https:443 http:80]]"
+        );
+        assert_eq!(
+            render("{{ printf \"%#v\" .ports }}"),
+            r#"map[string]interface {}{"https":443, "http":80}"#
+        );
+        assert_eq!(
+            render("{{ keys . .ports }}"),
+            "[name image ports https http]"
+        );
+        assert_eq!(render("{{ values .ports }}"), "[443 80]");
+        assert_eq!(
+            render("{{ len . }} {{ if omit .ports \"http\" \"https\" }}x{{ else }}empty{{ end }}"),
+            "3 empty"
+        );
+        assert_eq!(
+            render("{{ set .ports \"admin\" 8080 }}"),
+            "map[https:443 http:80 admin:8080]"
+        );
+        assert_eq!(render("{{ pick .ports \"http\" }}"), "map[http:80]");
+        assert_eq!(
+            render("{{ merge .ports (dict \"admin\" 8080 \"http\" 81) }}"),
+            "map[https:443 http:80 admin:8080]"
+        );
+        assert_eq!(
+            render("{{ merge (dict \"ports\" (dict \"admin\" 8080)) . | toString }}"),
+            "map[image:nginx name:web ports:map[admin:8080 http:80 https:443]]"
+        );
+        assert_eq!(
+            render(concat!(
+                "{{ deepEqual .ports (dict \"http\" 80 \"https\" 443) }} ",
+                "{{ deepEqual .ports (dict \"http\" 80) }} {{ eq (toString .ports) \"x\" }}"
+            )),
+            "true false false"
+        );
+        assert_eq!(
+            render("{{ index . \"ports\" \"http\" }} {{ hasKey . \"name\" }} {{ .ports.https }}"),
+            "80 true 443"
+        );
+        assert_eq!(render("{{ jsonpath \"$.ports.*\" . }}"), "[443 80]");
+        assert_eq!(
+            render("{{ table (list .ports (dict \"admin\" 8080 \"http\" 81)) }}"),
+            "https  http  admin\n-----  ----  -----\n443    80\n       81    8080"
+        );
     }
 
     #[test]
@@ -764,4 +5731,37 @@ mod tests_mocked {
         let ret = has_prefix(&vals);
         assert_eq!(ret.unwrap(), Value::from(true));
     }
+
+    #[test]
+    fn test_gtmpl_fn_variadic() {
+        gtmpl_fn!(
+            fn sum(...nums: u64) -> Result<u64, FuncError> {
+                Ok(nums.iter().sum())
+            }
+        );
+        assert_eq!(sum(&[]).unwrap(), Value::from(0u64));
+        let vals: Vec<Value> = vec![val!(1u32), val!(2u32), val!(3u32)];
+        assert_eq!(sum(&vals).unwrap(), Value::from(6u64));
+
+        gtmpl_fn!(
+            fn repeat(s: String, n: u64, ...seps: String) -> Result<String, FuncError> {
+                Ok(vec![s; n as usize].join(&seps.concat()))
+            }
+        );
+        let vals: Vec<Value> = vec![val!("a"), val!(3u32), val!("-"), val!("+")];
+        assert_eq!(repeat(&vals).unwrap(), Value::from("a-+a-+a"));
+        let vals: Vec<Value> = vec![val!("a"), val!(2u32)];
+        assert_eq!(repeat(&vals).unwrap(), Value::from("aa"));
+
+        let vals: Vec<Value> = vec![val!("a")];
+        assert!(matches!(
+            repeat(&vals),
+            Err(FuncError::AtLeastXArgs(ref name, 2)) if name == "repeat"
+        ));
+        let vals: Vec<Value> = vec![val!("a"), val!(2u32), val!(1u32)];
+        assert!(matches!(
+            repeat(&vals),
+            Err(FuncError::UnableToConvertFromValue)
+        ));
+    }
 }
@@ -0,0 +1,255 @@
+//! Typed arguments of functions written with the `gtmpl_fn` attribute.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use gtmpl_value::{FuncError, Value};
+
+use crate::ordered;
+use crate::print_verb::type_name;
+
+/// Converts an argument of a template function to a Rust type. The
+/// [`gtmpl_fn`](crate::funcs::gtmpl_fn) attribute of the `macros` feature uses it for the typed
+/// parameters of a function.
+///
+/// Numbers convert to any integer type they fit into and to floats. `Option<T>` parameters
+/// accept nil and may be left out at the end of a call.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{FromArg, Value};
+///
+/// let name = Value::from("World");
+/// assert_eq!(<&str>::from_arg("greet", &name).unwrap(), "World");
+/// assert_eq!(
+///     i64::from_arg("greet", &name).unwrap_err().to_string(),
+///     "greet: wrong type for value; expected int; got string"
+/// );
+/// assert_eq!(Option::<i64>::missing(), Some(None));
+/// ```
+pub trait FromArg<'a>: Sized {
+    /// Converts the argument `val` of the function `name`.
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError>;
+
+    /// The value of a left out argument, or `None` if the argument is required.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+fn wrong_type(name: &str, expected: &str, val: &Value) -> FuncError {
+    FuncError::Generic(format!(
+        "{}: wrong type for value; expected {}; got {}",
+        name,
+        expected,
+        type_name(val)
+    ))
+}
+
+impl<'a> FromArg<'a> for &'a Value {
+    fn from_arg(_name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        Ok(val)
+    }
+}
+
+impl<'a> FromArg<'a> for Value {
+    fn from_arg(_name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        Ok(val.clone())
+    }
+}
+
+impl<'a> FromArg<'a> for &'a str {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match *val {
+            Value::String(ref s) => Ok(s),
+            _ => Err(wrong_type(name, "string", val)),
+        }
+    }
+}
+
+impl<'a> FromArg<'a> for String {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        <&str>::from_arg(name, val).map(String::from)
+    }
+}
+
+impl<'a> FromArg<'a> for bool {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match *val {
+            Value::Bool(b) => Ok(b),
+            _ => Err(wrong_type(name, "bool", val)),
+        }
+    }
+}
+
+macro_rules! from_int_arg {
+    ($($ty:ty)*) => {
+        $(
+            impl<'a> FromArg<'a> for $ty {
+                fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+                    let n = match *val {
+                        Value::Number(ref n) => n,
+                        _ => return Err(wrong_type(name, "int", val)),
+                    };
+                    let i = match (n.as_i64(), n.as_u64()) {
+                        (Some(i), _) => i128::from(i),
+                        (_, Some(u)) => i128::from(u),
+                        _ => return Err(wrong_type(name, "int", val)),
+                    };
+                    <$ty>::try_from(i).map_err(|_| {
+                        FuncError::Generic(format!("{}: integer {} out of range", name, i))
+                    })
+                }
+            }
+        )*
+    };
+}
+
+from_int_arg!(i8 i16 i32 i64 isize u8 u16 u32 u64 usize);
+
+impl<'a> FromArg<'a> for f64 {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match *val {
+            Value::Number(ref n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+                (Some(i), _, _) => Ok(i as f64),
+                (_, Some(u), _) => Ok(u as f64),
+                (_, _, Some(f)) => Ok(f),
+                _ => Err(wrong_type(name, "number", val)),
+            },
+            _ => Err(wrong_type(name, "number", val)),
+        }
+    }
+}
+
+impl<'a> FromArg<'a> for f32 {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        f64::from_arg(name, val).map(|f| f as f32)
+    }
+}
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for Option<T> {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match *val {
+            Value::Nil | Value::NoValue => Ok(None),
+            _ => T::from_arg(name, val).map(Some),
+        }
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for Vec<T> {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match *val {
+            Value::Array(ref items) => items.iter().map(|v| T::from_arg(name, v)).collect(),
+            _ => Err(wrong_type(name, "slice", val)),
+        }
+    }
+}
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for HashMap<String, T> {
+    fn from_arg(name: &str, val: &'a Value) -> Result<Self, FuncError> {
+        match ordered::as_map(val) {
+            Some(m) => m
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), T::from_arg(name, v)?)))
+                .collect(),
+            None => Err(wrong_type(name, "map", val)),
+        }
+    }
+}
+
+// Fails unless the function `name` got between `min` and `max` arguments.
+pub fn check_arity(
+    name: &str,
+    args: &[Value],
+    min: usize,
+    max: Option<usize>,
+) -> Result<(), FuncError> {
+    let got = args.len();
+    match max {
+        Some(max) if max == min && got != max => Err(FuncError::ExactlyXArgs(name.into(), max)),
+        _ if got < min => Err(FuncError::AtLeastXArgs(name.into(), min)),
+        Some(max) if got > max => Err(FuncError::Generic(format!(
+            "{}: wrong number of args: want {} {} {}",
+            name,
+            min,
+            if max == min + 1 { "or" } else { "to" },
+            max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// The argument `i` of the function `name`, or the value of a left out argument.
+pub fn arg<'a, T: FromArg<'a>>(name: &str, args: &'a [Value], i: usize) -> Result<T, FuncError> {
+    match args.get(i) {
+        Some(val) => T::from_arg(name, val),
+        None => T::missing().ok_or_else(|| FuncError::AtLeastXArgs(name.into(), i + 1)),
+    }
+}
+
+// The arguments of the function `name` from `i` on.
+pub fn rest<'a, T: FromArg<'a>>(
+    name: &str,
+    args: &'a [Value],
+    i: usize,
+) -> Result<Vec<T>, FuncError> {
+    args.get(i..)
+        .unwrap_or_default()
+        .iter()
+        .map(|val| T::from_arg(name, val))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_arg() {
+        let err = |ret: Result<u8, FuncError>| ret.unwrap_err().to_string();
+        assert_eq!(u8::from_arg("f", &Value::from(255)).unwrap(), 255);
+        assert_eq!(
+            err(u8::from_arg("f", &Value::from(-1))),
+            "f: integer -1 out of range"
+        );
+        assert_eq!(
+            err(u8::from_arg("f", &Value::from(1.5))),
+            "f: wrong type for value; expected int; got float64"
+        );
+        assert_eq!(
+            f64::from_arg("f", &Value::from(u64::MAX)).unwrap(),
+            u64::MAX as f64
+        );
+        assert_eq!(Option::<&str>::from_arg("f", &Value::Nil).unwrap(), None);
+        let list = Value::from(vec![Value::from(1), Value::from(2)]);
+        assert_eq!(Vec::<i64>::from_arg("f", &list).unwrap(), vec![1, 2]);
+        assert!(Vec::<String>::from_arg("f", &list).is_err());
+    }
+
+    #[test]
+    fn test_arity() {
+        let args = [Value::from(1), Value::from(2)];
+        assert!(check_arity("f", &args, 1, Some(2)).is_ok());
+        assert!(check_arity("f", &args, 0, None).is_ok());
+        assert!(matches!(
+            check_arity("f", &args, 1, Some(1)),
+            Err(FuncError::ExactlyXArgs(_, 1))
+        ));
+        assert!(matches!(
+            check_arity("f", &args, 3, None),
+            Err(FuncError::AtLeastXArgs(_, 3))
+        ));
+        assert_eq!(
+            check_arity("f", &args, 0, Some(1)).unwrap_err().to_string(),
+            "f: wrong number of args: want 0 or 1"
+        );
+        assert_eq!(arg::<Option<i64>>("f", &args, 2).unwrap(), None);
+        assert!(arg::<i64>("f", &args, 2).is_err());
+        assert_eq!(rest::<i64>("f", &args, 1).unwrap(), vec![2]);
+        assert!(rest::<i64>("f", &args, 3).unwrap().is_empty());
+    }
+}
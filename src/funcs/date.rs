@@ -0,0 +1,822 @@
+//! Date functions.
+//!
+//! Times are passed around as RFC 3339 strings like `2006-01-02T15:04:05Z`, which keep their
+//! UTC offset. Functions taking a time also accept the seconds since the Unix epoch. Both Go
+//! layouts and `strftime` formats format the same times. Durations are Go durations like
+//! `1h30m0s` or numbers of seconds.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use chrono::format::{Fixed, Item, StrftimeItems};
+use chrono::{DateTime, Duration, FixedOffset, Local, SecondsFormat, Utc};
+use chrono_tz::{OffsetName, Tz};
+use gtmpl_value::{FuncError, Value};
+
+use super::{exact, string};
+use crate::clock;
+use crate::print_verb::type_name;
+
+/// A point in time and the name of its zone, if known.
+pub(crate) struct Time {
+    pub(crate) dt: DateTime<FixedOffset>,
+    pub(crate) zone: Option<String>,
+}
+
+impl Time {
+    fn utc(dt: DateTime<Utc>) -> Time {
+        Time {
+            dt: dt.fixed_offset(),
+            zone: Some("UTC".into()),
+        }
+    }
+
+    // The time argument of the function `name`.
+    pub(crate) fn from_value(name: &str, val: &Value) -> Result<Time, FuncError> {
+        match *val {
+            Value::String(ref s) => {
+                let dt = DateTime::parse_from_rfc3339(s).map_err(|e| {
+                    FuncError::Generic(format!("{}: parsing time {:?}: {}", name, s, e))
+                })?;
+                let zone = match dt.offset().local_minus_utc() {
+                    0 => Some("UTC".into()),
+                    _ => None,
+                };
+                Ok(Time { dt, zone })
+            }
+            Value::Number(ref n) => {
+                let dt = match (n.as_i64(), n.as_f64()) {
+                    (Some(secs), _) => DateTime::from_timestamp(secs, 0),
+                    (None, Some(secs)) if secs.is_finite() => {
+                        let nanos = (secs.fract() * 1e9).round() as i64;
+                        DateTime::from_timestamp(secs.trunc() as i64, 0)
+                            .and_then(|dt| dt.checked_add_signed(Duration::nanoseconds(nanos)))
+                    }
+                    _ => None,
+                };
+                dt.map(Time::utc)
+                    .ok_or_else(|| FuncError::Generic(format!("{}: time {} out of range", name, n)))
+            }
+            _ => Err(FuncError::Generic(format!(
+                "{}: wrong type for value; expected time; got {}",
+                name,
+                type_name(val)
+            ))),
+        }
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        Value::from(self.dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    // The time in the zone called `zone`, which is an IANA name like `Europe/Berlin`, `UTC` or
+    // `Local` for the zone of the system.
+    fn in_zone(&self, name: &str, zone: &str) -> Result<Time, FuncError> {
+        match zone {
+            "" | "UTC" => Ok(Time::utc(self.dt.with_timezone(&Utc))),
+            "Local" => Ok(Time {
+                dt: self.dt.with_timezone(&Local).fixed_offset(),
+                zone: None,
+            }),
+            _ => {
+                let tz: Tz = zone.parse().map_err(|_| {
+                    FuncError::Generic(format!("{}: unknown time zone {}", name, zone))
+                })?;
+                let dt = self.dt.with_timezone(&tz);
+                Ok(Time {
+                    zone: dt.offset().abbreviation().map(str::to_owned),
+                    dt: dt.fixed_offset(),
+                })
+            }
+        }
+    }
+}
+
+/// Returns the current time, or the frozen time of a deterministic execution, in UTC.
+///
+/// # Example
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use gtmpl::clock::Deterministic;
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.exec_options.deterministic = Some(Deterministic {
+///     now: UNIX_EPOCH + Duration::from_millis(1_500),
+///     seed: 0,
+/// });
+/// tmpl.parse("{{ now }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "1970-01-01T00:00:01.500Z");
+/// ```
+pub fn now(args: &[Value]) -> Result<Value, FuncError> {
+    exact::<0>("now", args)?;
+    Ok(Time::utc(DateTime::from(clock::now())).to_value())
+}
+
+/// Returns the time formatted with a layout of Go's `time` package, i.e. `date layout t`.
+/// Layouts show how the reference time `Mon Jan 2 15:04:05 MST 2006` would be formatted. The
+/// time keeps its UTC offset, see `dateInZone` to convert it.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let t = "2024-03-09T17:45:00+01:00";
+/// let date = template(r#"{{ date "Monday, 02-Jan-06 3:04PM -07:00" . }}"#, t);
+/// assert_eq!(&date.unwrap(), "Saturday, 09-Mar-24 5:45PM +01:00");
+/// ```
+pub fn date(args: &[Value]) -> Result<Value, FuncError> {
+    let [layout, t] = exact::<2>("date", args)?;
+    let layout = string("date", layout)?;
+    Ok(Value::from(format_go(
+        &Time::from_value("date", t)?,
+        layout,
+    )))
+}
+
+/// Returns the time formatted with a Go layout in a time zone, i.e. `dateInZone layout t zone`.
+/// Zones are IANA names like `Europe/Berlin`, `UTC` or `Local` for the zone of the system.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let date = template(r#"{{ dateInZone "2006-01-02 15:04 MST" . "Asia/Tokyo" }}"#, 0);
+/// assert_eq!(&date.unwrap(), "1970-01-01 09:00 JST");
+/// ```
+pub fn date_in_zone(args: &[Value]) -> Result<Value, FuncError> {
+    let [layout, t, zone] = exact::<3>("dateInZone", args)?;
+    let layout = string("dateInZone", layout)?;
+    let zone = string("dateInZone", zone)?;
+    let t = Time::from_value("dateInZone", t)?.in_zone("dateInZone", zone)?;
+    Ok(Value::from(format_go(&t, layout)))
+}
+
+/// Returns the time formatted like C's `strftime`, i.e. `strftime format t [zone]`. The
+/// optional zone converts the time like `dateInZone` does. See chrono's
+/// [`format::strftime`](https://docs.rs/chrono/0.4/chrono/format/strftime/index.html) for the
+/// supported specifiers.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let t = "2024-03-09T17:45:00+01:00";
+/// let date = template(r#"{{ strftime "%Y-%m-%d %H:%M %Z" . }}"#, t);
+/// assert_eq!(&date.unwrap(), "2024-03-09 17:45 +0100");
+/// let date = template(r#"{{ strftime "%a %-d %b, %I:%M %p %Z" . "Asia/Tokyo" }}"#, t);
+/// assert_eq!(&date.unwrap(), "Sun 10 Mar, 01:45 AM JST");
+/// ```
+pub fn strftime(args: &[Value]) -> Result<Value, FuncError> {
+    let (format, t, zone) = match args {
+        [format, t] => (format, t, None),
+        [format, t, zone] => (format, t, Some(string("strftime", zone)?)),
+        _ => {
+            return Err(FuncError::Generic(
+                "strftime: wrong number of args: want 2 or 3".into(),
+            ))
+        }
+    };
+    let format = string("strftime", format)?;
+    let mut t = Time::from_value("strftime", t)?;
+    if let Some(zone) = zone {
+        t = t.in_zone("strftime", zone)?;
+    }
+    // `%Z` prints the name of the zone like the `MST` of Go layouts instead of the offset.
+    let items = StrftimeItems::new(format)
+        .map(|item| match item {
+            Item::Fixed(Fixed::TimezoneName) => Ok(Item::OwnedLiteral(zone_name(&t).into())),
+            Item::Error => Err(FuncError::Generic(format!(
+                "strftime: invalid format {:?}",
+                format
+            ))),
+            item => Ok(item),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut out = String::new();
+    write!(out, "{}", t.dt.format_with_items(items.iter()))
+        .map_err(|_| FuncError::Generic(format!("strftime: unable to format {:?}", format)))?;
+    Ok(Value::from(out))
+}
+
+/// Returns the time moved by a duration like `-1.5h`, `2h45m` or a number of seconds, i.e.
+/// `dateModify duration t`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let t = "2024-03-01T12:00:00+01:00";
+/// let date = template(r#"{{ dateModify "-36h" . | dateModify 90 }}"#, t);
+/// assert_eq!(&date.unwrap(), "2024-02-29T00:01:30+01:00");
+/// ```
+pub fn date_modify(args: &[Value]) -> Result<Value, FuncError> {
+    let [duration, t] = exact::<2>("dateModify", args)?;
+    let nanos = duration_arg("dateModify", duration)?;
+    let mut t = Time::from_value("dateModify", t)?;
+    t.dt =
+        t.dt.checked_add_signed(Duration::nanoseconds(nanos))
+            .ok_or_else(|| FuncError::Generic("dateModify: time out of range".into()))?;
+    Ok(t.to_value())
+}
+
+/// Returns the seconds since the Unix epoch of a time.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let secs = template("{{ unixEpoch . }}", "2001-09-09T03:46:40+02:00");
+/// assert_eq!(&secs.unwrap(), "1000000000");
+/// ```
+pub fn unix_epoch(args: &[Value]) -> Result<Value, FuncError> {
+    let [t] = exact::<1>("unixEpoch", args)?;
+    Ok(Value::from(
+        Time::from_value("unixEpoch", t)?.dt.timestamp(),
+    ))
+}
+
+/// Returns the time between two times as a duration, i.e. `dateSub t u` is `t - u`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"{{ dateSub "2024-03-01T12:00:00+01:00" "2024-02-29T10:30:00Z" }}"#;
+/// assert_eq!(&template(tmpl, "").unwrap(), "24h30m0s");
+/// ```
+pub fn date_sub(args: &[Value]) -> Result<Value, FuncError> {
+    let [t, u] = exact::<2>("dateSub", args)?;
+    let t = Time::from_value("dateSub", t)?;
+    let u = Time::from_value("dateSub", u)?;
+    let nanos =
+        t.dt.signed_duration_since(u.dt)
+            .num_nanoseconds()
+            .ok_or_else(|| FuncError::Generic("dateSub: duration out of range".into()))?;
+    Ok(Value::from(format_duration(nanos)))
+}
+
+/// Returns a duration in Go's format, e.g. `1h30m0s` for `1h30m` or `1m35s` for 95 seconds.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let duration = template(r#"{{ duration "90m" }} {{ duration . }}"#, 0.0015);
+/// assert_eq!(&duration.unwrap(), "1h30m0s 1.5ms");
+/// ```
+pub fn duration(args: &[Value]) -> Result<Value, FuncError> {
+    let [d] = exact::<1>("duration", args)?;
+    Ok(Value::from(format_duration(duration_arg("duration", d)?)))
+}
+
+/// Returns a duration rounded down to its largest unit, e.g. `2d` for `50h`. Years have 365
+/// and months 30 days.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let rounded = template(r#"{{ durationRound "50h" }} {{ durationRound . }}"#, 400 * 86400);
+/// assert_eq!(&rounded.unwrap(), "2d 1y");
+/// ```
+pub fn duration_round(args: &[Value]) -> Result<Value, FuncError> {
+    let [d] = exact::<1>("durationRound", args)?;
+    let nanos = duration_arg("durationRound", d)?;
+    let sign = if nanos < 0 { "-" } else { "" };
+    let u = nanos.unsigned_abs();
+    let rounded = UNITS
+        .iter()
+        .find(|&&(_, _, _, n)| u >= n)
+        .map(|&(abbr, _, _, n)| format!("{}{}{}", sign, u / n, abbr))
+        .unwrap_or_else(|| "0s".into());
+    Ok(Value::from(rounded))
+}
+
+/// Returns a duration in words, e.g. `1 day 2 hours 5 seconds`. Parts of a second are left
+/// out.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let words = template("{{ humanizeDuration . }}", 3_665.5);
+/// assert_eq!(&words.unwrap(), "1 hour 1 minute 5 seconds");
+/// ```
+pub fn humanize_duration(args: &[Value]) -> Result<Value, FuncError> {
+    let [d] = exact::<1>("humanizeDuration", args)?;
+    let nanos = duration_arg("humanizeDuration", d)?;
+    let mut rest = nanos.unsigned_abs();
+    let mut parts = vec![];
+    // Years and months are left to `durationRound` as they are not exact.
+    for &(_, one, many, n) in &UNITS[2..] {
+        let count = rest / n;
+        rest %= n;
+        match count {
+            0 => {}
+            1 => parts.push(format!("1 {}", one)),
+            _ => parts.push(format!("{} {}", count, many)),
+        }
+    }
+    let words = match parts.join(" ") {
+        words if words.is_empty() => "0 seconds".into(),
+        words if nanos < 0 => format!("-{}", words),
+        words => words,
+    };
+    Ok(Value::from(words))
+}
+
+/// Returns the time since a time in words, rounded down to its largest unit, e.g. `2 hours ago`
+/// or `in 3 days` for times in the future. Years have 365 and months 30 days, and times less
+/// than a second away are `just now`.
+///
+/// # Example
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use gtmpl::clock::Deterministic;
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.exec_options.deterministic = Some(Deterministic {
+///     now: UNIX_EPOCH + Duration::from_secs(9_000),
+///     seed: 0,
+/// });
+/// tmpl.parse(r#"{{ timeAgo "1970-01-01T00:00:00Z" }}, {{ timeAgo 99000 }}"#)
+///     .unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "2 hours ago, in 1 day");
+/// ```
+pub fn time_ago(args: &[Value]) -> Result<Value, FuncError> {
+    let [t] = exact::<1>("timeAgo", args)?;
+    let t = Time::from_value("timeAgo", t)?;
+    let now = DateTime::<Utc>::from(clock::now());
+    let nanos = now
+        .signed_duration_since(t.dt)
+        .num_nanoseconds()
+        .ok_or_else(|| FuncError::Generic("timeAgo: duration out of range".into()))?;
+    let u = nanos.unsigned_abs();
+    let words = match UNITS.iter().find(|&&(_, _, _, n)| u >= n) {
+        Some(&(_, one, _, n)) if u / n == 1 => format!("1 {}", one),
+        Some(&(_, _, many, n)) => format!("{} {}", u / n, many),
+        None => return Ok(Value::from("just now")),
+    };
+    match nanos {
+        n if n < 0 => Ok(Value::from(format!("in {}", words))),
+        _ => Ok(Value::from(format!("{} ago", words))),
+    }
+}
+
+// The units of rounded and humanized durations: abbreviation, names and nanoseconds.
+const UNITS: [(&str, &str, &str, u64); 6] = [
+    ("y", "year", "years", 365 * 24 * 3_600_000_000_000),
+    ("mo", "month", "months", 30 * 24 * 3_600_000_000_000),
+    ("d", "day", "days", 24 * 3_600_000_000_000),
+    ("h", "hour", "hours", 3_600_000_000_000),
+    ("m", "minute", "minutes", 60_000_000_000),
+    ("s", "second", "seconds", 1_000_000_000),
+];
+
+// The duration argument of the function `name` in nanoseconds, either a Go duration or a
+// number of seconds.
+fn duration_arg(name: &str, val: &Value) -> Result<i64, FuncError> {
+    let out_of_range = || FuncError::Generic(format!("{}: duration {} out of range", name, val));
+    match *val {
+        Value::String(ref s) => parse_duration(s)
+            .ok_or_else(|| FuncError::Generic(format!("{}: invalid duration {:?}", name, s))),
+        Value::Number(ref n) => match (n.as_i64(), n.as_f64()) {
+            (Some(secs), _) => secs.checked_mul(1_000_000_000).ok_or_else(out_of_range),
+            (None, Some(secs)) => {
+                let nanos = (secs * 1e9).round();
+                // `i64::MAX as f64` rounds up to 2^63, which is out of range.
+                if nanos.is_finite() && nanos >= i64::MIN as f64 && nanos < i64::MAX as f64 {
+                    Ok(nanos as i64)
+                } else {
+                    Err(out_of_range())
+                }
+            }
+            _ => Err(out_of_range()),
+        },
+        _ => Err(FuncError::Generic(format!(
+            "{}: wrong type for value; expected duration; got {}",
+            name,
+            type_name(val)
+        ))),
+    }
+}
+
+/// Formats nanoseconds like Go's `time.Duration`, e.g. `1h30m0s`, `2.5s` or `1.5ms`.
+pub(crate) fn format_duration(nanos: i64) -> String {
+    // A number of `unit`s with the fraction of them `scale` nanoseconds make up.
+    fn frac(u: u64, scale: u64, unit: &str) -> String {
+        let (int, frac) = (u / scale, u % scale);
+        if frac == 0 {
+            return format!("{}{}", int, unit);
+        }
+        let width = scale.trailing_zeros() as usize;
+        let frac = format!("{:0width$}", frac, width = width);
+        format!("{}.{}{}", int, frac.trim_end_matches('0'), unit)
+    }
+
+    let sign = if nanos < 0 { "-" } else { "" };
+    let u = nanos.unsigned_abs();
+    let text = match u {
+        0 => return "0s".into(),
+        1..=999 => frac(u, 1, "ns"),
+        1_000..=999_999 => frac(u, 1_000, "µs"),
+        1_000_000..=999_999_999 => frac(u, 1_000_000, "ms"),
+        _ => {
+            let secs = u / 1_000_000_000;
+            let (h, m) = (secs / 3600, secs / 60 % 60);
+            let s = frac(u % 60_000_000_000, 1_000_000_000, "s");
+            match (h, m) {
+                (0, 0) => s,
+                (0, m) => format!("{}m{}", m, s),
+                (h, m) => format!("{}h{}m{}", h, m, s),
+            }
+        }
+    };
+    format!("{}{}", sign, text)
+}
+
+/// Parses a duration like Go's `time.ParseDuration`, e.g. `1h30m` or `-1.5s`, into
+/// nanoseconds.
+pub(crate) fn parse_duration(s: &str) -> Option<i64> {
+    let (neg, mut rest) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if rest == "0" {
+        return Some(0);
+    }
+    if rest.is_empty() {
+        return None;
+    }
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let mut total: u128 = 0;
+    while !rest.is_empty() {
+        let (int, after) = rest.split_at(digits(rest));
+        let (frac, after) = match after.strip_prefix('.') {
+            Some(after) => after.split_at(digits(after)),
+            None => ("", after),
+        };
+        if int.is_empty() && frac.is_empty() {
+            return None;
+        }
+        let unit_len = after
+            .find(|c: char| c == '.' || c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        let scale: u128 = match unit {
+            "ns" => 1,
+            "us" | "µs" | "μs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            "m" => 60_000_000_000,
+            "h" => 3_600_000_000_000,
+            _ => return None,
+        };
+        let int: u128 = if int.is_empty() { 0 } else { int.parse().ok()? };
+        // Digits beyond a nanosecond of an hour do not matter.
+        let frac = &frac[..frac.len().min(13)];
+        let frac_nanos = match frac {
+            "" => 0,
+            _ => frac.parse::<u128>().ok()? * scale / 10u128.pow(frac.len() as u32),
+        };
+        total = int
+            .checked_mul(scale)?
+            .checked_add(frac_nanos)?
+            .checked_add(total)?;
+        rest = after;
+    }
+    if neg {
+        i64::try_from(-i128::try_from(total).ok()?).ok()
+    } else {
+        i64::try_from(total).ok()
+    }
+}
+
+/// Formats `t` with a layout of Go's `time` package.
+pub(crate) fn format_go(t: &Time, layout: &str) -> String {
+    let mut out = String::with_capacity(layout.len() + 10);
+    let mut rest = layout;
+    while let Some(c) = rest.chars().next() {
+        match std_chunk(t, rest) {
+            Some((len, text)) => {
+                out.push_str(&text);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+// The length and formatted text of the layout element `rest` starts with, like Go's
+// `nextStdChunk`.
+fn std_chunk(t: &Time, rest: &str) -> Option<(usize, String)> {
+    use chrono::{Datelike, Timelike};
+
+    let dt = &t.dt;
+    let hour12 = match dt.hour() % 12 {
+        0 => 12,
+        h => h,
+    };
+    let chunk = |len: usize, text: String| Some((len, text));
+    let b = rest.as_bytes();
+    match b[0] {
+        b'J' if rest.starts_with("January") => chunk(7, dt.format("%B").to_string()),
+        b'J' if rest.starts_with("Jan") => chunk(3, dt.format("%b").to_string()),
+        b'M' if rest.starts_with("Monday") => chunk(6, dt.format("%A").to_string()),
+        b'M' if rest.starts_with("Mon") => chunk(3, dt.format("%a").to_string()),
+        b'M' if rest.starts_with("MST") => chunk(3, zone_name(t)),
+        b'0' if rest.starts_with("002") => chunk(3, format!("{:03}", dt.ordinal())),
+        b'0' => match b.get(1) {
+            Some(b'1') => chunk(2, format!("{:02}", dt.month())),
+            Some(b'2') => chunk(2, format!("{:02}", dt.day())),
+            Some(b'3') => chunk(2, format!("{:02}", hour12)),
+            Some(b'4') => chunk(2, format!("{:02}", dt.minute())),
+            Some(b'5') => chunk(2, format!("{:02}", dt.second())),
+            Some(b'6') => chunk(2, format!("{:02}", dt.year().rem_euclid(100))),
+            _ => None,
+        },
+        b'1' if rest.starts_with("15") => chunk(2, format!("{:02}", dt.hour())),
+        b'1' => chunk(1, dt.month().to_string()),
+        b'2' if rest.starts_with("2006") => chunk(4, format!("{:04}", dt.year())),
+        b'2' => chunk(1, dt.day().to_string()),
+        // `_2006` is a literal `_` followed by the year.
+        b'_' if rest.starts_with("_2") && !rest.starts_with("_2006") => {
+            chunk(2, format!("{:>2}", dt.day()))
+        }
+        b'_' if rest.starts_with("__2") => chunk(3, format!("{:>3}", dt.ordinal())),
+        b'3' => chunk(1, hour12.to_string()),
+        b'4' => chunk(1, dt.minute().to_string()),
+        b'5' => chunk(1, dt.second().to_string()),
+        b'P' if rest.starts_with("PM") => chunk(2, dt.format("%p").to_string()),
+        b'p' if rest.starts_with("pm") => chunk(2, dt.format("%P").to_string()),
+        b'-' | b'Z' => {
+            let z = b[0] == b'Z';
+            ["070000", "07:00:00", "0700", "07:00", "07"]
+                .iter()
+                .find(|&&f| rest[1..].starts_with(f))
+                .map(|&f| {
+                    (
+                        f.len() + 1,
+                        format_offset(dt.offset().local_minus_utc(), f, z),
+                    )
+                })
+        }
+        b'.' | b',' => {
+            let digit = *b.get(1).filter(|&&d| d == b'0' || d == b'9')?;
+            let n = b[1..].iter().take_while(|&&d| d == digit).count();
+            if b.get(n + 1).is_some_and(u8::is_ascii_digit) {
+                return None;
+            }
+            let nanos = format!("{:09}", dt.nanosecond() % 1_000_000_000);
+            let mut frac = &nanos[..n.min(9)];
+            if digit == b'9' {
+                frac = frac.trim_end_matches('0');
+                if frac.is_empty() {
+                    return chunk(n + 1, String::new());
+                }
+            }
+            chunk(n + 1, format!("{}{}", b[0] as char, frac))
+        }
+        _ => None,
+    }
+}
+
+// The abbreviation of the zone of `t`, or its offset like `-0700` if there is none.
+fn zone_name(t: &Time) -> String {
+    match t.zone {
+        Some(ref zone) => zone.clone(),
+        None => format_offset(t.dt.offset().local_minus_utc(), "0700", false),
+    }
+}
+
+// Formats a UTC offset like the Go layout element `-` or `Z` followed by `layout`.
+fn format_offset(secs: i32, layout: &str, z: bool) -> String {
+    if z && secs == 0 {
+        return "Z".into();
+    }
+    let sign = if secs < 0 { '-' } else { '+' };
+    let secs = secs.unsigned_abs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match layout {
+        "070000" => format!("{}{:02}{:02}{:02}", sign, h, m, s),
+        "07:00:00" => format!("{}{:02}:{:02}:{:02}", sign, h, m, s),
+        "0700" => format!("{}{:02}{:02}", sign, h, m),
+        "07:00" => format!("{}{:02}:{:02}", sign, h, m),
+        _ => format!("{}{:02}", sign, h),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> Time {
+        Time::from_value("test", &Value::from(s)).unwrap()
+    }
+
+    #[test]
+    fn test_format_go() {
+        let t = time("2006-01-02T15:04:05.12-07:00");
+        let cases = [
+            (
+                "Mon Jan _2 15:04:05 MST 2006",
+                "Mon Jan  2 15:04:05 -0700 2006",
+            ),
+            (
+                "Monday, 02-January-06 03:04:05 PM",
+                "Monday, 02-January-06 03:04:05 PM",
+            ),
+            ("1/2 3:4:5 pm", "1/2 3:4:5 pm"),
+            (
+                "2006-01-02T15:04:05.000Z07:00",
+                "2006-01-02T15:04:05.120-07:00",
+            ),
+            (".999 ,99 .9 .0", ".12 ,12 .1 .1"),
+            ("05.0001", "05.0001"),
+            ("002 __2 _2006", "002   2 _2006"),
+            ("-070000 -07:00:00 -07", "-070000 -07:00:00 -07"),
+        ];
+        for &(layout, want) in &cases {
+            assert_eq!(format_go(&t, layout), want, "layout {:?}", layout);
+        }
+
+        let t = time("2024-12-31T00:00:00Z");
+        assert_eq!(format_go(&t, "Z07:00 Z0700 MST .999"), "Z Z UTC ");
+        assert_eq!(format_go(&t, "002 3 PM"), "366 12 AM");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = [
+            ("0", Some(0)),
+            ("1h30m", Some(5_400_000_000_000)),
+            ("-1.5h", Some(-5_400_000_000_000)),
+            ("+.5s", Some(500_000_000)),
+            ("1.s", Some(1_000_000_000)),
+            ("3µs2ns", Some(3_002)),
+            ("1.0000000000000000001h", Some(3_600_000_000_000)),
+            ("2562047h47m16.854775807s", Some(i64::MAX)),
+            ("-2562047h47m16.854775808s", Some(i64::MIN)),
+            ("2562047h47m16.854775808s", None),
+            ("", None),
+            ("-", None),
+            ("1", None),
+            (".s", None),
+            ("1d", None),
+        ];
+        for &(s, want) in &cases {
+            assert_eq!(parse_duration(s), want, "duration {:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_strftime() {
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        let t = Value::from("2024-07-01T12:00:05.25Z");
+        let strftime_ = |format: &str| strftime(&[Value::from(format), t.clone()]);
+        assert_eq!(
+            strftime_("%F %T%.3f %Z %%").unwrap(),
+            Value::from("2024-07-01 12:00:05.250 UTC %")
+        );
+        assert_eq!(
+            strftime_("%s %j %:z").unwrap(),
+            Value::from("1719835205 183 +00:00")
+        );
+        assert_eq!(err(strftime_("%Q")), "strftime: invalid format \"%Q\"");
+        assert_eq!(
+            strftime(&[
+                Value::from("%H:%M %Z"),
+                t.clone(),
+                Value::from("Europe/Berlin")
+            ])
+            .unwrap(),
+            Value::from("14:00 CEST")
+        );
+        assert_eq!(
+            err(strftime(&[Value::from("%F")])),
+            "strftime: wrong number of args: want 2 or 3"
+        );
+    }
+
+    #[test]
+    fn test_durations() {
+        let cases = [
+            (0, "0s"),
+            (1, "1ns"),
+            (-1_500, "-1.5µs"),
+            (1_000_001, "1.000001ms"),
+            (999_999_999, "999.999999ms"),
+            (1_000_000_000, "1s"),
+            (61_500_000_000, "1m1.5s"),
+            (3_600_000_000_000, "1h0m0s"),
+            (i64::MIN, "-2562047h47m16.854775808s"),
+        ];
+        for &(nanos, want) in &cases {
+            assert_eq!(format_duration(nanos), want);
+            assert_eq!(parse_duration(want), Some(nanos));
+        }
+
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        assert_eq!(
+            duration(&[Value::from(1.5)]).unwrap(),
+            Value::from("1.5s")
+        );
+        assert_eq!(
+            err(duration(&[Value::from(u64::MAX)])),
+            format!("duration: duration {} out of range", u64::MAX)
+        );
+        assert_eq!(
+            err(duration(&[Value::from(9.3e9)])),
+            "duration: duration 9300000000 out of range"
+        );
+        assert_eq!(
+            err(duration(&[Value::from("soon")])),
+            "duration: invalid duration \"soon\""
+        );
+        assert_eq!(
+            duration_round(&[Value::from("-59.9s")]).unwrap(),
+            Value::from("-59s")
+        );
+        assert_eq!(
+            duration_round(&[Value::from("999ms")]).unwrap(),
+            Value::from("0s")
+        );
+        assert_eq!(
+            duration_round(&[Value::from("720h")]).unwrap(),
+            Value::from("1mo")
+        );
+        assert_eq!(
+            humanize_duration(&[Value::from("-49h0.5s")]).unwrap(),
+            Value::from("-2 days 1 hour")
+        );
+        assert_eq!(
+            humanize_duration(&[Value::from("0.9s")]).unwrap(),
+            Value::from("0 seconds")
+        );
+        let ago = |secs: i64| {
+            let t = Utc::now().timestamp() - secs;
+            time_ago(&[Value::from(t)]).unwrap().to_string()
+        };
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(90), "1 minute ago");
+        assert_eq!(ago(3 * 86_400 + 60), "3 days ago");
+        assert_eq!(ago(-3 * 86_400 - 60), "in 3 days");
+        assert_eq!(ago(400 * 86_400), "1 year ago");
+        assert_eq!(
+            date_sub(&[Value::from(0), Value::from("1970-01-01T00:00:01.5Z")]).unwrap(),
+            Value::from("-1.5s")
+        );
+        assert_eq!(
+            err(date_sub(&[
+                Value::from(1_000_000_000_000i64),
+                Value::from(0)
+            ])),
+            "dateSub: duration out of range"
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let err = |ret: Result<Value, FuncError>| ret.unwrap_err().to_string();
+        let t = Value::from("2024-07-01T12:00:00+02:00");
+        assert_eq!(
+            date_in_zone(&[
+                Value::from("15:04 MST"),
+                t.clone(),
+                Value::from("America/New_York")
+            ])
+            .unwrap(),
+            Value::from("06:00 EDT")
+        );
+        assert_eq!(
+            err(date_in_zone(&[
+                Value::from(""),
+                t.clone(),
+                Value::from("Mars/Base")
+            ])),
+            "dateInZone: unknown time zone Mars/Base"
+        );
+        assert_eq!(
+            date(&[Value::from("2006-01-02 15:04:05.000"), Value::from(1.25)]).unwrap(),
+            Value::from("1970-01-01 00:00:01.250")
+        );
+        assert_eq!(
+            date_modify(&[Value::from("1ns"), t.clone()]).unwrap(),
+            Value::from("2024-07-01T12:00:00.000000001+02:00")
+        );
+        assert_eq!(
+            err(date_modify(&[Value::from("1 day"), t])),
+            "dateModify: invalid duration \"1 day\""
+        );
+        assert!(err(unix_epoch(&[Value::from("yesterday")]))
+            .starts_with("unixEpoch: parsing time \"yesterday\""));
+        assert_eq!(
+            err(unix_epoch(&[Value::from(true)])),
+            "unixEpoch: wrong type for value; expected time; got bool"
+        );
+        assert_eq!(
+            err(date(&[Value::from(""), Value::from(u64::MAX)])),
+            format!("date: time {} out of range", u64::MAX)
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Functions reading files below a root directory.
+//!
+//! Templates can only call them after
+//! [`Template::add_file_funcs`](crate::Template::add_file_funcs), which sets the root directory.
+//! Paths are relative to the root, and paths leaving it, e.g. by `..` or symbolic links, are
+//! rejected.
+
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use gtmpl_value::{FuncError, Value};
+
+use super::{check_size, exact, string, MAX_SIZES};
+
+thread_local! {
+    // The root directory of the execution on the current thread.
+    static ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Returns the contents of a file as a string. Files larger than the maximum output size fail.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// let root = std::env::temp_dir().join("gtmpl-read-file-example");
+/// std::fs::create_dir_all(&root).unwrap();
+/// std::fs::write(root.join("motd.txt"), "Hello!").unwrap();
+/// let mut tmpl = Template::default();
+/// tmpl.add_file_funcs(&root);
+/// tmpl.parse(r#"{{ readFile "motd.txt" }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "Hello!");
+/// ```
+pub fn read_file(args: &[Value]) -> Result<Value, FuncError> {
+    let [path] = exact::<1>("readFile", args)?;
+    let path = string("readFile", path)?;
+    let resolved = resolve("readFile", path)?;
+    let err = |e: &dyn std::fmt::Display| FuncError::Generic(format!("readFile: {}: {}", path, e));
+    // One byte more than allowed is read to tell whether the file is too large.
+    let (max, _) = MAX_SIZES.with(Cell::get);
+    let mut contents = vec![];
+    fs::File::open(resolved)
+        .and_then(|file| file.take(max as u64 + 1).read_to_end(&mut contents))
+        .map_err(|e| err(&e))?;
+    check_size("readFile", Some(contents.len()))?;
+    String::from_utf8(contents)
+        .map(Value::from)
+        .map_err(|e| err(&e))
+}
+
+/// Returns whether a file or directory exists inside the root directory. Paths outside of it
+/// do not exist for templates.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_file_funcs(std::env::temp_dir());
+/// tmpl.parse(r#"{{ if fileExists "gtmpl-missing.txt" }}found{{ end }}"#)
+///     .unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "");
+/// ```
+pub fn file_exists(args: &[Value]) -> Result<Value, FuncError> {
+    let [path] = exact::<1>("fileExists", args)?;
+    let path = string("fileExists", path)?;
+    let root = root("fileExists")?;
+    let exists = join("fileExists", &root, path)
+        .ok()
+        .and_then(|joined| fs::canonicalize(joined).ok())
+        .is_some_and(|resolved| resolved.starts_with(&root));
+    Ok(Value::from(exists))
+}
+
+/// Returns the sorted paths matching a pattern like `posts/*.md`, relative to the root
+/// directory. Supports the wildcards `?`, `*`, `**` and character classes like `[a-z]`.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Context, Template};
+///
+/// let root = std::env::temp_dir().join("gtmpl-glob-example");
+/// std::fs::create_dir_all(root.join("posts")).unwrap();
+/// std::fs::write(root.join("posts/b.md"), "").unwrap();
+/// std::fs::write(root.join("posts/a.md"), "").unwrap();
+/// let mut tmpl = Template::default();
+/// tmpl.add_file_funcs(&root);
+/// tmpl.parse(r#"{{ range glob "posts/*.md" }}{{ . }} {{ end }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "posts/a.md posts/b.md ");
+/// ```
+pub fn glob(args: &[Value]) -> Result<Value, FuncError> {
+    let [pattern] = exact::<1>("glob", args)?;
+    let pattern = string("glob", pattern)?;
+    let root = root("glob")?;
+    let joined = join("glob", &root, pattern)?;
+    let (root_str, joined_str) = match (root.to_str(), joined.to_str()) {
+        (Some(root_str), Some(joined_str)) => (root_str, joined_str),
+        _ => {
+            return Err(FuncError::Generic(
+                "glob: root directory is not valid UTF-8".into(),
+            ))
+        }
+    };
+    // The root is matched literally, even if it contains wildcards.
+    let full = ::glob::Pattern::escape(root_str) + &joined_str[root_str.len()..];
+    let paths = ::glob::glob(&full).map_err(|e| FuncError::Generic(format!("glob: {}", e)))?;
+    let mut matches = vec![];
+    // Unreadable directories are skipped.
+    for path in paths.flatten() {
+        let inside = fs::canonicalize(&path).is_ok_and(|resolved| resolved.starts_with(&root));
+        if let (true, Ok(relative)) = (inside, path.strip_prefix(&root)) {
+            matches.push(Value::from(relative.to_string_lossy().into_owned()));
+        }
+    }
+    Ok(Value::Array(matches))
+}
+
+// Runs `f` with `root` as the root directory of the file functions.
+pub(crate) fn with_root<R>(root: &Path, f: impl FnOnce() -> R) -> R {
+    // Restores the root of an enclosing execution even if `f` panics.
+    struct Restore(Option<PathBuf>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let prev = self.0.take();
+            ROOT.with(|cell| *cell.borrow_mut() = prev);
+        }
+    }
+    let _restore = Restore(ROOT.with(|cell| cell.replace(Some(root.to_owned()))));
+    f()
+}
+
+// The canonical root directory of the current execution.
+fn root(name: &str) -> Result<PathBuf, FuncError> {
+    let root = ROOT
+        .with(|cell| cell.borrow().clone())
+        .ok_or_else(|| FuncError::Generic(format!("{}: no root directory", name)))?;
+    fs::canonicalize(&root).map_err(|e| {
+        FuncError::Generic(format!(
+            "{}: root directory {}: {}",
+            name,
+            root.display(),
+            e
+        ))
+    })
+}
+
+// The canonical path of the existing file `path`, which must be inside the root directory.
+fn resolve(name: &str, path: &str) -> Result<PathBuf, FuncError> {
+    let root = root(name)?;
+    let resolved = fs::canonicalize(join(name, &root, path)?)
+        .map_err(|e| FuncError::Generic(format!("{}: {}: {}", name, path, e)))?;
+    if !resolved.starts_with(&root) {
+        return Err(outside(name, path));
+    }
+    Ok(resolved)
+}
+
+// Appends the relative `path` to `root`. `..` is resolved lexically and must not leave the root.
+fn join(name: &str, root: &Path, path: &str) -> Result<PathBuf, FuncError> {
+    let mut joined = root.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => joined.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if joined != root => {
+                joined.pop();
+            }
+            _ => return Err(outside(name, path)),
+        }
+    }
+    Ok(joined)
+}
+
+fn outside(name: &str, path: &str) -> FuncError {
+    FuncError::Generic(format!(
+        "{}: {} is outside of the root directory",
+        name, path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory with a file `a.txt`, a directory `sub` and a file outside of it.
+    fn setup(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gtmpl-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root/sub")).unwrap();
+        fs::write(dir.join("root/a.txt"), "a").unwrap();
+        fs::write(dir.join("root/sub/b.txt"), "b").unwrap();
+        fs::write(dir.join("root/sub/c.md"), "c").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("root/link.txt")).unwrap();
+        dir.join("root")
+    }
+
+    fn call(f: gtmpl_value::Func, arg: &str) -> Result<Value, String> {
+        f(&[Value::from(arg)]).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_read_file() {
+        let root = setup("read-file");
+        with_root(&root, || {
+            assert_eq!(call(read_file, "a.txt"), Ok(Value::from("a")));
+            assert_eq!(call(read_file, "./sub/../sub/b.txt"), Ok(Value::from("b")));
+            assert_eq!(
+                call(read_file, "../secret.txt"),
+                Err("readFile: ../secret.txt is outside of the root directory".to_owned())
+            );
+            assert_eq!(
+                call(read_file, "sub/../../secret.txt"),
+                Err("readFile: sub/../../secret.txt is outside of the root directory".to_owned())
+            );
+            #[cfg(unix)]
+            {
+                let secret = root.parent().unwrap().join("secret.txt");
+                assert!(call(read_file, secret.to_str().unwrap())
+                    .unwrap_err()
+                    .ends_with("is outside of the root directory"));
+                assert_eq!(
+                    call(read_file, "link.txt"),
+                    Err("readFile: link.txt is outside of the root directory".to_owned())
+                );
+            }
+            assert!(call(read_file, "missing.txt")
+                .unwrap_err()
+                .starts_with("readFile: missing.txt: "));
+        });
+        assert_eq!(
+            call(read_file, "a.txt"),
+            Err("readFile: no root directory".to_owned())
+        );
+
+        fs::write(root.join("large.txt"), "12345").unwrap();
+        let read = |max_size: usize| {
+            with_root(&root, || {
+                crate::funcs::with_max_sizes(max_size, 1, || call(read_file, "large.txt"))
+            })
+        };
+        assert_eq!(read(5), Ok(Value::from("12345")));
+        assert_eq!(
+            read(4),
+            Err("readFile: result exceeds the maximum size of 4 bytes".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_file_exists() {
+        let root = setup("file-exists");
+        with_root(&root, || {
+            assert_eq!(call(file_exists, "a.txt"), Ok(Value::from(true)));
+            assert_eq!(call(file_exists, "sub"), Ok(Value::from(true)));
+            assert_eq!(call(file_exists, "sub/a.txt"), Ok(Value::from(false)));
+            assert_eq!(call(file_exists, "../secret.txt"), Ok(Value::from(false)));
+            assert_eq!(call(file_exists, "../root/a.txt"), Ok(Value::from(false)));
+            #[cfg(unix)]
+            assert_eq!(call(file_exists, "link.txt"), Ok(Value::from(false)));
+        });
+        assert_eq!(
+            call(file_exists, "a.txt"),
+            Err("fileExists: no root directory".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        let root = setup("glob");
+        let paths = |pattern: &str| {
+            with_root(&root, || call(glob, pattern)).map(|v| match v {
+                Value::Array(paths) => paths.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+                v => panic!("unexpected value {:?}", v),
+            })
+        };
+        let sep = std::path::MAIN_SEPARATOR;
+        assert_eq!(paths("*.txt").unwrap(), vec!["a.txt"]);
+        assert_eq!(
+            paths("**/*.?xt").unwrap(),
+            vec!["a.txt".to_owned(), format!("sub{}b.txt", sep)]
+        );
+        assert_eq!(
+            paths("sub/[a-c].md").unwrap(),
+            vec![format!("sub{}c.md", sep)]
+        );
+        assert_eq!(paths("*.json").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            paths("../*.txt").unwrap_err(),
+            "glob: ../*.txt is outside of the root directory"
+        );
+        assert!(paths("[").unwrap_err().starts_with("glob: "));
+    }
+}
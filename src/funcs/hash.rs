@@ -0,0 +1,137 @@
+//! Hash functions.
+//!
+//! Digests are returned as lower case hex strings like those of `sha256sum` and friends.
+
+use std::fmt::Write;
+
+use gtmpl_value::{FuncError, Value};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::{exact, string};
+
+/// Returns the SHA-1 digest of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ sha1sum . }}", "abc");
+/// assert_eq!(&sum.unwrap(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+/// ```
+pub fn sha1sum(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("sha1sum", args)?;
+    Ok(Value::from(hex(&Sha1::digest(string("sha1sum", s)?))))
+}
+
+/// Returns the SHA-256 digest of a string.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ sha256sum . }}", "abc");
+/// assert_eq!(
+///     &sum.unwrap(),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+pub fn sha256sum(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("sha256sum", args)?;
+    Ok(Value::from(hex(&Sha256::digest(string("sha256sum", s)?))))
+}
+
+/// Returns the MD5 digest of a string. MD5 is broken, so use it for checksums and cache
+/// keys only.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template("{{ md5sum . }}", "abc");
+/// assert_eq!(&sum.unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+/// ```
+pub fn md5sum(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("md5sum", args)?;
+    Ok(Value::from(hex(&Md5::digest(string("md5sum", s)?))))
+}
+
+/// Returns the HMAC-SHA256 of a message with a key, i.e. `hmacSha256 key message`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let msg = "The quick brown fox jumps over the lazy dog";
+/// let mac = template(r#"{{ . | hmacSha256 "key" }}"#, msg);
+/// assert_eq!(
+///     &mac.unwrap(),
+///     "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+/// );
+/// ```
+pub fn hmac_sha256(args: &[Value]) -> Result<Value, FuncError> {
+    let [key, msg] = exact::<2>("hmacSha256", args)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(string("hmacSha256", key)?.as_bytes())
+        .map_err(|e| FuncError::Generic(format!("hmacSha256: {}", e)))?;
+    mac.update(string("hmacSha256", msg)?.as_bytes());
+    Ok(Value::from(hex(&mac.finalize().into_bytes())))
+}
+
+/// Returns the CRC-32 checksum (IEEE) of a string as a number.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let sum = template(r#"{{ crc32 . | printf "%08x" }}"#, "abc");
+/// assert_eq!(&sum.unwrap(), "352441c2");
+/// ```
+pub fn crc32(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("crc32", args)?;
+    Ok(Value::from(crc32fast::hash(string("crc32", s)?.as_bytes())))
+}
+
+// The bytes as lower case hex digits.
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{:02x}", b);
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let call = |f: gtmpl_value::Func, s: &str| f(&[Value::from(s)]).unwrap();
+        assert_eq!(
+            call(sha1sum, ""),
+            Value::from("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        );
+        assert_eq!(
+            call(sha256sum, "é"),
+            Value::from("4a99557e4033c3539de2eb65472017cad5f9557f7a0625a09f1c3f6e2ba69c4c")
+        );
+        assert_eq!(
+            call(md5sum, ""),
+            Value::from("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        assert_eq!(call(crc32, ""), Value::from(0u32));
+        assert_eq!(
+            call(crc32, "The quick brown fox jumps over the lazy dog"),
+            Value::from(0x414f_a339u32)
+        );
+        assert_eq!(
+            hmac_sha256(&[Value::from(""), Value::from("")]).unwrap(),
+            Value::from("b613679a0814d9ec772f95d778c35fc5ff1697c493715653c6c712144292c5ad")
+        );
+        assert_eq!(
+            sha256sum(&[Value::from(1)]).unwrap_err().to_string(),
+            "sha256sum: wrong type for value; expected string; got int"
+        );
+        assert_eq!(
+            hmac_sha256(&[Value::from("key")]).unwrap_err().to_string(),
+            FuncError::ExactlyXArgs("hmacSha256".into(), 2).to_string()
+        );
+    }
+}
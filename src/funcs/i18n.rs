@@ -0,0 +1,294 @@
+//! Translation of messages from Fluent catalogs.
+//!
+//! Templates can only call `t` after
+//! [`Template::add_i18n_funcs`](crate::Template::add_i18n_funcs), which sets the catalog.
+//! Messages choose between plural forms with Fluent's selectors, which follow the plural rules
+//! of the catalog's locale.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use gtmpl_value::{FuncError, Value};
+use thiserror::Error;
+use unic_langid::LanguageIdentifier;
+
+use super::string;
+
+thread_local! {
+    // The catalog of the execution on the current thread.
+    static CATALOG: RefCell<Option<Arc<Catalog>>> = const { RefCell::new(None) };
+}
+
+/// Errors creating a [`Catalog`].
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("invalid locale {0}")]
+    InvalidLocale(String),
+    #[error("invalid messages: {0}")]
+    InvalidMessages(String),
+}
+
+/// Messages of a locale in the [Fluent](https://projectfluent.org) syntax.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::Catalog;
+///
+/// let mut catalog = Catalog::new("en-US").unwrap();
+/// catalog
+///     .add_messages("welcome = Welcome, { $name }!")
+///     .unwrap();
+/// assert!(catalog.add_messages("welcome = Hi!").is_err());
+/// ```
+pub struct Catalog {
+    locale: String,
+    bundle: FluentBundle<FluentResource>,
+}
+
+impl Catalog {
+    /// Creates an empty catalog for a locale like `en-US`, which selects the plural rules.
+    pub fn new(locale: &str) -> Result<Catalog, CatalogError> {
+        let id: LanguageIdentifier = locale
+            .parse()
+            .map_err(|_| CatalogError::InvalidLocale(locale.to_owned()))?;
+        let mut bundle = FluentBundle::new_concurrent(vec![id]);
+        // Unicode isolation marks would end up in plain text output.
+        bundle.set_use_isolating(false);
+        Ok(Catalog {
+            locale: locale.to_owned(),
+            bundle,
+        })
+    }
+
+    /// Adds the messages of a Fluent resource, e.g. the contents of a `.ftl` file. Messages
+    /// must not be defined twice.
+    pub fn add_messages(&mut self, source: &str) -> Result<(), CatalogError> {
+        let resource = FluentResource::try_new(source.to_owned())
+            .map_err(|(_, errors)| CatalogError::InvalidMessages(join_errors(&errors)))?;
+        self.bundle
+            .add_resource(resource)
+            .map_err(|errors| CatalogError::InvalidMessages(join_errors(&errors)))
+    }
+
+    /// The locale of the messages.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    // The message `id`, or its attribute for ids like `message.attribute`, formatted with the
+    // arguments given as name and value pairs.
+    fn format(&self, id: &str, args: &[Value]) -> Result<String, FuncError> {
+        let (name, attribute) = match id.split_once('.') {
+            Some((name, attribute)) => (name, Some(attribute)),
+            None => (id, None),
+        };
+        let message = self
+            .bundle
+            .get_message(name)
+            .ok_or_else(|| FuncError::Generic(format!("t: unknown message {}", id)))?;
+        let pattern = match attribute {
+            Some(attribute) => message.get_attribute(attribute).map(|a| a.value()),
+            None => message.value(),
+        }
+        .ok_or_else(|| FuncError::Generic(format!("t: unknown message {}", id)))?;
+        let mut fluent_args = FluentArgs::new();
+        for pair in args.chunks(2) {
+            fluent_args.set(string("t", &pair[0])?.to_owned(), fluent_value(&pair[1]));
+        }
+        let mut errors = vec![];
+        let text = self
+            .bundle
+            .format_pattern(pattern, Some(&fluent_args), &mut errors);
+        match errors.first() {
+            Some(e) => Err(FuncError::Generic(format!("t: {}: {}", id, e))),
+            None => Ok(text.into_owned()),
+        }
+    }
+}
+
+impl fmt::Debug for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Catalog")
+            .field("locale", &self.locale)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns a translated message, i.e. `t id name value ...`. The names and values are the
+/// arguments of the message, and numbers select its plural forms.
+///
+/// # Example
+/// ```
+/// use gtmpl::{Catalog, Context, Template};
+///
+/// let mut catalog = Catalog::new("en").unwrap();
+/// catalog
+///     .add_messages(
+///         "inbox = { $count ->
+///             [one] One new message
+///            *[other] { $count } new messages
+///         } for { $name }",
+///     )
+///     .unwrap();
+/// let mut tmpl = Template::default();
+/// tmpl.add_i18n_funcs(catalog);
+/// tmpl.parse(r#"{{ t "inbox" "count" . "name" "Ann" }}"#).unwrap();
+/// assert_eq!(tmpl.render(&Context::from(3)).unwrap(), "3 new messages for Ann");
+/// assert_eq!(tmpl.render(&Context::from(1)).unwrap(), "One new message for Ann");
+/// ```
+pub fn t(args: &[Value]) -> Result<Value, FuncError> {
+    let (id, args) = match args.split_first() {
+        Some((id, args)) if args.len() % 2 == 0 => (string("t", id)?, args),
+        Some(_) => {
+            return Err(FuncError::Generic(
+                "t: arguments must be pairs of names and values".into(),
+            ))
+        }
+        None => return Err(FuncError::AtLeastXArgs("t".into(), 1)),
+    };
+    CATALOG.with(|cell| match *cell.borrow() {
+        Some(ref catalog) => catalog.format(id, args).map(Value::from),
+        None => Err(FuncError::Generic("t: no message catalog".into())),
+    })
+}
+
+// Runs `f` with `catalog` as the catalog of `t`.
+pub(crate) fn with_catalog<R>(catalog: &Arc<Catalog>, f: impl FnOnce() -> R) -> R {
+    // Restores the catalog of an enclosing execution even if `f` panics.
+    struct Restore(Option<Arc<Catalog>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let prev = self.0.take();
+            CATALOG.with(|cell| *cell.borrow_mut() = prev);
+        }
+    }
+    let _restore = Restore(CATALOG.with(|cell| cell.replace(Some(catalog.clone()))));
+    f()
+}
+
+// Numbers stay numbers to select plural forms, other values are printed.
+fn fluent_value(val: &Value) -> FluentValue<'static> {
+    match *val {
+        Value::Number(ref n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => FluentValue::from(i),
+            (None, Some(f)) => FluentValue::from(f),
+            _ => FluentValue::from(n.to_string()),
+        },
+        Value::String(ref s) => FluentValue::from(s.clone()),
+        _ => FluentValue::from(val.to_string()),
+    }
+}
+
+fn join_errors<E: fmt::Display>(errors: &[E]) -> String {
+    let errors: Vec<String> = errors.iter().map(E::to_string).collect();
+    errors.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: &str = "
+hello = Hello, { $name }!
+emails = { $count ->
+    [one] { $count } email
+   *[other] { $count } emails
+}
+button = Send
+    .title = Send the message
+";
+
+    #[test]
+    fn test_t() {
+        let mut catalog = Catalog::new("en-US").unwrap();
+        catalog.add_messages(MESSAGES).unwrap();
+        let catalog = Arc::new(catalog);
+        let t_ = |args: &[Value]| with_catalog(&catalog, || t(args)).map_err(|e| e.to_string());
+        assert_eq!(
+            t_(&[Value::from("hello"), Value::from("name"), Value::from("Bo")]),
+            Ok(Value::from("Hello, Bo!"))
+        );
+        assert_eq!(
+            t_(&[Value::from("emails"), Value::from("count"), Value::from(1)]),
+            Ok(Value::from("1 email"))
+        );
+        assert_eq!(
+            t_(&[
+                Value::from("emails"),
+                Value::from("count"),
+                Value::from(2.5)
+            ]),
+            Ok(Value::from("2.5 emails"))
+        );
+        assert_eq!(
+            t_(&[Value::from("button.title")]),
+            Ok(Value::from("Send the message"))
+        );
+        assert_eq!(
+            t_(&[Value::from("button.label")]),
+            Err("t: unknown message button.label".to_owned())
+        );
+        assert_eq!(
+            t_(&[Value::from("bye")]),
+            Err("t: unknown message bye".to_owned())
+        );
+        assert!(t_(&[Value::from("hello")])
+            .unwrap_err()
+            .starts_with("t: hello: "));
+        assert_eq!(
+            t_(&[Value::from("hello"), Value::from("name")]),
+            Err("t: arguments must be pairs of names and values".to_owned())
+        );
+        assert_eq!(
+            t(&[Value::from("hello")]).unwrap_err().to_string(),
+            "t: no message catalog"
+        );
+    }
+
+    #[test]
+    fn test_plural_rules() {
+        let mut catalog = Catalog::new("pl").unwrap();
+        catalog
+            .add_messages(
+                "files = { $n ->
+                    [one] { $n } plik
+                    [few] { $n } pliki
+                   *[many] { $n } plików
+                }",
+            )
+            .unwrap();
+        let catalog = Arc::new(catalog);
+        let files = |n: i64| {
+            with_catalog(&catalog, || {
+                t(&[Value::from("files"), Value::from("n"), Value::from(n)])
+            })
+            .unwrap()
+            .to_string()
+        };
+        assert_eq!(files(1), "1 plik");
+        assert_eq!(files(3), "3 pliki");
+        assert_eq!(files(5), "5 plików");
+        assert_eq!(files(22), "22 pliki");
+    }
+
+    #[test]
+    fn test_catalog() {
+        assert_eq!(
+            Catalog::new("not a locale").unwrap_err().to_string(),
+            "invalid locale not a locale"
+        );
+        let mut catalog = Catalog::new("de").unwrap();
+        assert_eq!(catalog.locale(), "de");
+        assert!(catalog.add_messages("= broken").is_err());
+        catalog.add_messages("a = A").unwrap();
+        assert!(catalog
+            .add_messages("a = B")
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid messages: "));
+    }
+}
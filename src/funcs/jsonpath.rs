@@ -0,0 +1,579 @@
+//! JSONPath queries on values.
+//!
+//! The syntax follows RFC 9535: `$` is the value, `.name` or `['name']` selects a key of a
+//! map, `[0]` and `[-1]` select items of a list, `[1:3]` or `[::-1]` slice it, `*` selects
+//! all children, `..` descends into all nested values, `[a,b]` combines selectors, and
+//! `[?(@.price < 10 && !@.sold)]` filters the children with a condition on each child `@`.
+//! Unlike RFC 9535, a bare path like `[?(@.active)]` in a filter tests whether it selects a
+//! value which is true in the sense of `if`.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use gtmpl_value::{FuncError, Value};
+
+use super::{exact, number, string, Num};
+use crate::ordered;
+use crate::utils::is_true;
+
+/// Returns the values a JSONPath expression selects as a list, i.e. `jsonpath path value`.
+/// Children of maps are visited in the sorted order of their keys, or in insertion order for
+/// maps created by [`ordered_map`](crate::ordered_map).
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::{template, Value};
+///
+/// let item = |name: &str, active: bool| {
+///     let mut item = HashMap::new();
+///     item.insert("name".to_owned(), Value::from(name));
+///     item.insert("active".to_owned(), Value::from(active));
+///     Value::from(item)
+/// };
+/// let mut data = HashMap::new();
+/// data.insert("items".to_owned(), vec![item("a", true), item("b", false), item("c", true)]);
+/// let names = template(r#"{{ jsonpath "$.items[?(@.active)].name" . }}"#, data);
+/// assert_eq!(&names.unwrap(), "[a c]");
+/// ```
+pub fn jsonpath(args: &[Value]) -> Result<Value, FuncError> {
+    let [path, val] = exact::<2>("jsonpath", args)?;
+    let path = string("jsonpath", path)?;
+    let segments = Parser { text: path, pos: 0 }
+        .query()
+        .map_err(|e| FuncError::Generic(format!("jsonpath: invalid path {:?}: {}", path, e)))?;
+    let nodes = select(&segments, val, val);
+    Ok(Value::Array(nodes.into_iter().cloned().collect()))
+}
+
+// A step of a path, selecting from the current values or, for descendant segments, from them
+// and all values nested in them.
+struct Segment {
+    descendant: bool,
+    selectors: Vec<Selector>,
+}
+
+enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, i64),
+    Filter(Expr),
+}
+
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Ordering, bool, Operand),
+    Test(Operand),
+}
+
+enum Operand {
+    // A path starting at the root `$` or at the current child `@`.
+    Path(bool, Vec<Segment>),
+    Literal(Value),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn query(&mut self) -> Result<Vec<Segment>, String> {
+        self.skip_spaces();
+        self.expect("$")?;
+        let segments = self.segments()?;
+        self.skip_spaces();
+        match self.peek() {
+            None => Ok(segments),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    fn segments(&mut self) -> Result<Vec<Segment>, String> {
+        let mut segments = vec![];
+        loop {
+            let descendant = self.eat("..");
+            let selectors = if self.peek() == Some('[') {
+                self.brackets()?
+            } else if descendant || self.eat(".") {
+                match self.eat("*") {
+                    true => vec![Selector::Wildcard],
+                    false => vec![Selector::Name(self.name()?)],
+                }
+            } else {
+                return Ok(segments);
+            };
+            segments.push(Segment {
+                descendant,
+                selectors,
+            });
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        match self.pos > start {
+            true => Ok(self.text[start..self.pos].to_owned()),
+            false => Err(self.unexpected()),
+        }
+    }
+
+    fn brackets(&mut self) -> Result<Vec<Selector>, String> {
+        self.expect("[")?;
+        let mut selectors = vec![];
+        loop {
+            self.skip_spaces();
+            selectors.push(self.selector()?);
+            self.skip_spaces();
+            if !self.eat(",") {
+                self.expect("]")?;
+                return Ok(selectors);
+            }
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, String> {
+        match self.peek() {
+            Some('\'') | Some('"') => Ok(Selector::Name(self.string()?)),
+            _ if self.eat("*") => Ok(Selector::Wildcard),
+            _ if self.eat("?") => Ok(Selector::Filter(self.or()?)),
+            _ => {
+                let start = self.int()?;
+                self.skip_spaces();
+                if !self.eat(":") {
+                    return start.map(Selector::Index).ok_or_else(|| self.unexpected());
+                }
+                self.skip_spaces();
+                let end = self.int()?;
+                self.skip_spaces();
+                let step = match self.eat(":") {
+                    true => {
+                        self.skip_spaces();
+                        self.int()?
+                    }
+                    false => None,
+                };
+                Ok(Selector::Slice(start, end, step.unwrap_or(1)))
+            }
+        }
+    }
+
+    fn int(&mut self) -> Result<Option<i64>, String> {
+        let start = self.pos;
+        self.eat("-");
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        match &self.text[start..self.pos] {
+            "" => Ok(None),
+            int => int
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid integer {} at offset {}", int, start)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let quote = self.next().ok_or_else(|| self.unexpected())?;
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex = self.text.get(self.pos..self.pos + 4);
+                            self.pos += 4;
+                            hex.and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("invalid escape at offset {}", self.pos))?
+                        }
+                        Some(c @ ('\\' | '/' | '\'' | '"')) => c,
+                        _ => return Err(format!("invalid escape at offset {}", self.pos)),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+                None => return Err(format!("unterminated string at offset {}", start)),
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.skip_spaces() && self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.skip_spaces() && self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            self.skip_spaces();
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        self.skip_spaces();
+        let ops = [
+            ("==", Ordering::Equal, true),
+            ("!=", Ordering::Equal, false),
+            ("<=", Ordering::Greater, false),
+            (">=", Ordering::Less, false),
+            ("<", Ordering::Less, true),
+            (">", Ordering::Greater, true),
+        ];
+        match ops.iter().find(|&&(op, _, _)| self.eat(op)) {
+            Some(&(_, ordering, matches)) => {
+                self.skip_spaces();
+                Ok(Expr::Compare(left, ordering, matches, self.operand()?))
+            }
+            None => Ok(Expr::Test(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.peek() {
+            Some('$') | Some('@') => {
+                let root = self.next() == Some('$');
+                Ok(Operand::Path(root, self.segments()?))
+            }
+            Some('\'') | Some('"') => Ok(Operand::Literal(Value::from(self.string()?))),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let number = &self.text[start..self.pos];
+                match (number.parse::<i64>(), number.parse::<f64>()) {
+                    (Ok(i), _) => Ok(Operand::Literal(Value::from(i))),
+                    (_, Ok(f)) => Ok(Operand::Literal(Value::from(f))),
+                    _ => Err(format!("invalid number {} at offset {}", number, start)),
+                }
+            }
+            _ if self.eat("true") => Ok(Operand::Literal(Value::from(true))),
+            _ if self.eat("false") => Ok(Operand::Literal(Value::from(false))),
+            _ if self.eat("null") => Ok(Operand::Literal(Value::Nil)),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.text[self.pos..].starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        match self.eat(s) {
+            true => Ok(()),
+            false => Err(format!("expected {} at offset {}", s, self.pos)),
+        }
+    }
+
+    // Skips whitespace and returns true to chain with other conditions.
+    fn skip_spaces(&mut self) -> bool {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+        true
+    }
+
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("unexpected {:?} at offset {}", c, self.pos),
+            None => "unexpected end".into(),
+        }
+    }
+}
+
+// The values `segments` select from `node`.
+fn select<'v>(segments: &[Segment], node: &'v Value, root: &'v Value) -> Vec<&'v Value> {
+    let mut nodes = vec![node];
+    for segment in segments {
+        let mut selected = vec![];
+        for node in nodes {
+            let mut sources = vec![node];
+            if segment.descendant {
+                descendants(node, &mut sources);
+            }
+            for source in sources {
+                for selector in &segment.selectors {
+                    apply(selector, source, root, &mut selected);
+                }
+            }
+        }
+        nodes = selected;
+    }
+    nodes
+}
+
+// Adds the values nested in `node` to `out`, parents before their children.
+fn descendants<'v>(node: &'v Value, out: &mut Vec<&'v Value>) {
+    for child in children(node) {
+        out.push(child);
+        descendants(child, out);
+    }
+}
+
+// The items of a list or the values of a map in the order of its keys.
+fn children(node: &Value) -> Vec<&Value> {
+    match *node {
+        Value::Array(ref items) => items.iter().collect(),
+        _ => ordered::entries(node, true)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect(),
+    }
+}
+
+fn apply<'v>(selector: &Selector, node: &'v Value, root: &'v Value, out: &mut Vec<&'v Value>) {
+    match (selector, node) {
+        (Selector::Name(name), _) => out.extend(ordered::as_map(node).and_then(|m| m.get(name))),
+        (Selector::Wildcard, _) => out.extend(children(node)),
+        (&Selector::Index(i), Value::Array(items)) => {
+            let i = if i < 0 { i + items.len() as i64 } else { i };
+            out.extend(usize::try_from(i).ok().and_then(|i| items.get(i)));
+        }
+        (&Selector::Slice(start, end, step), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let index = |i: i64| if i < 0 { i + len } else { i };
+            if step > 0 {
+                let mut i = index(start.unwrap_or(0)).clamp(0, len);
+                let upper = index(end.unwrap_or(len)).clamp(0, len);
+                while i < upper {
+                    out.push(&items[i as usize]);
+                    i += step;
+                }
+            } else if step < 0 {
+                let mut i = index(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+                let lower = end.map_or(-1, |end| index(end).clamp(-1, len - 1));
+                while i > lower {
+                    out.push(&items[i as usize]);
+                    i += step;
+                }
+            }
+        }
+        (Selector::Filter(expr), _) => out.extend(
+            children(node)
+                .into_iter()
+                .filter(|child| test(expr, child, root)),
+        ),
+        _ => {}
+    }
+}
+
+fn test(expr: &Expr, current: &Value, root: &Value) -> bool {
+    match *expr {
+        Expr::Or(ref a, ref b) => test(a, current, root) || test(b, current, root),
+        Expr::And(ref a, ref b) => test(a, current, root) && test(b, current, root),
+        Expr::Not(ref a) => !test(a, current, root),
+        Expr::Test(Operand::Path(from_root, ref segments)) => {
+            let start = if from_root { root } else { current };
+            select(segments, start, root).into_iter().any(is_true)
+        }
+        Expr::Test(Operand::Literal(ref val)) => is_true(val),
+        Expr::Compare(ref left, ordering, matches, ref right) => {
+            let left = operand(left, current, root);
+            let right = operand(right, current, root);
+            (compare(left, right) == Some(ordering)) == matches
+        }
+    }
+}
+
+// The single value an operand selects, if any.
+fn operand<'v>(operand: &'v Operand, current: &'v Value, root: &'v Value) -> Option<&'v Value> {
+    match *operand {
+        Operand::Path(from_root, ref segments) => {
+            let start = if from_root { root } else { current };
+            match select(segments, start, root)[..] {
+                [val] => Some(val),
+                _ => None,
+            }
+        }
+        Operand::Literal(ref val) => Some(val),
+    }
+}
+
+// Numbers and strings are ordered, other values are only equal or not. Missing values are
+// only equal to each other.
+fn compare(left: Option<&Value>, right: Option<&Value>) -> Option<Ordering> {
+    match (left, right) {
+        (None, None) => Some(Ordering::Equal),
+        (Some(a @ Value::Number(_)), Some(b @ Value::Number(_))) => {
+            match (number("jsonpath", a).ok()?, number("jsonpath", b).ok()?) {
+                (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+                (a, b) => a.float().partial_cmp(&b.float()),
+            }
+        }
+        (Some(Value::String(a)), Some(Value::String(b))) => Some(a.cmp(b)),
+        (Some(Value::Nil | Value::NoValue), Some(Value::Nil | Value::NoValue)) => {
+            Some(Ordering::Equal)
+        }
+        (Some(a), Some(b)) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::printf::format_value;
+
+    fn store() -> Value {
+        let book = |title: &str, price: f64, tags: Vec<&str>| {
+            let mut book = HashMap::new();
+            book.insert("title".to_owned(), Value::from(title));
+            book.insert("price".to_owned(), Value::from(price));
+            book.insert("tags".to_owned(), Value::from(tags));
+            Value::from(book)
+        };
+        let mut store = HashMap::new();
+        store.insert(
+            "books".to_owned(),
+            Value::from(vec![
+                book("Sayings", 8.95, vec!["classic"]),
+                book("Sword", 12.99, vec![]),
+                book("Moby Dick", 8.99, vec!["classic", "sea"]),
+            ]),
+        );
+        store.insert("max price".to_owned(), Value::from(9));
+        Value::from(store)
+    }
+
+    fn query(path: &str) -> Result<String, String> {
+        jsonpath(&[Value::from(path), store()])
+            .map(|v| format_value(&v).into_owned())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(query("$.books[0].title"), Ok("[Sayings]".into()));
+        assert_eq!(query("$['books'][-1]['title']"), Ok("[Moby Dick]".into()));
+        assert_eq!(query("$[\"max price\"]"), Ok("[9]".into()));
+        assert_eq!(query("$.books[3]"), Ok("[]".into()));
+        assert_eq!(query("$.missing.title"), Ok("[]".into()));
+        assert_eq!(
+            query("$.books[*].title"),
+            Ok("[Sayings Sword Moby Dick]".into())
+        );
+        assert_eq!(query("$.books[1:].title"), Ok("[Sword Moby Dick]".into()));
+        assert_eq!(
+            query("$.books[::-1].title"),
+            Ok("[Moby Dick Sword Sayings]".into())
+        );
+        assert_eq!(query("$.books[-2:-1].title"), Ok("[Sword]".into()));
+        assert_eq!(query("$.books[0:3:0]"), Ok("[]".into()));
+        assert_eq!(
+            query("$.books[0,2].tags[0]"),
+            Ok("[classic classic]".into())
+        );
+        assert_eq!(query("$..tags[1]"), Ok("[sea]".into()));
+        assert_eq!(
+            query("$.books[2].*"),
+            Ok("[8.99 [classic sea] Moby Dick]".into())
+        );
+        assert_eq!(query("$..price"), Ok("[8.95 12.99 8.99]".into()));
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(
+            query("$.books[?(@.price < 9)].title"),
+            Ok("[Sayings Moby Dick]".into())
+        );
+        assert_eq!(
+            query("$.books[?@.price <= $['max price'] && @.tags[1]].title"),
+            Ok("[Moby Dick]".into())
+        );
+        assert_eq!(
+            query("$.books[?(!@.tags || @.title == 'Sayings')].title"),
+            Ok("[Sayings Sword]".into())
+        );
+        assert_eq!(
+            query("$.books[?(@.title != \"Sword\" && (@.price > 9 || @.price >= 8.99))].title"),
+            Ok("[Moby Dick]".into())
+        );
+        assert_eq!(query("$.books[?(@.missing == null)]"), Ok("[]".into()));
+        assert_eq!(
+            query("$.books[?(@.missing == @.other)].title"),
+            Ok("[Sayings Sword Moby Dick]".into())
+        );
+        assert_eq!(query("$..[?(@ == 'sea')]"), Ok("[sea]".into()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            query("books"),
+            Err("jsonpath: invalid path \"books\": expected $ at offset 0".into())
+        );
+        assert_eq!(
+            query("$.books[0"),
+            Err("jsonpath: invalid path \"$.books[0\": expected ] at offset 9".into())
+        );
+        assert_eq!(
+            query("$.books[?(@.price < )]"),
+            Err(
+                "jsonpath: invalid path \"$.books[?(@.price < )]\": unexpected ')' at offset 20"
+                    .into()
+            )
+        );
+        assert_eq!(
+            query("$['a"),
+            Err("jsonpath: invalid path \"$['a\": unterminated string at offset 2".into())
+        );
+        assert_eq!(
+            query("$.a b"),
+            Err("jsonpath: invalid path \"$.a b\": unexpected 'b' at offset 4".into())
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Functions fetching data over the network.
+//!
+//! Templates can only call them after
+//! [`Template::add_net_funcs`](crate::Template::add_net_funcs), which sets their limits.
+
+use std::cell::Cell;
+use std::fmt::Display;
+use std::io::Read;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use gtmpl_value::{FuncError, Value};
+
+use super::{exact, string};
+
+thread_local! {
+    // The limits of the execution on the current thread.
+    static LIMITS: Cell<Option<NetOptions>> = const { Cell::new(None) };
+}
+
+/// Limits of the network functions added by
+/// [`Template::add_net_funcs`](crate::Template::add_net_funcs).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetOptions {
+    /// Time after which a request fails, including connecting and reading the response, and
+    /// after which a DNS lookup fails.
+    pub timeout: Duration,
+    /// Maximum size of a response body in bytes. Larger responses fail.
+    pub max_response_size: usize,
+}
+
+impl Default for NetOptions {
+    fn default() -> NetOptions {
+        NetOptions {
+            timeout: Duration::from_secs(10),
+            max_response_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Returns the body of the response to a GET request for a URL. Responses with an error
+/// status fail.
+///
+/// # Example
+/// ```no_run
+/// use gtmpl::{Context, NetOptions, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_net_funcs(NetOptions::default());
+/// tmpl.parse(r#"ip: {{ httpGet "https://checkip.amazonaws.com" | trim }}"#)
+///     .unwrap();
+/// println!("{}", tmpl.render(&Context::empty()).unwrap());
+/// ```
+pub fn http_get(args: &[Value]) -> Result<Value, FuncError> {
+    let [url] = exact::<1>("httpGet", args)?;
+    let url = string("httpGet", url)?;
+    let limits = limits("httpGet")?;
+    let err = |e: &dyn Display| FuncError::Generic(format!("httpGet: {}", e));
+    let agent = ureq::AgentBuilder::new().timeout(limits.timeout).build();
+    let response = agent.get(url).call().map_err(|e| err(&e))?;
+    let max = limits.max_response_size;
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(max as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| err(&e))?;
+    if body.len() > max {
+        return Err(err(&format!("response exceeds {} bytes", max)));
+    }
+    String::from_utf8(body)
+        .map(Value::from)
+        .map_err(|e| err(&e))
+}
+
+/// Returns the sorted IP addresses of a host name. Lookups which take longer than the timeout
+/// fail.
+///
+/// # Example
+/// ```no_run
+/// use gtmpl::{Context, NetOptions, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_net_funcs(NetOptions::default());
+/// tmpl.parse(r#"{{ range dnsLookup "example.com" }}server {{ . }}{{ end }}"#)
+///     .unwrap();
+/// println!("{}", tmpl.render(&Context::empty()).unwrap());
+/// ```
+pub fn dns_lookup(args: &[Value]) -> Result<Value, FuncError> {
+    let [host] = exact::<1>("dnsLookup", args)?;
+    let host = string("dnsLookup", host)?;
+    let limits = limits("dnsLookup")?;
+    let name = host.to_owned();
+    let addrs = call_with_timeout(limits.timeout, move || (name.as_str(), 0).to_socket_addrs())
+        .ok_or_else(|| {
+            FuncError::Generic(format!(
+                "dnsLookup: {}: timed out after {:?}",
+                host, limits.timeout
+            ))
+        })?
+        .map_err(|e| FuncError::Generic(format!("dnsLookup: {}: {}", host, e)))?;
+    let mut ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+    ips.sort();
+    ips.dedup();
+    Ok(Value::Array(
+        ips.iter().map(|ip| Value::from(ip.to_string())).collect(),
+    ))
+}
+
+// Runs `f` on a thread of its own and returns its result, or `None` if it takes longer than
+// `timeout`. The system resolver cannot be cancelled, so a lookup which times out finishes in
+// the background.
+fn call_with_timeout<T, F>(timeout: Duration, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+    receiver.recv_timeout(timeout).ok()
+}
+
+// Runs `f` with `limits` as the limits of the network functions.
+pub(crate) fn with_limits<R>(limits: NetOptions, f: impl FnOnce() -> R) -> R {
+    // Restores the limits of an enclosing execution even if `f` panics.
+    struct Restore(Option<NetOptions>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LIMITS.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(LIMITS.with(|cell| cell.replace(Some(limits))));
+    f()
+}
+
+fn limits(name: &str) -> Result<NetOptions, FuncError> {
+    LIMITS
+        .with(Cell::get)
+        .ok_or_else(|| FuncError::Generic(format!("{}: network functions are not enabled", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    // Serves `response` to a single request and returns the URL to request.
+    fn serve(response: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(response.as_bytes());
+        });
+        url
+    }
+
+    fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn test_http_get() {
+        let limits = NetOptions {
+            max_response_size: 5,
+            ..NetOptions::default()
+        };
+        let get = |response: String| {
+            let url = serve(response);
+            with_limits(limits, || http_get(&[Value::from(url)])).map_err(|e| e.to_string())
+        };
+        assert_eq!(get(ok("hello")), Ok(Value::from("hello")));
+        assert_eq!(
+            get(ok("hello!")),
+            Err("httpGet: response exceeds 5 bytes".to_owned())
+        );
+        let not_found = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned();
+        assert!(get(not_found).unwrap_err().contains("404"));
+        assert_eq!(
+            http_get(&[Value::from("http://127.0.0.1/")])
+                .unwrap_err()
+                .to_string(),
+            "httpGet: network functions are not enabled"
+        );
+    }
+
+    #[test]
+    fn test_dns_lookup() {
+        let lookup =
+            |host: &str| with_limits(NetOptions::default(), || dns_lookup(&[Value::from(host)]));
+        assert_eq!(
+            lookup("127.0.0.1").unwrap(),
+            Value::Array(vec![Value::from("127.0.0.1")])
+        );
+        assert!(lookup("invalid host name").is_err());
+        assert!(dns_lookup(&[Value::from("localhost")]).is_err());
+    }
+
+    #[test]
+    fn test_call_with_timeout() {
+        assert_eq!(call_with_timeout(Duration::from_secs(10), || 1), Some(1));
+        let slow = || thread::sleep(Duration::from_secs(10));
+        assert_eq!(call_with_timeout(Duration::from_millis(10), slow), None);
+    }
+}
@@ -0,0 +1,466 @@
+//! Conversion of values from and to data formats like JSON, YAML and TOML.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use gtmpl_value::{FuncError, Value};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use super::{exact, string};
+use crate::ordered;
+
+/// Returns its argument encoded as JSON. Keys of maps and objects are sorted.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let mut data = HashMap::new();
+/// data.insert("name".to_owned(), vec!["a", "b"]);
+/// let json = template("{{ toJson . }}", data);
+/// assert_eq!(&json.unwrap(), r#"{"name":["a","b"]}"#);
+/// ```
+#[cfg(feature = "json")]
+pub fn to_json(args: &[Value]) -> Result<Value, FuncError> {
+    let [v] = exact::<1>("toJson", args)?;
+    serde_json::to_string(&Ser(v))
+        .map(Value::from)
+        .map_err(|e| FuncError::Generic(format!("toJson: {}", e)))
+}
+
+/// Returns its argument encoded as JSON indented by two spaces.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let json = template("{{ toPrettyJson . }}", vec![1, 2]);
+/// assert_eq!(&json.unwrap(), "[\n  1,\n  2\n]");
+/// ```
+#[cfg(feature = "json")]
+pub fn to_pretty_json(args: &[Value]) -> Result<Value, FuncError> {
+    let [v] = exact::<1>("toPrettyJson", args)?;
+    serde_json::to_string_pretty(&Ser(v))
+        .map(Value::from)
+        .map_err(|e| FuncError::Generic(format!("toPrettyJson: {}", e)))
+}
+
+/// Returns the value a JSON string encodes. Objects become maps and `null` becomes nil.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ (fromJson .).items | len }}"#, r#"{"items": [1, 2, 3]}"#);
+/// assert_eq!(&value.unwrap(), "3");
+/// ```
+#[cfg(feature = "json")]
+pub fn from_json(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("fromJson", args)?;
+    serde_json::from_str(string("fromJson", s)?)
+        .map(|De(v)| v)
+        .map_err(|e| FuncError::Generic(format!("fromJson: {}", e)))
+}
+
+/// Returns its argument encoded as YAML without a trailing newline, so it can be indented
+/// as a block, e.g. by `nindent`. Strings with newlines are written as literal blocks. Keys
+/// of maps and objects are sorted.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let mut data = HashMap::new();
+/// data.insert("name".to_owned(), "job");
+/// data.insert("script".to_owned(), "make\nmake test");
+/// let yaml = template("{{ toYaml . }}", data);
+/// assert_eq!(&yaml.unwrap(), "name: job\nscript: |-\n  make\n  make test");
+/// ```
+#[cfg(feature = "yaml")]
+pub fn to_yaml(args: &[Value]) -> Result<Value, FuncError> {
+    let [v] = exact::<1>("toYaml", args)?;
+    let yaml =
+        serde_yaml::to_string(&Ser(v)).map_err(|e| FuncError::Generic(format!("toYaml: {}", e)))?;
+    Ok(Value::from(yaml.strip_suffix('\n').unwrap_or(&yaml)))
+}
+
+/// Returns the value a YAML document encodes. Mappings become maps and `null` or `~` become
+/// nil.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template("{{ (fromYaml .).spec.replicas }}", "spec:\n  replicas: 3\n");
+/// assert_eq!(&value.unwrap(), "3");
+/// ```
+#[cfg(feature = "yaml")]
+pub fn from_yaml(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("fromYaml", args)?;
+    serde_yaml::from_str(string("fromYaml", s)?)
+        .map(|De(v)| v)
+        .map_err(|e| FuncError::Generic(format!("fromYaml: {}", e)))
+}
+
+/// Returns a map encoded as a TOML document. Nil values are left out, since TOML has no
+/// null.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// use gtmpl::template;
+///
+/// let mut data = HashMap::new();
+/// data.insert("name".to_owned(), "gtmpl");
+/// let toml = template("[package]\n{{ toToml . }}", data);
+/// assert_eq!(&toml.unwrap(), "[package]\nname = \"gtmpl\"\n");
+/// ```
+#[cfg(feature = "toml")]
+pub fn to_toml(args: &[Value]) -> Result<Value, FuncError> {
+    let [v] = exact::<1>("toToml", args)?;
+    toml::to_string(&Ser(v))
+        .map(Value::from)
+        .map_err(|e| FuncError::Generic(format!("toToml: {}", e)))
+}
+
+/// Returns the map a TOML document encodes. Dates and times become strings in RFC 3339
+/// format.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let value = template(r#"{{ (fromToml .).package.edition }}"#, "[package]\nedition = 2018");
+/// assert_eq!(&value.unwrap(), "2018");
+/// ```
+#[cfg(feature = "toml")]
+pub fn from_toml(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("fromToml", args)?;
+    toml::from_str(string("fromToml", s)?)
+        .map(|De(v)| v)
+        .map_err(|e| FuncError::Generic(format!("fromToml: {}", e).trim_end().to_owned()))
+}
+
+/// Serializes a value. Maps and objects are serialized with sorted keys, so the output does
+/// not depend on the order of a `HashMap`, and byte slices as base64 strings like in Go.
+pub(crate) struct Ser<'a>(pub(crate) &'a Value);
+
+impl Serialize for Ser<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self.0 {
+            Value::NoValue | Value::Nil => serializer.serialize_none(),
+            Value::Bool(b) => serializer.serialize_bool(b),
+            Value::String(ref s) => serializer.serialize_str(s),
+            Value::Number(ref n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+                (Some(i), _, _) => serializer.serialize_i64(i),
+                (_, Some(u), _) => serializer.serialize_u64(u),
+                (_, _, Some(f)) => serializer.serialize_f64(f),
+                _ => Err(ser::Error::custom(format!("unsupported number {}", n))),
+            },
+            Value::Array(ref a) => {
+                let mut seq = serializer.serialize_seq(Some(a.len()))?;
+                for item in a {
+                    seq.serialize_element(&Ser(item))?;
+                }
+                seq.end()
+            }
+            Value::Bytes(ref b) => serializer.serialize_str(&base64(b)),
+            Value::Object(_) | Value::Map(_) | Value::OrderedMap(_) => {
+                let entries = ordered::entries(self.0, true).unwrap_or_default();
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k, &Ser(v))?;
+                }
+                map.end()
+            }
+            Value::Function(_) => Err(ser::Error::custom("unsupported type func")),
+        }
+    }
+}
+
+// Encodes bytes in standard base64 with padding, like Go's `encoding/json` writes `[]byte`.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// A deserialized value. Maps become `Value::Map`s and null becomes `Value::Nil`.
+pub(crate) struct De(pub(crate) Value);
+
+impl<'de> Deserialize<'de> for De {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<De, D::Error> {
+        deserializer.deserialize_any(ValueVisitor).map(De)
+    }
+}
+
+struct ValueVisitor;
+
+#[cfg(feature = "toml")]
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::from(b))
+    }
+
+    fn visit_i64<E>(self, i: i64) -> Result<Value, E> {
+        Ok(Value::from(i))
+    }
+
+    fn visit_u64<E>(self, u: u64) -> Result<Value, E> {
+        Ok(Value::from(u))
+    }
+
+    fn visit_f64<E>(self, f: f64) -> Result<Value, E> {
+        Ok(Value::from(f))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::from(s))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::from(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        De::deserialize(deserializer).map(|De(v)| v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(De(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((De(k), De(v))) = map.next_entry()? {
+            // TOML passes dates and times as maps with a single private key.
+            #[cfg(feature = "toml")]
+            if matches!(k, Value::String(ref s) if s == TOML_DATETIME) {
+                return Ok(v);
+            }
+            let k = match k {
+                Value::String(s) => s,
+                Value::Bool(_) | Value::Number(_) => k.to_string(),
+                _ => {
+                    return Err(de::Error::custom(
+                        "map keys must be strings, numbers or bools",
+                    ))
+                }
+            };
+            entries.insert(k, v);
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "yaml")]
+    use crate::funcs::nindent;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        let mut map = HashMap::new();
+        map.insert("b".to_owned(), Value::Nil);
+        map.insert(
+            "a".to_owned(),
+            Value::from(vec![Value::from(-1), Value::from(1.5)]),
+        );
+        map.insert("c".to_owned(), Value::from(u64::MAX));
+        let json = r#"{"a":[-1,1.5],"b":null,"c":18446744073709551615}"#;
+        assert_eq!(
+            to_json(&[Value::Map(map.clone())]).unwrap(),
+            Value::from(json)
+        );
+        assert_eq!(from_json(&[Value::from(json)]).unwrap(), Value::Map(map));
+
+        let floats = Value::from(vec![
+            Value::from(-1.5),
+            Value::from(-0.25),
+            Value::from(-1e-7),
+        ]);
+        let json = to_json(&[floats.clone()]).unwrap();
+        assert_eq!(json, Value::from("[-1.5,-0.25,-1e-7]"));
+        assert_eq!(from_json(&[json]).unwrap(), floats);
+        assert_eq!(
+            from_json(&[Value::from("-1.5")]).unwrap(),
+            Value::from(-1.5)
+        );
+
+        let ordered = crate::ordered_map(vec![("b", Value::Nil), ("a", Value::from(1))]);
+        assert_eq!(
+            to_json(&[ordered]).unwrap(),
+            Value::from(r#"{"b":null,"a":1}"#)
+        );
+        let bytes = ["", "h", "hi", "hi!", "\u{ff}\u{3}"]
+            .iter()
+            .map(|s| crate::byte_slice(s))
+            .collect();
+        assert_eq!(
+            to_json(&[Value::Array(bytes)]).unwrap(),
+            Value::from(r#"["","aA==","aGk=","aGkh","w78D"]"#)
+        );
+        let set = crate::template(
+            r#"{{ set (dict) "b" . | toJson }}"#,
+            crate::byte_slice("hi"),
+        );
+        assert_eq!(set.unwrap(), r#"{"b":"aGk="}"#);
+
+        assert_eq!(
+            from_json(&[Value::from(r#"[true, "x", {}]"#)]).unwrap(),
+            Value::Array(vec![
+                Value::from(true),
+                Value::from("x"),
+                Value::Map(HashMap::new())
+            ])
+        );
+        assert_eq!(to_json(&[Value::NoValue]).unwrap(), Value::from("null"));
+        assert_eq!(
+            to_json(&[Value::from(f64::NAN)]).unwrap(),
+            Value::from("null")
+        );
+        let f = Value::from(to_json as gtmpl_value::Func);
+        assert_eq!(
+            to_json(&[Value::Array(vec![f])]).unwrap_err().to_string(),
+            "toJson: unsupported type func"
+        );
+        assert!(from_json(&[Value::from("{")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("fromJson: EOF while parsing an object"));
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        let mut inner = HashMap::new();
+        inner.insert("text".to_owned(), Value::from("a\n  b\n"));
+        inner.insert("empty".to_owned(), Value::Array(vec![]));
+        let mut map = HashMap::new();
+        map.insert("inner".to_owned(), Value::Map(inner));
+        map.insert(
+            "list".to_owned(),
+            Value::from(vec![Value::Nil, Value::from("yes")]),
+        );
+        let yaml = "inner:\n  empty: []\n  text: |\n    a\n      b\nlist:\n- null\n- yes";
+        assert_eq!(
+            to_yaml(&[Value::Map(map.clone())]).unwrap(),
+            Value::from(yaml)
+        );
+        assert_eq!(from_yaml(&[Value::from(yaml)]).unwrap(), Value::Map(map));
+
+        let mut keys = HashMap::new();
+        keys.insert("1".to_owned(), Value::from(true));
+        keys.insert("~".to_owned(), Value::Nil);
+        assert_eq!(
+            from_yaml(&[Value::from("1: true\n'~': ~")]).unwrap(),
+            Value::Map(keys)
+        );
+        assert_eq!(to_yaml(&[Value::from(1.5)]).unwrap(), Value::from("1.5"));
+        let floats = Value::from(vec![Value::from(-1.5), Value::from(-0.25)]);
+        let yaml = to_yaml(&[floats.clone()]).unwrap();
+        assert_eq!(yaml, Value::from("- -1.5\n- -0.25"));
+        assert_eq!(from_yaml(&[yaml]).unwrap(), floats);
+        let ordered = crate::ordered_map(vec![
+            ("name", Value::from("web")),
+            (
+                "ports",
+                crate::ordered_map(vec![("https", 443), ("http", 80)]),
+            ),
+        ]);
+        assert_eq!(
+            to_yaml(&[ordered]).unwrap(),
+            Value::from("name: web\nports:\n  https: 443\n  http: 80")
+        );
+        assert_eq!(
+            to_yaml(&[crate::byte_slice("hi")]).unwrap(),
+            Value::from("aGk=")
+        );
+        let list = Value::from(vec!["a", "b"]);
+        let nested = nindent(&[Value::from(2), to_yaml(&[list]).unwrap()]);
+        assert_eq!(nested.unwrap(), Value::from("\n  - a\n  - b"));
+        assert!(from_yaml(&[Value::from("a: [")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("fromYaml: "));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let mut package = HashMap::new();
+        package.insert("name".to_owned(), Value::from("gtmpl"));
+        package.insert("license".to_owned(), Value::Nil);
+        package.insert("keywords".to_owned(), Value::from(vec!["go", "template"]));
+        let mut map = HashMap::new();
+        map.insert("package".to_owned(), Value::Map(package.clone()));
+        map.insert("version".to_owned(), Value::from(1));
+        let toml =
+            "version = 1\n\n[package]\nkeywords = [\"go\", \"template\"]\nname = \"gtmpl\"\n";
+        assert_eq!(
+            to_toml(&[Value::Map(map.clone())]).unwrap(),
+            Value::from(toml)
+        );
+        package.remove("license");
+        map.insert("package".to_owned(), Value::Map(package));
+        assert_eq!(from_toml(&[Value::from(toml)]).unwrap(), Value::Map(map));
+
+        let mut dates = HashMap::new();
+        dates.insert("at".to_owned(), Value::from("1979-05-27T07:32:00Z"));
+        dates.insert("on".to_owned(), Value::from("1979-05-27"));
+        assert_eq!(
+            from_toml(&[Value::from("at = 1979-05-27T07:32:00Z\non = 1979-05-27")]).unwrap(),
+            Value::Map(dates)
+        );
+        let mut floats = HashMap::new();
+        floats.insert("x".to_owned(), Value::from(-1.5));
+        let toml = to_toml(&[Value::Map(floats.clone())]).unwrap();
+        assert_eq!(toml, Value::from("x = -1.5\n"));
+        assert_eq!(from_toml(&[toml]).unwrap(), Value::Map(floats));
+        assert!(to_toml(&[Value::from(vec![1])])
+            .unwrap_err()
+            .to_string()
+            .starts_with("toToml: "));
+        assert!(from_toml(&[Value::from("a = ")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("fromToml: "));
+    }
+}
@@ -0,0 +1,232 @@
+//! Functions styling text for terminals with ANSI escape codes.
+//!
+//! Styles end with the code resetting only their own attribute, so they can be nested. With
+//! [`ExecOptions::colors`](crate::ExecOptions::colors) disabled, e.g. when the output is not a
+//! terminal, the functions return the text as it is.
+
+use std::cell::Cell;
+
+use gtmpl_value::{FuncError, Value};
+
+use super::{exact, string};
+
+thread_local! {
+    // Whether the execution on the current thread emits escape codes.
+    static COLORS: Cell<bool> = const { Cell::new(true) };
+}
+
+// The names of the basic colors in the order of their codes.
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Returns text in a foreground color, i.e. `color name text`. Colors are the basic names
+/// like `red`, their bright variants like `brightRed`, numbers of the 256 color palette and
+/// hex codes like `#ff8700`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let colored = template(r##"{{ color "red" . }} {{ color "#00ff00" "ok" }}"##, "failed");
+/// assert_eq!(
+///     &colored.unwrap(),
+///     "\x1b[31mfailed\x1b[39m \x1b[38;2;0;255;0mok\x1b[39m"
+/// );
+/// ```
+pub fn color(args: &[Value]) -> Result<Value, FuncError> {
+    let [name, text] = exact::<2>("color", args)?;
+    let code = color_code("color", name, 30)?;
+    styled("color", &code, "39", text)
+}
+
+/// Returns text on a background color, i.e. `bgColor name text`. Colors are given like for
+/// `color`.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let colored = template(r#"{{ bgColor "brightBlue" . }}"#, "note");
+/// assert_eq!(&colored.unwrap(), "\x1b[104mnote\x1b[49m");
+/// ```
+pub fn bg_color(args: &[Value]) -> Result<Value, FuncError> {
+    let [name, text] = exact::<2>("bgColor", args)?;
+    let code = color_code("bgColor", name, 40)?;
+    styled("bgColor", &code, "49", text)
+}
+
+/// Returns text in bold.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let bold = template(r#"{{ bold (color "red" .) }}"#, "error");
+/// assert_eq!(&bold.unwrap(), "\x1b[1m\x1b[31merror\x1b[39m\x1b[22m");
+/// ```
+pub fn bold(args: &[Value]) -> Result<Value, FuncError> {
+    let [text] = exact::<1>("bold", args)?;
+    styled("bold", "1", "22", text)
+}
+
+/// Returns text dimmed.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let dim = template("{{ dim . }}", "hint");
+/// assert_eq!(&dim.unwrap(), "\x1b[2mhint\x1b[22m");
+/// ```
+pub fn dim(args: &[Value]) -> Result<Value, FuncError> {
+    let [text] = exact::<1>("dim", args)?;
+    styled("dim", "2", "22", text)
+}
+
+/// Returns text in italics.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let italic = template("{{ italic . }}", "quote");
+/// assert_eq!(&italic.unwrap(), "\x1b[3mquote\x1b[23m");
+/// ```
+pub fn italic(args: &[Value]) -> Result<Value, FuncError> {
+    let [text] = exact::<1>("italic", args)?;
+    styled("italic", "3", "23", text)
+}
+
+/// Returns text underlined.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let underlined = template("{{ underline . }}", "link");
+/// assert_eq!(&underlined.unwrap(), "\x1b[4mlink\x1b[24m");
+/// ```
+pub fn underline(args: &[Value]) -> Result<Value, FuncError> {
+    let [text] = exact::<1>("underline", args)?;
+    styled("underline", "4", "24", text)
+}
+
+// Runs `f` with escape codes enabled or disabled.
+pub(crate) fn with_colors<R>(enabled: bool, f: impl FnOnce() -> R) -> R {
+    // Restores the setting of an enclosing execution even if `f` panics.
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            COLORS.with(|cell| cell.set(self.0));
+        }
+    }
+    let _restore = Restore(COLORS.with(|cell| cell.replace(enabled)));
+    f()
+}
+
+// The string argument `text` of the function `name` between the codes `start` and `end`.
+fn styled(name: &str, start: &str, end: &str, text: &Value) -> Result<Value, FuncError> {
+    let text = string(name, text)?;
+    if !COLORS.with(Cell::get) {
+        return Ok(Value::from(text));
+    }
+    Ok(Value::from(format!("\x1b[{}m{}\x1b[{}m", start, text, end)))
+}
+
+// The code selecting the color `name` for the function `func`, where `base` is the code of
+// black, i.e. 30 for the foreground and 40 for the background.
+fn color_code(func: &str, name: &Value, base: u8) -> Result<String, FuncError> {
+    let unknown = || FuncError::Generic(format!("{}: unknown color {}", func, name));
+    if let Value::Number(ref n) = *name {
+        return match n.as_u64() {
+            Some(n @ 0..=255) => Ok(format!("{};5;{}", base + 8, n)),
+            _ => Err(unknown()),
+        };
+    }
+    let name = string(func, name)?;
+    if let Some(hex) = name.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(unknown)
+        };
+        if hex.len() != 6 {
+            return Err(unknown());
+        }
+        return Ok(format!(
+            "{};2;{};{};{}",
+            base + 8,
+            channel(0)?,
+            channel(2)?,
+            channel(4)?
+        ));
+    }
+    let (bright, basic) = match name.strip_prefix("bright") {
+        Some(basic) => (true, basic.to_ascii_lowercase()),
+        None => (false, name.to_owned()),
+    };
+    let index = COLOR_NAMES
+        .iter()
+        .position(|&n| n == basic)
+        .ok_or_else(unknown)?;
+    let bright = if bright { 60 } else { 0 };
+    Ok((base + bright + index as u8).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color() {
+        let color_ = |name: Value| {
+            color(&[name, Value::from("x")])
+                .map(|v| v.to_string())
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(color_(Value::from("white")), Ok("\x1b[37mx\x1b[39m".into()));
+        assert_eq!(
+            color_(Value::from("brightBlack")),
+            Ok("\x1b[90mx\x1b[39m".into())
+        );
+        assert_eq!(
+            color_(Value::from(208)),
+            Ok("\x1b[38;5;208mx\x1b[39m".into())
+        );
+        assert_eq!(
+            color_(Value::from("#FF8700")),
+            Ok("\x1b[38;2;255;135;0mx\x1b[39m".into())
+        );
+        assert_eq!(
+            color_(Value::from("pink")),
+            Err("color: unknown color pink".into())
+        );
+        assert_eq!(
+            color_(Value::from("#ff87")),
+            Err("color: unknown color #ff87".into())
+        );
+        assert_eq!(
+            color_(Value::from("#ff87é")),
+            Err("color: unknown color #ff87é".into())
+        );
+        assert_eq!(
+            color_(Value::from(256)),
+            Err("color: unknown color 256".into())
+        );
+        assert_eq!(
+            bg_color(&[Value::from(0), Value::from("x")]).unwrap(),
+            Value::from("\x1b[48;5;0mx\x1b[49m")
+        );
+    }
+
+    #[test]
+    fn test_without_colors() {
+        with_colors(false, || {
+            assert_eq!(
+                color(&[Value::from("red"), Value::from("x")]).unwrap(),
+                Value::from("x")
+            );
+            assert_eq!(bold(&[Value::from("x")]).unwrap(), Value::from("x"));
+            assert!(color(&[Value::from("pink"), Value::from("x")]).is_err());
+        });
+        assert_eq!(
+            underline(&[Value::from("x")]).unwrap(),
+            Value::from("\x1b[4mx\x1b[24m")
+        );
+    }
+}
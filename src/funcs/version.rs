@@ -0,0 +1,135 @@
+//! Semantic version functions.
+//!
+//! Versions follow Semantic Versioning 2.0 and may start with a `v` like `v1.2.3`. Like in
+//! Sprig, missing minor and patch numbers are zero, so `v1.2` is `1.2.0`. Constraints use the
+//! syntax of Cargo, e.g. `>=1.2, <2` or `^1.4`, and their versions may start with a `v` too.
+
+use std::collections::HashMap;
+
+use gtmpl_value::{FuncError, Value};
+use semver::{Version, VersionReq};
+
+use super::{exact, string};
+
+/// Returns a parsed version with the fields `Major`, `Minor`, `Patch`, `Prerelease`,
+/// `Metadata` and `Original`, like the version objects of Sprig.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let version = template("{{ with semver . }}{{ .Major }}.{{ .Minor }} {{ .Prerelease }}{{ end }}", "v2.1.0-rc.1");
+/// assert_eq!(&version.unwrap(), "2.1 rc.1");
+/// ```
+pub fn semver(args: &[Value]) -> Result<Value, FuncError> {
+    let [s] = exact::<1>("semver", args)?;
+    let s = string("semver", s)?;
+    let version = parse("semver", s)?;
+    let mut fields = HashMap::new();
+    fields.insert("Major".to_owned(), Value::from(version.major));
+    fields.insert("Minor".to_owned(), Value::from(version.minor));
+    fields.insert("Patch".to_owned(), Value::from(version.patch));
+    fields.insert("Prerelease".to_owned(), Value::from(version.pre.as_str()));
+    fields.insert("Metadata".to_owned(), Value::from(version.build.as_str()));
+    fields.insert("Original".to_owned(), Value::from(s));
+    Ok(Value::Map(fields))
+}
+
+/// Returns whether a version satisfies a constraint, i.e. `semverCompare constraint version`.
+/// Pre-releases only satisfy constraints naming a pre-release of the same version.
+///
+/// # Example
+/// ```
+/// use gtmpl::template;
+/// let tmpl = r#"{{ if semverCompare ">=1.2, <2" . }}supported{{ end }}"#;
+/// assert_eq!(&template(tmpl, "1.4.0").unwrap(), "supported");
+/// assert_eq!(&template(tmpl, "2.0.0").unwrap(), "");
+/// ```
+pub fn semver_compare(args: &[Value]) -> Result<Value, FuncError> {
+    let [constraint, version] = exact::<2>("semverCompare", args)?;
+    let constraint = string("semverCompare", constraint)?;
+    let req = VersionReq::parse(&strip_v(constraint))
+        .map_err(|e| FuncError::Generic(format!("semverCompare: {}: {}", constraint, e)))?;
+    let version = parse("semverCompare", string("semverCompare", version)?)?;
+    Ok(Value::from(req.matches(&version)))
+}
+
+fn parse(name: &str, s: &str) -> Result<Version, FuncError> {
+    let version = s.strip_prefix('v').unwrap_or(s);
+    // Fill in the missing minor and patch numbers of e.g. `1.2-rc.1`.
+    let core = version.find(['-', '+']).unwrap_or(version.len());
+    let missing = 2usize.saturating_sub(version[..core].matches('.').count());
+    let version = format!(
+        "{}{}{}",
+        &version[..core],
+        ".0".repeat(missing),
+        &version[core..]
+    );
+    Version::parse(&version).map_err(|e| FuncError::Generic(format!("{}: {}: {}", name, s, e)))
+}
+
+// Removes the `v` in front of the versions of a constraint like `>=v1.2, <v2`.
+fn strip_v(constraint: &str) -> String {
+    let mut stripped = String::with_capacity(constraint.len());
+    let mut prev: Option<char> = None;
+    let mut chars = constraint.chars().peekable();
+    while let Some(c) = chars.next() {
+        let starts_version = !matches!(prev, Some(p) if p.is_ascii_alphanumeric());
+        let before_digit = matches!(chars.peek(), Some(d) if d.is_ascii_digit());
+        if !(c == 'v' && starts_version && before_digit) {
+            stripped.push(c);
+        }
+        prev = Some(c);
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_semver() {
+        let version = semver(&[Value::from("1.2.3-beta.1+build.5")]).unwrap();
+        let field = |name: &str| match version {
+            Value::Map(ref fields) => fields[name].clone(),
+            _ => panic!("unexpected value {:?}", version),
+        };
+        assert_eq!(field("Major"), Value::from(1u64));
+        assert_eq!(field("Minor"), Value::from(2u64));
+        assert_eq!(field("Patch"), Value::from(3u64));
+        assert_eq!(field("Prerelease"), Value::from("beta.1"));
+        assert_eq!(field("Metadata"), Value::from("build.5"));
+        assert_eq!(field("Original"), Value::from("1.2.3-beta.1+build.5"));
+        assert!(semver(&[Value::from("1.2.x")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("semver: 1.2.x: "));
+        let version = semver(&[Value::from("v1.2-rc.1")]).unwrap();
+        match version {
+            Value::Map(ref fields) => {
+                assert_eq!(fields["Patch"], Value::from(0u64));
+                assert_eq!(fields["Prerelease"], Value::from("rc.1"));
+                assert_eq!(fields["Original"], Value::from("v1.2-rc.1"));
+            }
+            _ => panic!("unexpected value {:?}", version),
+        }
+
+        let compare = |constraint: &str, version: &str| {
+            semver_compare(&[Value::from(constraint), Value::from(version)])
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(compare("^1.2", "v1.9.0"), Ok(Value::from(true)));
+        assert_eq!(compare("~1.2", "1.3.0"), Ok(Value::from(false)));
+        assert_eq!(compare(">=1.2, <2", "2.0.0"), Ok(Value::from(false)));
+        assert_eq!(compare(">=1.2", "1.3.0-rc.1"), Ok(Value::from(false)));
+        assert_eq!(compare(">=1.3.0-rc.0", "1.3.0-rc.1"), Ok(Value::from(true)));
+        assert_eq!(compare("*", "0.0.1"), Ok(Value::from(true)));
+        assert_eq!(compare(">=1.0", "v1.2"), Ok(Value::from(true)));
+        assert_eq!(compare(">= v1.0, <v2", "1"), Ok(Value::from(true)));
+        assert_eq!(compare("^v2", "v1"), Ok(Value::from(false)));
+        assert!(compare("=>1", "1.0.0")
+            .unwrap_err()
+            .starts_with("semverCompare: =>1: "));
+        assert!(compare("1", "x").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Callbacks into the execution of templates.
+use std::ops::ControlFlow;
+
+use gtmpl_value::{FuncError, Value};
+
+use crate::node::Nodes;
+
+/// Hooks called during the execution of a template, e.g. for auditing, metrics or redaction.
+///
+/// All methods do nothing by default. `template` is the name of the template the node belongs
+/// to.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, ExecHook, FuncError, Template, Value};
+///
+/// struct Redact;
+///
+/// impl ExecHook for Redact {
+///     fn after_call(&self, name: &str, _args: &[Value], result: &mut Result<Value, FuncError>) {
+///         if name == "secret" {
+///             *result = Ok(Value::from("***"));
+///         }
+///     }
+/// }
+///
+/// fn secret(_args: &[Value]) -> Result<Value, FuncError> {
+///     Ok(Value::from("hunter2"))
+/// }
+///
+/// let mut tmpl = Template::default();
+/// tmpl.add_func("secret", secret);
+/// tmpl.add_hook(Redact);
+/// tmpl.parse("password: {{ secret }}").unwrap();
+/// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "password: ***");
+/// ```
+pub trait ExecHook: Send + Sync {
+    /// Called before a node of the tree is executed.
+    fn before_node(&self, _template: &str, _node: &Nodes) {}
+
+    /// Called after a node of the tree was executed, even if it failed.
+    fn after_node(&self, _template: &str, _node: &Nodes) {}
+
+    /// Called before the value of an action is printed. The value can be replaced.
+    fn before_print(&self, _template: &str, _node: &Nodes, _value: &mut Value) {}
+
+    /// Called before a function or method `name` is called with `args`.
+    fn before_call(&self, _name: &str, _args: &[Value]) {}
+
+    /// Called after a function or method `name` was called. The result can be replaced.
+    fn after_call(&self, _name: &str, _args: &[Value], _result: &mut Result<Value, FuncError>) {}
+
+    /// Called every [`ExecOptions::yield_interval`](crate::ExecOptions::yield_interval) nodes
+    /// with the number of nodes executed so far. The hook can pause the execution, e.g. to run
+    /// other tasks of a single-threaded embedder, or abort it by returning `Break`, which stops
+    /// it with `ExecError::Cancelled`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::ops::ControlFlow;
+    ///
+    /// use gtmpl::{Context, ExecHook, Template};
+    ///
+    /// struct Budget(usize);
+    ///
+    /// impl ExecHook for Budget {
+    ///     fn on_yield(&self, _template: &str, nodes: usize) -> ControlFlow<()> {
+    ///         if nodes >= self.0 {
+    ///             return ControlFlow::Break(());
+    ///         }
+    ///         ControlFlow::Continue(())
+    ///     }
+    /// }
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_hook(Budget(1000));
+    /// tmpl.exec_options.yield_interval = 100;
+    /// tmpl.parse("{{ range . }}{{ . }}{{ end }}").unwrap();
+    /// assert!(tmpl.render(&Context::from(vec![1; 10])).is_ok());
+    /// assert!(tmpl.render(&Context::from(vec![1; 10_000])).is_err());
+    /// ```
+    fn on_yield(&self, _template: &str, _nodes: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
@@ -1,6 +1,7 @@
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread;
 
@@ -45,6 +46,7 @@ pub enum ItemType {
     ItemLeftDelim,  // left action delimiter
     ItemLeftParen,  // '(' inside action
     ItemNumber,     // simple number, including imaginary
+    ItemOperator,   // infix or prefix operator such as '==' or '!'
     ItemPipe,       // pipe symbol
     ItemRawString,  // raw quoted string (includes quotes)
     ItemRightDelim, // right action delimiter
@@ -110,7 +112,9 @@ struct LexerStateMachine {
     width: Pos,                 // width of last rune read from input
     items_sender: Sender<Item>, // channel of scanned items
     paren_depth: usize,         // nesting depth of ( ) exprs
-    line: usize,                // 1+number of newlines seen
+    line: usize,                // 1+number of newlines before line_pos
+    line_pos: Pos,              // position up to which newlines have been counted
+    stop: Pos,                  // position to stop scanning at when outside of an action
 }
 
 #[derive(Debug)]
@@ -156,16 +160,26 @@ impl Iterator for Lexer {
 
 impl Lexer {
     pub fn new(input: String) -> Lexer {
+        let len = input.len();
+        Lexer::with_range(input, 0..len)
+    }
+
+    /// Creates a lexer scanning only `range` of `input`. The range has to start outside of an
+    /// action. Scanning ends with EOF at the first position outside of an action at or after
+    /// `range.end`.
+    pub fn with_range(input: String, range: Range<Pos>) -> Lexer {
         let (tx, rx) = channel();
         let mut l = LexerStateMachine {
             input,
             state: State::LexText,
-            pos: 0,
-            start: 0,
+            pos: range.start,
+            start: range.start,
             width: 0,
             items_sender: tx,
             paren_depth: 0,
             line: 1,
+            line_pos: 0,
+            stop: range.end,
         };
         thread::spawn(move || l.run());
         Lexer {
@@ -193,9 +207,6 @@ impl Iterator for LexerStateMachine {
             Some(c) => {
                 self.width = c.len_utf8();
                 self.pos += self.width;
-                if c == '\n' {
-                    self.line += 1;
-                }
                 Some(c)
             }
             None => {
@@ -232,15 +243,6 @@ impl LexerStateMachine {
 
     fn backup(&mut self) {
         self.pos -= 1;
-        if self.width == 1
-            && self.input[self.pos..]
-                .chars()
-                .next()
-                .and_then(|c| if c == '\n' { Some(()) } else { None })
-                .is_some()
-        {
-            self.line -= 1;
-        }
     }
 
     fn peek(&mut self) -> Option<char> {
@@ -249,19 +251,20 @@ impl LexerStateMachine {
         c
     }
 
+    // Returns the line of the current item's start.
+    fn start_line(&mut self) -> usize {
+        let newlines = self.input[self.line_pos..self.start].matches('\n').count();
+        self.line += newlines;
+        self.line_pos = self.start;
+        self.line
+    }
+
     fn emit(&mut self, t: ItemType) {
+        let line = self.start_line();
         let s = &self.input[self.start..self.pos];
-        let lines = match t {
-            ItemType::ItemText
-            | ItemType::ItemRawString
-            | ItemType::ItemLeftDelim
-            | ItemType::ItemRightDelim => 1,
-            _ => s.chars().filter(|c| *c == '\n').count(),
-        };
         self.items_sender
-            .send(Item::new(t, self.start, s, self.line))
+            .send(Item::new(t, self.start, s, line))
             .unwrap();
-        self.line += lines;
         self.start = self.pos;
     }
 
@@ -282,14 +285,19 @@ impl LexerStateMachine {
     }
 
     fn errorf(&mut self, msg: &str) -> State {
+        let line = self.start_line();
         self.items_sender
-            .send(Item::new(ItemType::ItemError, self.start, msg, self.line))
+            .send(Item::new(ItemType::ItemError, self.start, msg, line))
             .unwrap();
         State::End
     }
 
     fn lex_text(&mut self) -> State {
         self.width = 0;
+        if self.pos >= self.stop {
+            self.emit(ItemType::ItemEOF);
+            return State::End;
+        }
         let x = self.input[self.pos..].find(&LEFT_DELIM);
         match x {
             Some(x) => {
@@ -306,6 +314,10 @@ impl LexerStateMachine {
                 }
                 self.pos += trim;
                 self.ignore();
+                if self.pos >= self.stop {
+                    self.emit(ItemType::ItemEOF);
+                    return State::End;
+                }
                 State::LexLeftDelim
             }
             None => {
@@ -429,6 +441,12 @@ impl LexerStateMachine {
                         _ => self.errorf("expected :="),
                     },
                     '|' => {
+                        #[cfg(feature = "gtmpl_infix")]
+                        {
+                            if self.input[self.pos..].starts_with('|') {
+                                return self.lex_operator(c);
+                            }
+                        }
                         self.emit(ItemType::ItemPipe);
                         State::LexInsideAction
                     }
@@ -440,9 +458,19 @@ impl LexerStateMachine {
                         _ => State::LexField,
                     },
                     '+' | '-' | '0'..='9' => {
+                        #[cfg(feature = "gtmpl_infix")]
+                        {
+                            let number = self.input[self.pos..]
+                                .starts_with(|c: char| c.is_ascii_digit() || c == '.');
+                            if !c.is_ascii_digit() && !number {
+                                return self.lex_operator(c);
+                            }
+                        }
                         self.backup();
                         State::LexNumber
                     }
+                    #[cfg(feature = "gtmpl_infix")]
+                    '=' | '!' | '<' | '>' | '&' | '*' | '/' => self.lex_operator(c),
                     _ if c.is_whitespace() => State::LexSpace,
                     _ if c.is_alphanumeric() || c == '_' => {
                         self.backup();
@@ -459,6 +487,30 @@ impl LexerStateMachine {
         }
     }
 
+    // Scans an operator starting with `c`, which has already been consumed.
+    #[cfg(feature = "gtmpl_infix")]
+    fn lex_operator(&mut self, c: char) -> State {
+        let next = self.input[self.pos..].chars().next();
+        match (c, next) {
+            ('=', Some('='))
+            | ('!', Some('='))
+            | ('<', Some('='))
+            | ('>', Some('='))
+            | ('&', Some('&'))
+            | ('|', Some('|')) => {
+                self.next();
+            }
+            ('=', _) => {
+                self.emit(ItemType::ItemChar);
+                return State::LexInsideAction;
+            }
+            ('&', _) => return self.errorf("expected &&"),
+            _ => {}
+        }
+        self.emit(ItemType::ItemOperator);
+        State::LexInsideAction
+    }
+
     fn lex_space(&mut self) -> State {
         while self.peek().map(|c| c.is_whitespace()).unwrap_or_default() {
             self.next();
@@ -604,9 +656,7 @@ impl LexerStateMachine {
     }
 
     fn lex_raw_quote(&mut self) -> State {
-        let start_line = self.line;
         if !self.any(|c| c == '`') {
-            self.line = start_line;
             return self.errorf("unterminated raw quoted string");
         }
         self.emit(ItemType::ItemRawString);
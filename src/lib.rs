@@ -7,22 +7,87 @@
 //! let output = gtmpl::template("Finally! Some {{ . }} for Rust", "gtmpl");
 //! assert_eq!(&output.unwrap(), "Finally! Some gtmpl for Rust");
 //! ```
+mod audit;
+mod byte_slice;
+pub mod clock;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
 pub mod error;
 mod exec;
+mod fold;
+mod func_map;
 pub mod funcs;
+mod hook;
 mod lexer;
+mod metrics;
 pub mod node;
+mod ordered;
 mod parse;
 mod print_verb;
 mod printf;
+mod profile;
+#[cfg(feature = "serde")]
+mod ser;
+mod source_map;
 mod template;
 mod utils;
+mod validate;
+mod warnings;
 
 #[doc(inline)]
-pub use crate::template::Template;
+pub use crate::template::{FuncResolver, Template};
 
 #[doc(inline)]
-pub use crate::exec::Context;
+pub use crate::audit::{AuditRecord, AuditSink};
+
+#[doc(inline)]
+pub use crate::byte_slice::byte_slice;
+
+#[doc(inline)]
+pub use crate::exec::{Chunks, Context, ExecOptions, MissingKey, MissingTemplate};
+
+#[cfg(feature = "tokio")]
+#[doc(inline)]
+pub use crate::exec::{AsyncFunc, AsyncFuncResult};
+
+#[cfg(feature = "i18n")]
+#[doc(inline)]
+pub use crate::funcs::{Catalog, CatalogError};
+
+#[cfg(feature = "net")]
+#[doc(inline)]
+pub use crate::funcs::NetOptions;
+
+#[doc(inline)]
+pub use crate::func_map::{FuncDef, FuncMap, SharedFunc};
+
+#[doc(inline)]
+pub use crate::funcs::FromArg;
+
+#[doc(inline)]
+pub use crate::hook::ExecHook;
+
+#[doc(inline)]
+pub use crate::metrics::Metrics;
+
+#[doc(inline)]
+pub use crate::ordered::ordered_map;
+
+#[doc(inline)]
+pub use crate::parse::ParseOptions;
+
+#[doc(inline)]
+pub use crate::profile::{Profile, ProfileEntry};
+
+#[cfg(feature = "serde")]
+#[doc(inline)]
+pub use crate::ser::{to_value, ToValueError};
+
+#[doc(inline)]
+pub use crate::source_map::{Mapping, SourceMap};
+
+#[doc(inline)]
+pub use crate::utils::is_true;
 
 #[doc(inline)]
 pub use gtmpl_value::Func;
@@ -32,6 +97,12 @@ pub use gtmpl_value::FuncError;
 #[doc(inline)]
 pub use gtmpl_value::from_value;
 
+// Used by the code generated by `funcs::gtmpl_fn`.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::funcs::arg::{arg, check_arity, rest};
+}
+
 pub use error::TemplateError;
 pub use gtmpl_value::Value;
 
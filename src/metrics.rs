@@ -0,0 +1,33 @@
+//! Counters of the work done by an execution.
+
+/// What an execution of a template did, e.g. for capacity planning or to enforce soft limits.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{Context, Template};
+///
+/// let mut tmpl = Template::default();
+/// tmpl.parse(r#"{{ define "item" }}<{{ . }}>{{ end }}{{ range . }}{{ template "item" . }}{{ end }}"#)
+///     .unwrap();
+/// let (output, metrics) = tmpl.render_with_metrics(&Context::from(vec![1, 2]));
+/// assert_eq!(output.unwrap(), "<1><2>");
+/// assert_eq!(metrics.templates, 2);
+/// assert_eq!(metrics.bytes_written, 6);
+/// assert_eq!(metrics.max_depth, 1);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Number of nodes executed, e.g. actions, texts and control structures.
+    pub nodes: usize,
+    /// Number of function and method calls.
+    pub calls: usize,
+    /// Number of bytes written to the output.
+    pub bytes_written: usize,
+    /// Number of templates invoked by `{{template}}`.
+    pub templates: usize,
+    /// Number of `range` iterations.
+    pub range_iterations: usize,
+    /// Deepest nesting of template invocations and parenthesized pipelines.
+    pub max_depth: usize,
+}
@@ -1,8 +1,9 @@
 use std::fmt::{Display, Formatter};
+use std::ops::Range;
 
 use crate::error::NodeError;
 use crate::lexer::ItemType;
-use crate::utils::unquote_char;
+use crate::utils::{quote_str, unquote_char};
 
 use gtmpl_value::Value;
 
@@ -45,6 +46,11 @@ macro_rules! nodes {
                     $(Nodes::$name(ref t) => t.tree(),)*
                 }
             }
+            fn shift_pos(&mut self, delta: isize) {
+                match *self {
+                    $(Nodes::$name(ref mut t) => t.shift_pos(delta),)*
+                }
+            }
         }
     }
 }
@@ -96,6 +102,10 @@ pub type Pos = usize;
 
 pub type TreeId = usize;
 
+fn shift(pos: Pos, delta: isize) -> Pos {
+    (pos as isize + delta) as Pos
+}
+
 pub trait Node: Display {
     fn typ(&self) -> &NodeType;
     fn pos(&self) -> Pos;
@@ -114,6 +124,11 @@ macro_rules! node {
             tr: TreeId,
             $(pub $field: $typ,)*
         }
+        impl $name {
+            pub(crate) fn shift_pos(&mut self, delta: isize) {
+                self.pos = shift(self.pos, delta);
+            }
+        }
         impl Node for $name {
             fn typ(&self) -> &NodeType {
                 &self.typ
@@ -132,7 +147,7 @@ impl Nodes {
     pub fn is_empty_tree(&self) -> Result<bool, NodeError> {
         match *self {
             Nodes::List(ref n) => n.is_empty_tree(),
-            Nodes::Text(ref n) => Ok(n.text.is_empty()),
+            Nodes::Text(ref n) => Ok(n.text.trim().is_empty()),
             Nodes::Action(_)
             | Nodes::If(_)
             | Nodes::Range(_)
@@ -141,6 +156,37 @@ impl Nodes {
             _ => Err(NodeError::NaTN),
         }
     }
+
+    /// Moves the node and all of its children by `delta` bytes.
+    pub(crate) fn shift(&mut self, delta: isize) {
+        match *self {
+            Nodes::List(ref mut n) => n.shift(delta),
+            Nodes::Pipe(ref mut n) => n.shift(delta),
+            Nodes::Action(ref mut n) => {
+                n.shift_pos(delta);
+                n.span = shift(n.span.start, delta)..shift(n.span.end, delta);
+                n.pipe.shift(delta);
+            }
+            Nodes::Command(ref mut n) => n.shift(delta),
+            Nodes::Chain(ref mut n) => {
+                n.shift_pos(delta);
+                n.node.shift(delta);
+            }
+            Nodes::If(ref mut n) | Nodes::With(ref mut n) | Nodes::Range(ref mut n) => {
+                n.shift(delta)
+            }
+            Nodes::Template(ref mut n) => {
+                n.shift_pos(delta);
+                if let PipeOrString::Pipe(ref mut pipe) = n.name {
+                    pipe.shift(delta);
+                }
+                if let Some(ref mut pipe) = n.pipe {
+                    pipe.shift(delta);
+                }
+            }
+            _ => self.shift_pos(delta),
+        }
+    }
 }
 
 node!(
@@ -161,6 +207,12 @@ impl ListNode {
             nodes: vec![],
         }
     }
+    pub(crate) fn shift(&mut self, delta: isize) {
+        self.shift_pos(delta);
+        for n in &mut self.nodes {
+            n.shift(delta);
+        }
+    }
     pub fn is_empty_tree(&self) -> Result<bool, NodeError> {
         for n in &self.nodes {
             match n.is_empty_tree() {
@@ -205,6 +257,7 @@ impl Display for TextNode {
 
 node!(
     PipeNode {
+        is_assign: bool,
         decl: Vec<VariableNode>,
         cmds: Vec<CommandNode>
     }
@@ -216,6 +269,7 @@ impl PipeNode {
             typ: NodeType::Pipe,
             tr,
             pos,
+            is_assign: false,
             decl,
             cmds: vec![],
         }
@@ -224,6 +278,16 @@ impl PipeNode {
     pub fn append(&mut self, cmd: CommandNode) {
         self.cmds.push(cmd);
     }
+
+    pub(crate) fn shift(&mut self, delta: isize) {
+        self.shift_pos(delta);
+        for v in &mut self.decl {
+            v.shift_pos(delta);
+        }
+        for c in &mut self.cmds {
+            c.shift(delta);
+        }
+    }
 }
 
 impl Display for PipeNode {
@@ -233,12 +297,13 @@ impl Display for PipeNode {
         } else {
             write!(
                 f,
-                "{} := ",
+                "{} {} ",
                 self.decl
                     .iter()
                     .map(|n| n.to_string())
                     .collect::<Vec<String>>()
-                    .join(", ")
+                    .join(", "),
+                if self.is_assign { "=" } else { ":=" }
             )
         };
         decl.and_then(|_| {
@@ -255,7 +320,11 @@ impl Display for PipeNode {
     }
 }
 
-node!(ActionNode { pipe: PipeNode });
+// `span` covers the action including its delimiters.
+node!(ActionNode {
+    pipe: PipeNode,
+    span: Range<Pos>,
+});
 
 impl ActionNode {
     pub fn new(tr: TreeId, pos: Pos, pipe: PipeNode) -> ActionNode {
@@ -263,6 +332,7 @@ impl ActionNode {
             typ: NodeType::Action,
             tr,
             pos,
+            span: pos..pos,
             pipe,
         }
     }
@@ -293,6 +363,13 @@ impl CommandNode {
     pub fn append(&mut self, node: Nodes) {
         self.args.push(node);
     }
+
+    pub(crate) fn shift(&mut self, delta: isize) {
+        self.shift_pos(delta);
+        for n in &mut self.args {
+            n.shift(delta);
+        }
+    }
 }
 
 impl Display for CommandNode {
@@ -300,9 +377,10 @@ impl Display for CommandNode {
         let s = self
             .args
             .iter()
-            .map(|n|
-                // Handle PipeNode.
-                n.to_string())
+            .map(|n| match *n {
+                Nodes::Pipe(ref pipe) => format!("({})", pipe),
+                _ => n.to_string(),
+            })
             .collect::<Vec<String>>()
             .join(" ");
         write!(f, "{}", s)
@@ -425,7 +503,10 @@ impl FieldNode {
 
 impl Display for FieldNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "{}", self.ident.join("."))
+        for ident in &self.ident {
+            write!(f, ".{}", ident)?;
+        }
+        Ok(())
     }
 }
 
@@ -455,9 +536,9 @@ impl ChainNode {
 
 impl Display for ChainNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        if let Err(e) = {
-            // Handle PipeNode.
-            write!(f, "{}", self.node)
+        if let Err(e) = match *self.node {
+            Nodes::Pipe(ref pipe) => write!(f, "({})", pipe),
+            _ => write!(f, "{}", self.node),
         } {
             return Err(e);
         }
@@ -582,12 +663,13 @@ impl NumberNode {
                     return Err(NodeError::NaN);
                 }
 
-                let value = if is_u64 {
+                // Numbers with a fraction or an exponent are floats like in Go, e.g. `1.0`.
+                let value = if is_f64 {
+                    Value::from(as_f64)
+                } else if is_u64 {
                     Value::from(as_u64)
-                } else if is_i64 {
-                    Value::from(as_i64)
                 } else {
-                    Value::from(as_f64)
+                    Value::from(as_i64)
                 };
 
                 Ok(NumberNode {
@@ -734,6 +816,15 @@ impl BranchNode {
             else_list,
         }
     }
+
+    pub(crate) fn shift(&mut self, delta: isize) {
+        self.shift_pos(delta);
+        self.pipe.shift(delta);
+        self.list.shift(delta);
+        if let Some(ref mut else_list) = self.else_list {
+            else_list.shift(delta);
+        }
+    }
 }
 
 impl Display for BranchNode {
@@ -794,8 +885,8 @@ pub enum PipeOrString {
 impl Display for PipeOrString {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match *self {
-            PipeOrString::Pipe(ref pipe_node) => write!(f, "{}", pipe_node),
-            PipeOrString::String(ref s) => write!(f, "{}", s),
+            PipeOrString::Pipe(ref pipe_node) => write!(f, "({})", pipe_node),
+            PipeOrString::String(ref s) => write!(f, "{}", quote_str(s)),
         }
     }
 }
@@ -0,0 +1,98 @@
+//! Maps which keep the order their keys were inserted in.
+//!
+//! [`ordered_map`] creates a `Value::OrderedMap`. `range`, printing, `keys`, `values` and the
+//! serialization functions visit the entries of such maps in that order, while functions like
+//! `index`, `hasKey` and `merge` treat them like any other map.
+use std::collections::HashMap;
+
+use gtmpl_value::Value;
+
+/// Creates a map which keeps the order of `entries`, e.g. to generate YAML or JSON with keys in
+/// a given order. Other maps are visited in sorted key order.
+///
+/// Keys added later, e.g. by `set` or `merge`, follow the ordered keys in sorted order.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{ordered_map, template, Value};
+///
+/// let map = ordered_map(vec![("name", Value::from("web")), ("image", Value::from("nginx"))]);
+/// let output = template("{{ range $k, $v := . }}{{ $k }}: {{ $v }}\n{{ end }}{{ . }}", map);
+/// assert_eq!(&output.unwrap(), "name: web\nimage: nginx\nmap[name:web image:nginx]");
+/// ```
+pub fn ordered_map<I, K, V>(entries: I) -> Value
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: Into<Value>,
+{
+    Value::OrderedMap(entries.into_iter().collect())
+}
+
+/// The entries of `val` if it is a map or an object.
+pub(crate) fn as_map(val: &Value) -> Option<&HashMap<String, Value>> {
+    match *val {
+        Value::Map(ref m) | Value::Object(ref m) => Some(m),
+        Value::OrderedMap(ref m) => Some(m.as_map()),
+        _ => None,
+    }
+}
+
+/// The entries of `val` to change if it is a map or an object.
+pub(crate) fn as_map_mut(val: &mut Value) -> Option<&mut HashMap<String, Value>> {
+    match *val {
+        Value::Map(ref mut m) | Value::Object(ref mut m) => Some(m),
+        Value::OrderedMap(ref mut m) => Some(m.as_map_mut()),
+        _ => None,
+    }
+}
+
+/// The entries of `val` if it is a map or an object, in the order they were inserted in for
+/// ordered maps, and in sorted key order or, unless `sort` is set, in unspecified order
+/// otherwise.
+pub(crate) fn entries(val: &Value, sort: bool) -> Option<Vec<(&String, &Value)>> {
+    let map = match *val {
+        Value::OrderedMap(ref m) => return Some(m.iter().collect()),
+        Value::Map(ref m) | Value::Object(ref m) => m,
+        _ => return None,
+    };
+    let mut entries: Vec<_> = map.iter().collect();
+    if sort {
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let keys = |m: &Value| {
+            entries(m, true)
+                .unwrap()
+                .into_iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+        };
+        let mut m = ordered_map(vec![("b", 1), ("a", 2), ("c", 3), ("a", 4)]);
+        assert_eq!(keys(&m), vec!["b=1", "a=4", "c=3"]);
+
+        let changed = as_map_mut(&mut m).unwrap();
+        changed.remove("a");
+        changed.insert("e".to_owned(), Value::from(5));
+        changed.insert("d".to_owned(), Value::from(6));
+        assert_eq!(keys(&m), vec!["b=1", "c=3", "d=6", "e=5"]);
+        assert_eq!(as_map(&m).map(HashMap::len), Some(4));
+
+        let plain = Value::Map(
+            as_map(&ordered_map(vec![("b", 1), ("a", 2)]))
+                .unwrap()
+                .clone(),
+        );
+        assert_eq!(keys(&plain), vec!["a=2", "b=1"]);
+        assert_eq!(entries(&Value::from(1), true), None);
+    }
+}
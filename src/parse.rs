@@ -1,15 +1,27 @@
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
 
-use crate::error::ParseError;
+use crate::error::{ErrorContext, ParseError};
+use crate::func_map::FuncMap;
 use crate::lexer::{Item, ItemType, Lexer};
 use crate::node::*;
 use crate::utils::*;
 
+const MAX_DEPTH: usize = 10_000;
+const MAX_EXPR_DEPTH: usize = 50;
+
 pub struct Parser {
     name: String,
-    pub funcs: HashSet<String>,
+    pub funcs: FuncMap,
+    /// Names of the async functions, which can be called besides `funcs`.
+    pub async_funcs: HashSet<String>,
+    options: ParseOptions,
     lex: Option<Lexer>,
     line: usize,
+    pos: Pos,
+    end: Pos,
     token: VecDeque<Item>,
     peek_count: usize,
     pub tree_set: HashMap<String, Tree>,
@@ -17,6 +29,53 @@ pub struct Parser {
     tree: Option<Tree>,
     tree_stack: VecDeque<Tree>,
     max_tree_id: TreeId,
+    after_block: bool,
+    expr_depth: usize,
+    text_start: Pos,
+    items: Vec<TopItem>,
+    defined: Vec<String>,
+    nodes: usize,
+}
+
+/// Options controlling how template text is turned into trees.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Remove the first newline after a block action (`if`, `range`, `with`, `else`, `end`,
+    /// `define` and `block`).
+    pub trim_blocks: bool,
+    /// Keep a single newline at the very end of the template text.
+    pub keep_trailing_newline: bool,
+    /// Maximum nesting depth of control structures.
+    pub max_depth: usize,
+    /// Maximum nesting depth of parenthesized pipelines. These are parsed recursively, so the
+    /// limit has to fit into the stack of the parsing thread.
+    pub max_expr_depth: usize,
+    /// Maximum size of the template text in bytes.
+    pub max_source_size: usize,
+    /// Maximum number of trees, i.e. the template itself and its definitions, parsed from a
+    /// single text.
+    pub max_trees: usize,
+    /// Maximum number of nodes parsed from a single text. Text, actions, control structures
+    /// and each operand count as one node.
+    pub max_nodes: usize,
+    /// Accept calls of unknown functions. They are looked up when executed instead, see
+    /// [`Template::set_func_resolver`](crate::Template::set_func_resolver).
+    pub late_bound_funcs: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            trim_blocks: false,
+            keep_trailing_newline: true,
+            max_depth: MAX_DEPTH,
+            max_expr_depth: MAX_EXPR_DEPTH,
+            max_source_size: usize::MAX,
+            max_trees: usize::MAX,
+            max_nodes: usize::MAX,
+            late_bound_funcs: false,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -24,17 +83,112 @@ pub struct Tree {
     name: String,
     id: TreeId,
     pub root: Option<Nodes>,
-    vars: Vec<String>,
+    vars: Scopes,
     pub fields: HashSet<String>,
+    len: usize,
+    items: Vec<TopItem>,
+    text: Arc<str>,
+}
+
+/// A top-level item of the parsed text and the parser state it started with. Used to re-parse
+/// only the items touched by an edit.
+#[derive(Clone)]
+struct TopItem {
+    start: Pos,
+    first_node: usize,
+    vars_len: usize,
+    after_block: bool,
+    trees: Vec<String>,
+    fields: HashSet<String>,
+}
+
+/// Variables declared so far, one list per scope with the innermost scope last. Control
+/// structures open a scope, `define` and `block` start over with only `$` declared.
+#[derive(Clone, Debug, PartialEq)]
+struct Scopes {
+    scopes: Vec<Vec<String>>,
+}
+
+impl Scopes {
+    fn new() -> Scopes {
+        Scopes {
+            scopes: vec![vec![String::from("$")]],
+        }
+    }
+
+    // Only the top-level scope with its first `n` variables.
+    fn top_level(&self, n: usize) -> Scopes {
+        Scopes {
+            scopes: vec![self.scopes[0][..n].to_vec()],
+        }
+    }
+
+    fn top_level_len(&self) -> usize {
+        self.scopes[0].len()
+    }
+
+    fn push(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    fn pop(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    fn declare(&mut self, name: String) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(name);
+        }
+    }
+
+    fn is_declared(&self, name: &str) -> bool {
+        self.scopes.iter().flatten().any(|v| v == name)
+    }
+}
+
+/// The result of parsing a single item of a list.
+enum Step {
+    Node(Nodes),
+    Open(Control),
+    Else(Nodes),
+    End(Nodes),
+}
+
+/// The header of a control structure.
+struct Control {
+    kind: FrameKind,
+    pos: Pos,
+    pipe: PipeNode,
+}
+
+enum FrameKind {
+    If,
+    Range,
+    With,
+    Block(String),
+}
+
+/// A control structure whose body is still being parsed.
+struct Frame {
+    control: Control,
+    parent: ListNode,
+    list: Option<ListNode>,
+    else_if: bool,
 }
 
 impl Parser {
     pub fn new(name: String) -> Parser {
         Parser {
             name,
-            funcs: HashSet::new(),
+            funcs: FuncMap::new(),
+            async_funcs: HashSet::new(),
+            options: ParseOptions::default(),
             lex: None,
             line: 0,
+            pos: 0,
+            end: 0,
             token: VecDeque::new(),
             peek_count: 0,
             tree_set: HashMap::new(),
@@ -42,6 +196,12 @@ impl Parser {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            after_block: false,
+            expr_depth: 0,
+            text_start: 0,
+            items: vec![],
+            defined: vec![],
+            nodes: 0,
         }
     }
 }
@@ -52,29 +212,227 @@ impl Tree {
             name,
             id,
             root: None,
-            vars: vec![],
+            vars: Scopes::new(),
             fields: Default::default(),
+            len: 0,
+            items: vec![],
+            text: Arc::from(""),
         }
     }
 
-    pub fn pop_vars(&mut self, n: usize) {
-        self.vars.truncate(n);
+    /// The location of `pos` in the text this tree was parsed from.
+    pub(crate) fn error_context(&self, pos: Pos) -> ErrorContext {
+        let before = self.text.get(..pos).unwrap_or(&self.text);
+        ErrorContext {
+            name: self.name.clone(),
+            line: 1 + before.matches('\n').count(),
+            pos,
+        }
+    }
+
+    /// Whether the tree only consists of whitespace, like Go's `IsEmptyTree`.
+    pub(crate) fn is_empty(&self) -> bool {
+        match self.root {
+            Some(ref root) => root.is_empty_tree().unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Names of the templates defined by the text this tree was parsed from.
+    pub(crate) fn defined(&self) -> impl Iterator<Item = &String> {
+        self.items.iter().flat_map(|item| item.trees.iter())
+    }
+
+    fn bound(&self, i: usize) -> Pos {
+        self.items.get(i).map(|item| item.start).unwrap_or(self.len)
+    }
+
+    fn first_node(&self, i: usize) -> usize {
+        match self.items.get(i) {
+            Some(item) => item.first_node,
+            None => match self.root {
+                Some(Nodes::List(ref list)) => list.nodes.len(),
+                _ => 0,
+            },
+        }
+    }
+
+    fn vars_len(&self, i: usize) -> usize {
+        self.items
+            .get(i)
+            .map(|item| item.vars_len)
+            .unwrap_or_else(|| self.vars.top_level_len())
     }
 }
 
 pub fn parse(
     name: String,
     text: String,
-    funcs: HashSet<String>,
+    funcs: &FuncMap,
+    async_funcs: HashSet<String>,
+    options: ParseOptions,
 ) -> Result<HashMap<String, Tree>, ParseError> {
+    if text.len() > options.max_source_size {
+        return Err(ParseError::SourceTooLarge {
+            size: text.len(),
+            max: options.max_source_size,
+        });
+    }
     let mut p = Parser::new(name);
-    p.funcs = funcs;
+    p.funcs = funcs.clone();
+    p.async_funcs = async_funcs;
+    p.options = options;
+    let shared = Arc::from(text.as_str());
     p.lex = Some(Lexer::new(text));
     p.parse_tree()?;
+    p.set_text(shared);
     Ok(p.tree_set)
 }
 
+/// Parses `new_text`, which is `old_text` with the bytes in `edited` replaced, reusing the trees
+/// `name` and its definitions in `tree_set` were parsed from `old_text`.
+///
+/// Only the top-level items touching the edit and their direct neighbours are lexed and parsed
+/// again. Returns `None` if the edit can not be applied this way, e.g. because the re-parsed
+/// items do not end on an item boundary, change the variables visible to later items or do not
+/// parse at all. Callers fall back to a full `parse` then.
+#[allow(clippy::too_many_arguments)]
+pub fn reparse(
+    tree_set: &HashMap<String, Tree>,
+    name: &str,
+    old_text: &str,
+    new_text: &str,
+    edited: Range<usize>,
+    funcs: &FuncMap,
+    async_funcs: HashSet<String>,
+    options: ParseOptions,
+) -> Option<HashMap<String, Tree>> {
+    // Limits on the whole text can not be checked by parsing parts of it.
+    if options.max_source_size != usize::MAX
+        || options.max_trees != usize::MAX
+        || options.max_nodes != usize::MAX
+    {
+        return None;
+    }
+    let old = tree_set.get(name)?;
+    let delta = new_text.len() as isize - old_text.len() as isize;
+    let new_end = (edited.end as isize + delta) as usize;
+    if old.len != old_text.len()
+        || old.items.is_empty()
+        || edited.start > edited.end
+        || new_end < edited.start
+        || old_text.get(..edited.start)? != new_text.get(..edited.start)?
+        || old_text.get(edited.end..)? != new_text.get(new_end..)?
+    {
+        return None;
+    }
+
+    // Items whose span touches the edit, plus one on each side for trim markers.
+    let n = old.items.len();
+    let first = (0..n).find(|&i| old.bound(i + 1) >= edited.start)?;
+    let last = (first..n)
+        .take_while(|&i| old.bound(i) <= edited.end)
+        .last()?;
+    let first = first.saturating_sub(1);
+    let last = (last + 1).min(n - 1);
+    let start = old.bound(first);
+    let stop = (old.bound(last + 1) as isize + delta) as usize;
+
+    let inside: HashSet<&String> = old.items[first..=last]
+        .iter()
+        .flat_map(|item| item.trees.iter())
+        .collect();
+    let mut p = Parser::new(name.to_owned());
+    p.funcs = funcs.clone();
+    p.async_funcs = async_funcs;
+    p.options = options;
+    p.lex = Some(Lexer::with_range(new_text.to_owned(), start..stop));
+    p.text_start = start;
+    p.after_block = old.items[first].after_block;
+    p.tree_set = old
+        .defined()
+        .filter(|name| !inside.contains(name))
+        .filter_map(|name| tree_set.get(name).map(|t| (name.clone(), t.clone())))
+        .collect();
+    p.start_parse(name.to_owned(), old.id);
+    p.tree.as_mut()?.vars = old.vars.top_level(old.vars_len(first));
+
+    let (list, _) = p.parse_list(true).ok()?;
+    match p.next() {
+        Some(ref eof) if eof.typ == ItemType::ItemEOF && eof.pos == stop => {}
+        _ => return None,
+    }
+    if p.tree.as_ref()?.vars != old.vars.top_level(old.vars_len(last + 1))
+        || (last + 1 < n && p.after_block != old.items[last + 1].after_block)
+    {
+        return None;
+    }
+
+    // Splice the new items and nodes in between the unchanged ones.
+    let old_nodes = match old.root {
+        Some(Nodes::List(ref list)) => &list.nodes,
+        _ => return None,
+    };
+    let (node_start, node_end) = (old.first_node(first), old.first_node(last + 1));
+    let node_delta = list.nodes.len() as isize - (node_end - node_start) as isize;
+    let mut root = match old.root {
+        Some(Nodes::List(ref root)) if first > 0 => ListNode::new(root.tree(), root.pos()),
+        _ => ListNode::new(old.id, list.pos()),
+    };
+    root.nodes.extend(old_nodes[..node_start].iter().cloned());
+    root.nodes.extend(list.nodes);
+    root.nodes.extend(old_nodes[node_end..].iter().map(|node| {
+        let mut node = node.clone();
+        node.shift(delta);
+        node
+    }));
+
+    let mut items = old.items[..first].to_vec();
+    items.extend(p.items.drain(..).map(|mut item| {
+        item.first_node += node_start;
+        item
+    }));
+    for item in &old.items[last + 1..] {
+        let mut item = item.clone();
+        item.start = (item.start as isize + delta) as Pos;
+        item.first_node = (item.first_node as isize + node_delta) as usize;
+        for name in &item.trees {
+            if let Some(Nodes::List(ref mut list)) =
+                p.tree_set.get_mut(name).and_then(|t| t.root.as_mut())
+            {
+                list.shift(delta);
+            }
+        }
+        items.push(item);
+    }
+
+    let tree = p.tree.as_mut()?;
+    tree.vars = old.vars.clone();
+    tree.root = Some(Nodes::List(root));
+    tree.fields = items
+        .iter()
+        .flat_map(|item| item.fields.iter().cloned())
+        .collect();
+    tree.items = items;
+    if last + 1 == n && !p.options.keep_trailing_newline {
+        p.trim_trailing_newline();
+    }
+    p.stop_parse().ok()?;
+    p.set_text(Arc::from(new_text));
+    Some(p.tree_set)
+}
+
 impl Parser {
+    // Sets the text all trees were parsed from. It is needed to locate errors during execution.
+    fn set_text(&mut self, text: Arc<str>) {
+        if let Some(tree) = self.tree_set.get_mut(&self.name) {
+            tree.len = text.len();
+        }
+        for tree in self.tree_set.values_mut() {
+            tree.text = text.clone();
+        }
+    }
+
     fn next_from_lex(&mut self) -> Option<Item> {
         match self.lex {
             Some(ref mut l) => l.next(),
@@ -101,8 +459,7 @@ impl Parser {
     }
 
     fn next_must(&mut self, context: &str) -> Result<Item, ParseError> {
-        self.next()
-            .ok_or_else(|| self.error_msg(&format!("unexpected end in {}", context)))
+        self.next().ok_or_else(|| self.unexpected_eof(context))
     }
 
     fn next_non_space(&mut self) -> Option<Item> {
@@ -111,7 +468,7 @@ impl Parser {
 
     fn next_non_space_must(&mut self, context: &str) -> Result<Item, ParseError> {
         self.next_non_space()
-            .ok_or_else(|| self.unexpected("end", context))
+            .ok_or_else(|| self.unexpected_eof(context))
     }
 
     fn peek_non_space_must(&mut self, context: &str) -> Result<&Item, ParseError> {
@@ -119,7 +476,7 @@ impl Parser {
             self.backup(t);
             return Ok(self.token.front().unwrap());
         }
-        self.error(&format!("unexpected end in {}", context))
+        Err(self.unexpected_eof(context))
     }
 
     fn peek(&mut self) -> Option<&Item> {
@@ -135,7 +492,7 @@ impl Parser {
             self.backup(t);
             return Ok(self.token.front().unwrap());
         }
-        self.error(&format!("unexpected end in {}", context))
+        Err(self.unexpected_eof(context))
     }
 
     fn start_parse(&mut self, name: String, id: TreeId) {
@@ -164,26 +521,77 @@ impl Parser {
     }
 
     fn add_tree(&mut self, name: String, t: Tree) {
+        self.defined.push(name.clone());
         self.tree_set.insert(name, t);
     }
 
+    // Starts a new top-level item at `pos` with `first_node` as the index of its first node in
+    // the root list.
+    fn begin_item(&mut self, pos: Pos, first_node: usize) {
+        let start = if self.items.is_empty() {
+            self.text_start
+        } else {
+            pos
+        };
+        let vars_len = self
+            .tree
+            .as_ref()
+            .map(|t| t.vars.top_level_len())
+            .unwrap_or(0);
+        self.items.push(TopItem {
+            start,
+            first_node,
+            vars_len,
+            after_block: self.after_block,
+            trees: vec![],
+            fields: HashSet::new(),
+        });
+    }
+
+    // Records the templates and fields of the current top-level item.
+    fn finish_item(&mut self) {
+        if let Some(item) = self.items.last_mut() {
+            item.trees = mem::take(&mut self.defined);
+            if let Some(tree) = self.tree.as_mut() {
+                item.fields = mem::take(&mut tree.fields);
+            }
+        }
+    }
+
     fn error<T>(&self, msg: &str) -> Result<T, ParseError> {
         Err(self.error_msg(msg))
     }
 
     fn error_msg(&self, msg: &str) -> ParseError {
+        ParseError::WithContext(self.ctx(), msg.to_owned())
+    }
+
+    fn ctx(&self) -> ErrorContext {
         let name = if let Some(t) = self.tree.as_ref() {
             &t.name
         } else {
             &self.name
         };
-        ParseError::with_context(name, self.line, msg)
+        ErrorContext {
+            name: name.clone(),
+            line: self.line,
+            pos: self.pos,
+        }
     }
 
     fn expect(&mut self, expected: &ItemType, context: &str) -> Result<Item, ParseError> {
         let token = self.next_non_space_must(context)?;
         if token.typ != *expected {
-            return Err(self.unexpected(&token, context));
+            let expected = match *expected {
+                ItemType::ItemRightDelim => String::from("right delimiter"),
+                ref typ => format!("{:?}", typ),
+            };
+            return Err(ParseError::UnexpectedToken {
+                ctx: self.ctx(),
+                expected: Some(expected),
+                found: token.to_string(),
+                context: context.to_owned(),
+            });
         }
         Ok(token)
     }
@@ -193,99 +601,99 @@ impl Parser {
         token: impl std::fmt::Display,
         context: impl std::fmt::Display,
     ) -> ParseError {
-        self.error_msg(&format!("unexpected {} in {}", token, context))
+        ParseError::UnexpectedToken {
+            ctx: self.ctx(),
+            expected: None,
+            found: token.to_string(),
+            context: context.to_string(),
+        }
+    }
+
+    fn unexpected_eof(&self, context: &str) -> ParseError {
+        ParseError::UnexpectedEof {
+            ctx: self.ctx(),
+            context: context.to_owned(),
+        }
     }
 
     fn add_var(&mut self, name: String) -> Result<(), ParseError> {
-        let mut tree = self.tree.take().ok_or_else(|| self.error_msg("no tree"))?;
-        tree.vars.push(name);
+        let mut tree = self.tree.take().ok_or(ParseError::NoTree)?;
+        tree.vars.declare(name);
         self.tree = Some(tree);
         Ok(())
     }
 
     fn add_to_tree_set(&mut self) -> Result<(), ParseError> {
-        let tree = self.tree.take().ok_or_else(|| self.error_msg("no tree"))?;
+        let tree = self.tree.take().ok_or(ParseError::NoTree)?;
+        // Like Go, a definition may replace an empty one, and empty definitions are ignored
+        // if the template is defined elsewhere.
         if let Some(t) = self.tree_set.get(tree.name.as_str()) {
-            if let Some(ref r) = t.root {
-                match r.is_empty_tree() {
-                    Err(e) => return Err(e.into()),
-                    Ok(false) => {
-                        let err =
-                            format!("template multiple definitions of template {}", &tree.name);
-                        return self.error(&err);
-                    }
-                    Ok(true) => {}
+            if !t.is_empty() {
+                if tree.is_empty() {
+                    return Ok(());
                 }
+                return Err(ParseError::MultipleDefinitions {
+                    ctx: self.ctx(),
+                    name: tree.name,
+                });
             }
+        } else if self.tree_set.len() >= self.options.max_trees {
+            return Err(ParseError::TooManyTrees {
+                ctx: self.ctx(),
+                max: self.options.max_trees,
+            });
         }
         self.add_tree(tree.name.clone(), tree);
         Ok(())
     }
 
+    fn count_node(&mut self) -> Result<(), ParseError> {
+        if self.nodes >= self.options.max_nodes {
+            return Err(ParseError::TooManyNodes {
+                ctx: self.ctx(),
+                max: self.options.max_nodes,
+            });
+        }
+        self.nodes += 1;
+        Ok(())
+    }
+
     fn has_func(&self, name: &str) -> bool {
         self.funcs.contains(name)
+            || self.async_funcs.contains(name)
+            || self.options.late_bound_funcs
     }
 
     fn parse(&mut self) -> Result<(), ParseError> {
         if self.tree.is_none() {
-            return self.error("no tree");
+            return Err(ParseError::NoTree);
         }
-        let id = self.tree_id;
-        let mut t = match self.next() {
-            None => return self.error(&format!("unable to peek for tree {}", id)),
-            Some(t) => t,
-        };
+        let (root, _) = self.parse_list(true)?;
+        let items = mem::take(&mut self.items);
         if let Some(tree) = self.tree.as_mut() {
-            tree.root = Some(Nodes::List(ListNode::new(id, t.pos)));
-        }
-        while t.typ != ItemType::ItemEOF {
-            if t.typ == ItemType::ItemLeftDelim {
-                let nns = self.next_non_space();
-                match nns {
-                    Some(ref item) if item.typ == ItemType::ItemDefine => {
-                        self.parse_definition()?;
-                        t = match self.next() {
-                            None => return self.error(&format!("unable to peek for tree {}", id)),
-                            Some(t) => t,
-                        };
-                        continue;
-                    }
-                    _ => {}
-                };
-                if let Some(t2) = nns {
-                    self.backup2(t, t2);
-                } else {
-                    self.backup(t);
+            tree.root = Some(Nodes::List(root));
+            tree.fields = items
+                .iter()
+                .flat_map(|item| item.fields.iter().cloned())
+                .collect();
+            tree.items = items;
+        }
+        if !self.options.keep_trailing_newline {
+            self.trim_trailing_newline();
+        }
+        Ok(())
+    }
+
+    fn trim_trailing_newline(&mut self) {
+        if let Some(Nodes::List(ref mut root)) = self.tree.as_mut().and_then(|t| t.root.as_mut()) {
+            if let Some(Nodes::Text(ref mut text)) = root.nodes.last_mut() {
+                if text.text.ends_with("\r\n") {
+                    text.text.truncate(text.text.len() - 2);
+                } else if text.text.ends_with('\n') {
+                    text.text.pop();
                 }
-            } else {
-                self.backup(t);
             }
-            let node = match self.text_or_action() {
-                Ok(Nodes::Else(node)) => return self.error(&format!("unexpected {}", node)),
-                Ok(Nodes::End(node)) => return self.error(&format!("unexpected {}", node)),
-                Ok(node) => node,
-                Err(e) => return Err(e),
-            };
-            self.tree
-                .as_mut()
-                .and_then(|tree| {
-                    tree.root.as_mut().and_then(|r| match *r {
-                        Nodes::List(ref mut r) => {
-                            r.append(node);
-                            Some(())
-                        }
-                        _ => None,
-                    })
-                })
-                .ok_or_else(|| self.error_msg("invalid root node"))?;
-
-            t = match self.next() {
-                None => return self.error(&format!("unable to peek for tree {}", id)),
-                Some(t) => t,
-            };
         }
-        self.backup(t);
-        Ok(())
     }
 
     fn parse_definition(&mut self) -> Result<(), ParseError> {
@@ -294,6 +702,7 @@ impl Parser {
         let token = self.next_non_space_must(context)?;
         let name = self.parse_template_name(&token, context)?;
         self.expect(&ItemType::ItemRightDelim, "define end")?;
+        self.after_block = true;
         self.start_parse(name, id + 1);
         let (list, end) = self.item_list()?;
         if *end.typ() != NodeType::End {
@@ -306,126 +715,250 @@ impl Parser {
     }
 
     fn item_list(&mut self) -> Result<(ListNode, Nodes), ParseError> {
+        match self.parse_list(false)? {
+            (list, Some(end)) => Ok((list, end)),
+            (_, None) => Err(self.unexpected_eof("item list")),
+        }
+    }
+
+    // Parses a list of items until an `else` or `end` which does not belong to a nested control
+    // structure, or until EOF on the top level. Nested control structures are kept on an
+    // explicit stack instead of recursing, so their depth is bounded by
+    // `ParseOptions::max_depth` rather than the size of the thread's stack.
+    fn parse_list(&mut self, top_level: bool) -> Result<(ListNode, Option<Nodes>), ParseError> {
         let pos = self.peek_non_space_must("item list")?.pos;
         let mut list = ListNode::new(self.tree_id, pos);
-        while self.peek_non_space_must("item list")?.typ != ItemType::ItemEOF {
-            let node = self.text_or_action()?;
-            match *node.typ() {
-                NodeType::End | NodeType::Else => return Ok((list, node)),
-                _ => list.append(node),
+        let mut frames: Vec<Frame> = vec![];
+        loop {
+            let token = self.next_non_space_must("item list")?;
+            if top_level && frames.is_empty() {
+                self.finish_item();
+                if token.typ != ItemType::ItemEOF {
+                    self.begin_item(token.pos, list.nodes.len());
+                }
             }
+            match token.typ {
+                ItemType::ItemEOF if top_level && frames.is_empty() => {
+                    self.backup(token);
+                    return Ok((list, None));
+                }
+                ItemType::ItemEOF => return Err(self.unexpected_eof("item list")),
+                ItemType::ItemLeftDelim if top_level && frames.is_empty() => {
+                    match self.next_non_space() {
+                        Some(item) if item.typ == ItemType::ItemDefine => {
+                            self.parse_definition()?;
+                            continue;
+                        }
+                        Some(item) => self.backup2(token, item),
+                        None => self.backup(token),
+                    }
+                }
+                _ => self.backup(token),
+            }
+            let node = match self.text_or_action()? {
+                Step::Node(node) => node,
+                Step::Open(control) => {
+                    self.open_frame(&mut frames, &mut list, control, false)?;
+                    continue;
+                }
+                Step::Else(node) | Step::End(node) if frames.is_empty() => {
+                    if top_level {
+                        return Err(self.unexpected(&node, "input"));
+                    }
+                    return Ok((list, Some(node)));
+                }
+                Step::Else(node) => {
+                    self.enter_else(&mut frames, &mut list, node)?;
+                    continue;
+                }
+                Step::End(_) => self.close_frames(&mut frames, &mut list)?,
+            };
+            self.count_node()?;
+            list.append(node);
         }
-        self.error("unexpected EOF")
     }
 
-    fn text_or_action(&mut self) -> Result<Nodes, ParseError> {
-        match self.next_non_space() {
-            Some(ref item) if item.typ == ItemType::ItemText => Ok(Nodes::Text(TextNode::new(
-                self.tree_id,
-                item.pos,
-                item.val.clone(),
-            ))),
-            Some(ref item) if item.typ == ItemType::ItemLeftDelim => self.action(),
-            Some(ref item) => Err(self.unexpected(item, "input")),
-            _ => self.error("unexpected end of input"),
+    fn open_frame(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        list: &mut ListNode,
+        control: Control,
+        else_if: bool,
+    ) -> Result<(), ParseError> {
+        if frames.len() >= self.options.max_depth {
+            return Err(ParseError::MaxDepthExceeded { ctx: self.ctx() });
         }
+        let body = ListNode::new(self.tree_id, self.peek_non_space_must("item list")?.pos);
+        frames.push(Frame {
+            control,
+            parent: std::mem::replace(list, body),
+            list: None,
+            else_if,
+        });
+        Ok(())
     }
 
-    fn action(&mut self) -> Result<Nodes, ParseError> {
-        let token = self.next_non_space_must("action")?;
-        match token.typ {
-            ItemType::ItemBlock => return self.block_control(),
-            ItemType::ItemElse => return self.else_control(),
-            ItemType::ItemEnd => return self.end_control(),
-            ItemType::ItemIf => return self.if_control(),
-            ItemType::ItemRange => return self.range_control(),
-            ItemType::ItemTemplate => return self.template_control(),
-            ItemType::ItemWith => return self.with_control(),
+    fn enter_else(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        list: &mut ListNode,
+        node: Nodes,
+    ) -> Result<(), ParseError> {
+        let frame = match frames.last_mut() {
+            Some(frame) => frame,
+            None => return Err(self.unexpected(&node, "input")),
+        };
+        match frame.control.kind {
+            FrameKind::Block(_) => return Err(self.unexpected(&node, "block clause")),
+            _ if frame.list.is_some() => {
+                return Err(ParseError::UnexpectedToken {
+                    ctx: self.ctx(),
+                    expected: Some(String::from("{{end}}")),
+                    found: node.to_string(),
+                    context: String::from("else clause"),
+                });
+            }
             _ => {}
         }
-        let pos = token.pos;
-        self.backup(token);
-        Ok(Nodes::Action(ActionNode::new(
-            self.tree_id,
-            pos,
-            self.pipeline("command")?,
-        )))
+        let else_if = matches!(frame.control.kind, FrameKind::If)
+            && self.peek_must("else if")?.typ == ItemType::ItemIf;
+        let else_list = if else_if {
+            ListNode::new(self.tree_id, node.pos())
+        } else {
+            ListNode::new(self.tree_id, self.peek_non_space_must("item list")?.pos)
+        };
+        frame.list = Some(std::mem::replace(list, else_list));
+        if else_if {
+            self.next_must("else if")?;
+            let control = self.control(FrameKind::If, "if")?;
+            self.open_frame(frames, list, control, true)?;
+        }
+        Ok(())
     }
 
-    fn parse_control(
+    // Closes the innermost control structure. An `if` opened by `else if` shares its `end` with
+    // the enclosing `if`, so both are closed together.
+    fn close_frames(
         &mut self,
-        allow_else_if: bool,
-        context: &str,
-    ) -> Result<(Pos, PipeNode, ListNode, Option<ListNode>), ParseError> {
-        let vars_len = self
-            .tree
-            .as_ref()
-            .map(|t| t.vars.len())
-            .ok_or(ParseError::NoTree)?;
-        let pipe = self.pipeline(context)?;
-        let (list, next) = self.item_list()?;
-        let else_list = match *next.typ() {
-            NodeType::End => None,
-            NodeType::Else => {
-                if allow_else_if && self.peek_must("else if")?.typ == ItemType::ItemIf {
-                    self.next_must("else if")?;
-                    let mut else_list = ListNode::new(self.tree_id, next.pos());
-                    else_list.append(self.if_control()?);
-                    Some(else_list)
-                } else {
-                    let (else_list, next) = self.item_list()?;
-                    if *next.typ() != NodeType::End {
-                        return self.error(&format!("expected end; found {}", next));
-                    }
-                    Some(else_list)
-                }
+        frames: &mut Vec<Frame>,
+        list: &mut ListNode,
+    ) -> Result<Nodes, ParseError> {
+        loop {
+            let frame = match frames.pop() {
+                Some(frame) => frame,
+                None => return Err(self.unexpected("{{end}}", "input")),
+            };
+            let body = std::mem::replace(list, frame.parent);
+            let (body, else_list) = match frame.list {
+                Some(body_before_else) => (body_before_else, Some(body)),
+                None => (body, None),
+            };
+            let node = self.close_control(frame.control, body, else_list)?;
+            if !frame.else_if {
+                return Ok(node);
             }
-            _ => return self.error(&format!("expected end; found {}", next)),
-        };
+            self.count_node()?;
+            list.append(node);
+        }
+    }
+
+    fn close_control(
+        &mut self,
+        control: Control,
+        list: ListNode,
+        else_list: Option<ListNode>,
+    ) -> Result<Nodes, ParseError> {
+        let Control { kind, pos, pipe } = control;
+        if let FrameKind::Block(name) = kind {
+            if let Some(tree) = self.tree.as_mut() {
+                tree.root = Some(Nodes::List(list));
+            }
+            self.stop_parse()?;
+            return Ok(Nodes::Template(TemplateNode::new(
+                self.tree_id,
+                pos,
+                PipeOrString::String(name),
+                Some(pipe),
+            )));
+        }
         if let Some(t) = self.tree.as_mut() {
-            t.pop_vars(vars_len);
+            t.vars.pop();
         }
-        Ok((pipe.pos(), pipe, list, else_list))
+        let tree_id = self.tree_id;
+        Ok(match kind {
+            FrameKind::If => Nodes::If(IfNode::new_if(tree_id, pos, pipe, list, else_list)),
+            FrameKind::Range => {
+                Nodes::Range(RangeNode::new_range(tree_id, pos, pipe, list, else_list))
+            }
+            _ => Nodes::With(WithNode::new_with(tree_id, pos, pipe, list, else_list)),
+        })
     }
 
-    fn if_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(true, "if")?;
-        Ok(Nodes::If(IfNode::new_if(
-            self.tree_id,
-            pos,
-            pipe,
-            list,
-            else_list,
-        )))
+    fn text_or_action(&mut self) -> Result<Step, ParseError> {
+        match self.next_non_space() {
+            Some(item) if item.typ == ItemType::ItemText => {
+                let mut text = item.val;
+                if self.after_block && self.options.trim_blocks {
+                    if text.starts_with("\r\n") {
+                        text.drain(..2);
+                    } else if text.starts_with('\n') {
+                        text.remove(0);
+                    }
+                }
+                self.after_block = false;
+                Ok(Step::Node(Nodes::Text(TextNode::new(
+                    self.tree_id,
+                    item.pos,
+                    text,
+                ))))
+            }
+            Some(ref item) if item.typ == ItemType::ItemLeftDelim => self.action(item.pos),
+            Some(ref item) => Err(self.unexpected(item, "input")),
+            _ => Err(self.unexpected_eof("input")),
+        }
     }
 
-    fn range_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "range")?;
-        Ok(Nodes::Range(RangeNode::new_range(
-            self.tree_id,
-            pos,
-            pipe,
-            list,
-            else_list,
-        )))
+    fn action(&mut self, start: Pos) -> Result<Step, ParseError> {
+        let token = self.next_non_space_must("action")?;
+        match token.typ {
+            ItemType::ItemBlock => return self.block_control().map(Step::Open),
+            ItemType::ItemElse => return self.else_control().map(Step::Else),
+            ItemType::ItemEnd => return self.end_control().map(Step::End),
+            ItemType::ItemIf => return self.control(FrameKind::If, "if").map(Step::Open),
+            ItemType::ItemRange => {
+                return self.control(FrameKind::Range, "range").map(Step::Open);
+            }
+            ItemType::ItemTemplate => return self.template_control().map(Step::Node),
+            ItemType::ItemWith => return self.control(FrameKind::With, "with").map(Step::Open),
+            _ => {}
+        }
+        let pos = token.pos;
+        self.backup(token);
+        let pipe = self.pipeline("command")?;
+        self.after_block = false;
+        let mut action = ActionNode::new(self.tree_id, pos, pipe);
+        action.span = start..self.end;
+        Ok(Step::Node(Nodes::Action(action)))
     }
 
-    fn with_control(&mut self) -> Result<Nodes, ParseError> {
-        let (pos, pipe, list, else_list) = self.parse_control(false, "with")?;
-        Ok(Nodes::With(WithNode::new_with(
-            self.tree_id,
-            pos,
+    fn control(&mut self, kind: FrameKind, context: &str) -> Result<Control, ParseError> {
+        self.tree
+            .as_mut()
+            .map(|t| t.vars.push())
+            .ok_or(ParseError::NoTree)?;
+        let pipe = self.pipeline(context)?;
+        self.after_block = true;
+        Ok(Control {
+            kind,
+            pos: pipe.pos(),
             pipe,
-            list,
-            else_list,
-        )))
+        })
     }
 
     fn end_control(&mut self) -> Result<Nodes, ParseError> {
-        Ok(Nodes::End(EndNode::new(
-            self.tree_id,
-            self.expect(&ItemType::ItemRightDelim, "end")?.pos,
-        )))
+        let pos = self.expect(&ItemType::ItemRightDelim, "end")?.pos;
+        self.after_block = true;
+        Ok(Nodes::End(EndNode::new(self.tree_id, pos)))
     }
 
     fn else_control(&mut self) -> Result<Nodes, ParseError> {
@@ -434,32 +967,25 @@ impl Parser {
             return Ok(Nodes::Else(ElseNode::new(peek.pos, peek.line)));
         }
         let token = self.expect(&ItemType::ItemRightDelim, "else")?;
+        self.after_block = true;
         Ok(Nodes::Else(ElseNode::new(token.pos, token.line)))
     }
 
-    fn block_control(&mut self) -> Result<Nodes, ParseError> {
+    fn block_control(&mut self) -> Result<Control, ParseError> {
         let context = "block clause";
         let token = self.next_non_space_must(context)?;
         let name = self.parse_template_name(&token, context)?;
         let pipe = self.pipeline(context)?;
+        self.after_block = true;
 
         self.max_tree_id += 1;
         let tree_id = self.max_tree_id;
         self.start_parse(name.clone(), tree_id);
-        let (root, end) = self.item_list()?;
-        if let Some(tree) = self.tree.as_mut() {
-            tree.root = Some(Nodes::List(root));
-        }
-        if end.typ() != &NodeType::End {
-            return self.error(&format!("unexpected {} in {}", end, context));
-        }
-        self.stop_parse()?;
-        Ok(Nodes::Template(TemplateNode::new(
-            self.tree_id,
-            token.pos,
-            PipeOrString::String(name),
-            Some(pipe),
-        )))
+        Ok(Control {
+            kind: FrameKind::Block(name),
+            pos: token.pos,
+            pipe,
+        })
     }
 
     fn template_control(&mut self) -> Result<Nodes, ParseError> {
@@ -484,6 +1010,7 @@ impl Parser {
         } else {
             None
         };
+        self.after_block = false;
         Ok(Nodes::Template(TemplateNode::new(
             self.tree_id,
             token.pos,
@@ -494,6 +1021,7 @@ impl Parser {
 
     fn pipeline(&mut self, context: &str) -> Result<PipeNode, ParseError> {
         let mut decl = vec![];
+        let mut is_assign = false;
         let mut token = self.next_non_space_must("pipeline")?;
         let pos = token.pos;
         // TODO: test this hard!
@@ -503,7 +1031,7 @@ impl Parser {
                 let next = if token_after_var.typ == ItemType::ItemSpace {
                     let next = self.next_non_space_must("variable")?;
                     if next.typ != ItemType::ItemColonEquals
-                        && !(next.typ == ItemType::ItemChar && next.val == ",")
+                        && !(next.typ == ItemType::ItemChar && (next.val == "," || next.val == "="))
                     {
                         self.backup3(token, token_after_var, next);
                         break;
@@ -513,17 +1041,19 @@ impl Parser {
                     token_after_var
                 };
                 if next.typ == ItemType::ItemColonEquals
-                    || (next.typ == ItemType::ItemChar && next.val == ",")
+                    || (next.typ == ItemType::ItemChar && (next.val == "," || next.val == "="))
                 {
-                    let variable = VariableNode::new(self.tree_id, token.pos, &token.val);
-                    self.add_var(token.val.clone())?;
-                    decl.push(variable);
+                    is_assign = next.typ == ItemType::ItemChar && next.val == "=";
+                    decl.push(VariableNode::new(self.tree_id, token.pos, &token.val));
                     if next.typ == ItemType::ItemChar && next.val == "," {
                         if context == "range" && decl.len() < 2 {
                             token = self.next_non_space_must("variable")?;
                             continue;
                         }
-                        return self.error(&format!("to many decalarations in {}", context));
+                        return Err(ParseError::TooManyDeclarations {
+                            ctx: self.ctx(),
+                            context: context.to_owned(),
+                        });
                     }
                 } else {
                     self.backup2(token, next);
@@ -533,7 +1063,16 @@ impl Parser {
         } else {
             self.backup(token);
         }
+        for variable in &decl {
+            let name = &variable.ident[0];
+            if is_assign {
+                self.use_var(self.tree_id, variable.pos(), name)?;
+            } else {
+                self.add_var(name.clone())?;
+            }
+        }
         let mut pipe = PipeNode::new(self.tree_id, pos, decl);
+        pipe.is_assign = is_assign;
         let mut token = self.next_non_space_must("pipeline")?;
         loop {
             match token.typ {
@@ -558,6 +1097,11 @@ impl Parser {
                     self.backup(token);
                     pipe.append(self.command()?);
                 }
+                #[cfg(feature = "gtmpl_infix")]
+                ItemType::ItemOperator => {
+                    self.backup(token);
+                    pipe.append(self.command()?);
+                }
                 _ => return Err(self.unexpected(&token, context)),
             }
             token = self.next_non_space_must("pipeline")?;
@@ -566,7 +1110,10 @@ impl Parser {
 
     fn check_pipeline(&mut self, pipe: &mut PipeNode, context: &str) -> Result<(), ParseError> {
         if pipe.cmds.is_empty() {
-            return self.error(&format!("missing value for {}", context));
+            return Err(ParseError::MissingValue {
+                ctx: self.ctx(),
+                context: context.to_owned(),
+            });
         }
         for (i, c) in pipe.cmds.iter().enumerate().skip(1) {
             match c.args.first() {
@@ -576,24 +1123,138 @@ impl Parser {
                     | NodeType::Nil
                     | NodeType::Number
                     | NodeType::String => {
-                        return self.error(&format!(
-                            "non executable command in pipeline stage {}",
-                            i + 2
-                        ))
+                        return Err(ParseError::NonExecutableCommand {
+                            ctx: self.ctx(),
+                            stage: i + 1,
+                        })
                     }
                     _ => {}
                 },
                 None => {
-                    return self.error(&format!(
-                        "non executable command in pipeline stage {}",
-                        i + 2
-                    ))
+                    return Err(ParseError::NonExecutableCommand {
+                        ctx: self.ctx(),
+                        stage: i + 1,
+                    })
                 }
             }
         }
         Ok(())
     }
 
+    #[cfg(feature = "gtmpl_infix")]
+    fn command(&mut self) -> Result<CommandNode, ParseError> {
+        let cmd = self.infix_expr(0)?;
+        let token = self.next_non_space_must("command")?;
+        match token.typ {
+            ItemType::ItemError => return self.error(&token.val),
+            ItemType::ItemRightDelim | ItemType::ItemRightParen => self.backup(token),
+            ItemType::ItemPipe => {}
+            _ => return Err(self.unexpected(&token, "operand")),
+        }
+        Ok(cmd)
+    }
+
+    // Parses operands joined by binary operators, binding tighter than `min_prec`. Operators are
+    // translated to calls of the corresponding functions, e.g. `a > b` to `gt a b`.
+    #[cfg(feature = "gtmpl_infix")]
+    fn infix_expr(&mut self, min_prec: u8) -> Result<CommandNode, ParseError> {
+        let mut lhs = self.infix_unary()?;
+        loop {
+            let token = self.next_non_space_must("command")?;
+            let prec = match (&token.typ, token.val.as_str()) {
+                (ItemType::ItemOperator, "||") => 1,
+                (ItemType::ItemOperator, "&&") => 2,
+                (ItemType::ItemOperator, "==" | "!=" | "<" | "<=" | ">" | ">=") => 3,
+                (ItemType::ItemOperator, "+" | "-") => 4,
+                (ItemType::ItemOperator, "*" | "/") => 5,
+                _ => 0,
+            };
+            if prec == 0 || prec < min_prec {
+                self.backup(token);
+                return Ok(lhs);
+            }
+            let rhs = self.infix_expr(prec + 1)?;
+            lhs = self.infix_call(&token, vec![lhs, rhs])?;
+        }
+    }
+
+    #[cfg(feature = "gtmpl_infix")]
+    fn infix_unary(&mut self) -> Result<CommandNode, ParseError> {
+        let token = self.next_non_space_must("operand")?;
+        if token.typ == ItemType::ItemOperator && token.val == "!" {
+            let operand = self.infix_unary()?;
+            return self.infix_call(&token, vec![operand]);
+        }
+        self.backup(token);
+        let mut cmd = CommandNode::new(self.tree_id, self.peek_non_space_must("command")?.pos);
+        loop {
+            match self.peek_non_space_must("operand")?.typ {
+                ItemType::ItemOperator
+                | ItemType::ItemPipe
+                | ItemType::ItemRightDelim
+                | ItemType::ItemRightParen => break,
+                _ => {}
+            }
+            match self.operand()? {
+                Some(operand) => cmd.append(operand),
+                None => {
+                    let token = self.next_non_space_must("operand")?;
+                    return Err(self.unexpected(&token, "operand"));
+                }
+            }
+        }
+        if cmd.args.is_empty() {
+            return Err(ParseError::MissingValue {
+                ctx: self.ctx(),
+                context: String::from("operand"),
+            });
+        }
+        Ok(cmd)
+    }
+
+    #[cfg(feature = "gtmpl_infix")]
+    fn infix_call(&mut self, op: &Item, args: Vec<CommandNode>) -> Result<CommandNode, ParseError> {
+        let name = match op.val.as_str() {
+            "||" => "or",
+            "&&" => "and",
+            "==" => "eq",
+            "!=" => "ne",
+            "<" => "lt",
+            "<=" => "le",
+            ">" => "gt",
+            ">=" => "ge",
+            "+" => "add",
+            "-" => "sub",
+            "*" => "mul",
+            "/" => "div",
+            "!" => "not",
+            _ => return Err(self.unexpected(op, "operand")),
+        };
+        if !self.has_func(name) {
+            return Err(ParseError::UndefinedFunction {
+                ctx: self.ctx(),
+                name: name.to_owned(),
+            });
+        }
+        let mut ident = IdentifierNode::new(name.to_owned());
+        ident.set_pos(op.pos);
+        ident.set_tree(self.tree_id);
+        let mut call = CommandNode::new(self.tree_id, op.pos);
+        call.append(Nodes::Identifier(ident));
+        for mut cmd in args {
+            let arg = if cmd.args.len() == 1 {
+                cmd.args.remove(0)
+            } else {
+                let mut pipe = PipeNode::new(self.tree_id, cmd.pos(), vec![]);
+                pipe.append(cmd);
+                Nodes::Pipe(pipe)
+            };
+            call.append(arg);
+        }
+        Ok(call)
+    }
+
+    #[cfg(not(feature = "gtmpl_infix"))]
     fn command(&mut self) -> Result<CommandNode, ParseError> {
         let mut cmd = CommandNode::new(self.tree_id, self.peek_non_space_must("command")?.pos);
         loop {
@@ -607,12 +1268,12 @@ impl Parser {
                 ItemType::ItemError => return self.error(&token.val),
                 ItemType::ItemRightDelim | ItemType::ItemRightParen => self.backup(token),
                 ItemType::ItemPipe => {}
-                _ => return self.error(&format!("unexpected {} in operand", token)),
+                _ => return Err(self.unexpected(&token, "operand")),
             };
             break;
         }
         if cmd.args.is_empty() {
-            return self.error("empty command");
+            return Err(ParseError::EmptyCommand { ctx: self.ctx() });
         }
         Ok(cmd)
     }
@@ -674,7 +1335,10 @@ impl Parser {
             ItemType::ItemError => return self.error(&token.val),
             ItemType::ItemIdentifier => {
                 if !self.has_func(&token.val) {
-                    return self.error(&format!("function {} not defined", token.val));
+                    return Err(ParseError::UndefinedFunction {
+                        ctx: self.ctx(),
+                        name: token.val,
+                    });
                 }
                 let mut node = IdentifierNode::new(token.val);
                 node.set_pos(token.pos);
@@ -695,16 +1359,30 @@ impl Parser {
                 Nodes::Bool(BoolNode::new(self.tree_id, token.pos, token.val == "true"))
             }
             ItemType::ItemCharConstant | ItemType::ItemNumber => {
-                match NumberNode::new(self.tree_id, token.pos, token.val, &token.typ) {
+                match NumberNode::new(self.tree_id, token.pos, token.val.clone(), &token.typ) {
                     Ok(n) => Nodes::Number(n),
-                    Err(e) => return self.error(&e.to_string()),
+                    Err(source) => {
+                        return Err(ParseError::InvalidNumber {
+                            ctx: self.ctx(),
+                            text: token.val,
+                            source,
+                        })
+                    }
                 }
             }
             ItemType::ItemLeftParen => {
+                if self.expr_depth >= self.options.max_expr_depth {
+                    return Err(ParseError::MaxExprDepthExceeded { ctx: self.ctx() });
+                }
+                self.expr_depth += 1;
                 let pipe = self.pipeline("parenthesized pipeline")?;
+                self.expr_depth -= 1;
                 let next = self.next_must("parenthesized pipeline")?;
                 if next.typ != ItemType::ItemRightParen {
-                    return self.error(&format!("unclosed right paren: unexpected {}", next));
+                    return Err(ParseError::UnclosedParen {
+                        ctx: self.ctx(),
+                        found: next.to_string(),
+                    });
                 }
                 Nodes::Pipe(pipe)
             }
@@ -712,7 +1390,10 @@ impl Parser {
                 if let Some(s) = unquote_str(&token.val) {
                     Nodes::String(StringNode::new(self.tree_id, token.pos, token.val, s))
                 } else {
-                    return self.error(&format!("unable to unqote string: {}", token.val));
+                    return Err(ParseError::InvalidString {
+                        ctx: self.ctx(),
+                        text: token.val,
+                    });
                 }
             }
 
@@ -721,6 +1402,7 @@ impl Parser {
                 return Ok(None);
             }
         };
+        self.count_node()?;
         Ok(Some(node))
     }
 
@@ -730,13 +1412,12 @@ impl Parser {
         }
         self.tree
             .as_ref()
-            .and_then(|t| {
-                t.vars
-                    .iter()
-                    .find(|&v| v == name)
-                    .map(|_| VariableNode::new(tree_id, pos, name))
+            .filter(|t| t.vars.is_declared(name))
+            .map(|_| VariableNode::new(tree_id, pos, name))
+            .ok_or_else(|| ParseError::UndefinedVariable {
+                ctx: self.ctx(),
+                name: name.to_owned(),
             })
-            .ok_or_else(|| self.error_msg(&format!("undefined variable {}", name)))
     }
 
     fn parse_template_name(&self, token: &Item, context: &str) -> Result<String, ParseError> {
@@ -760,6 +1441,8 @@ impl Iterator for Parser {
         match item {
             Some(item) => {
                 self.line = item.line;
+                self.pos = item.pos;
+                self.end = item.pos + item.val.len();
                 Some(item)
             }
             _ => None,
@@ -771,7 +1454,11 @@ impl Iterator for Parser {
 mod tests_mocked {
     use super::*;
     use crate::lexer::ItemType;
-    use gtmpl_value::Value;
+    use gtmpl_value::{Func, FuncError, Value};
+
+    fn nop(_: &[Value]) -> Result<Value, FuncError> {
+        Ok(Value::Nil)
+    }
 
     /*
        ItemText
@@ -802,9 +1489,13 @@ mod tests_mocked {
         let lex = Lexer::new(s.to_owned());
         Parser {
             name: String::from("foo"),
-            funcs: funcs.iter().map(|&k| k.to_owned()).collect(),
+            funcs: funcs.iter().map(|&k| (k, nop as Func)).collect(),
+            async_funcs: HashSet::new(),
+            options: ParseOptions::default(),
             lex: Some(lex),
             line: 0,
+            pos: 0,
+            end: 0,
             token: VecDeque::new(),
             peek_count: 0,
             tree_set: HashMap::new(),
@@ -812,6 +1503,12 @@ mod tests_mocked {
             tree: None,
             tree_stack: VecDeque::new(),
             max_tree_id: 0,
+            after_block: false,
+            expr_depth: 0,
+            text_start: 0,
+            items: vec![],
+            defined: vec![],
+            nodes: 0,
         }
     }
 
@@ -881,7 +1578,14 @@ mod tests_mocked {
     #[test]
     fn test_display() {
         let raw = r#"{{if .}}2000{{else}} 3000 {{end}}"#;
-        let mut ts = parse(String::default(), String::from(raw), HashSet::default()).unwrap();
+        let mut ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         let tree = ts.get_mut("").unwrap();
         if let Some(ref root) = tree.root {
             assert_eq!(raw, format!("{}", root))
@@ -896,7 +1600,7 @@ mod tests_mocked {
         let r = p.parse_tree();
         assert_eq!(
             r.err().unwrap().to_string(),
-            "template: foo:2:function eq not defined"
+            "template: foo:1:function eq not defined"
         );
         let funcs = &["eq"];
         let mut p = make_parser_with_funcs(r#"{{ if eq .foo "bar" }} 2000 {{ end }}"#, funcs);
@@ -956,4 +1660,294 @@ mod tests_mocked {
             panic!()
         }
     }
+
+    #[test]
+    fn test_trim_blocks() {
+        let raw = "{{if .}}\nfoo\n{{else}}\nbar\n{{end}}\n{{.}}\nbaz\n";
+        let options = ParseOptions {
+            trim_blocks: true,
+            keep_trailing_newline: false,
+            ..Default::default()
+        };
+        let ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            options,
+        )
+        .unwrap();
+        let root = ts.get("").and_then(|t| t.root.as_ref()).unwrap();
+        assert_eq!(
+            root.to_string(),
+            "{{if .}}foo\n{{else}}bar\n{{end}}{{.}}\nbaz"
+        );
+
+        let ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        let root = ts.get("").and_then(|t| t.root.as_ref()).unwrap();
+        assert_eq!(root.to_string(), raw);
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 5_000;
+        let raw = format!(
+            "{}x{}",
+            "{{if true}}".repeat(depth),
+            "{{end}}".repeat(depth)
+        );
+        let ts = parse(
+            String::default(),
+            raw.clone(),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        );
+        assert!(ts.is_ok());
+
+        let options = ParseOptions {
+            max_depth: 100,
+            max_expr_depth: 10,
+            ..Default::default()
+        };
+        let ts = parse(
+            String::default(),
+            raw,
+            &FuncMap::new(),
+            HashSet::default(),
+            options.clone(),
+        );
+        assert!(ts
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with("max nesting depth exceeded"));
+
+        let raw = format!("{{{{{}true{}}}}}", "(".repeat(11), ")".repeat(11));
+        let ts = parse(
+            String::default(),
+            raw,
+            &FuncMap::new(),
+            HashSet::default(),
+            options,
+        );
+        assert!(ts
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with("max expression depth exceeded"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let parse_with = |text: &str, options: ParseOptions| {
+            parse(
+                String::default(),
+                String::from(text),
+                &FuncMap::new(),
+                HashSet::default(),
+                options,
+            )
+            .err()
+        };
+        let text = r#"{{ define "a" }}a{{ end }}{{ define "b" }}{{ . }}{{ end }}b {{ if . }}{{ . }}{{ end }}"#;
+        let limits = |max_source_size, max_trees, max_nodes| ParseOptions {
+            max_source_size,
+            max_trees,
+            max_nodes,
+            ..Default::default()
+        };
+        assert!(parse_with(text, limits(text.len(), 3, 8)).is_none());
+        assert!(matches!(
+            parse_with(text, limits(text.len() - 1, 3, 8)),
+            Some(ParseError::SourceTooLarge { .. })
+        ));
+        assert!(matches!(
+            parse_with(text, limits(text.len(), 2, 8)),
+            Some(ParseError::TooManyTrees { max: 2, .. })
+        ));
+        assert!(matches!(
+            parse_with(text, limits(text.len(), 3, 7)),
+            Some(ParseError::TooManyNodes { max: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let raw = r#"{{if 1}}1{{else if 2}}2{{else if 3}}3{{else}}4{{end}}{{.}}"#;
+        let ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        let root = ts.get("").and_then(|t| t.root.as_ref()).unwrap();
+        assert_eq!(
+            root.to_string(),
+            "{{if 1}}1{{else}}{{if 2}}2{{else}}{{if 3}}3{{else}}4{{end}}{{end}}{{end}}{{.}}"
+        );
+
+        let raw = r#"{{if .a}}1{{else}}2{{else}}3{{end}}"#;
+        let ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        );
+        assert!(ts.is_err());
+
+        let raw = r#"{{range .a}}1{{else if .b}}2{{end}}"#;
+        let ts = parse(
+            String::default(),
+            String::from(raw),
+            &FuncMap::new(),
+            HashSet::default(),
+            ParseOptions::default(),
+        );
+        assert!(ts.is_err());
+    }
+
+    fn check_reparse(old: &str, edited: Range<usize>, with: &str, options: ParseOptions) -> bool {
+        let new = format!("{}{}{}", &old[..edited.start], with, &old[edited.end..]);
+        let parse_text = |text: &str| {
+            parse(
+                String::from("t"),
+                text.to_owned(),
+                &FuncMap::new(),
+                HashSet::default(),
+                options.clone(),
+            )
+            .unwrap()
+        };
+        let inc = match reparse(
+            &parse_text(old),
+            "t",
+            old,
+            &new,
+            edited,
+            &FuncMap::new(),
+            HashSet::default(),
+            options.clone(),
+        ) {
+            Some(inc) => inc,
+            None => return false,
+        };
+        let full = parse_text(&new);
+        let mut names: Vec<_> = inc.keys().collect();
+        names.sort();
+        let mut full_names: Vec<_> = full.keys().collect();
+        full_names.sort();
+        assert_eq!(names, full_names);
+        for name in names {
+            let (a, b) = (&inc[name], &full[name]);
+            assert_eq!(format!("{:?}", a.root), format!("{:?}", b.root));
+            assert_eq!(a.fields, b.fields);
+            assert_eq!(a.vars, b.vars);
+        }
+        true
+    }
+
+    #[test]
+    fn test_reparse() {
+        let opts = ParseOptions::default;
+        let text = r#"{{ define "a" }}{{ .x }}{{ end }}a {{ $v := .y }}b {{ if .z }}c{{ end }} d {{ $v }} e {{ .w }} f"#;
+        let span = |s: &str| text.find(s).unwrap()..text.find(s).unwrap() + s.len();
+
+        assert!(check_reparse(text, span(" d "), " dd ", opts()));
+        assert!(check_reparse(text, span(".w"), ".w.v", opts()));
+        assert!(check_reparse(text, span("c{{"), "{{ .c }}{{", opts()));
+        assert!(check_reparse(text, span(".x"), "$", opts()));
+        let trim = span("{{ .w").start + 2;
+        assert!(check_reparse(text, trim..trim, "-", opts()));
+        let define = span("{{ define").start..span("a {{").start;
+        assert!(check_reparse(text, define.clone(), "", opts()));
+        assert!(check_reparse(
+            text,
+            define,
+            r#"{{ define "b" }}{{ end }}"#,
+            opts()
+        ));
+        let options = ParseOptions {
+            keep_trailing_newline: false,
+            ..Default::default()
+        };
+        assert!(check_reparse(text, span(" f"), " f\n", options));
+
+        // Changes to variables used later and unbalanced control structures need a full parse.
+        assert!(!check_reparse(text, span("$v :="), "$u :=", opts()));
+        assert!(!check_reparse(text, span(" d "), " {{ if .u }}", opts()));
+    }
+
+    #[test]
+    fn test_variable_scopes() {
+        let parse_text = |text: &str| {
+            parse(
+                String::from("t"),
+                String::from(text),
+                &FuncMap::new(),
+                HashSet::default(),
+                ParseOptions::default(),
+            )
+        };
+        assert!(parse_text("{{ $x := 1 }}{{ $x := 2 }}{{ $x = 3 }}{{ $x }}").is_ok());
+        assert!(parse_text("{{ if true }}{{ $x := 1 }}{{ end }}{{ $x }}").is_err());
+        assert!(parse_text("{{ range $i, $e := . }}{{ end }}{{ $e }}").is_err());
+        assert!(parse_text("{{ with $x := . }}{{ else }}{{ $x }}{{ end }}").is_ok());
+        assert!(parse_text("{{ $x = 1 }}").is_err());
+        assert!(parse_text("{{ define \"a\" }}{{ $x := 1 }}{{ end }}{{ $x }}").is_err());
+        assert!(parse_text("{{ $ = 1 }}{{ $ }}").is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_variants() {
+        let parse_text = |text: &str| {
+            parse(
+                String::from("t"),
+                String::from(text),
+                &FuncMap::new(),
+                HashSet::default(),
+                ParseOptions::default(),
+            )
+            .err()
+            .unwrap()
+        };
+        assert!(matches!(
+            parse_text("{{ $x }}"),
+            ParseError::UndefinedVariable { ref name, .. } if name == "$x"
+        ));
+        assert!(matches!(
+            parse_text("{{ if . }}"),
+            ParseError::UnexpectedEof { .. }
+        ));
+        assert!(matches!(
+            parse_text("{{ define \"a\" . }}{{ end }}"),
+            ParseError::UnexpectedToken { expected: Some(_), ref found, .. } if found == "."
+        ));
+        assert!(matches!(
+            parse_text("{{ . | 1 }}"),
+            ParseError::NonExecutableCommand { stage: 2, .. }
+        ));
+        assert!(matches!(
+            parse_text("{{ () }}"),
+            ParseError::MissingValue { ref context, .. } if context == "parenthesized pipeline"
+        ));
+        assert!(matches!(
+            parse_text("{{ define \"a\" }}a{{ end }}{{ define \"a\" }}b{{ end }}"),
+            ParseError::MultipleDefinitions { ref name, .. } if name == "a"
+        ));
+        let err = parse_text("{{ define \"a\" }}\n{{ $x }}{{ end }}");
+        assert_eq!(err.code(), "gtmpl::parse::undefined_variable");
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.name.as_str(), ctx.line, ctx.pos), ("a", 2, 20));
+    }
 }
@@ -1,318 +1,529 @@
 use std::char;
-use std::fmt;
+use std::convert::TryFrom;
+use std::fmt::Write;
 
-use crate::error::PrintError;
-use crate::printf::{params_to_chars, FormatParams};
+use crate::ordered;
+use crate::utils::quote;
 
 use gtmpl_value::Value;
 
+/// Flags, width and precision of a verb.
+#[derive(Clone, Default)]
+pub struct FormatParams {
+    pub sharp: bool,
+    pub zero: bool,
+    pub plus: bool,
+    pub minus: bool,
+    pub space: bool,
+    /// `#` of `%v`, printing values in Go syntax.
+    pub sharp_v: bool,
+    /// `+` of `%v`, which only changes the format of structs in Go.
+    pub plus_v: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+impl FormatParams {
+    // The same parameters, but padding with spaces.
+    fn no_zero(&self) -> FormatParams {
+        FormatParams {
+            zero: false,
+            ..self.clone()
+        }
+    }
+}
+
 /// Print a verb like golang's printf.
-pub fn print(p: &FormatParams, typ: char, val: &Value) -> Result<String, PrintError> {
+///
+/// Options, including nested ones, hold either nil or their value once converted to a `Value`,
+/// so they print like pointers which Go's templates dereference: a value prints like the value
+/// and nil like a nil `interface {}`, e.g. `<nil>` for `%v` and `%!d(<nil>)` for `%d`.
+pub fn print(buf: &mut String, p: &FormatParams, verb: char, val: &Value) {
     match *val {
-        Value::Number(ref n) if n.as_u64().is_some() => {
-            let u = n.as_u64().unwrap();
-            Ok(match typ {
-                'b' => printf_b(p, u),
-                'd' | 'v' => printf_generic(p, u),
-                'o' => printf_o(p, u),
-                'c' => {
-                    let c = char::from_u32(u as u32).ok_or(PrintError::NotAValidChar(u as i128))?;
-                    printf_generic(p, c)
-                }
-                'q' => {
-                    let c = char::from_u32(u as u32).ok_or(PrintError::NotAValidChar(u as i128))?;
-                    printf_generic(p, format!("'{}'", escape_char(c)))
+        Value::Nil | Value::NoValue => match verb {
+            'T' | 'v' => pad(buf, p, "<nil>"),
+            _ => bad_verb(buf, p, verb, val),
+        },
+        _ if verb == 'T' => fmt_s(buf, p, &type_name(val)),
+        Value::Bool(b) => match verb {
+            't' | 'v' => pad(buf, p, if b { "true" } else { "false" }),
+            _ => bad_verb(buf, p, verb, val),
+        },
+        Value::Number(ref n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, _) => fmt_integer(buf, p, verb, i.unsigned_abs(), i < 0, val),
+            (_, Some(u), _) => fmt_integer(buf, p, verb, u, false, val),
+            (_, _, Some(f)) => fmt_float(buf, p, verb, f, val),
+            _ => bad_verb(buf, p, verb, val),
+        },
+        Value::String(ref s) => fmt_string(buf, p, verb, s, val),
+        Value::Bytes(ref b) => fmt_bytes(buf, p, verb, b, "[]byte"),
+        Value::Array(ref a) => {
+            if p.sharp_v {
+                buf.push_str(&type_name(val));
+                buf.push('{');
+            } else {
+                buf.push('[');
+            }
+            for (i, v) in a.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(if p.sharp_v { ", " } else { " " });
                 }
-                'x' => printf_x(p, u),
-                'X' => printf_xx(p, u),
-                'U' => printf_generic(p, format!("U+{:X}", u)),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-            })
+                print_element(buf, p, verb, v);
+            }
+            buf.push(if p.sharp_v { '}' } else { ']' });
         }
-        Value::Number(ref n) if n.as_i64().is_some() => {
-            let i = n.as_i64().unwrap();
-            Ok(match typ {
-                'b' => printf_b(p, i),
-                'd' => printf_generic(p, i),
-                'o' => printf_o(p, i),
-                'c' => {
-                    let c = char::from_u32(i as u32).ok_or(PrintError::NotAValidChar(i as i128))?;
-                    printf_generic(p, c)
-                }
-                'q' => {
-                    let c = char::from_u32(i as u32).ok_or(PrintError::NotAValidChar(i as i128))?;
-                    printf_generic(p, format!("'{}'", escape_char(c)))
+        // Objects are structs in Go, but their fields are named like with `%+v` since their order
+        // is unknown.
+        Value::Map(_) | Value::OrderedMap(_) | Value::Object(_) => {
+            let object = matches!(*val, Value::Object(_));
+            if p.sharp_v {
+                buf.push_str(&type_name(val));
+                buf.push('{');
+            } else if object {
+                buf.push('{');
+            } else {
+                buf.push_str("map[");
+            }
+            let entries = ordered::entries(val, true).unwrap_or_default();
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(if p.sharp_v { ", " } else { " " });
                 }
-                'x' => printf_x(p, i),
-                'X' => printf_xx(p, i),
-                'U' => printf_generic(p, format!("U+{:X}", i)),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-            })
+                fmt_string(buf, p, verb, k, &Value::from(k.as_str()));
+                buf.push(':');
+                print_element(buf, p, verb, v);
+            }
+            buf.push(if p.sharp_v || object { '}' } else { ']' });
         }
-        Value::Number(ref n) if n.as_f64().is_some() => {
-            let f = n.as_f64().unwrap();
-            Ok(match typ {
-                'e' => printf_e(p, f),
-                'E' => printf_ee(p, f),
-                'f' | 'F' => printf_generic(p, f),
-                _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-            })
+        Value::Function(_) => match verb {
+            'v' => pad(buf, p, &val.to_string()),
+            _ => bad_verb(buf, p, verb, val),
+        },
+    }
+}
+
+/// The name of the Go type a value corresponds to, as printed by `%T`.
+pub fn type_name(val: &Value) -> String {
+    match *val {
+        Value::Nil | Value::NoValue => "<nil>",
+        Value::Bool(_) => "bool",
+        Value::Number(ref n) if n.as_i64().is_some() => "int",
+        Value::Number(ref n) if n.as_u64().is_some() => "uint64",
+        Value::Number(_) => "float64",
+        Value::String(_) => "string",
+        Value::Array(_) => "[]interface {}",
+        Value::Bytes(_) => "[]uint8",
+        Value::Map(_) | Value::OrderedMap(_) | Value::Object(_) => "map[string]interface {}",
+        Value::Function(_) => "func",
+    }
+    .to_owned()
+}
+
+// Prints an element of an array or map. Nil elements are printed regardless of the verb, like
+// nil interfaces in Go.
+fn print_element(buf: &mut String, p: &FormatParams, verb: char, val: &Value) {
+    match *val {
+        Value::Nil | Value::NoValue if p.sharp_v => buf.push_str("interface {}(nil)"),
+        Value::Nil | Value::NoValue => buf.push_str("<nil>"),
+        // Go names the type of nested byte slices by reflection.
+        Value::Bytes(ref b) => fmt_bytes(buf, p, verb, b, "[]uint8"),
+        _ => print(buf, p, verb, val),
+    }
+}
+
+// Prints a verb that does not apply to the value, like `%!d(string=foo)`.
+fn bad_verb(buf: &mut String, p: &FormatParams, verb: char, val: &Value) {
+    buf.push_str("%!");
+    buf.push(verb);
+    buf.push('(');
+    match *val {
+        Value::Nil | Value::NoValue => buf.push_str("<nil>"),
+        _ => {
+            buf.push_str(&type_name(val));
+            buf.push('=');
+            print(buf, p, 'v', val);
         }
-        Value::Bool(ref b) => Ok(match typ {
-            'v' | 't' => printf_generic(p, b),
-            _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-        }),
-        Value::String(ref s) => Ok(match typ {
-            's' | 'v' => printf_generic(p, s),
-            'x' => printf_x(p, Hexer::from(s.as_str())),
-            'X' => printf_xx(p, Hexer::from(s.as_str())),
-            'q' => {
-                let s = s
-                    .chars()
-                    .map(|c| c.escape_default().to_string())
-                    .collect::<String>();
-                printf_generic(p, s)
+    }
+    buf.push(')');
+}
+
+// Prints a byte slice like Go: as numbers for `%v` and `%d`, like a string for the string
+// verbs, and with each byte as a `uint8` otherwise. `%#v` names the type `type_name`.
+fn fmt_bytes(buf: &mut String, p: &FormatParams, verb: char, bytes: &[u8], type_name: &str) {
+    match verb {
+        'v' if p.sharp_v => {
+            buf.push_str(type_name);
+            buf.push('{');
+            let hex = FormatParams {
+                sharp: true,
+                sharp_v: false,
+                ..p.clone()
+            };
+            for (i, &b) in bytes.iter().enumerate() {
+                if i > 0 {
+                    buf.push_str(", ");
+                }
+                fmt_int(buf, &hex, 'x', u64::from(b), false, 16, false);
             }
-            _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-        }),
-        Value::Array(ref a) => Ok(match typ {
-            'v' => {
-                let values: Vec<String> = a.iter().map(|v| printf_generic(p, v)).collect();
-                let res = format!("[{}]", values.join(" "));
-                printf_generic(p, res)
+            buf.push('}');
+        }
+        'v' | 'd' => {
+            buf.push('[');
+            for (i, &b) in bytes.iter().enumerate() {
+                if i > 0 {
+                    buf.push(' ');
+                }
+                fmt_int(buf, p, 'd', u64::from(b), false, 10, false);
             }
-            _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-        }),
-        Value::Map(ref m) => Ok(match typ {
-            'v' => {
-                let values: Vec<String> = m
-                    .iter()
-                    .map(|(k, v)| {
-                        let v_str = printf_generic(p, v);
-                        format!("{}:{}", k, v_str)
-                    })
-                    .collect();
-                let res = format!("map[{}]", values.join(" "));
-                printf_generic(p, res)
+            buf.push(']');
+        }
+        's' | 'q' => fmt_string(buf, p, verb, &String::from_utf8_lossy(bytes), &Value::Nil),
+        'x' => fmt_sx(buf, p, bytes, false),
+        'X' => fmt_sx(buf, p, bytes, true),
+        'T' => fmt_s(buf, p, "[]uint8"),
+        _ => {
+            buf.push('[');
+            for (i, &b) in bytes.iter().enumerate() {
+                if i > 0 {
+                    buf.push(' ');
+                }
+                write!(buf, "%!{}(uint8={})", verb, b).unwrap();
             }
-            _ => return Err(PrintError::UnableToFormat(val.clone(), typ)),
-        }),
-        _ => Err(PrintError::UnableToFormat(val.clone(), typ)),
+            buf.push(']');
+        }
     }
 }
 
-fn printf_b<B: fmt::Binary>(p: &FormatParams, u: B) -> String {
-    match params_to_chars(p) {
-        ('#', '_', '+', '_', _) => format!("{:+#width$b}", u, width = p.width),
-        ('_', '_', '+', '_', _) => format!("{:+width$b}", u, width = p.width),
-        ('#', '_', '_', '_', _) => format!("{:#width$b}", u, width = p.width),
-        ('#', '0', '+', '_', _) => format!("{:+#0width$b}", u, width = p.width),
-        ('_', '0', '+', '_', _) => format!("{:+0width$b}", u, width = p.width),
-        ('#', '0', '_', '_', _) => format!("{:#0width$b}", u, width = p.width),
-        ('#', '_', '+', '-', _) => format!("{:<+#width$b}", u, width = p.width),
-        ('_', '_', '+', '-', _) => format!("{:<+width$b}", u, width = p.width),
-        ('#', '_', '_', '-', _) => format!("{:<#width$b}", u, width = p.width),
-        ('#', '0', '+', '-', _) => format!("{:<+#0width$b}", u, width = p.width),
-        ('_', '0', '+', '-', _) => format!("{:<+0width$b}", u, width = p.width),
-        ('#', '0', '_', '-', _) => format!("{:<#0width$b}", u, width = p.width),
-        (_, _, _, _, _) => format!("{:width$b}", u, width = p.width),
+fn fmt_integer(
+    buf: &mut String,
+    p: &FormatParams,
+    verb: char,
+    u: u64,
+    negative: bool,
+    val: &Value,
+) {
+    // Go converts negative numbers to their two's complement for characters.
+    let bits = if negative { u.wrapping_neg() } else { u };
+    match verb {
+        'v' | 'd' => fmt_int(buf, p, verb, u, negative, 10, false),
+        'b' => fmt_int(buf, p, verb, u, negative, 2, false),
+        'o' | 'O' => fmt_int(buf, p, verb, u, negative, 8, false),
+        'x' => fmt_int(buf, p, verb, u, negative, 16, false),
+        'X' => fmt_int(buf, p, verb, u, negative, 16, true),
+        'c' => pad(buf, p, &to_char(bits).to_string()),
+        'q' => pad(buf, p, &quote(&to_char(bits).to_string(), '\'', p.plus)),
+        // Values store integral floats as integers, so float verbs accept integers.
+        'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+            let f = u as f64;
+            fmt_float(buf, p, verb, if negative { -f } else { f }, val)
+        }
+        'U' => {
+            let prec = p.precision.filter(|&prec| prec > 4).unwrap_or(4);
+            let mut s = format!("U+{:0prec$X}", bits, prec = prec);
+            if p.sharp {
+                if let Some(c) = u32::try_from(bits).ok().and_then(char::from_u32) {
+                    if !c.is_control() {
+                        write!(s, " '{}'", c).unwrap();
+                    }
+                }
+            }
+            pad(buf, &p.no_zero(), &s);
+        }
+        _ => bad_verb(buf, p, verb, val),
     }
 }
 
-fn printf_o<B: fmt::Octal>(p: &FormatParams, u: B) -> String {
-    match params_to_chars(p) {
-        ('#', '_', '+', '_', _) => format!("{:+#width$o}", u, width = p.width),
-        ('_', '_', '+', '_', _) => format!("{:+width$o}", u, width = p.width),
-        ('#', '_', '_', '_', _) => format!("{:#width$o}", u, width = p.width),
-        ('#', '0', '+', '_', _) => format!("{:+#0width$o}", u, width = p.width),
-        ('_', '0', '+', '_', _) => format!("{:+0width$o}", u, width = p.width),
-        ('#', '0', '_', '_', _) => format!("{:#0width$o}", u, width = p.width),
-        ('#', '_', '+', '-', _) => format!("{:<+#width$o}", u, width = p.width),
-        ('_', '_', '+', '-', _) => format!("{:<+width$o}", u, width = p.width),
-        ('#', '_', '_', '-', _) => format!("{:<#width$o}", u, width = p.width),
-        ('#', '0', '+', '-', _) => format!("{:<+#0width$o}", u, width = p.width),
-        ('_', '0', '+', '-', _) => format!("{:<+0width$o}", u, width = p.width),
-        ('#', '0', '_', '-', _) => format!("{:<#0width$o}", u, width = p.width),
-        (_, _, _, _, _) => format!("{:width$o}", u, width = p.width),
-    }
+fn to_char(u: u64) -> char {
+    u32::try_from(u)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
 }
 
-fn printf_x<B: fmt::LowerHex>(p: &FormatParams, u: B) -> String {
-    match params_to_chars(p) {
-        ('#', '_', '+', '_', _) => format!("{:+#width$x}", u, width = p.width),
-        ('_', '_', '+', '_', _) => format!("{:+width$x}", u, width = p.width),
-        ('#', '_', '_', '_', _) => format!("{:#width$x}", u, width = p.width),
-        ('#', '0', '+', '_', _) => format!("{:+#0width$x}", u, width = p.width),
-        ('_', '0', '+', '_', _) => format!("{:+0width$x}", u, width = p.width),
-        ('#', '0', '_', '_', _) => format!("{:#0width$x}", u, width = p.width),
-        ('#', '_', '+', '-', _) => format!("{:<+#width$x}", u, width = p.width),
-        ('_', '_', '+', '-', _) => format!("{:<+width$x}", u, width = p.width),
-        ('#', '_', '_', '-', _) => format!("{:<#width$x}", u, width = p.width),
-        ('#', '0', '+', '-', _) => format!("{:<+#0width$x}", u, width = p.width),
-        ('_', '0', '+', '-', _) => format!("{:<+0width$x}", u, width = p.width),
-        ('#', '0', '_', '-', _) => format!("{:<#0width$x}", u, width = p.width),
-        (_, _, _, _, _) => format!("{:width$x}", u, width = p.width),
+fn fmt_int(
+    buf: &mut String,
+    p: &FormatParams,
+    verb: char,
+    u: u64,
+    negative: bool,
+    base: u32,
+    upper: bool,
+) {
+    // Two ways to ask for extra leading zero digits: %.3d or %03d. If both are specified the
+    // zero flag is ignored and padding with spaces is used instead.
+    let mut prec = 0;
+    if let Some(precision) = p.precision {
+        prec = precision;
+        // Precision of 0 and value of 0 means "print nothing" but padding.
+        if prec == 0 && u == 0 {
+            write_padding(buf, &p.no_zero(), p.width.unwrap_or(0));
+            return;
+        }
+    } else if let (true, false, Some(width)) = (p.zero, p.minus, p.width) {
+        prec = width;
+        if negative || p.plus || p.space {
+            // Leave room for the sign.
+            prec = prec.saturating_sub(1);
+        }
+    }
+
+    let digits = match (base, upper) {
+        (2, _) => format!("{:b}", u),
+        (8, _) => format!("{:o}", u),
+        (16, false) => format!("{:x}", u),
+        (16, true) => format!("{:X}", u),
+        _ => u.to_string(),
+    };
+    let mut s = String::with_capacity(prec.max(digits.len()) + 3);
+    if negative {
+        s.push('-');
+    } else if p.plus {
+        s.push('+');
+    } else if p.space {
+        s.push(' ');
+    }
+    if p.sharp {
+        match base {
+            2 => s.push_str("0b"),
+            8 if prec <= digits.len() && !digits.starts_with('0') => s.push('0'),
+            16 if upper => s.push_str("0X"),
+            16 => s.push_str("0x"),
+            _ => {}
+        }
     }
+    if verb == 'O' {
+        s.push_str("0o");
+    }
+    s.push_str(&"0".repeat(prec.saturating_sub(digits.len())));
+    s.push_str(&digits);
+    // Padding with zeros has already been handled like a precision.
+    pad(buf, &p.no_zero(), &s);
 }
 
-fn printf_xx<B: fmt::UpperHex>(p: &FormatParams, u: B) -> String {
-    match params_to_chars(p) {
-        ('#', '_', '+', '_', _) => format!("{:+#width$X}", u, width = p.width),
-        ('_', '_', '+', '_', _) => format!("{:+width$X}", u, width = p.width),
-        ('#', '_', '_', '_', _) => format!("{:#width$X}", u, width = p.width),
-        ('#', '0', '+', '_', _) => format!("{:+#0width$X}", u, width = p.width),
-        ('_', '0', '+', '_', _) => format!("{:+0width$X}", u, width = p.width),
-        ('#', '0', '_', '_', _) => format!("{:#0width$X}", u, width = p.width),
-        ('#', '_', '+', '-', _) => format!("{:<+#width$X}", u, width = p.width),
-        ('_', '_', '+', '-', _) => format!("{:<+width$X}", u, width = p.width),
-        ('#', '_', '_', '-', _) => format!("{:<#width$X}", u, width = p.width),
-        ('#', '0', '+', '-', _) => format!("{:<+#0width$X}", u, width = p.width),
-        ('_', '0', '+', '-', _) => format!("{:<+0width$X}", u, width = p.width),
-        ('#', '0', '_', '-', _) => format!("{:<#0width$X}", u, width = p.width),
-        (_, _, _, _, _) => format!("{:width$X}", u, width = p.width),
+fn fmt_float(buf: &mut String, p: &FormatParams, verb: char, f: f64, val: &Value) {
+    let (verb, prec) = match verb {
+        'v' => ('g', p.precision),
+        'g' | 'G' => (verb, p.precision),
+        'e' | 'E' | 'f' | 'F' => (verb, p.precision.or(Some(6))),
+        _ => return bad_verb(buf, p, verb, val),
+    };
+    let num = format_float(f, verb, prec);
+    let (mut sign, num) = match num.strip_prefix('-') {
+        Some(num) => ('-', num),
+        None => ('+', num.trim_start_matches('+')),
+    };
+    // The space flag means to add a leading space instead of a "+" sign unless the plus flag
+    // is used.
+    if p.space && sign == '+' && !p.plus {
+        sign = ' ';
+    }
+    // Infinities and NaN don't look like a number so they are not padded with zeros.
+    if num == "Inf" || num == "NaN" {
+        let s = if num == "NaN" && !p.space && !p.plus {
+            num.to_owned()
+        } else {
+            format!("{}{}", sign, num)
+        };
+        return pad(buf, &p.no_zero(), &s);
+    }
+    if p.plus || sign != '+' {
+        let len = num.len() + 1;
+        match p.width {
+            // The sign goes before the leading zeros.
+            Some(width) if p.zero && !p.minus && width > len => {
+                buf.push(sign);
+                write_padding(buf, p, width - len);
+                buf.push_str(num);
+            }
+            _ => pad(buf, p, &format!("{}{}", sign, num)),
+        }
+        return;
     }
+    pad(buf, p, num)
 }
 
-fn printf_generic<D: fmt::Display>(p: &FormatParams, c: D) -> String {
-    if let Some(pr) = p.precision {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$.pr$}", c, width = p.width, pr = pr),
-            ('_', '_', '+', '_', _) => format!("{:+width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '_', '_', '_', _) => format!("{:#width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$.pr$}", c, width = p.width, pr = pr),
-            ('_', '0', '+', '_', _) => format!("{:+0width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '0', '_', '_', _) => format!("{:#0width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$.pr$}", c, width = p.width, pr = pr),
-            ('_', '_', '+', '-', _) => format!("{:<+width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '_', '_', '-', _) => format!("{:<#width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$}", c, width = p.width, pr = pr),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$}", c, width = p.width, pr = pr),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$}", c, width = p.width, pr = pr),
-            (_, _, _, _, _) => format!("{:width$.pr$}", c, width = p.width, pr = pr),
+// Formats `f` like Go's `strconv.FormatFloat` for the formats `e`, `f` and `g`. A precision of
+// `None` means the shortest representation.
+fn format_float(f: f64, verb: char, prec: Option<usize>) -> String {
+    if f.is_nan() {
+        return "NaN".to_owned();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "+Inf" } else { "-Inf" }.to_owned();
+    }
+    let mut s = String::new();
+    if f.is_sign_negative() {
+        s.push('-');
+    }
+    let f = f.abs();
+    match verb {
+        'e' | 'E' => {
+            let prec = prec.unwrap_or(6);
+            let (digits, dp) = decimal(f, Some(prec + 1));
+            fmt_e(&mut s, &digits, dp, prec, verb);
         }
-    } else {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$}", c, width = p.width),
-            ('_', '_', '+', '_', _) => format!("{:+width$}", c, width = p.width),
-            ('#', '_', '_', '_', _) => format!("{:#width$}", c, width = p.width),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$}", c, width = p.width),
-            ('_', '0', '+', '_', _) => format!("{:+0width$}", c, width = p.width),
-            ('#', '0', '_', '_', _) => format!("{:#0width$}", c, width = p.width),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$}", c, width = p.width),
-            ('_', '_', '+', '-', _) => format!("{:<+width$}", c, width = p.width),
-            ('#', '_', '_', '-', _) => format!("{:<#width$}", c, width = p.width),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$}", c, width = p.width),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$}", c, width = p.width),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$}", c, width = p.width),
-            (_, _, _, _, _) => format!("{:width$}", c, width = p.width),
+        'f' | 'F' => write!(s, "{:.*}", prec.unwrap_or(6), f).unwrap(),
+        _ => {
+            let (digits, dp) = decimal(f, prec.map(|prec| prec.max(1)));
+            let nd = digits.len() as i32;
+            let shortest = prec.is_none();
+            let mut prec = prec.map_or(nd, |prec| prec.max(1) as i32);
+            // %e is used if the exponent from the conversion is less than -4 or greater than
+            // or equal to the precision. The shortest representation uses a precision of 6 for
+            // this decision.
+            let mut eprec = prec;
+            if eprec > nd && nd >= dp {
+                eprec = nd;
+            }
+            if shortest {
+                eprec = 6;
+            }
+            let exp = dp - 1;
+            if exp < -4 || exp >= eprec {
+                if prec > nd {
+                    prec = nd;
+                }
+                let e = if verb == 'G' { 'E' } else { 'e' };
+                fmt_e(&mut s, &digits, dp, (prec - 1).max(0) as usize, e);
+            } else {
+                if prec > dp {
+                    prec = nd;
+                }
+                fmt_f(&mut s, &digits, dp, (prec - dp).max(0) as usize);
+            }
         }
     }
+    s
 }
 
-fn printf_e<E: fmt::LowerExp>(p: &FormatParams, f: E) -> String {
-    if let Some(pr) = p.precision {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$.pr$e}", f, width = p.width, pr = pr),
-            ('_', '_', '+', '_', _) => format!("{:+width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '_', '_', '_', _) => format!("{:#width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$.pr$e}", f, width = p.width, pr = pr),
-            ('_', '0', '+', '_', _) => format!("{:+0width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '0', '_', '_', _) => format!("{:#0width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$.pr$e}", f, width = p.width, pr = pr),
-            ('_', '_', '+', '-', _) => format!("{:<+width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '_', '_', '-', _) => format!("{:<#width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$e}", f, width = p.width, pr = pr),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$e}", f, width = p.width, pr = pr),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$e}", f, width = p.width, pr = pr),
-            (_, _, _, _, _) => format!("{:width$.pr$e}", f, width = p.width, pr = pr),
-        }
-    } else {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$e}", f, width = p.width),
-            ('_', '_', '+', '_', _) => format!("{:+width$e}", f, width = p.width),
-            ('#', '_', '_', '_', _) => format!("{:#width$e}", f, width = p.width),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$e}", f, width = p.width),
-            ('_', '0', '+', '_', _) => format!("{:+0width$e}", f, width = p.width),
-            ('#', '0', '_', '_', _) => format!("{:#0width$e}", f, width = p.width),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$e}", f, width = p.width),
-            ('_', '_', '+', '-', _) => format!("{:<+width$e}", f, width = p.width),
-            ('#', '_', '_', '-', _) => format!("{:<#width$e}", f, width = p.width),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$e}", f, width = p.width),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$e}", f, width = p.width),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$e}", f, width = p.width),
-            (_, _, _, _, _) => format!("{:width$e}", f, width = p.width),
+// Returns the decimal digits of `f` rounded to `digits` significant digits, or the shortest
+// digits representing `f`, and the position of the decimal point. Zero has no digits.
+fn decimal(f: f64, digits: Option<usize>) -> (Vec<u8>, i32) {
+    if f == 0.0 {
+        return (vec![], 0);
+    }
+    let s = match digits {
+        Some(digits) => format!("{:.*e}", digits - 1, f),
+        None => format!("{:e}", f),
+    };
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let mut digits: Vec<u8> = mantissa.bytes().filter(|b| b.is_ascii_digit()).collect();
+    while digits.len() > 1 && digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+    (digits, exp + 1)
+}
+
+// %e: d.ddddde±dd
+fn fmt_e(s: &mut String, digits: &[u8], dp: i32, prec: usize, e: char) {
+    s.push(digits.first().map_or('0', |&d| d as char));
+    if prec > 0 {
+        s.push('.');
+        for i in 1..=prec {
+            s.push(digits.get(i).map_or('0', |&d| d as char));
         }
     }
+    s.push(e);
+    let exp = if digits.is_empty() { 0 } else { dp - 1 };
+    write!(s, "{}{:02}", if exp < 0 { '-' } else { '+' }, exp.abs()).unwrap();
 }
 
-fn printf_ee<E: fmt::UpperExp>(p: &FormatParams, f: E) -> String {
-    if let Some(pr) = p.precision {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$.pr$E}", f, width = p.width, pr = pr),
-            ('_', '_', '+', '_', _) => format!("{:+width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '_', '_', '_', _) => format!("{:#width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$.pr$E}", f, width = p.width, pr = pr),
-            ('_', '0', '+', '_', _) => format!("{:+0width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '0', '_', '_', _) => format!("{:#0width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$.pr$E}", f, width = p.width, pr = pr),
-            ('_', '_', '+', '-', _) => format!("{:<+width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '_', '_', '-', _) => format!("{:<#width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$.pr$E}", f, width = p.width, pr = pr),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$.pr$E}", f, width = p.width, pr = pr),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$.pr$E}", f, width = p.width, pr = pr),
-            (_, _, _, _, _) => format!("{:width$.pr$E}", f, width = p.width, pr = pr),
+// %f: ddddddd.ddddd
+fn fmt_f(s: &mut String, digits: &[u8], dp: i32, prec: usize) {
+    if dp > 0 {
+        for i in 0..dp as usize {
+            s.push(digits.get(i).map_or('0', |&d| d as char));
         }
     } else {
-        match params_to_chars(p) {
-            ('#', '_', '+', '_', _) => format!("{:+#width$E}", f, width = p.width),
-            ('_', '_', '+', '_', _) => format!("{:+width$E}", f, width = p.width),
-            ('#', '_', '_', '_', _) => format!("{:#width$E}", f, width = p.width),
-            ('#', '0', '+', '_', _) => format!("{:+#0width$E}", f, width = p.width),
-            ('_', '0', '+', '_', _) => format!("{:+0width$E}", f, width = p.width),
-            ('#', '0', '_', '_', _) => format!("{:#0width$E}", f, width = p.width),
-            ('#', '_', '+', '-', _) => format!("{:<+#width$E}", f, width = p.width),
-            ('_', '_', '+', '-', _) => format!("{:<+width$E}", f, width = p.width),
-            ('#', '_', '_', '-', _) => format!("{:<#width$E}", f, width = p.width),
-            ('#', '0', '+', '-', _) => format!("{:<+#0width$E}", f, width = p.width),
-            ('_', '0', '+', '-', _) => format!("{:<+0width$E}", f, width = p.width),
-            ('#', '0', '_', '-', _) => format!("{:<#0width$E}", f, width = p.width),
-            (_, _, _, _, _) => format!("{:width$E}", f, width = p.width),
+        s.push('0');
+    }
+    if prec > 0 {
+        s.push('.');
+        for i in 1..=prec as i32 {
+            let j = dp + i - 1;
+            let d = if j >= 0 { digits.get(j as usize) } else { None };
+            s.push(d.map_or('0', |&d| d as char));
         }
     }
 }
 
-fn escape_char(c: char) -> String {
-    let mut s = c.escape_default().to_string();
-    if s.starts_with(r"\u") {
-        s = s.replace("{", "").replace("}", "");
+fn fmt_string(buf: &mut String, p: &FormatParams, verb: char, s: &str, val: &Value) {
+    match verb {
+        'v' if p.sharp_v => fmt_q(buf, p, s),
+        'v' | 's' => fmt_s(buf, p, s),
+        'x' => fmt_sx(buf, p, s.as_bytes(), false),
+        'X' => fmt_sx(buf, p, s.as_bytes(), true),
+        'q' => fmt_q(buf, p, s),
+        _ => bad_verb(buf, p, verb, val),
     }
-    s
 }
 
-struct Hexer<'a> {
-    s: &'a str,
+// Truncates `s` to the precision, if present.
+fn truncate<'s>(p: &FormatParams, s: &'s str) -> &'s str {
+    match p.precision.and_then(|prec| s.char_indices().nth(prec)) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
 }
 
-impl<'a> From<&'a str> for Hexer<'a> {
-    fn from(s: &'a str) -> Self {
-        Hexer { s }
+fn fmt_s(buf: &mut String, p: &FormatParams, s: &str) {
+    pad(buf, p, truncate(p, s))
+}
+
+fn fmt_q(buf: &mut String, p: &FormatParams, s: &str) {
+    let s = truncate(p, s);
+    if p.sharp && can_backquote(s) {
+        pad(buf, p, &format!("`{}`", s))
+    } else {
+        pad(buf, p, &quote(s, '"', p.plus))
     }
 }
 
-impl<'a> fmt::UpperHex for Hexer<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for u in self.s.as_bytes() {
-            write!(f, "{:X}", u)?
+fn can_backquote(s: &str) -> bool {
+    s.chars()
+        .all(|c| c != '`' && c != '\u{feff}' && (c == '\t' || !c.is_control()))
+}
+
+// Hex encodes `bytes`, separated by spaces with the space flag.
+fn fmt_sx(buf: &mut String, p: &FormatParams, bytes: &[u8], upper: bool) {
+    let bytes = &bytes[..p.precision.unwrap_or(bytes.len()).min(bytes.len())];
+    let prefix = if upper { "0X" } else { "0x" };
+    let mut hex = String::with_capacity(bytes.len() * 5);
+    for (i, b) in bytes.iter().enumerate() {
+        if p.sharp && (i == 0 || p.space) {
+            hex.push_str(prefix);
+        }
+        if upper {
+            write!(hex, "{:02X}", b).unwrap();
+        } else {
+            write!(hex, "{:02x}", b).unwrap();
+        }
+        if p.space && i + 1 < bytes.len() {
+            hex.push(' ');
         }
-        Ok(())
     }
+    pad(buf, p, &hex)
 }
 
-impl<'a> fmt::LowerHex for Hexer<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for u in self.s.as_bytes() {
-            write!(f, "{:x}", u)?
+// Appends `s` padded to the width.
+fn pad(buf: &mut String, p: &FormatParams, s: &str) {
+    let len = s.chars().count();
+    match p.width {
+        Some(width) if width > len && p.minus => {
+            buf.push_str(s);
+            write_padding(buf, p, width - len);
+        }
+        Some(width) if width > len => {
+            write_padding(buf, p, width - len);
+            buf.push_str(s);
         }
-        Ok(())
+        _ => buf.push_str(s),
     }
 }
+
+fn write_padding(buf: &mut String, p: &FormatParams, n: usize) {
+    // Zero padding is allowed only to the left.
+    let c = if p.zero && !p.minus { "0" } else { " " };
+    buf.push_str(&c.repeat(n));
+}
@@ -1,327 +1,452 @@
-use std::char;
-
-use gtmpl_value::{FromValue, Value};
-
-use crate::error::PrintError;
-use crate::print_verb::print;
-
-pub fn sprintf(s: &str, args: &[Value]) -> Result<String, PrintError> {
-    let tokens = tokenize(s)?;
-    let mut fmt = String::new();
-    let mut i = 0;
-    let mut index = 0;
-    for t in tokens {
-        fmt.push_str(&s[i..t.start]);
-        let (s, idx) = process_verb(&s[t.start + 1..t.end], t.typ, args, index)?;
-        fmt.push_str(&s);
-        index = idx;
-        i = t.end + 1;
+use std::borrow::Cow;
+
+use gtmpl_value::Value;
+
+use crate::print_verb::{print, type_name, FormatParams};
+
+/// Formats `args` according to `format` like Go's `fmt.Sprintf`. Like in Go, problems with
+/// verbs or arguments are reported in the output, e.g. `%!d(string=foo)` or `%!s(MISSING)`.
+pub fn sprintf(format: &str, args: &[Value]) -> String {
+    let mut p = Printer {
+        buf: String::new(),
+        args,
+        arg_num: 0,
+        reordered: false,
+        good_arg_num: true,
+    };
+    p.do_printf(format);
+    p.buf
+}
+
+/// Formats the value of an action like Go's templates: missing and nil values as `<no value>`
+/// and everything else like `%v`, e.g. `map[a:1 b:2]`, `[1 2 3]` or `{name:x}` for objects.
+pub fn format_value(val: &Value) -> Cow<'_, str> {
+    match *val {
+        Value::NoValue | Value::Nil => Cow::Borrowed("<no value>"),
+        Value::String(ref s) => Cow::Borrowed(s),
+        _ => {
+            let mut buf = String::new();
+            print(&mut buf, &FormatParams::default(), 'v', val);
+            Cow::Owned(buf)
+        }
     }
-    fmt.push_str(&s[i..]);
-    Ok(fmt)
 }
 
-struct FormatArg {
-    pub start: usize,
-    pub end: usize,
-    pub typ: char,
+/// Formats `args` like Go's `fmt.Sprint`. Spaces are added between operands when neither is a
+/// string.
+pub fn sprint(args: &[Value]) -> String {
+    let mut buf = String::new();
+    let mut prev_string = false;
+    for (i, arg) in args.iter().enumerate() {
+        let is_string = matches!(*arg, Value::String(_));
+        if i > 0 && !is_string && !prev_string {
+            buf.push(' ');
+        }
+        print(&mut buf, &FormatParams::default(), 'v', arg);
+        prev_string = is_string;
+    }
+    buf
+}
+
+/// Formats `args` like Go's `fmt.Sprintln`. Spaces are always added between operands and a
+/// newline is appended.
+pub fn sprintln(args: &[Value]) -> String {
+    let mut buf = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            buf.push(' ');
+        }
+        print(&mut buf, &FormatParams::default(), 'v', arg);
+    }
+    buf.push('\n');
+    buf
 }
 
-static TYPS: &str = "vVtTbcdoqxXUeEfFgGsp";
-
-#[derive(Default)]
-pub struct FormatParams {
-    pub sharp: bool,
-    pub zero: bool,
-    pub plus: bool,
-    pub minus: bool,
-    pub space: bool,
-    pub width: usize,
-    pub precision: Option<usize>,
+struct Printer<'a> {
+    buf: String,
+    args: &'a [Value],
+    arg_num: usize,
+    // Whether an explicit argument index was used.
+    reordered: bool,
+    // Whether the last argument index was valid.
+    good_arg_num: bool,
 }
 
-fn process_verb(
-    s: &str,
-    typ: char,
-    args: &[Value],
-    mut index: usize,
-) -> Result<(String, usize), PrintError> {
-    let mut params = FormatParams::default();
-    let mut complex = false;
-    let mut pos = 0;
-    for (i, c) in s.chars().enumerate() {
-        match c {
-            '#' => params.sharp = true,
-            '0' => params.zero = true,
-            '+' => params.plus = true,
-            '-' => {
-                params.minus = true;
-                // Golang does not pad with zeros to the right.
-                params.zero = false;
+impl<'a> Printer<'a> {
+    fn do_printf(&mut self, format: &str) {
+        let bytes = format.as_bytes();
+        let end = format.len();
+        let mut i = 0;
+        while i < end {
+            self.good_arg_num = true;
+            let last = i;
+            while i < end && bytes[i] != b'%' {
+                i += 1;
             }
-            ' ' => params.space = true,
-            _ => {
-                pos = i;
-                complex = true;
+            self.buf.push_str(&format[last..i]);
+            if i >= end {
                 break;
             }
-        }
-    }
-    if complex {
-        let mut after_index = false;
-        let arg_num = parse_index(&s[pos..])?.map(|(i, till)| {
-            pos += till;
-            after_index = true;
-            index = i;
-            i
-        });
-        if s[pos..].starts_with('*') {
-            pos += 1;
-            let arg_num = arg_num.unwrap_or_else(|| {
-                let i = index;
-                index += 1;
-                i
-            });
-            if let Some(width) = args.get(arg_num).and_then(|v| i64::from_value(v)) {
-                if width < 0 {
-                    params.minus = true;
-                    // Golang does not pad with zeros to the right.
-                    params.zero = false;
+            // Skip the %.
+            i += 1;
+
+            let mut params = FormatParams::default();
+            while i < end {
+                match bytes[i] {
+                    b'#' => params.sharp = true,
+                    // Only allow zero padding to the left.
+                    b'0' => params.zero = !params.minus,
+                    b'+' => params.plus = true,
+                    b'-' => {
+                        params.minus = true;
+                        params.zero = false;
+                    }
+                    b' ' => params.space = true,
+                    _ => break,
                 }
-                params.width = width.abs() as usize;
+                i += 1;
             }
-            after_index = false;
-        } else if let Some((width, till)) = parse_num(&s[pos..])? {
-            if after_index {
-                return Err(PrintError::WithAfterIndex);
-            }
-            pos += till;
-            params.width = width;
-        }
 
-        if pos + 1 < s.len() && s[pos..].starts_with('.') {
-            pos += 1;
-            if after_index {
-                return Err(PrintError::PrecisionAfterIndex);
+            let (next, mut after_index) = self.arg_number(format, i);
+            i = next;
+
+            if i < end && bytes[i] == b'*' {
+                i += 1;
+                match self.int_from_arg() {
+                    Some(width) => {
+                        if width < 0 {
+                            params.minus = true;
+                            params.zero = false;
+                        }
+                        params.width = Some(width.unsigned_abs() as usize);
+                    }
+                    None => self.buf.push_str("%!(BADWIDTH)"),
+                }
+                after_index = false;
+            } else {
+                let (width, next) = parse_num(format, i, end);
+                i = next;
+                if width.is_some() && after_index {
+                    self.good_arg_num = false;
+                }
+                params.width = match width {
+                    Some(width) if too_large(width) => {
+                        self.buf.push_str("%!(BADWIDTH)");
+                        None
+                    }
+                    width => width,
+                };
             }
 
-            let arg_num = parse_index(&s[pos..])?.map(|(i, till)| {
-                pos += till;
-                after_index = true;
-                index = i;
-                i
-            });
-            if s[pos..].starts_with('*') {
-                pos += 1;
-                let arg_num = arg_num.unwrap_or_else(|| {
-                    let i = index;
-                    index += 1;
-                    i
-                });
-                if let Some(prec) = args.get(arg_num).and_then(|v| i64::from_value(v)) {
-                    if prec < 0 {
-                        params.precision = None;
+            if i + 1 < end && bytes[i] == b'.' {
+                i += 1;
+                if after_index {
+                    self.good_arg_num = false;
+                }
+                let (next, found) = self.arg_number(format, i);
+                i = next;
+                after_index = found;
+                if i < end && bytes[i] == b'*' {
+                    i += 1;
+                    match self.int_from_arg() {
+                        Some(prec) if prec >= 0 => params.precision = Some(prec as usize),
+                        _ => self.buf.push_str("%!(BADPREC)"),
                     }
-                    params.precision = Some(prec.abs() as usize);
+                    after_index = false;
+                } else {
+                    let (prec, next) = parse_num(format, i, end);
+                    i = next;
+                    params.precision = match prec.unwrap_or(0) {
+                        prec if too_large(prec) => {
+                            self.buf.push_str("%!(BADPREC)");
+                            None
+                        }
+                        prec => Some(prec),
+                    };
                 }
-            } else if let Some((prec, till)) = parse_num(&s[pos..])? {
-                if after_index {
-                    return Err(PrintError::PrecisionAfterIndex);
+            }
+
+            if !after_index {
+                i = self.arg_number(format, i).0;
+            }
+
+            let verb = match format[i..].chars().next() {
+                Some(verb) => verb,
+                None => {
+                    self.buf.push_str("%!(NOVERB)");
+                    break;
                 }
-                pos += till;
-                params.precision = Some(prec);
+            };
+            i += verb.len_utf8();
+
+            if verb == '%' {
+                self.buf.push('%');
+            } else if !self.good_arg_num {
+                self.buf.push_str("%!");
+                self.buf.push(verb);
+                self.buf.push_str("(BADINDEX)");
+            } else if self.arg_num >= self.args.len() {
+                self.buf.push_str("%!");
+                self.buf.push(verb);
+                self.buf.push_str("(MISSING)");
+            } else {
+                if verb == 'v' {
+                    params.sharp_v = params.sharp;
+                    params.sharp = false;
+                    params.plus_v = params.plus;
+                    params.plus = false;
+                }
+                print(&mut self.buf, &params, verb, &self.args[self.arg_num]);
+                self.arg_num += 1;
             }
         }
-    }
-
-    let arg_num = if let Some((i, _)) = parse_index(&s[pos..])? {
-        index = i;
-        i
-    } else {
-        let i = index;
-        index += 1;
-        i
-    };
 
-    if arg_num < args.len() {
-        return print(&params, typ, &args[arg_num]).map(|s| (s, index));
+        if !self.reordered && self.arg_num < self.args.len() {
+            self.buf.push_str("%!(EXTRA ");
+            for (i, arg) in self.args[self.arg_num..].iter().enumerate() {
+                if i > 0 {
+                    self.buf.push_str(", ");
+                }
+                if let Value::Nil | Value::NoValue = *arg {
+                    self.buf.push_str("<nil>");
+                } else {
+                    self.buf.push_str(&type_name(arg));
+                    self.buf.push('=');
+                    print(&mut self.buf, &FormatParams::default(), 'v', arg);
+                }
+            }
+            self.buf.push(')');
+        }
     }
-    Err(PrintError::UnableToProcessVerb(s.to_string()))
-}
 
-fn parse_index(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
-    if s.starts_with('[') {
-        let till = s
-            .find(']')
-            .ok_or_else(|| PrintError::MissingClosingBracket(s.to_string()))?;
-        s[1..till]
-            .parse::<usize>()
-            .map(|u| Some((u - 1, till + 1)))
-            .map_err(PrintError::UnableToParseIndex)
-    } else {
-        Ok(None)
+    // Handles an explicit argument index like `[3]` at `i`. Returns the position after it and
+    // whether there was one.
+    fn arg_number(&mut self, format: &str, i: usize) -> (usize, bool) {
+        if !format[i..].starts_with('[') {
+            return (i, false);
+        }
+        self.reordered = true;
+        let (index, width, ok) = parse_arg_number(&format[i..]);
+        match index {
+            Some(index) if ok && index < self.args.len() => {
+                self.arg_num = index;
+                (i + width, true)
+            }
+            _ => {
+                self.good_arg_num = false;
+                (i + width, ok)
+            }
+        }
     }
-}
 
-fn parse_num(s: &str) -> Result<Option<(usize, usize)>, PrintError> {
-    let till = s.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| s.len());
-    if till > 0 {
-        s[..till]
-            .parse()
-            .map(|u| Some((u, till)))
-            .map_err(PrintError::UnableToParseWidth)
-    } else {
-        Ok(None)
+    // Takes the next argument as width or precision.
+    fn int_from_arg(&mut self) -> Option<i64> {
+        let arg = self.args.get(self.arg_num)?;
+        self.arg_num += 1;
+        match *arg {
+            Value::Number(ref n) => n.as_i64().filter(|n| !too_large(n.unsigned_abs() as usize)),
+            _ => None,
+        }
     }
 }
 
-fn tokenize(s: &str) -> Result<Vec<FormatArg>, PrintError> {
-    let mut iter = s.char_indices().peekable();
-    let mut args = Vec::new();
-    loop {
-        let from = match iter.next() {
-            None => break,
-            Some((i, '%')) => i,
-            _ => continue,
-        };
-
-        if let Some(&(_, '%')) = iter.peek() {
-            iter.next();
-            continue;
-        }
+// Whether a width or precision is too large to be sensible.
+fn too_large(n: usize) -> bool {
+    n > 1_000_000
+}
 
-        loop {
-            match iter.next() {
-                None => {
-                    return Err(PrintError::UnableToTerminateFormatArg(
-                        s[from..].to_string(),
-                    ))
-                }
-                Some((i, t)) if TYPS.contains(t) => {
-                    args.push(FormatArg {
-                        start: from,
-                        end: i,
-                        typ: t,
-                    });
-                    break;
-                }
-                _ => continue,
-            };
-        }
+// Parses the number at `start`, returning it and the position after it. Numbers which overflow
+// saturate, so that they are reported as too large.
+fn parse_num(s: &str, start: usize, end: usize) -> (Option<usize>, usize) {
+    let bytes = s.as_bytes();
+    let mut num = None;
+    let mut i = start;
+    while i < end && bytes[i].is_ascii_digit() {
+        let n: usize = num.unwrap_or(0);
+        num = Some(
+            n.saturating_mul(10)
+                .saturating_add(usize::from(bytes[i] - b'0')),
+        );
+        i += 1;
     }
-    Ok(args)
+    (num, i)
 }
 
-pub fn params_to_chars(params: &FormatParams) -> (char, char, char, char, char) {
-    (
-        if params.sharp { '#' } else { '_' },
-        if params.zero { '0' } else { '_' },
-        if params.plus { '+' } else { '_' },
-        if params.minus { '-' } else { '_' },
-        if params.space { ' ' } else { '_' },
-    )
+// Parses an argument index like `[3]`. Returns the zero based index, if any, the length of the
+// index and whether it was well-formed.
+fn parse_arg_number(s: &str) -> (Option<usize>, usize, bool) {
+    // There must be at least 3 bytes: [n].
+    if s.len() < 3 {
+        return (None, 1, false);
+    }
+    match s.find(']') {
+        Some(close) => match parse_num(s, 1, close) {
+            (Some(num), next) if next == close => (num.checked_sub(1), close + 1, true),
+            _ => (None, close + 1, false),
+        },
+        None => (None, 1, false),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
+    use std::slice;
 
     use super::*;
 
     #[test]
     fn test_sprinttf_to_format() {
         let s = sprintf("foo%v2000", &["bar".into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"foobar2000");
 
+        // Like in Go the plus flag only prints field names for %v.
         let s = sprintf("%+0v", &[1.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
-        assert_eq!(s, r"+1");
+        assert_eq!(s, r"1");
     }
 
     #[test]
     fn test_sprintf_fancy() {
         let s = sprintf("%+-#10c", &[10000.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"✐         ");
 
         let s = sprintf("%+-#10q", &[10000.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"'\u2710'  ");
     }
 
     #[test]
     fn test_sprintf_string_to_hex() {
         let s = sprintf("%x", &["foobar2000".into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"666f6f62617232303030");
 
         let s = sprintf("%X", &["foobar2000".into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"666F6F62617232303030");
+
+        let s = sprintf("% x", &["foo".into()]);
+        assert_eq!(s, r"66 6f 6f");
     }
 
     #[test]
     fn test_sprintf_string_prec() {
         let s = sprintf("%.6s", &["foobar2000".into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"foobar");
+
+        let s = sprintf("%-8.3s|%8s|", &["foobar".into(), "bar".into()]);
+        assert_eq!(s, r"foo     |     bar|");
     }
 
     #[test]
     fn test_sprintf_index() {
-        let s = sprintf("%[1]v %v", &["foo".into(), "bar".into(), 2000.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
+        let s = sprintf("%[1]v %v", &["foo".into(), "bar".into()]);
         assert_eq!(s, r"foo bar");
 
         let s = sprintf(
             "%[2]v %v%[1]v %v%[1]v",
             &["!".into(), "wtf".into(), "golang".into()],
         );
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"wtf golang! wtf!");
+
+        let s = sprintf("%[3]*.[2]*[1]f", &[12.0.into(), 2.into(), 6.into()]);
+        assert_eq!(s, r" 12.00");
     }
 
     #[test]
     fn test_sprintf_number() {
         let s = sprintf("foobar%d", &[2000.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"foobar2000");
 
         let s = sprintf("%+0d", &[1.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"+1");
 
         let s = sprintf("%+0b", &[5.into()]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"+101");
+
+        let s = sprintf(
+            "%05d|%-5d|%x|%#o|%#X",
+            &[(-42).into(), 7.into(), (-255).into(), 8.into(), 255.into()],
+        );
+        assert_eq!(s, r"-0042|7    |-ff|010|0XFF");
+
+        let s = sprintf("%U|%#U", &[0x1F600.into(), 120.into()]);
+        assert_eq!(s, r"U+1F600|U+0078 'x'");
+    }
+
+    #[test]
+    fn test_sprintf_float() {
+        let s = sprintf("%v|%v|%v", &[1234567.5.into(), 0.5.into(), 1e-7.into()]);
+        assert_eq!(s, r"1.2345675e+06|0.5|1e-07");
+
+        let s = sprintf("%f|%.2f|%8.3f", &[3.5.into(), 3.14159.into(), (-2).into()]);
+        assert_eq!(s, r"3.500000|3.14|  -2.000");
+
+        let s = sprintf(
+            "%e|%.2E|%g|%.3g",
+            &[
+                1234567.0.into(),
+                0.000123.into(),
+                0.0001.into(),
+                3.14159.into(),
+            ],
+        );
+        assert_eq!(s, r"1.234567e+06|1.23E-04|0.0001|3.14");
+    }
+
+    #[test]
+    fn test_sprintf_misc_verbs() {
+        let s = sprintf(
+            "%t|%q|%#q|%T|%T",
+            &[
+                true.into(),
+                "a\"b".into(),
+                "ab".into(),
+                "x".into(),
+                1.5.into(),
+            ],
+        );
+        assert_eq!(s, r#"true|"a\"b"|`ab`|string|float64"#);
+
+        let s = sprintf("%v|%s", &[Value::Nil, Value::Nil]);
+        assert_eq!(s, r"<nil>|%!s(<nil>)");
+    }
+
+    #[test]
+    fn test_sprintf_errors() {
+        assert_eq!(sprintf("%d", &["foo".into()]), r"%!d(string=foo)");
+        assert_eq!(sprintf("%s %s", &["foo".into()]), r"foo %!s(MISSING)");
+        assert_eq!(
+            sprintf("%s", &["foo".into(), 1.into()]),
+            r"foo%!(EXTRA int=1)"
+        );
+        assert_eq!(sprintf("%[3]d", &[1.into()]), r"%!d(BADINDEX)");
+        assert_eq!(sprintf("%*d", &["x".into(), 1.into()]), r"%!(BADWIDTH)1");
+        assert_eq!(sprintf("%.*d", &[(-1).into(), 1.into()]), r"%!(BADPREC)1");
+        assert_eq!(sprintf("foo%", &[]), r"foo%!(NOVERB)");
+        assert_eq!(sprintf("%100000000000d", &[1.into()]), r"%!(BADWIDTH)1");
+        assert_eq!(
+            sprintf("%.100000000000000000000000f", &[1.5.into()]),
+            r"%!(BADPREC)1.500000"
+        );
+        assert_eq!(
+            sprintf("%[100000000000000000000000]d", &[1.into()]),
+            r"%!d(BADINDEX)"
+        );
+        assert_eq!(sprintf(" %6.2 ", &[]), r" %! (MISSING)");
+        assert_eq!(sprintf(" foo %% bar ", &[]), r" foo % bar ");
     }
 
     #[test]
     fn test_sprintf_array() {
         let values: Vec<Value> = vec!["hello".into(), "world".into()];
         let s = sprintf("foo %v", &[Value::Array(values)]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"foo [hello world]");
 
         let values: Vec<Value> = vec![42.into(), 100.into()];
         let s = sprintf("foo %v", &[Value::Array(values)]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"foo [42 100]");
+
+        let values: Vec<Value> = vec![10.into(), 255.into()];
+        let s = sprintf("%02x", &[Value::Array(values)]);
+        assert_eq!(s, r"[0a ff]");
     }
 
     #[test]
@@ -330,60 +455,121 @@ mod test {
         values.insert("hello".into(), "world".into());
         values.insert("number".into(), 42.into());
         let s = sprintf("foo %v", &[Value::Map(values)]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
-        // The print order is unpredictable, we can't write
-        // a straight comparison
-        assert!(s == "foo map[number:42 hello:world]" || s == "foo map[hello:world number:42]");
+        // Keys are sorted like in Go.
+        assert_eq!(s, r"foo map[hello:world number:42]");
 
         let mut values: HashMap<String, Value> = HashMap::new();
         values.insert("float".into(), 4.2.into());
         let s = sprintf("%v", &[Value::Map(values)]);
-        assert!(s.is_ok());
-        let s = s.unwrap();
         assert_eq!(s, r"map[float:4.2]");
     }
 
     #[test]
-    fn test_tokenize() {
-        let t = tokenize("foobar%6.2ffoobar");
-        assert!(t.is_ok());
-        let t = t.unwrap();
-        assert_eq!(t.len(), 1);
-        let a = &t[0];
-        assert_eq!(a.start, 6);
-        assert_eq!(a.end, 10);
-        assert_eq!(a.typ, 'f');
+    fn test_sprintf_nil_elements() {
+        let values = Value::Array(vec![Value::Nil, 104.into(), Value::NoValue]);
+        assert_eq!(sprintf("%v", slice::from_ref(&values)), "[<nil> 104 <nil>]");
+        assert_eq!(
+            sprintf("%s", slice::from_ref(&values)),
+            "[<nil> %!s(int=104) <nil>]"
+        );
+        assert_eq!(sprintf("%x", slice::from_ref(&values)), "[<nil> 68 <nil>]");
+        assert_eq!(
+            sprintf("%#v", &[values]),
+            "[]interface {}{interface {}(nil), 104, interface {}(nil)}"
+        );
+        assert_eq!(
+            sprintf("%s %d", &[Value::Nil, Value::Nil]),
+            "%!s(<nil>) %!d(<nil>)"
+        );
     }
 
+    // The expected outputs are those of Go for `[]byte`.
     #[test]
-    fn test_tokenize_err() {
-        let t = tokenize(" %6.2 ");
-        assert!(t.is_err());
+    fn test_sprintf_bytes() {
+        let hi = crate::byte_slice("hi");
+        let cases = [
+            ("%v", "[104 105]"),
+            ("%d", "[104 105]"),
+            ("%s", "hi"),
+            ("%5s", "   hi"),
+            ("%.1s", "h"),
+            ("%q", r#""hi""#),
+            ("%x", "6869"),
+            ("% x", "68 69"),
+            ("%#x", "0x6869"),
+            ("%# x", "0x68 0x69"),
+            ("%#v", "[]byte{0x68, 0x69}"),
+            ("%T", "[]uint8"),
+            ("%t", "[%!t(uint8=104) %!t(uint8=105)]"),
+        ];
+        for &(format, expected) in &cases {
+            assert_eq!(
+                sprintf(format, slice::from_ref(&hi)),
+                expected,
+                "{}",
+                format
+            );
+        }
+        let bytes = crate::byte_slice([1, 0xab]);
+        assert_eq!(sprintf("%3d|%X", &[bytes.clone(), bytes]), "[  1 171]|01AB");
+        let empty = crate::byte_slice([]);
+        assert_eq!(
+            sprintf(
+                "%v|%#v|%x|%s",
+                &[empty.clone(), empty.clone(), empty.clone(), empty]
+            ),
+            "[]|[]byte{}||"
+        );
+
+        let values = Value::Array(vec![hi.clone(), Value::Nil]);
+        assert_eq!(sprintf("%v", slice::from_ref(&values)), "[[104 105] <nil>]");
+        assert_eq!(sprintf("%s", slice::from_ref(&values)), "[hi <nil>]");
+        assert_eq!(
+            sprintf("%#v", &[values]),
+            "[]interface {}{[]uint8{0x68, 0x69}, interface {}(nil)}"
+        );
+        assert_eq!(format_value(&hi), "[104 105]");
     }
 
+    // Go's templates dereference pointers, so nested options print like their value or nil.
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_tokenize_none() {
-        let t = tokenize(" foo %% bar ");
-        assert!(t.is_ok());
-        let t = t.unwrap();
-        assert!(t.is_empty());
+    fn test_sprintf_nested_options() {
+        let some = crate::to_value(&Some(Some(1))).unwrap();
+        let none = crate::to_value(&Some(None::<i32>)).unwrap();
+        assert_eq!(
+            sprintf(
+                "%v %d %v %d",
+                &[some.clone(), some.clone(), none.clone(), none]
+            ),
+            "1 1 <nil> %!d(<nil>)"
+        );
+        assert_eq!(format_value(&some), "1");
+
+        let values = crate::to_value(&vec![Some(Some(1)), Some(None), None]).unwrap();
+        assert_eq!(sprintf("%v", slice::from_ref(&values)), "[1 <nil> <nil>]");
+        assert_eq!(sprintf("%d", slice::from_ref(&values)), "[1 <nil> <nil>]");
+        assert_eq!(
+            sprintf("%#v", &[values]),
+            "[]interface {}{1, interface {}(nil), interface {}(nil)}"
+        );
     }
 
     #[test]
-    fn test_parse_index() {
-        let x = parse_index("[12]");
-        assert!(x.is_ok());
-        let x = x.unwrap();
-        // Go starts with 1 in stead of 0
-        assert_eq!(x, Some((11, 4)));
+    fn test_sprint() {
+        let s = sprint(&["foo".into(), 1.into(), 2.into(), "bar".into(), Value::Nil]);
+        assert_eq!(s, r"foo1 2bar<nil>");
 
-        let x = parse_index("[12");
-        assert!(x.is_err());
+        let s = sprintln(&["foo".into(), 1.into(), 2.into()]);
+        assert_eq!(s, "foo 1 2\n");
+    }
 
-        let x = parse_index("*[12]");
-        assert!(x.is_ok());
-        let x = x.unwrap();
-        assert_eq!(x, None);
+    #[test]
+    fn test_parse_arg_number() {
+        // Go starts with 1 in stead of 0
+        assert_eq!(parse_arg_number("[12]"), (Some(11), 4, true));
+        assert_eq!(parse_arg_number("[0]"), (None, 3, true));
+        assert_eq!(parse_arg_number("[12"), (None, 1, false));
+        assert_eq!(parse_arg_number("[x]d"), (None, 3, false));
     }
 }
@@ -0,0 +1,133 @@
+//! Wall time and invocation counts of template nodes.
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::node::{Nodes, Pos};
+use crate::template::Template;
+
+/// Measurements of a single node of a template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileEntry {
+    /// Name of the template containing the node.
+    pub template: String,
+    /// Line of the node in the template text.
+    pub line: usize,
+    /// Byte offset of the node in the template text.
+    pub pos: Pos,
+    /// The beginning of the node's text.
+    pub node: String,
+    /// How often the node was executed.
+    pub count: usize,
+    /// Time spent executing the node, including the nodes it contains.
+    pub total: Duration,
+    /// Time spent executing the node, excluding the nodes it contains.
+    pub self_time: Duration,
+}
+
+/// Execution times of the nodes of a template, ordered by `self_time` with the slowest first.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    entries: Vec<ProfileEntry>,
+    index: HashMap<String, HashMap<Pos, usize>>,
+    // Time spent in the children of each node currently executing.
+    children: Vec<Duration>,
+}
+
+impl Profile {
+    /// All measured nodes, the slowest first.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("{{ range . }}\n{{ . }}{{ end }}").unwrap();
+    /// let (output, profile) = tmpl.render_profiled(&Context::from(vec![1, 2])).unwrap();
+    /// assert_eq!(output, "\n1\n2");
+    /// let action = profile.entries().iter().find(|e| e.node == "{{.}}").unwrap();
+    /// assert_eq!((action.line, action.count), (2, 2));
+    /// ```
+    pub fn entries(&self) -> &[ProfileEntry] {
+        &self.entries
+    }
+
+    pub(crate) fn enter(&mut self) -> Instant {
+        self.children.push(Duration::default());
+        Instant::now()
+    }
+
+    pub(crate) fn exit(&mut self, template: &str, node: &Nodes, start: Instant) {
+        let elapsed = start.elapsed();
+        let children = self.children.pop().unwrap_or_default();
+        if let Some(parent) = self.children.last_mut() {
+            *parent += elapsed;
+        }
+        let pos = node.pos();
+        let i = match self.index.get(template).and_then(|m| m.get(&pos)) {
+            Some(&i) => i,
+            None => {
+                self.entries.push(ProfileEntry {
+                    template: template.to_owned(),
+                    line: 0,
+                    pos,
+                    node: truncate(node.to_string()),
+                    count: 0,
+                    total: Duration::default(),
+                    self_time: Duration::default(),
+                });
+                let i = self.entries.len() - 1;
+                self.index
+                    .entry(template.to_owned())
+                    .or_default()
+                    .insert(pos, i);
+                i
+            }
+        };
+        let entry = &mut self.entries[i];
+        entry.count += 1;
+        entry.total += elapsed;
+        entry.self_time += elapsed.saturating_sub(children);
+    }
+
+    // Looks up the lines of the nodes and sorts the entries.
+    pub(crate) fn finish(&mut self, template: &Template) {
+        for entry in &mut self.entries {
+            if let Some(tree) = template.tree_set.get(&entry.template) {
+                entry.line = tree.error_context(entry.pos).line;
+            }
+        }
+        self.entries.sort_by_key(|e| Reverse(e.self_time));
+        self.index.clear();
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12} {:>12} {:>8}  location", "self", "total", "count")?;
+        for e in &self.entries {
+            writeln!(
+                f,
+                "{:>12} {:>12} {:>8}  {}:{} {}",
+                format!("{:?}", e.self_time),
+                format!("{:?}", e.total),
+                e.count,
+                e.template,
+                e.line,
+                e.node
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Shortens the text of a node like Go does for error contexts.
+fn truncate(mut s: String) -> String {
+    if let Some((i, _)) = s.char_indices().nth(20) {
+        s.truncate(i);
+        s.push_str("...");
+    }
+    s
+}
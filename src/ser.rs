@@ -0,0 +1,627 @@
+//! Conversion of serializable Rust values into template values.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+use gtmpl_value::Value;
+use serde::ser::{self, Impossible, Serialize};
+use thiserror::Error;
+
+use crate::byte_slice::byte_slice;
+
+/// An error converting a value with [`to_value`].
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct ToValueError(String);
+
+impl ser::Error for ToValueError {
+    fn custom<T: Display>(msg: T) -> ToValueError {
+        ToValueError(msg.to_string())
+    }
+}
+
+/// Converts any value implementing `serde::Serialize` into a `Value`, e.g. to use structs of
+/// an application as context without implementing `Into<Value>` or deriving `Gtmpl`.
+///
+/// Structs become objects and maps become maps, whose keys must be strings, numbers, booleans
+/// or unit enum variants. `None` and `()` become nil, and so do nested options like
+/// `Some(None)`. Bytes, e.g. with `serde_bytes`, become a [`byte_slice`]. Enum variants with
+/// data become a map from the name of the variant to the data, like in JSON.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{to_value, Context, Template};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+///     roles: Vec<&'static str>,
+///     manager: Option<String>,
+/// }
+///
+/// let user = User {
+///     name: "Ann".to_owned(),
+///     roles: vec!["admin", "dev"],
+///     manager: None,
+/// };
+/// let mut tmpl = Template::default();
+/// tmpl.parse(r#"{{ .name }}: {{ join ", " .roles }}{{ with .manager }} ({{ . }}){{ end }}"#)
+///     .unwrap();
+/// let ctx = Context::from(to_value(&user).unwrap());
+/// assert_eq!(tmpl.render(&ctx).unwrap(), "Ann: admin, dev");
+/// ```
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, ToValueError> {
+    value.serialize(ValueSerializer)
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = ToValueError;
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVariant<SerializeVec>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, ToValueError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, ToValueError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, ToValueError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, ToValueError> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, ToValueError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, ToValueError> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => self.serialize_u128(u128::try_from(v).map_err(ser::Error::custom)?),
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, ToValueError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, ToValueError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, ToValueError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, ToValueError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, ToValueError> {
+        u64::try_from(v)
+            .map(Value::from)
+            .map_err(|_| ser::Error::custom(format!("number {} out of range", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, ToValueError> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, ToValueError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, ToValueError> {
+        Ok(Value::from(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, ToValueError> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, ToValueError> {
+        Ok(byte_slice(v))
+    }
+
+    fn serialize_none(self) -> Result<Value, ToValueError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, ToValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, ToValueError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ToValueError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, ToValueError> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, ToValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, ToValueError> {
+        Ok(variant_map(variant, to_value(value)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, ToValueError> {
+        Ok(SerializeVec(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, ToValueError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, ToValueError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeVec>, ToValueError> {
+        Ok(SerializeVariant(variant, self.serialize_seq(Some(len))?))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap, ToValueError> {
+        Ok(SerializeMap {
+            map: HashMap::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap, ToValueError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeMap>, ToValueError> {
+        Ok(SerializeVariant(variant, self.serialize_map(Some(len))?))
+    }
+}
+
+// Enum variants with data are maps from the name of the variant to the data.
+fn variant_map(variant: &str, value: Value) -> Value {
+    let mut map = HashMap::new();
+    map.insert(variant.to_owned(), value);
+    Value::Map(map)
+}
+
+struct SerializeVec(Vec<Value>);
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ToValueError> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        Ok(Value::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ToValueError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ToValueError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct SerializeMap {
+    map: HashMap<String, Value>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ToValueError> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ToValueError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("map value without a key"))?;
+        self.map.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        Ok(Value::Map(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ToValueError> {
+        self.map.insert(key.to_owned(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        Ok(Value::Object(self.map))
+    }
+}
+
+struct SerializeVariant<T>(&'static str, T);
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeVec> {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ToValueError> {
+        ser::SerializeSeq::serialize_element(&mut self.1, value)
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        Ok(variant_map(self.0, ser::SerializeSeq::end(self.1)?))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = Value;
+    type Error = ToValueError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), ToValueError> {
+        ser::SerializeStruct::serialize_field(&mut self.1, key, value)
+    }
+
+    fn end(self) -> Result<Value, ToValueError> {
+        Ok(variant_map(self.0, ser::SerializeStruct::end(self.1)?))
+    }
+}
+
+// Serializes the keys of maps, which are strings in templates.
+struct KeySerializer;
+
+impl KeySerializer {
+    fn key<T: Display>(v: T) -> Result<String, ToValueError> {
+        Ok(v.to_string())
+    }
+
+    fn unsupported(typ: &str) -> ToValueError {
+        ser::Error::custom(format!("map keys must be strings, not {}", typ))
+    }
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = ToValueError;
+    type SerializeSeq = Impossible<String, ToValueError>;
+    type SerializeTuple = Impossible<String, ToValueError>;
+    type SerializeTupleStruct = Impossible<String, ToValueError>;
+    type SerializeTupleVariant = Impossible<String, ToValueError>;
+    type SerializeMap = Impossible<String, ToValueError>;
+    type SerializeStruct = Impossible<String, ToValueError>;
+    type SerializeStructVariant = Impossible<String, ToValueError>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported("f64"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, ToValueError> {
+        KeySerializer::key(v)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported("none"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, ToValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported("()"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, ToValueError> {
+        KeySerializer::key(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, ToValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, ToValueError> {
+        Err(KeySerializer::unsupported("sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, ToValueError> {
+        Err(KeySerializer::unsupported("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, ToValueError> {
+        Err(KeySerializer::unsupported("map"))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, ToValueError> {
+        Err(KeySerializer::unsupported(name))
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect(u32, u32),
+        Line { len: u8 },
+    }
+
+    #[derive(Serialize)]
+    struct Doc {
+        id: u64,
+        tags: BTreeMap<i32, char>,
+        shapes: Vec<Shape>,
+        parent: Option<Box<Doc>>,
+        raw: &'static [u8],
+    }
+
+    fn render(text: &str, value: Value) -> String {
+        let mut t = crate::Template::default();
+        t.parse(text).unwrap();
+        t.render(&crate::Context::from(value)).unwrap()
+    }
+
+    #[test]
+    fn test_to_value() {
+        let mut tags = BTreeMap::new();
+        tags.insert(-1, 'x');
+        let doc = Doc {
+            id: 7,
+            tags,
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(1.5),
+                Shape::Rect(2, 3),
+                Shape::Line { len: 4 },
+            ],
+            parent: None,
+            raw: b"ab",
+        };
+        let value = to_value(&doc).unwrap();
+        assert!(matches!(value, Value::Object(_)));
+        assert_eq!(
+            render(
+                r#"{{ .id }} {{ index .tags "-1" }} {{ .parent }} {{ .raw }} {{ range .shapes }}{{ . }};{{ end }}"#,
+                value
+            ),
+            "7 x <no value> [97 98] Point;map[Circle:1.5];map[Rect:[2 3]];map[Line:{len:4}];"
+        );
+    }
+
+    #[test]
+    fn test_to_value_bytes() {
+        struct Raw(&'static [u8]);
+
+        impl Serialize for Raw {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(self.0)
+            }
+        }
+
+        let value = to_value(&Raw(b"ab")).unwrap();
+        assert_eq!(
+            render(r#"{{ . }} {{ len . }} {{ printf "%s" . }}"#, value),
+            "[97 98] 2 ab"
+        );
+    }
+
+    #[test]
+    fn test_to_value_errors() {
+        let mut map = HashMap::new();
+        map.insert(vec![1], 1);
+        assert_eq!(
+            to_value(&map).unwrap_err().to_string(),
+            "map keys must be strings, not sequence"
+        );
+        assert!(to_value(&u128::MAX).is_err());
+        assert_eq!(to_value(&-1i128).unwrap(), Value::from(-1i64));
+    }
+}
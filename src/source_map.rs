@@ -0,0 +1,66 @@
+//! Mapping of rendered output back to the template nodes which produced it.
+use std::ops::Range;
+
+use crate::node::Pos;
+
+/// A piece of rendered output and the node it was produced by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mapping {
+    /// Byte range in the rendered output.
+    pub output: Range<usize>,
+    /// Name of the template containing the node.
+    pub template: String,
+    /// Byte range of the node in the template text. For actions this includes the delimiters.
+    pub span: Range<Pos>,
+}
+
+/// Mappings from rendered output to template nodes, ordered by output position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceMap {
+    mappings: Vec<Mapping>,
+    len: usize,
+}
+
+impl SourceMap {
+    /// All mappings ordered by their position in the output.
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    /// Returns the mapping of the output byte at `offset`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.parse("key: {{ . }}").unwrap();
+    /// let (output, map) = tmpl
+    ///     .render_with_source_map(&Context::from("foo"))
+    ///     .unwrap();
+    /// assert_eq!(output, "key: foo");
+    /// let mapping = map.lookup(output.find("foo").unwrap()).unwrap();
+    /// assert_eq!(mapping.span, 5..12);
+    /// ```
+    pub fn lookup(&self, offset: usize) -> Option<&Mapping> {
+        let i = self
+            .mappings
+            .partition_point(|mapping| mapping.output.end <= offset);
+        self.mappings
+            .get(i)
+            .filter(|mapping| mapping.output.start <= offset)
+    }
+
+    pub(crate) fn record(&mut self, template: &str, span: Range<Pos>, written: usize) {
+        if written == 0 {
+            return;
+        }
+        self.mappings.push(Mapping {
+            output: self.len..self.len + written,
+            template: template.to_owned(),
+            span,
+        });
+        self.len += written;
+    }
+}
@@ -1,18 +1,45 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+#[cfg(feature = "files")]
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use crate::audit::AuditSink;
 use crate::error::{ParseError, TemplateError};
-use crate::funcs::BUILTINS;
-use crate::parse::{parse, Tree};
+#[cfg(feature = "tokio")]
+use crate::exec::AsyncFunc;
+use crate::exec::ExecOptions;
+use crate::func_map::{FuncDef, FuncMap, SharedFunc};
+use crate::funcs::ENV_FUNCS;
+#[cfg(feature = "files")]
+use crate::funcs::FILE_FUNCS;
+#[cfg(feature = "i18n")]
+use crate::funcs::{Catalog, I18N_FUNCS};
+#[cfg(feature = "net")]
+use crate::funcs::{NetOptions, NET_FUNCS};
+use crate::hook::ExecHook;
+use crate::parse::{parse, reparse, ParseOptions, Tree};
 
-use gtmpl_value::Func;
+use gtmpl_value::{Func, FuncError, Value};
+
+/// Looks up functions which are not known to a template when they are called, see
+/// [`Template::set_func_resolver`].
+pub type FuncResolver = Arc<dyn Fn(&str) -> Option<Func> + Send + Sync>;
 
 /// The main template structure.
 #[derive(Clone)]
 pub struct Template {
     pub name: String,
     pub text: String,
-    pub funcs: HashMap<String, Func>,
+    pub funcs: FuncMap,
+    #[cfg(feature = "tokio")]
+    pub async_funcs: HashMap<String, AsyncFunc>,
     pub tree_set: HashMap<String, Tree>,
+    pub parse_options: ParseOptions,
+    pub exec_options: ExecOptions,
+    pub hooks: Vec<Arc<dyn ExecHook>>,
+    pub func_resolver: Option<FuncResolver>,
+    pub audit_sink: Option<Arc<dyn AuditSink>>,
 }
 
 impl Default for Template {
@@ -20,8 +47,15 @@ impl Default for Template {
         Template {
             name: String::default(),
             text: String::from(""),
-            funcs: BUILTINS.iter().map(|&(k, v)| (k.to_owned(), v)).collect(),
+            funcs: FuncMap::defaults(),
+            #[cfg(feature = "tokio")]
+            async_funcs: HashMap::default(),
             tree_set: HashMap::default(),
+            parse_options: ParseOptions::default(),
+            exec_options: ExecOptions::default(),
+            hooks: Vec::new(),
+            func_resolver: None,
+            audit_sink: None,
         }
     }
 }
@@ -35,6 +69,39 @@ impl Template {
         }
     }
 
+    /// Creates a new empty template with a given `name` which starts with `funcs` instead of
+    /// the [default functions](FuncMap::defaults), e.g. to share the functions of an engine
+    /// between its templates. Functions added to the template later take precedence over
+    /// `funcs` and do not change them.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Func, FuncError, FuncMap, Template, Value};
+    ///
+    /// fn site(_args: &[Value]) -> Result<Value, FuncError> {
+    ///     Ok(Value::from("example.com"))
+    /// }
+    ///
+    /// let engine = FuncMap::builtins().with("site", site as Func);
+    ///
+    /// let mut page = Template::with_funcs("page", &engine);
+    /// page.parse("{{ site }}").unwrap();
+    /// assert_eq!(page.render(&Context::empty()).unwrap(), "example.com");
+    ///
+    /// let mut mail = Template::with_funcs("mail", &engine);
+    /// mail.funcs.remove("site");
+    /// assert!(mail.parse("{{ site }}").is_err());
+    /// assert!(engine.contains("site"));
+    /// ```
+    pub fn with_funcs<T: Into<String>>(name: T, funcs: &FuncMap) -> Template {
+        Template {
+            name: name.into(),
+            funcs: funcs.clone(),
+            ..Default::default()
+        }
+    }
+
     /// Adds a single custom function to the template.
     ///
     /// ## Example
@@ -53,7 +120,66 @@ impl Template {
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
     pub fn add_func(&mut self, name: &str, func: Func) {
-        self.funcs.insert(name.to_owned(), func);
+        self.funcs.insert(name, func);
+    }
+
+    /// Adds a single custom closure to the template. Closures can capture state, e.g. an
+    /// `Arc` of the application state to access caches, databases or configuration.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    ///
+    /// use gtmpl::{Context, Value};
+    ///
+    /// struct Config {
+    ///     base_url: String,
+    /// }
+    ///
+    /// let config = Arc::new(Config { base_url: "https://example.com".to_owned() });
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.add_closure("url", move |args: &[Value]| {
+    ///     Ok(Value::from(format!("{}/{}", config.base_url, args[0])))
+    /// });
+    /// tmpl.parse(r#"{{ url "about" }}"#).unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "https://example.com/about");
+    /// ```
+    pub fn add_closure<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, FuncError> + Send + Sync + 'static,
+    {
+        self.funcs.insert(name, FuncDef::from_fn(func));
+    }
+
+    /// Adds a single custom function whose result only depends on its arguments. Calls with
+    /// the same arguments are only made once per execution, e.g. for expensive helpers called
+    /// in loops.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use gtmpl::{Context, FuncError, Value};
+    ///
+    /// static CALLS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// fn translate(args: &[Value]) -> Result<Value, FuncError> {
+    ///     CALLS.fetch_add(1, Ordering::SeqCst);
+    ///     Ok(Value::from(format!("[{}]", args[0])))
+    /// }
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.add_pure_func("translate", translate);
+    /// tmpl.parse(r#"{{ range . }}{{ translate "item" }} {{ . }} {{ end }}"#).unwrap();
+    /// let output = tmpl.render(&Context::from(vec![1, 2, 3]));
+    /// assert_eq!(&output.unwrap(), "[item] 1 [item] 2 [item] 3 ");
+    /// assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn add_pure_func(&mut self, name: &str, func: Func) {
+        self.funcs.insert(name, FuncDef::new(func).pure());
     }
 
     /// Adds custom functions to the template.
@@ -77,8 +203,269 @@ impl Template {
     /// assert_eq!(&output.unwrap(), "Hello World!");
     /// ```
     pub fn add_funcs<T: Into<String> + Clone>(&mut self, funcs: &[(T, Func)]) {
-        self.funcs
-            .extend(funcs.iter().cloned().map(|(k, v)| (k.into(), v)));
+        self.funcs.extend(funcs.iter().cloned());
+    }
+
+    /// Adds the functions `env` and `expandenv`, which read environment variables. They are
+    /// not added by default, so templates from untrusted sources cannot read secrets from the
+    /// environment.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// assert!(tmpl.parse(r#"{{ env "HOME" }}"#).is_err());
+    /// tmpl.add_env_funcs();
+    /// tmpl.parse(r#"{{ env "GTMPL_UNSET_VARIABLE" | default "none" }}"#).unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "none");
+    /// ```
+    pub fn add_env_funcs(&mut self) {
+        self.add_funcs(ENV_FUNCS);
+    }
+
+    /// Adds the functions `readFile`, `fileExists` and `glob`, which read the files below
+    /// `root`. Their paths are relative to `root` and must not leave it, neither by `..` nor
+    /// by symbolic links. The root is stored in `ExecOptions::file_root`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_file_funcs(std::env::temp_dir());
+    /// tmpl.parse(r#"{{ readFile "../etc/passwd" }}"#).unwrap();
+    /// let err = tmpl.render(&Context::empty()).unwrap_err();
+    /// assert!(err.to_string().ends_with("is outside of the root directory"));
+    /// ```
+    #[cfg(feature = "files")]
+    pub fn add_file_funcs<P: Into<PathBuf>>(&mut self, root: P) {
+        self.add_funcs(FILE_FUNCS);
+        self.exec_options.file_root = Some(root.into());
+    }
+
+    /// Adds the functions `httpGet` and `dnsLookup`, which fetch data over the network, e.g.
+    /// for provisioning templates. Requests fail after `options.timeout` and responses larger
+    /// than `options.max_response_size`. The options are stored in `ExecOptions::net`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use gtmpl::{NetOptions, Template};
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_net_funcs(NetOptions {
+    ///     timeout: Duration::from_secs(2),
+    ///     max_response_size: 64 * 1024,
+    /// });
+    /// tmpl.parse(r#"{{ httpGet "https://example.com/motd.txt" }}"#).unwrap();
+    /// ```
+    #[cfg(feature = "net")]
+    pub fn add_net_funcs(&mut self, options: NetOptions) {
+        self.add_funcs(NET_FUNCS);
+        self.exec_options.net = Some(options);
+    }
+
+    /// Adds the function `t`, which translates the messages of `catalog`, e.g. for localized
+    /// emails. The catalog is stored in `ExecOptions::catalog`, where it can be replaced to
+    /// render the same templates in another locale.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    ///
+    /// use gtmpl::{Catalog, Context, Template};
+    ///
+    /// let mut en = Catalog::new("en").unwrap();
+    /// en.add_messages("greeting = Hello").unwrap();
+    /// let mut de = Catalog::new("de").unwrap();
+    /// de.add_messages("greeting = Hallo").unwrap();
+    ///
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_i18n_funcs(en);
+    /// tmpl.parse(r#"{{ t "greeting" }}, {{ . }}!"#).unwrap();
+    /// assert_eq!(tmpl.render(&Context::from("Ann")).unwrap(), "Hello, Ann!");
+    /// tmpl.exec_options.catalog = Some(Arc::new(de));
+    /// assert_eq!(tmpl.render(&Context::from("Ann")).unwrap(), "Hallo, Ann!");
+    /// ```
+    #[cfg(feature = "i18n")]
+    pub fn add_i18n_funcs(&mut self, catalog: Catalog) {
+        self.add_funcs(I18N_FUNCS);
+        self.exec_options.catalog = Some(Arc::new(catalog));
+    }
+
+    /// Adds a single async function to the template. Async functions can only be called by
+    /// `execute_async`.
+    #[cfg(feature = "tokio")]
+    pub fn add_async_func(&mut self, name: &str, func: AsyncFunc) {
+        self.async_funcs.insert(name.to_owned(), func);
+    }
+
+    /// Adds a hook which is called during execution. Hooks are called in the order they were
+    /// added.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// use gtmpl::{node::Nodes, Context, ExecHook, Template};
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct CountActions(Arc<AtomicUsize>);
+    ///
+    /// impl ExecHook for CountActions {
+    ///     fn before_node(&self, _template: &str, node: &Nodes) {
+    ///         if let Nodes::Action(_) = node {
+    ///             self.0.fetch_add(1, Ordering::Relaxed);
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let count = CountActions::default();
+    /// let mut tmpl = Template::default();
+    /// tmpl.add_hook(count.clone());
+    /// tmpl.parse("{{ 1 }}{{ range . }}{{ . }}{{ end }}").unwrap();
+    /// tmpl.render(&Context::from(vec![2, 3])).unwrap();
+    /// assert_eq!(count.0.load(Ordering::Relaxed), 3);
+    /// ```
+    pub fn add_hook<H: ExecHook + 'static>(&mut self, hook: H) {
+        self.hooks.push(Arc::new(hook));
+    }
+
+    /// Sets a resolver for functions which are not added to the template. Calls of unknown
+    /// functions are accepted while parsing and looked up with `resolver` when executed, e.g.
+    /// to bind helpers provided by plugins late. Calls the resolver does not know fail with
+    /// [`ExecError::UndefinedFunction`](crate::error::ExecError::UndefinedFunction).
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, Func, FuncError, Value};
+    ///
+    /// fn shout(args: &[Value]) -> Result<Value, FuncError> {
+    ///     match args.first() {
+    ///         Some(Value::String(s)) => Ok(Value::from(s.to_uppercase() + "!")),
+    ///         _ => Err(FuncError::UnableToConvertFromValue),
+    ///     }
+    /// }
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.set_func_resolver(|name| match name {
+    ///     "shout" => Some(shout as Func),
+    ///     _ => None,
+    /// });
+    /// tmpl.parse(r#"{{ shout "hi" }}"#).unwrap();
+    /// assert_eq!(tmpl.render(&Context::empty()).unwrap(), "HI!");
+    /// ```
+    pub fn set_func_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&str) -> Option<Func> + Send + Sync + 'static,
+    {
+        self.func_resolver = Some(Arc::new(resolver));
+        self.parse_options.late_bound_funcs = true;
+    }
+
+    /// Sets a sink which receives a record of every function and method call of subsequent
+    /// executions, e.g. to review what templates written by users did. Calls are recorded
+    /// before they are made, so failed calls are recorded as well.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use gtmpl::{AuditRecord, Context, Template};
+    ///
+    /// let log = Arc::new(Mutex::new(vec![]));
+    /// let sink = Arc::clone(&log);
+    /// let mut tmpl = Template::with_name("mail");
+    /// tmpl.set_audit_sink(move |record: AuditRecord| sink.lock().unwrap().push(record));
+    /// tmpl.parse("Hello\n{{ . | upper }}").unwrap();
+    /// tmpl.render(&Context::from("ann")).unwrap();
+    ///
+    /// let log = log.lock().unwrap();
+    /// assert_eq!(
+    ///     log[0],
+    ///     AuditRecord {
+    ///         func: "upper".to_owned(),
+    ///         args: vec!["ann".to_owned()],
+    ///         template: "mail".to_owned(),
+    ///         line: 2,
+    ///     }
+    /// );
+    /// ```
+    pub fn set_audit_sink<S: AuditSink + 'static>(&mut self, sink: S) {
+        self.audit_sink = Some(Arc::new(sink));
+    }
+
+    // The function called `name` with `args` arguments, falling back to the resolver.
+    pub(crate) fn func(&self, name: &str, args: usize) -> Option<SharedFunc> {
+        match self.funcs.get(name) {
+            Some(def) => Some(def.func_for(args).clone()),
+            None => self
+                .func_resolver
+                .as_ref()
+                .and_then(|resolve| resolve(name))
+                .map(|f| Arc::new(f) as SharedFunc),
+        }
+    }
+
+    // Names of the async functions, which templates can call besides `funcs`.
+    pub(crate) fn async_func_names(&self) -> HashSet<String> {
+        let names = std::iter::empty();
+        #[cfg(feature = "tokio")]
+        let names = names.chain(self.async_funcs.keys().cloned());
+        names.collect()
+    }
+
+    /// Sets the parse options used by subsequent calls to `parse` and `add_template`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{Context, ParseOptions};
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.set_parse_options(ParseOptions {
+    ///     trim_blocks: true,
+    ///     keep_trailing_newline: false,
+    ///     ..Default::default()
+    /// });
+    /// tmpl.parse("{{ if true }}\nHello World!\n{{ end }}\n").unwrap();
+    /// let output = tmpl.render(&Context::empty());
+    /// assert_eq!(&output.unwrap(), "Hello World!\n");
+    /// ```
+    pub fn set_parse_options(&mut self, options: ParseOptions) {
+        self.parse_options = options;
+    }
+
+    /// Sets the options used by subsequent executions.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    ///
+    /// use gtmpl::{Context, ExecOptions, MissingKey};
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.set_exec_options(ExecOptions {
+    ///     missing_key: MissingKey::Error,
+    ///     ..Default::default()
+    /// });
+    /// tmpl.parse("{{ .name }}").unwrap();
+    /// let data: HashMap<String, String> = HashMap::new();
+    /// assert!(tmpl.render(&Context::from(data)).is_err());
+    /// ```
+    pub fn set_exec_options(&mut self, options: ExecOptions) {
+        self.exec_options = options;
     }
 
     /// Parse the given `text` as template body.
@@ -93,8 +480,65 @@ impl Template {
         let tree_set = parse(
             self.name.clone(),
             text.into(),
-            self.funcs.keys().cloned().collect(),
+            &self.funcs,
+            self.async_func_names(),
+            self.parse_options.clone(),
         )?;
+        self.add_trees(tree_set);
+        Ok(())
+    }
+
+    /// Parse `new_text` as template body after an edit of the previously parsed `old_text`.
+    ///
+    /// `edited` is the byte range of `old_text` that was replaced. Only the top-level items
+    /// around the edit are parsed again, everything else is taken over from the previous parse.
+    /// Edits that can not be handled this way fall back to parsing all of `new_text`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::Context;
+    ///
+    /// let old = "{{ define \"a\" }}A{{ end }}Hello {{ . }}!{{ template \"a\" }}";
+    /// let new = "{{ define \"a\" }}A{{ end }}Bye {{ . }}!{{ template \"a\" }}";
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse(old).unwrap();
+    /// let start = old.find("Hello").unwrap();
+    /// tmpl.reparse(old, new, start..start + "Hello".len()).unwrap();
+    /// let output = tmpl.render(&Context::from("World"));
+    /// assert_eq!(&output.unwrap(), "Bye World!A");
+    /// ```
+    pub fn reparse(
+        &mut self,
+        old_text: &str,
+        new_text: &str,
+        edited: Range<usize>,
+    ) -> Result<(), ParseError> {
+        let async_funcs = self.async_func_names();
+        let tree_set = match reparse(
+            &self.tree_set,
+            &self.name,
+            old_text,
+            new_text,
+            edited,
+            &self.funcs,
+            async_funcs.clone(),
+            self.parse_options.clone(),
+        ) {
+            Some(tree_set) => tree_set,
+            None => parse(
+                self.name.clone(),
+                new_text.to_owned(),
+                &self.funcs,
+                async_funcs,
+                self.parse_options.clone(),
+            )?,
+        };
+        if let Some(tree) = self.tree_set.remove(&self.name) {
+            for name in tree.defined() {
+                self.tree_set.remove(name);
+            }
+        }
         self.tree_set.extend(tree_set);
         Ok(())
     }
@@ -120,11 +564,24 @@ impl Template {
         let tree_set = parse(
             name.into(),
             text.into(),
-            self.funcs.keys().cloned().collect(),
+            &self.funcs,
+            self.async_func_names(),
+            self.parse_options.clone(),
         )?;
-        self.tree_set.extend(tree_set);
+        self.add_trees(tree_set);
         Ok(())
     }
+
+    // Adds parsed templates like Go's `AddParseTree`. Later definitions replace earlier ones,
+    // e.g. to override a `block`, unless they are empty.
+    fn add_trees(&mut self, tree_set: HashMap<String, Tree>) {
+        for (name, tree) in tree_set {
+            if tree.is_empty() && self.tree_set.contains_key(&name) {
+                continue;
+            }
+            self.tree_set.insert(name, tree);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -137,4 +594,55 @@ mod tests_mocked {
         assert!(t.parse(r#"{{ if eq "bar" "bar" }} 2000 {{ end }}"#).is_ok());
         assert!(t.tree_set.contains_key("foo"));
     }
+
+    #[test]
+    fn test_parse_register_missing_func() {
+        use gtmpl_value::{FuncError, Value};
+
+        fn shout(args: &[Value]) -> Result<Value, FuncError> {
+            Ok(Value::from(args[0].to_string().to_uppercase()))
+        }
+
+        let mut t = Template::default();
+        let text = r#"{{ "foo" | shout }}"#;
+        let err = t.parse(text).unwrap_err();
+        match err {
+            ParseError::UndefinedFunction { ref ctx, ref name } => {
+                assert_eq!(name, "shout");
+                assert_eq!(ctx.pos, text.find("shout").unwrap());
+                t.add_func(name, shout);
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(t.parse(text).is_ok());
+    }
+
+    #[test]
+    fn test_add_closure() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct AppState {
+            greeting: String,
+            calls: AtomicUsize,
+        }
+
+        let state = Arc::new(AppState {
+            greeting: "Hello".to_owned(),
+            calls: AtomicUsize::new(0),
+        });
+        let mut t = Template::default();
+        let captured = Arc::clone(&state);
+        t.add_closure("greet", move |args: &[Value]| {
+            captured.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Value::from(format!("{} {}", captured.greeting, args[0])))
+        });
+        t.parse(r#"{{ greet "a" }}, {{ "b" | greet }}"#).unwrap();
+        let ctx = crate::Context::empty();
+        assert_eq!(t.render(&ctx).unwrap(), "Hello a, Hello b");
+        assert_eq!(state.calls.load(Ordering::SeqCst), 2);
+
+        let copy = t.clone();
+        assert_eq!(copy.render(&ctx).unwrap(), "Hello a, Hello b");
+        assert_eq!(state.calls.load(Ordering::SeqCst), 4);
+    }
 }
@@ -41,6 +41,40 @@ pub fn unquote_str(s: &str) -> Option<String> {
     Some(r)
 }
 
+/// Quotes `s` like Go's `strconv.Quote`.
+pub fn quote_str(s: &str) -> String {
+    quote(s, '"', false)
+}
+
+/// Quotes `s` with `quote` like Go's `strconv.Quote` for `"` and `strconv.QuoteRune` for `'`.
+/// With `ascii` all non-ASCII characters are escaped like `strconv.QuoteToASCII` does.
+pub fn quote(s: &str, quote: char, ascii: bool) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => r.push_str("\\\\"),
+            c if c == quote => {
+                r.push('\\');
+                r.push(c);
+            }
+            '\u{7}' => r.push_str("\\a"),
+            '\u{8}' => r.push_str("\\b"),
+            '\u{c}' => r.push_str("\\f"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            '\u{b}' => r.push_str("\\v"),
+            c if !c.is_control() && (!ascii || c.is_ascii()) => r.push(c),
+            c if (c as u32) < 0x80 => r.push_str(&format!("\\x{:02x}", c as u32)),
+            c if (c as u32) < 0x10000 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    r.push(quote);
+    r
+}
+
 fn unqote(raw: &str) -> Option<(String, usize)> {
     if raw.starts_with('\\') {
         match &raw[..2] {
@@ -108,23 +142,42 @@ fn extract_bytes_x(s: &str) -> Option<(String, usize)> {
     String::from_utf8(bytes).ok().map(|s| (s, i))
 }
 
-/// Returns
+/// Reports whether a value is true like Go's `template.IsTrue`, i.e. not the zero value of its
+/// type. `false`, `0`, empty strings, arrays and maps, `nil` and missing values are false.
+/// Objects are structs in Go and always true.
+///
+/// In contrast to Go, every `Value` has a truth value, so `if` and `with` never fail on them.
+///
+/// ## Example
+///
+/// ```rust
+/// use gtmpl::{is_true, Value};
+///
+/// assert!(!is_true(&Value::from(0.0)));
+/// assert!(is_true(&Value::from(-1)));
+/// ```
 pub fn is_true(val: &Value) -> bool {
     match *val {
-        Value::Bool(ref b) => *b,
+        Value::Bool(b) => b,
         Value::String(ref s) => !s.is_empty(),
         Value::Array(ref a) => !a.is_empty(),
-        Value::Object(ref o) => !o.is_empty(),
+        Value::Bytes(ref b) => !b.is_empty(),
         Value::Map(ref m) => !m.is_empty(),
-        Value::Function(_) => true,
+        Value::OrderedMap(ref m) => !m.is_empty(),
+        Value::Object(_) | Value::Function(_) => true,
         Value::NoValue | Value::Nil => false,
-        Value::Number(ref n) => n.as_u64().map(|u| u != 0).unwrap_or_else(|| true),
+        // Integers too large for an `i64` are never zero.
+        Value::Number(ref n) => match n.as_i64() {
+            Some(i) => i != 0,
+            None => !matches!(n.as_f64(), Some(f) if f == 0.0),
+        },
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_unquote_char() {
@@ -176,11 +229,34 @@ mod tests {
         assert_eq!(u, Some("Fran & Freddie's Diner\t☺".to_owned()));
     }
 
+    #[test]
+    fn test_quote_str() {
+        assert_eq!(quote_str("foo"), r#""foo""#);
+        assert_eq!(quote_str("a\"b\\c\td\n"), r#""a\"b\\c\td\n""#);
+        assert_eq!(quote_str("\u{1}ä\u{85}"), r#""\x01ä\u0085""#);
+        assert_eq!(unquote_str(&quote_str("x\"\u{7f}y")).unwrap(), "x\"\u{7f}y");
+        assert_eq!(quote("'\"", '\'', false), r#"'\'"'"#);
+        assert_eq!(quote("ä✐", '"', true), r#""\u00e4\u2710""#);
+    }
+
     #[test]
     fn test_is_true() {
         let t = Value::from(1i8);
         assert!(is_true(&t));
         let t = Value::from(0u32);
         assert!(!is_true(&t));
+        assert!(is_true(&Value::from(-3)));
+        assert!(!is_true(&Value::from(0i64)));
+        assert!(!is_true(&Value::from(0.0)));
+        assert!(!is_true(&Value::from(-0.0)));
+        assert!(is_true(&Value::from(0.5)));
+        assert!(is_true(&Value::from(f64::NAN)));
+        assert!(!is_true(&Value::from("")));
+        assert!(is_true(&Value::from("0")));
+        assert!(!is_true(&Value::Array(vec![])));
+        assert!(!is_true(&Value::Map(HashMap::new())));
+        assert!(is_true(&Value::Object(HashMap::new())));
+        assert!(!is_true(&Value::Nil));
+        assert!(!is_true(&Value::NoValue));
     }
 }
@@ -0,0 +1,537 @@
+//! Validation of templates against a sample context without executing them.
+use std::collections::HashSet;
+
+use gtmpl_value::Value;
+
+use crate::error::{ErrorContext, ValidationError};
+use crate::exec::MissingTemplate;
+use crate::func_map::accepts;
+use crate::node::*;
+use crate::ordered;
+use crate::print_verb::type_name;
+use crate::template::Template;
+use crate::utils::is_true;
+
+impl Template {
+    /// Checks the template against `sample_ctx` without producing output and returns all
+    /// mismatches, e.g. to detect drift between templates and their data in CI.
+    ///
+    /// Every field path is resolved against the sample, the number of arguments of functions
+    /// with a known [`arity`](crate::FuncDef::arity) is checked against all their overloads
+    /// and `range` targets must be arrays or maps. Both branches of `if` and `with` are
+    /// checked and the body of `range` is checked against every element. Values returned by
+    /// functions are unknown, so fields of them are not checked.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use gtmpl::Value;
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.parse("{{ .name }} {{ range .items }}{{ .id }}{{ end }} {{ len }}").unwrap();
+    ///
+    /// let mut item = HashMap::new();
+    /// item.insert("id".to_owned(), 1);
+    /// let mut ctx = HashMap::new();
+    /// ctx.insert("items".to_owned(), Value::from(vec![item]));
+    ///
+    /// let errors = tmpl.validate(&Value::from(ctx)).unwrap_err();
+    /// let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    /// assert_eq!(
+    ///     errors,
+    ///     vec![
+    ///         "template: :1: map has no entry for key \"name\"",
+    ///         "template: :1: wrong number of args for len: want 1 got 0",
+    ///     ]
+    /// );
+    /// ```
+    pub fn validate(&self, sample_ctx: &Value) -> Result<(), Vec<ValidationError>> {
+        let mut validator = Validator {
+            template: self,
+            tree_name: &self.name,
+            vars: vec![vec![("$".to_owned(), Some(sample_ctx.clone()))]],
+            stack: vec![self.name.clone()],
+            errors: vec![],
+            seen: HashSet::new(),
+        };
+        if let Some(root) = self.tree_set.get(&self.name).and_then(|t| t.root.as_ref()) {
+            validator.walk(&Some(sample_ctx.clone()), root);
+        }
+        if validator.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(validator.errors)
+        }
+    }
+}
+
+// Walks the tree like the executor, but with values that may be unknown (`None`).
+struct Validator<'a> {
+    template: &'a Template,
+    tree_name: &'a str,
+    vars: Vec<Vec<(String, Option<Value>)>>,
+    // Templates currently being validated, to stop at recursive calls.
+    stack: Vec<String>,
+    errors: Vec<ValidationError>,
+    seen: HashSet<String>,
+}
+
+impl<'a> Validator<'a> {
+    fn context(&self, pos: Pos) -> ErrorContext {
+        match self.template.tree_set.get(self.tree_name) {
+            Some(tree) => tree.error_context(pos),
+            None => ErrorContext {
+                name: self.tree_name.to_owned(),
+                line: 0,
+                pos,
+            },
+        }
+    }
+
+    // Records a mismatch once, even if the node is checked for several range elements.
+    fn report(&mut self, err: ValidationError) {
+        if self.seen.insert(err.to_string()) {
+            self.errors.push(err);
+        }
+    }
+
+    fn walk_list(&mut self, dot: &Option<Value>, list: &ListNode) {
+        for node in &list.nodes {
+            self.walk(dot, node);
+        }
+    }
+
+    fn walk(&mut self, dot: &Option<Value>, node: &Nodes) {
+        match *node {
+            Nodes::Action(ref n) => {
+                self.eval_pipeline(dot, &n.pipe);
+            }
+            Nodes::If(ref n) | Nodes::With(ref n) => {
+                self.vars.push(vec![]);
+                let val = self.eval_pipeline(dot, &n.pipe);
+                match *node {
+                    Nodes::With(_) => {
+                        let inner = val.filter(is_true);
+                        self.walk_list(&inner, &n.list);
+                    }
+                    _ => self.walk_list(dot, &n.list),
+                }
+                if let Some(ref else_list) = n.else_list {
+                    self.walk_list(dot, else_list);
+                }
+                self.vars.pop();
+            }
+            Nodes::Range(ref n) => self.walk_range(dot, n),
+            Nodes::List(ref n) => self.walk_list(dot, n),
+            Nodes::Template(ref n) => self.walk_template(dot, n),
+            _ => {}
+        }
+    }
+
+    fn walk_range(&mut self, dot: &Option<Value>, range: &RangeNode) {
+        self.vars.push(vec![]);
+        let val = self.eval_pipeline(dot, &range.pipe);
+        let items: Vec<(Value, Value)> = match val {
+            Some(Value::Array(ref vec)) => vec
+                .iter()
+                .enumerate()
+                .map(|(k, v)| (Value::from(k), v.clone()))
+                .collect(),
+            Some(ref map @ (Value::Map(_) | Value::OrderedMap(_) | Value::Object(_))) => {
+                ordered::entries(map, false)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(k, v)| (Value::from(k.as_str()), v.clone()))
+                    .collect()
+            }
+            Some(Value::Bytes(ref bytes)) => bytes
+                .iter()
+                .enumerate()
+                .map(|(k, &b)| (Value::from(k), Value::from(b)))
+                .collect(),
+            Some(Value::Function(_)) | Some(Value::NoValue) | Some(Value::Nil) => vec![],
+            Some(val) => {
+                let ctx = self.context(range.pipe.pos());
+                self.report(ValidationError::InvalidRange { ctx, value: val });
+                vec![]
+            }
+            None => vec![],
+        };
+        if items.is_empty() {
+            self.one_iteration(None, None, range);
+        }
+        for (key, val) in items {
+            self.one_iteration(Some(key), Some(val), range);
+        }
+        if let Some(ref else_list) = range.else_list {
+            self.walk_list(dot, else_list);
+        }
+        self.vars.pop();
+    }
+
+    fn one_iteration(&mut self, key: Option<Value>, val: Option<Value>, range: &RangeNode) {
+        let decl = &range.pipe.decl;
+        if let Some(elem) = decl.last() {
+            self.set_var(&elem.ident[0], val.clone());
+        }
+        if decl.len() > 1 {
+            self.set_var(&decl[0].ident[0], key);
+        }
+        self.vars.push(vec![]);
+        self.walk_list(&val, &range.list);
+        self.vars.pop();
+    }
+
+    fn walk_template(&mut self, dot: &Option<Value>, template: &TemplateNode) {
+        let name = match template.name {
+            PipeOrString::String(ref name) => name.clone(),
+            PipeOrString::Pipe(ref pipe) => match self.eval_pipeline(dot, pipe) {
+                Some(Value::String(s)) => s,
+                _ => return,
+            },
+        };
+        let val = match template.pipe {
+            Some(ref pipe) => self.eval_pipeline(dot, pipe),
+            None => Some(Value::NoValue),
+        };
+        let (tree_name, root) = match self.template.tree_set.get_key_value(&name) {
+            Some((tree_name, tree)) => match tree.root {
+                Some(ref root) => (tree_name, root),
+                None => return,
+            },
+            None => {
+                if self.template.exec_options.missing_template == MissingTemplate::Error {
+                    let ctx = self.context(template.pos());
+                    self.report(ValidationError::TemplateNotDefined { ctx, name });
+                }
+                return;
+            }
+        };
+        if self.stack.contains(&name) {
+            return;
+        }
+        let vars = std::mem::replace(&mut self.vars, vec![vec![("$".to_owned(), val.clone())]]);
+        let parent = std::mem::replace(&mut self.tree_name, tree_name);
+        self.stack.push(name);
+        self.walk(&val, root);
+        self.stack.pop();
+        self.tree_name = parent;
+        self.vars = vars;
+    }
+
+    fn eval_pipeline(&mut self, dot: &Option<Value>, pipe: &PipeNode) -> Option<Value> {
+        let mut val = None;
+        for (i, cmd) in pipe.cmds.iter().enumerate() {
+            val = self.eval_command(dot, cmd, i > 0);
+        }
+        for var in &pipe.decl {
+            if pipe.is_assign {
+                self.set_var(&var.ident[0], val.clone());
+            } else if let Some(scope) = self.vars.last_mut() {
+                scope.push((var.ident[0].clone(), val.clone()));
+            }
+        }
+        val
+    }
+
+    // Evaluates a command. `piped` tells whether the result of the previous command is passed
+    // as the final argument.
+    fn eval_command(
+        &mut self,
+        dot: &Option<Value>,
+        cmd: &CommandNode,
+        piped: bool,
+    ) -> Option<Value> {
+        let first = cmd.args.first()?;
+        match *first {
+            Nodes::Identifier(ref n) => {
+                self.eval_function(dot, n, &cmd.args, piped);
+                None
+            }
+            Nodes::Field(ref n) => {
+                self.eval_args(dot, &cmd.args);
+                self.eval_field_chain(n.pos(), dot.clone(), &n.ident)
+            }
+            Nodes::Variable(ref n) => {
+                self.eval_args(dot, &cmd.args);
+                let val = self.var_value(&n.ident[0]);
+                self.eval_field_chain(n.pos(), val, &n.ident[1..])
+            }
+            Nodes::Chain(ref n) => {
+                self.eval_args(dot, &cmd.args);
+                let val = self.eval_arg(dot, &n.node);
+                self.eval_field_chain(n.pos(), val, &n.field)
+            }
+            _ => self.eval_arg(dot, first),
+        }
+    }
+
+    fn eval_function(
+        &mut self,
+        dot: &Option<Value>,
+        ident: &IdentifierNode,
+        args: &[Nodes],
+        piped: bool,
+    ) {
+        self.eval_args(dot, args);
+        let name = &ident.ident;
+        let arities = self.template.funcs.get(name).and_then(|def| def.arities());
+        if let Some(arities) = arities {
+            let got = args.len() - 1 + usize::from(piped);
+            if !arities.iter().any(|&arity| accepts(arity, got)) {
+                let want: Vec<String> = arities
+                    .iter()
+                    .map(|&(min, max)| match max {
+                        Some(max) if max == min => min.to_string(),
+                        Some(max) => format!("{} to {}", min, max),
+                        None => format!("at least {}", min),
+                    })
+                    .collect();
+                let ctx = self.context(ident.pos());
+                self.report(ValidationError::WrongArgCount {
+                    ctx,
+                    func: name.clone(),
+                    want: want.join(" or "),
+                    got,
+                });
+            }
+        }
+    }
+
+    // Checks the arguments following the function or method name.
+    fn eval_args(&mut self, dot: &Option<Value>, args: &[Nodes]) {
+        for arg in args.iter().skip(1) {
+            self.eval_arg(dot, arg);
+        }
+    }
+
+    fn eval_arg(&mut self, dot: &Option<Value>, node: &Nodes) -> Option<Value> {
+        match *node {
+            Nodes::Dot(_) => dot.clone(),
+            Nodes::Field(ref n) => self.eval_field_chain(n.pos(), dot.clone(), &n.ident),
+            Nodes::Variable(ref n) => {
+                let val = self.var_value(&n.ident[0]);
+                self.eval_field_chain(n.pos(), val, &n.ident[1..])
+            }
+            Nodes::Pipe(ref n) => self.eval_pipeline(dot, n),
+            Nodes::Identifier(ref n) => {
+                self.eval_function(dot, n, &[], false);
+                None
+            }
+            Nodes::Chain(ref n) => {
+                let val = self.eval_arg(dot, &n.node);
+                self.eval_field_chain(n.pos(), val, &n.field)
+            }
+            Nodes::String(ref n) => Some(n.value.clone()),
+            Nodes::Bool(ref n) => Some(n.value.clone()),
+            Nodes::Number(ref n) => Some(n.value.clone()),
+            _ => None,
+        }
+    }
+
+    // Resolves the fields `ident` of `receiver`. Stops at the first mismatch and at methods,
+    // whose results are unknown.
+    fn eval_field_chain(
+        &mut self,
+        pos: Pos,
+        receiver: Option<Value>,
+        ident: &[String],
+    ) -> Option<Value> {
+        let mut val = receiver;
+        for field in ident {
+            let receiver = val?;
+            val = match (ordered::as_map(&receiver), &receiver) {
+                (Some(o), _) => match o.get(field) {
+                    Some(&Value::Function(_)) => return None,
+                    Some(v) => Some(v.clone()),
+                    None if matches!(receiver, Value::Object(_)) => {
+                        let ctx = self.context(pos);
+                        self.report(ValidationError::NoField {
+                            ctx,
+                            field: field.clone(),
+                            typ: type_name(&receiver),
+                        });
+                        return None;
+                    }
+                    None => {
+                        let ctx = self.context(pos);
+                        self.report(ValidationError::MissingKey {
+                            ctx,
+                            key: field.clone(),
+                        });
+                        return None;
+                    }
+                },
+                // The sample has no value to check the fields against.
+                (None, Value::NoValue | Value::Nil) => return None,
+                _ => {
+                    let ctx = self.context(pos);
+                    self.report(ValidationError::NoField {
+                        ctx,
+                        field: field.clone(),
+                        typ: type_name(&receiver),
+                    });
+                    return None;
+                }
+            };
+        }
+        val
+    }
+
+    fn var_value(&self, name: &str) -> Option<Value> {
+        self.vars
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| n == name)
+            .and_then(|(_, val)| val.clone())
+    }
+
+    fn set_var(&mut self, name: &str, val: Option<Value>) {
+        let var = self
+            .vars
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|(n, _)| n == name);
+        if let Some(var) = var {
+            var.1 = val;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use super::*;
+    use gtmpl_derive::Gtmpl;
+    use std::collections::HashMap;
+
+    fn validate(text: &str, ctx: Value) -> Vec<String> {
+        let mut t = Template::default();
+        t.parse(text).unwrap();
+        match t.validate(&ctx) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    fn map(entries: &[(&str, Value)]) -> Value {
+        let map: HashMap<String, Value> = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+        Value::from(map)
+    }
+
+    #[test]
+    fn test_validate_ok() {
+        let ctx = map(&[
+            ("name", Value::from("foo")),
+            ("tags", Value::from(vec!["a", "b"])),
+            ("user", map(&[("id", Value::from(1))])),
+        ]);
+        let text = r#"{{ $u := .user }}{{ .name | printf "%s" }}{{ range $i, $t := .tags }}{{ $i }}{{ $t }}{{ $.name }}{{ end }}{{ with .user }}{{ .id }}{{ end }}{{ $u.id }}"#;
+        assert_eq!(validate(text, ctx), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_validate_fields() {
+        #[derive(Gtmpl)]
+        struct User {
+            name: String,
+        }
+        let user = User {
+            name: "foo".to_owned(),
+        };
+        let ctx = map(&[("user", Value::from(user)), ("n", Value::from(1))]);
+        let errors = validate("{{ .user.age }}\n{{ .n.x }}\n{{ .missing.x }}", ctx);
+        assert_eq!(
+            errors,
+            vec![
+                "template: :1: can't evaluate field age in type map[string]interface {}",
+                "template: :2: can't evaluate field x in type int",
+                "template: :3: map has no entry for key \"missing\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_range() {
+        let items = vec![map(&[("a", Value::from(1))]), map(&[("b", Value::from(2))])];
+        let ctx = map(&[("items", Value::from(items)), ("n", Value::from(3))]);
+        let errors = validate(
+            "{{ range .items }}{{ .a }}{{ end }}{{ range .n }}{{ end }}",
+            ctx,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "template: :1: map has no entry for key \"a\"",
+                "template: :1: range can't iterate over 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_arity() {
+        let errors = validate(
+            r#"{{ "a" | len "b" }}{{ 1 | eq 1 }}{{ not }}"#,
+            Value::NoValue,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "template: :1: wrong number of args for len: want 1 got 2",
+                "template: :1: wrong number of args for not: want 1 got 0",
+            ]
+        );
+
+        fn pair(_: &[Value]) -> Result<Value, gtmpl_value::FuncError> {
+            Ok(Value::Nil)
+        }
+        let mut t = Template::default();
+        t.funcs
+            .insert("pair", crate::FuncDef::new(pair).with_arity(2, Some(2)));
+        t.add_func("not", pair);
+        t.parse("{{ pair 1 }}{{ not }}").unwrap();
+        let errors: Vec<String> = t
+            .validate(&Value::NoValue)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!["template: :1: wrong number of args for pair: want 2 got 1"]
+        );
+
+        t.funcs
+            .overload("pair", crate::FuncDef::new(pair).with_arity(4, None));
+        t.parse("{{ pair 1 2 3 4 }}{{ pair 1 2 }}{{ pair 1 2 3 }}")
+            .unwrap();
+        let errors: Vec<String> = t
+            .validate(&Value::NoValue)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!["template: :1: wrong number of args for pair: want 2 or at least 4 got 3"]
+        );
+    }
+
+    #[test]
+    fn test_validate_templates() {
+        let ctx = map(&[("user", map(&[("id", Value::from(1))]))]);
+        let text = r#"{{ define "user" }}{{ .id }}{{ .name }}{{ template "user" . }}{{ end }}{{ template "user" .user }}{{ with .nothing }}{{ .x }}{{ end }}"#;
+        assert_eq!(
+            validate(text, ctx),
+            vec![
+                "template: user:1: map has no entry for key \"name\"",
+                "template: :1: map has no entry for key \"nothing\"",
+            ]
+        );
+    }
+}
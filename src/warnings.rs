@@ -0,0 +1,125 @@
+//! Warnings about parsed templates, e.g. calls of deprecated functions.
+use crate::error::ParseWarning;
+use crate::node::*;
+use crate::parse::Tree;
+use crate::template::Template;
+
+impl Template {
+    /// Returns the warnings about the parsed templates, ordered by template name and position,
+    /// e.g. calls of functions [marked as deprecated](crate::FuncDef::deprecated). Unlike
+    /// parse errors, warnings do not prevent executing the templates.
+    ///
+    /// ## Example
+    ///
+    /// ```rust
+    /// use gtmpl::{FuncDef, FuncError, Value};
+    ///
+    /// fn fmt_date(_args: &[Value]) -> Result<Value, FuncError> {
+    ///     Ok(Value::from("2024-01-01"))
+    /// }
+    ///
+    /// let mut tmpl = gtmpl::Template::default();
+    /// tmpl.funcs.insert(
+    ///     "fmtDate",
+    ///     FuncDef::new(fmt_date).deprecated("use date instead"),
+    /// );
+    /// tmpl.parse("Today\nis {{ fmtDate }}").unwrap();
+    ///
+    /// let warnings = tmpl.warnings();
+    /// assert_eq!(warnings[0].code(), "gtmpl::parse::deprecated_function");
+    /// assert_eq!(warnings[0].context().line, 2);
+    /// ```
+    pub fn warnings(&self) -> Vec<ParseWarning> {
+        let mut names: Vec<&String> = self.tree_set.keys().collect();
+        names.sort_unstable();
+        let mut warnings = vec![];
+        for name in names {
+            let tree = &self.tree_set[name];
+            if let Some(ref root) = tree.root {
+                self.warn(tree, root, &mut warnings);
+            }
+        }
+        warnings
+    }
+
+    fn warn(&self, tree: &Tree, node: &Nodes, warnings: &mut Vec<ParseWarning>) {
+        match *node {
+            Nodes::List(ref list) => self.warn_list(tree, list, warnings),
+            Nodes::Action(ref action) => self.warn_pipe(tree, &action.pipe, warnings),
+            Nodes::If(ref n) | Nodes::With(ref n) | Nodes::Range(ref n) => {
+                self.warn_pipe(tree, &n.pipe, warnings);
+                self.warn_list(tree, &n.list, warnings);
+                if let Some(ref else_list) = n.else_list {
+                    self.warn_list(tree, else_list, warnings);
+                }
+            }
+            Nodes::Template(ref n) => {
+                if let PipeOrString::Pipe(ref pipe) = n.name {
+                    self.warn_pipe(tree, pipe, warnings);
+                }
+                if let Some(ref pipe) = n.pipe {
+                    self.warn_pipe(tree, pipe, warnings);
+                }
+            }
+            Nodes::Pipe(ref pipe) => self.warn_pipe(tree, pipe, warnings),
+            Nodes::Chain(ref chain) => self.warn(tree, &chain.node, warnings),
+            Nodes::Identifier(ref ident) => {
+                let note = self
+                    .funcs
+                    .get(&ident.ident)
+                    .and_then(|def| def.deprecated.as_ref());
+                if let Some(note) = note {
+                    warnings.push(ParseWarning::DeprecatedFunction {
+                        ctx: tree.error_context(ident.pos()),
+                        name: ident.ident.clone(),
+                        note: note.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn warn_list(&self, tree: &Tree, list: &ListNode, warnings: &mut Vec<ParseWarning>) {
+        for node in &list.nodes {
+            self.warn(tree, node, warnings);
+        }
+    }
+
+    fn warn_pipe(&self, tree: &Tree, pipe: &PipeNode, warnings: &mut Vec<ParseWarning>) {
+        for cmd in &pipe.cmds {
+            for arg in &cmd.args {
+                self.warn(tree, arg, warnings);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_mocked {
+    use crate::template::Template;
+
+    #[test]
+    fn test_deprecated_function() {
+        let mut t = Template::default();
+        assert!(t.funcs.alias("length", "len"));
+        assert!(t.funcs.deprecate("length", "use len"));
+        assert!(!t.funcs.deprecate("missing", "use len"));
+        assert!(t.funcs.get("len").unwrap().deprecated.is_none());
+        t.add_template("b", r#"{{ template "a" (length .) }}"#)
+            .unwrap();
+        t.parse(
+            "{{ define \"a\" }}{{ if length . }}\n{{ (length .) | printf \"%d\" }}{{ end }}{{ end }}{{ len . }}",
+        )
+        .unwrap();
+        let warnings: Vec<String> = t.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "template: a:1:function length is deprecated: use len",
+                "template: a:2:function length is deprecated: use len",
+                "template: b:1:function length is deprecated: use len",
+            ]
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! `FuncMap::set_defaults` changes every template created afterwards, so it is tested in a
+//! process of its own.
+use gtmpl::{Context, Func, FuncError, FuncMap, Template, Value};
+
+fn brand(_args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::from("ACME"))
+}
+
+fn local_brand(_args: &[Value]) -> Result<Value, FuncError> {
+    Ok(Value::from("ACME Europe"))
+}
+
+fn render(tmpl: &mut Template, text: &str) -> Result<String, String> {
+    tmpl.parse(text).map_err(|e| e.to_string())?;
+    tmpl.render(&Context::empty()).map_err(|e| e.to_string())
+}
+
+#[test]
+fn defaults_precedence_and_reset() {
+    let mut before = Template::default();
+    FuncMap::set_defaults(FuncMap::builtins().with("brand", brand as Func));
+
+    let mut tmpl = Template::with_name("page");
+    assert_eq!(render(&mut tmpl, "{{ brand | lower }}").unwrap(), "acme");
+
+    // Functions of the template win over the defaults.
+    let mut tmpl = Template::default();
+    tmpl.add_func("brand", local_brand);
+    assert_eq!(render(&mut tmpl, "{{ brand }}").unwrap(), "ACME Europe");
+    assert_eq!(FuncMap::defaults().len(), FuncMap::builtins().len() + 1);
+
+    // Templates created before or with their own functions do not see the defaults.
+    assert!(render(&mut before, "{{ brand }}").is_err());
+    let mut tmpl = Template::with_funcs("mail", &FuncMap::builtins());
+    assert!(render(&mut tmpl, "{{ brand }}").is_err());
+
+    // Leaving out a builtin forbids it in new templates.
+    let mut defaults = FuncMap::builtins();
+    defaults.remove("upper");
+    FuncMap::set_defaults(defaults);
+    assert!(render(&mut Template::default(), r#"{{ upper "a" }}"#).is_err());
+    assert!(render(&mut Template::default(), "{{ brand }}").is_err());
+
+    FuncMap::set_defaults(FuncMap::builtins());
+    let mut tmpl = Template::default();
+    assert_eq!(render(&mut tmpl, "{{ len \"ab\" }}").unwrap(), "2");
+    assert!(FuncMap::defaults().contains("upper"));
+    assert!(!FuncMap::defaults().contains("brand"));
+}
@@ -1,5 +1,7 @@
-use gtmpl::{Context, Template};
+use gtmpl::error::ExecError;
+use gtmpl::{Context, MissingTemplate, Template};
 use gtmpl_derive::Gtmpl;
+use std::collections::HashMap;
 
 #[test]
 fn simple_define() {
@@ -155,3 +157,115 @@ fn dynamic_template() {
     assert!(output.is_ok());
     assert_eq!(output.unwrap(), "there is some other template".to_string());
 }
+
+#[test]
+fn block_default() {
+    let mut template = Template::default();
+    template
+        .parse(r#"<{{ block "content" . }}default {{ . }}{{ end }}>"#)
+        .unwrap();
+
+    let output = template.render(&Context::from(1));
+    assert_eq!(output.unwrap(), "<default 1>".to_string());
+}
+
+#[test]
+fn block_override() {
+    let mut template = Template::default();
+    template
+        .parse(r#"<{{ block "content" . }}default {{ . }}{{ end }}>"#)
+        .unwrap();
+    template
+        .parse(r#"{{ define "content" }}override {{ . }}{{ end }}"#)
+        .unwrap();
+
+    let output = template.render(&Context::from(1));
+    assert_eq!(output.unwrap(), "<override 1>".to_string());
+
+    template
+        .parse(r#"{{ define "content" }} {{ end }}"#)
+        .unwrap();
+    let output = template.render(&Context::from(1));
+    assert_eq!(output.unwrap(), "<override 1>".to_string());
+
+    template.add_template("content", "added {{ . }}").unwrap();
+    let output = template.render(&Context::from(1));
+    assert_eq!(output.unwrap(), "<added 1>".to_string());
+}
+
+#[test]
+fn block_override_order() {
+    let mut template = Template::default();
+    template
+        .parse(r#"{{ define "content" }}override{{ end }}"#)
+        .unwrap();
+    template
+        .parse(r#"<{{ block "content" . }}default{{ end }}>"#)
+        .unwrap();
+
+    let output = template.render(&Context::empty());
+    assert_eq!(output.unwrap(), "<default>".to_string());
+}
+
+#[test]
+fn block_redefined_in_same_text() {
+    let mut template = Template::default();
+    let err = template
+        .parse(r#"{{ block "content" . }}default{{ end }}{{ define "content" }}x{{ end }}"#)
+        .unwrap_err();
+    assert_eq!(err.code(), "gtmpl::parse::multiple_definitions");
+
+    template
+        .parse(r#"{{ block "content" . }}default{{ end }}{{ define "content" }} {{ end }}"#)
+        .unwrap();
+    let output = template.render(&Context::empty());
+    assert_eq!(output.unwrap(), "default".to_string());
+}
+
+#[test]
+fn render_associated_template() {
+    let mut template = Template::default();
+    template
+        .parse(
+            r#"{{ define "name" }}{{ .name }}{{ end -}}
+               {{ define "subject" }}Welcome {{ template "name" . }}{{ end -}}
+               {{ define "body" }}Hello {{ template "name" . }}, {{ $.id }}{{ end -}}
+               main"#,
+        )
+        .unwrap();
+
+    let mut map = HashMap::new();
+    map.insert("name".to_owned(), "Ann".to_owned());
+    map.insert("id".to_owned(), "42".to_owned());
+    let context = Context::from(map);
+
+    let output = template.render_template("subject", &context);
+    assert_eq!(output.unwrap(), "Welcome Ann".to_string());
+    let output = template.render_template("body", &context);
+    assert_eq!(output.unwrap(), "Hello Ann, 42".to_string());
+    let output = template.render(&context);
+    assert_eq!(output.unwrap(), "main".to_string());
+    assert!(matches!(
+        template.render_template("footer", &context),
+        Err(ExecError::TemplateNotDefined(ref name)) if name == "footer"
+    ));
+}
+
+#[test]
+fn missing_template() {
+    let mut template = Template::default();
+    template
+        .parse(r#"a{{ template "partial" . }}b{{ template "partial" (index . 9) }}c"#)
+        .unwrap();
+    assert!(matches!(
+        template.render(&Context::empty()),
+        Err(ExecError::TemplateNotDefined(ref name)) if name == "partial"
+    ));
+
+    template.exec_options.missing_template = MissingTemplate::Skip;
+    assert_eq!(template.render(&Context::empty()).unwrap(), "abc");
+    assert!(template.validate(&gtmpl::Value::NoValue).is_ok());
+
+    template.exec_options.missing_template = MissingTemplate::Fallback("-".to_owned());
+    assert_eq!(template.render(&Context::empty()).unwrap(), "a-b-c");
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "gtmpl_infix")]
+use gtmpl::{Context, Template};
+use gtmpl_derive::Gtmpl;
+
+#[derive(Gtmpl)]
+struct Item {
+    count: u64,
+    enabled: bool,
+}
+
+#[test]
+fn comparison_and_logic() {
+    let item = Item {
+        count: 5,
+        enabled: true,
+    };
+    let output = gtmpl::template(
+        "{{ if .count > 3 && .enabled }}many{{ else }}few{{ end }}",
+        item,
+    );
+    assert_eq!(&output.unwrap(), "many");
+
+    let item = Item {
+        count: 5,
+        enabled: false,
+    };
+    let output = gtmpl::template(
+        "{{ if .count >= 5 && !.enabled || .count == 0 }}off{{ end }}",
+        item,
+    );
+    assert_eq!(&output.unwrap(), "off");
+}
+
+#[test]
+fn arithmetic_precedence() {
+    let output = gtmpl::template("{{ 1 + 2 * 3 - 4 / 2 }}", 0);
+    assert_eq!(&output.unwrap(), "5");
+
+    let output = gtmpl::template("{{ (1 + 2) * 3 }} {{ . - 1 }} {{ -1 + 1 }}", 3);
+    assert_eq!(&output.unwrap(), "9 2 0");
+}
+
+#[test]
+fn function_calls_bind_tighter() {
+    let output = gtmpl::template(
+        r#"{{ len . + 1 > 3 | not }} {{ printf "%d" 2 != "2" }}"#,
+        "abc",
+    );
+    assert_eq!(&output.unwrap(), "false false");
+}
+
+#[test]
+fn errors() {
+    let mut template = Template::default();
+    assert!(template.parse("{{ 1 + }}").is_err());
+    assert!(template.parse("{{ 1 & 2 }}").is_err());
+
+    let mut template = Template::default();
+    template.funcs.remove("add");
+    assert!(template.parse("{{ 1 + 2 }}").is_err());
+
+    let mut template = Template::default();
+    template.parse("{{ 1 / 0 }}").unwrap();
+    assert!(template.render(&Context::empty()).is_err());
+}
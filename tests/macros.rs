@@ -0,0 +1,76 @@
+#![cfg(feature = "macros")]
+use std::collections::HashMap;
+
+use gtmpl::funcs::gtmpl_fn;
+use gtmpl::{Context, FuncError, Template, Value};
+
+#[gtmpl_fn]
+fn describe(name: &str, age: u8, tags: Vec<String>) -> Result<String, FuncError> {
+    Ok(format!("{} ({}): {}", name, age, tags.join(", ")))
+}
+
+#[gtmpl_fn]
+fn round_to(x: f64, places: Option<i32>) -> Result<f64, FuncError> {
+    let scale = 10f64.powi(places.unwrap_or(0));
+    Ok((x * scale).round() / scale)
+}
+
+#[gtmpl_fn]
+fn lookup(map: HashMap<String, Value>, #[rest] keys: Vec<&str>) -> Result<Vec<Value>, FuncError> {
+    Ok(keys
+        .iter()
+        .map(|k| map.get(*k).cloned().unwrap_or(Value::Nil))
+        .collect())
+}
+
+/// Fails for negative numbers.
+#[gtmpl_fn]
+pub fn checked(n: i64) -> Result<i64, anyhow::Error> {
+    if n < 0 {
+        anyhow::bail!("negative: {}", n);
+    }
+    Ok(n)
+}
+
+fn render(text: &str, ctx: Value) -> Result<String, String> {
+    let mut tmpl = Template::default();
+    tmpl.add_func("describe", describe);
+    tmpl.add_func("roundTo", round_to);
+    tmpl.add_func("lookup", lookup);
+    tmpl.add_func("checked", checked);
+    tmpl.parse(text).map_err(|e| e.to_string())?;
+    tmpl.render(&Context::from(ctx)).map_err(|e| e.to_string())
+}
+
+#[test]
+fn typed_params() {
+    let tags = Value::from(vec!["a".to_owned(), "b".to_owned()]);
+    assert_eq!(
+        render(r#"{{ describe "Ann" 42 . }}"#, tags).unwrap(),
+        "Ann (42): a, b"
+    );
+    assert_eq!(
+        render("{{ roundTo 3.14159 2 }} {{ roundTo 2.5 }}", Value::Nil).unwrap(),
+        "3.14 3"
+    );
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), 1);
+    map.insert("b".to_owned(), 2);
+    assert_eq!(
+        render(r#"{{ lookup . "b" "a" "c" }}"#, Value::from(map)).unwrap(),
+        "[2 1 <nil>]"
+    );
+    assert_eq!(render("{{ checked 1 }}", Value::Nil).unwrap(), "1");
+}
+
+#[test]
+fn wrong_args() {
+    let err = |text: &str| render(text, Value::Nil).unwrap_err();
+    assert!(err(r#"{{ describe "Ann" 300 list }}"#).contains("describe: integer 300 out of range"));
+    assert!(err(r#"{{ describe 1 2 list }}"#)
+        .contains("describe: wrong type for value; expected string; got int"));
+    assert!(err(r#"{{ describe "Ann" 1 }}"#).contains("describe requires exactly 3 argument(s)"));
+    assert!(err("{{ roundTo 1 2 3 }}").contains("round_to: wrong number of args: want 1 or 2"));
+    assert!(err("{{ lookup }}").contains("lookup requires at least 1 argument(s)"));
+    assert!(err("{{ checked -1 }}").contains("negative: -1"));
+}
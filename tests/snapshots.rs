@@ -0,0 +1,52 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use gtmpl::Template;
+
+/// Parses every `tests/snapshots/*.tmpl` and compares the printed tree with the matching `.snap`
+/// file, to catch unintended changes of `Display` for nodes.
+///
+/// `go run tests/snapshots/gen.go` writes the snapshots with Go's `tree.String()`, so a
+/// mismatch after running it is a difference to Go. Run with `GTMPL_UPDATE_SNAPSHOTS=1` to
+/// rewrite them with gtmpl's own output instead and review the changes by hand.
+#[test]
+fn tree_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let update = env::var_os("GTMPL_UPDATE_SNAPSHOTS").is_some();
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "tmpl"))
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty());
+
+    let mut failed = vec![];
+    for path in entries {
+        let text = fs::read_to_string(&path).unwrap();
+        let mut tmpl = Template::default();
+        tmpl.parse(text).unwrap();
+        let tree = tmpl.tree_set[""]
+            .root
+            .as_ref()
+            .map(|root| root.to_string())
+            .unwrap_or_default();
+
+        let snapshot = path.with_extension("snap");
+        if update {
+            fs::write(&snapshot, &tree).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&snapshot).unwrap();
+        if tree != expected {
+            failed.push(format!(
+                "{}:\n  expected: {}\n  got:      {}",
+                path.display(),
+                expected,
+                tree
+            ));
+        }
+    }
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
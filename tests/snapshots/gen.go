@@ -0,0 +1,47 @@
+//go:build ignore
+
+// Writes the tree Go's text/template parses from every *.tmpl file in this directory to the
+// matching *.snap file, so tests/snapshots.rs compares gtmpl's Display of nodes with Go's
+// tree.String().
+//
+// Run from the root of the repository:
+//
+//	go run tests/snapshots/gen.go
+package main
+
+import (
+	"log"
+	"os"
+	"path/filepath"
+	"strings"
+	"text/template"
+)
+
+func main() {
+	dir := filepath.Join("tests", "snapshots")
+	paths, err := filepath.Glob(filepath.Join(dir, "*.tmpl"))
+	if err != nil {
+		log.Fatal(err)
+	}
+	if len(paths) == 0 {
+		log.Fatalf("no templates in %s", dir)
+	}
+	for _, path := range paths {
+		text, err := os.ReadFile(path)
+		if err != nil {
+			log.Fatal(err)
+		}
+		tmpl, err := template.New("").Parse(string(text))
+		if err != nil {
+			log.Fatalf("%s: %v", path, err)
+		}
+		tree := ""
+		if tmpl.Tree != nil && tmpl.Tree.Root != nil {
+			tree = tmpl.Tree.Root.String()
+		}
+		snap := strings.TrimSuffix(path, ".tmpl") + ".snap"
+		if err := os.WriteFile(snap, []byte(tree), 0o644); err != nil {
+			log.Fatal(err)
+		}
+	}
+}